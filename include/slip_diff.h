@@ -0,0 +1,52 @@
+/* C interface to slip-diff, built as the crate's cdylib (libslip_diff). */
+#ifndef SLIP_DIFF_H
+#define SLIP_DIFF_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SLIP_DIFF_EVENT_VERSION 1
+#define SLIP_DIFF_EVENT_WATCH_LOST 2
+#define SLIP_DIFF_EVENT_FILE_ROTATED 3
+#define SLIP_DIFF_EVENT_ERROR 4
+
+typedef struct SlipDiffWatch SlipDiffWatch;
+
+typedef struct SlipDiffEvent {
+    /* One of the SLIP_DIFF_EVENT_* constants. */
+    int kind;
+    /* The version's number, for SLIP_DIFF_EVENT_VERSION. */
+    size_t number;
+    /* The version's contents or the error's message, else NULL. Free with
+     * slip_diff_string_free. */
+    char *text;
+} SlipDiffEvent;
+
+/* Starts watching the file at path. Returns NULL on failure. */
+SlipDiffWatch *slip_diff_watch_open(const char *path);
+
+/* Waits up to timeout_ms milliseconds, or for ever if negative, for the next
+ * event. Returns 1 if event was filled in, 0 on timeout, -1 on bad arguments
+ * and -2 once the watcher has stopped and no more events will come. The first
+ * event is the file's current version. */
+int slip_diff_next_event(SlipDiffWatch *watch, int64_t timeout_ms, SlipDiffEvent *event);
+
+void slip_diff_watch_close(SlipDiffWatch *watch);
+
+/* Renders the change from old to new with the named renderer ("builtin",
+ * "side-by-side", "delta", "unified" or "auto"), or as a plain unified diff
+ * if renderer is NULL. Returns NULL on failure; free the result with
+ * slip_diff_string_free. */
+char *slip_diff_render(const char *old, const char *new_, const char *renderer);
+
+void slip_diff_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SLIP_DIFF_H */
@@ -0,0 +1,235 @@
+use std::{
+    ffi::{CStr, CString},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Who may use a daemon's socket and what they may do, for a daemon shared
+/// by a team on one machine.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct AccessArgs {
+    /// Let members of this group use the socket too, e.g. for a daemon run as root with `--socket /run/slip-diff.sock`
+    #[clap(long)]
+    pub group: Option<String>,
+
+    /// Only members of this group may pause, resume, snapshot or stop the daemon; everyone else may only list, show and attach [default: only the daemon's user and root]
+    #[clap(long, requires = "group")]
+    pub admin_group: Option<String>,
+
+    /// Append who connected and what they asked for to this file
+    #[clap(long)]
+    pub audit_log: Option<PathBuf>,
+}
+
+/// A user connected to the daemon, from the socket's peer credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub uid: u32,
+    pub gid: u32,
+    /// The user's login name, or their uid if they have none.
+    pub name: String,
+}
+
+impl Peer {
+    pub fn new(uid: u32, gid: u32) -> Self {
+        Self {
+            uid,
+            gid,
+            name: user_name(uid).unwrap_or_else(|| uid.to_string()),
+        }
+    }
+}
+
+/// A group as it was when the daemon started. Changes to its members take a
+/// restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub gid: u32,
+    pub members: Vec<String>,
+}
+
+impl Group {
+    pub fn lookup(name: &str) -> io::Result<Self> {
+        let c_name = CString::new(name).map_err(io::Error::other)?;
+        let mut buf = vec![0 as libc::c_char; 16 * 1024];
+        // SAFETY: `getgrnam_r` fills `group` with pointers into `buf`, which
+        // outlives every use of them below.
+        unsafe {
+            let mut group: libc::group = std::mem::zeroed();
+            let mut found = std::ptr::null_mut();
+            let error = libc::getgrnam_r(
+                c_name.as_ptr(),
+                &mut group,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            );
+            if error != 0 {
+                return Err(io::Error::from_raw_os_error(error));
+            }
+            if found.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no group named {name}"),
+                ));
+            }
+            let mut members = Vec::new();
+            let mut member = group.gr_mem;
+            while !member.is_null() && !(*member).is_null() {
+                members.push(CStr::from_ptr(*member).to_string_lossy().into_owned());
+                member = member.add(1);
+            }
+            Ok(Self {
+                name: name.to_string(),
+                gid: group.gr_gid,
+                members,
+            })
+        }
+    }
+
+    fn contains(&self, peer: &Peer) -> bool {
+        peer.gid == self.gid || self.members.contains(&peer.name)
+    }
+}
+
+/// Decides what each [`Peer`] may ask the daemon and keeps the audit log.
+pub struct Access {
+    /// The group allowed on the socket besides the daemon's user.
+    group: Option<Group>,
+    admins: Option<Group>,
+    /// The daemon's own user.
+    owner: u32,
+    audit: Option<Mutex<File>>,
+}
+
+impl Access {
+    pub fn new(args: &AccessArgs) -> io::Result<Self> {
+        let audit = match &args.audit_log {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            group: args.group.as_deref().map(Group::lookup).transpose()?,
+            admins: args.admin_group.as_deref().map(Group::lookup).transpose()?,
+            // SAFETY: `getuid` always succeeds.
+            owner: unsafe { libc::getuid() },
+            audit,
+        })
+    }
+
+    /// Lets the daemon's user, and the `--group` if there is one, use the
+    /// socket at `path`, which nobody else should be able to reach yet.
+    pub fn restrict(&self, path: &Path) -> io::Result<()> {
+        let Some(group) = &self.group else {
+            return fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+        };
+        std::os::unix::fs::chown(path, None, Some(group.gid))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o660))
+    }
+
+    /// Whether `peer` may change what the daemon does rather than only look:
+    /// root, the daemon's user, and the `--admin-group` if there is one.
+    pub fn is_admin(&self, peer: &Peer) -> bool {
+        peer.uid == 0
+            || peer.uid == self.owner
+            || self
+                .admins
+                .as_ref()
+                .is_some_and(|admins| admins.contains(peer))
+    }
+
+    /// Appends `what` `peer` did to the audit log, if there is one.
+    pub fn audit(&self, at: SystemTime, peer: &Peer, what: &str) {
+        tracing::info!("{} (uid {}) {what}", peer.name, peer.uid);
+        let Some(audit) = &self.audit else {
+            return;
+        };
+        let line = format!(
+            "{}\t{}\t{}\t{what}\n",
+            humantime::format_rfc3339_seconds(at),
+            peer.name,
+            peer.uid
+        );
+        if let Err(error) = audit.lock().unwrap().write_all(line.as_bytes()) {
+            tracing::warn!("can't write to the audit log: {error}");
+        }
+    }
+}
+
+/// The login name of `uid`.
+fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: `getpwuid_r` fills `passwd` with pointers into `buf`, which
+    // outlives the one use of them.
+    unsafe {
+        let mut passwd: libc::passwd = std::mem::zeroed();
+        let mut found = std::ptr::null_mut();
+        let error = libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut found);
+        if error != 0 || found.is_null() {
+            return None;
+        }
+        Some(
+            CStr::from_ptr(passwd.pw_name)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(admins: Option<Group>) -> Access {
+        Access {
+            group: None,
+            admins,
+            owner: 1000,
+            audit: None,
+        }
+    }
+
+    fn peer(uid: u32, gid: u32, name: &str) -> Peer {
+        Peer {
+            uid,
+            gid,
+            name: name.into(),
+        }
+    }
+
+    #[test]
+    fn only_root_and_the_owner_are_admins_by_default() {
+        let access = access(None);
+        assert!(access.is_admin(&peer(0, 0, "root")));
+        assert!(access.is_admin(&peer(1000, 1000, "ops")));
+        assert!(!access.is_admin(&peer(1001, 1001, "dev")));
+    }
+
+    #[test]
+    fn admin_group_members_are_admins() {
+        let access = access(Some(Group {
+            name: "wheel".into(),
+            gid: 10,
+            members: vec!["alex".into()],
+        }));
+        assert!(access.is_admin(&peer(1001, 1001, "alex")));
+        assert!(access.is_admin(&peer(1002, 10, "sam")));
+        assert!(!access.is_admin(&peer(1003, 1003, "kim")));
+    }
+
+    #[test]
+    fn looks_up_groups() {
+        assert_eq!(Group::lookup("root").unwrap().gid, 0);
+        assert_eq!(
+            Group::lookup("no-such-group-here").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
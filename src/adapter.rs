@@ -0,0 +1,338 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+
+#[cfg(feature = "archive")]
+use crate::archive;
+#[cfg(feature = "sqlite")]
+use crate::database;
+#[cfg(feature = "image")]
+use crate::images;
+use crate::{
+    extract::Extract,
+    notebook,
+    rules::{self, Rules, MAX_HEX_SIZE},
+};
+
+/// Turns files of some binary format into canonical text to diff, so a
+/// change to e.g. a spreadsheet shows up as the cells that changed rather
+/// than as bytes.
+pub trait ContentAdapter: Send + Sync {
+    /// What `--no-adapter` calls it.
+    fn name(&self) -> &str;
+
+    /// Whether the file at `path`, whose contents are `bytes`, is one this
+    /// adapts, judging by as little as possible.
+    fn sniff(&self, path: &Path, bytes: &[u8]) -> bool;
+
+    /// The canonical text of a file this sniffed. A file too broken to read,
+    /// e.g. one still being written, should be adapted to the reason why
+    /// rather than fail, so the next complete version still diffs against
+    /// the last readable one.
+    fn adapt(&self, path: &Path, bytes: &[u8]) -> io::Result<Adapted>;
+}
+
+/// What a file was adapted to.
+pub struct Adapted {
+    pub text: String,
+    /// The file's bytes if it is an image, which `text` describes, for
+    /// frontends that can show it.
+    pub image: Option<Vec<u8>>,
+}
+
+impl Adapted {
+    pub fn text(text: String) -> Self {
+        Self { text, image: None }
+    }
+}
+
+/// PDF and Word documents, as their text.
+pub struct Documents(pub Extract);
+
+impl ContentAdapter for Documents {
+    fn name(&self) -> &str {
+        "documents"
+    }
+
+    fn sniff(&self, _path: &Path, bytes: &[u8]) -> bool {
+        self.0.handles(bytes)
+    }
+
+    fn adapt(&self, _path: &Path, bytes: &[u8]) -> io::Result<Adapted> {
+        Ok(Adapted::text(self.0.text(bytes).unwrap_or_default()))
+    }
+}
+
+/// Jupyter notebooks, as their cells.
+pub struct Notebooks {
+    pub outputs: bool,
+}
+
+impl ContentAdapter for Notebooks {
+    fn name(&self) -> &str {
+        "notebooks"
+    }
+
+    fn sniff(&self, path: &Path, _bytes: &[u8]) -> bool {
+        notebook::is_notebook(path)
+    }
+
+    fn adapt(&self, _path: &Path, bytes: &[u8]) -> io::Result<Adapted> {
+        Ok(Adapted::text(notebook::describe(bytes, self.outputs)))
+    }
+}
+
+/// SQLite databases, as their schema and rows.
+#[cfg(feature = "sqlite")]
+pub struct Databases {
+    pub tables: Vec<String>,
+}
+
+#[cfg(feature = "sqlite")]
+impl ContentAdapter for Databases {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn sniff(&self, _path: &Path, bytes: &[u8]) -> bool {
+        database::is_database(bytes)
+    }
+
+    fn adapt(&self, path: &Path, _bytes: &[u8]) -> io::Result<Adapted> {
+        Ok(Adapted::text(database::dump(path, &self.tables)))
+    }
+}
+
+/// Files a `--rule` says to diff as bytes, as a hex dump.
+pub struct Hex(pub Rules);
+
+impl ContentAdapter for Hex {
+    fn name(&self) -> &str {
+        "hex"
+    }
+
+    fn sniff(&self, path: &Path, bytes: &[u8]) -> bool {
+        bytes.len() <= MAX_HEX_SIZE && self.0.is_hex(path)
+    }
+
+    fn adapt(&self, _path: &Path, bytes: &[u8]) -> io::Result<Adapted> {
+        Ok(Adapted::text(rules::hex_dump(bytes)))
+    }
+}
+
+/// Zip, tar and gzipped tar archives, as their members, with the `--rule`s
+/// applied to them.
+#[cfg(feature = "archive")]
+pub struct Archives(pub Rules);
+
+#[cfg(feature = "archive")]
+impl ContentAdapter for Archives {
+    fn name(&self) -> &str {
+        "archives"
+    }
+
+    fn sniff(&self, _path: &Path, bytes: &[u8]) -> bool {
+        archive::is_archive(bytes)
+    }
+
+    fn adapt(&self, _path: &Path, bytes: &[u8]) -> io::Result<Adapted> {
+        Ok(Adapted::text(archive::describe(bytes, &self.0)))
+    }
+}
+
+/// Images, as their format, dimensions and optionally perceptual hash.
+#[cfg(feature = "image")]
+pub struct Images {
+    pub perceptual: bool,
+}
+
+#[cfg(feature = "image")]
+impl ContentAdapter for Images {
+    fn name(&self) -> &str {
+        "images"
+    }
+
+    fn sniff(&self, _path: &Path, bytes: &[u8]) -> bool {
+        images::is_image(bytes)
+    }
+
+    fn adapt(&self, _path: &Path, bytes: &[u8]) -> io::Result<Adapted> {
+        Ok(Adapted {
+            text: images::describe(bytes, self.perceptual),
+            image: Some(bytes.to_vec()),
+        })
+    }
+}
+
+/// An adapter configured in `adapters.json`, which runs a command to turn a
+/// file into text, e.g.
+///
+/// ```json
+/// [{"name": "xlsx", "extensions": ["xlsx"], "command": ["xlsx2csv", "{}"]}]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandAdapter {
+    pub name: String,
+    /// File extensions it adapts, without the dot.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Bytes that files it adapts begin with, as an alternative to their
+    /// extension.
+    #[serde(default)]
+    pub magic: Option<String>,
+    /// The program and its arguments, where `{}` is replaced by the file's
+    /// path, or the path is appended if there is none. Its standard output
+    /// is the file's text.
+    pub command: Vec<String>,
+}
+
+impl CommandAdapter {
+    /// Loads the adapters configured in `path`, if it exists.
+    pub fn load(path: &Path) -> io::Result<Vec<Self>> {
+        let adapters: Vec<Self> = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {error}", path.display()),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if let Some(adapter) = adapters.iter().find(|adapter| adapter.command.is_empty()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: adapter `{}` has no command",
+                    path.display(),
+                    adapter.name
+                ),
+            ));
+        }
+        Ok(adapters)
+    }
+
+    /// `$XDG_CONFIG_HOME/slip-diff/adapters.json`, falling back to
+    /// `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("slip-diff").join("adapters.json"))
+    }
+}
+
+impl ContentAdapter for CommandAdapter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sniff(&self, path: &Path, bytes: &[u8]) -> bool {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let by_extension = extension.is_some_and(|extension| {
+            self.extensions
+                .iter()
+                .any(|listed| listed.eq_ignore_ascii_case(extension))
+        });
+        let by_magic = self
+            .magic
+            .as_ref()
+            .is_some_and(|magic| bytes.starts_with(magic.as_bytes()));
+        by_extension || by_magic
+    }
+
+    fn adapt(&self, path: &Path, _bytes: &[u8]) -> io::Result<Adapted> {
+        let path = path.to_string_lossy();
+        let mut args: Vec<String> = self
+            .command
+            .iter()
+            .map(|arg| arg.replace("{}", &path))
+            .collect();
+        if !self.command.iter().any(|arg| arg.contains("{}")) {
+            args.push(path.into_owned());
+        }
+        let output = Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("running adapter `{}`: {error}", self.name),
+                )
+            })?;
+        if !output.status.success() {
+            return Ok(Adapted::text(format!(
+                "unreadable by {}: {}\n",
+                self.name,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )));
+        }
+        Ok(Adapted::text(
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureArgs;
+
+    const ADAPTERS: &str = r#"[
+        {"name": "upper", "extensions": ["UP"], "command": ["sh", "-c", "tr a-z A-Z < {}"]},
+        {"name": "shout", "magic": "SHOUT", "command": ["sed", "s/this/THIS/"]},
+        {"name": "broken", "extensions": ["broken"], "command": ["sh", "-c", "echo no >&2; exit 1"]}
+    ]"#;
+
+    #[test]
+    fn adapters_are_picked_by_extension_or_magic() {
+        let adapters: Vec<CommandAdapter> = serde_json::from_str(ADAPTERS).unwrap();
+        let picked = |path: &str, bytes: &[u8]| {
+            adapters
+                .iter()
+                .find(|adapter| adapter.sniff(Path::new(path), bytes))
+                .map(|adapter| adapter.name().to_string())
+        };
+        assert_eq!(picked("notes.up", b"").as_deref(), Some("upper"));
+        assert_eq!(picked("notes.txt", b"SHOUT hi").as_deref(), Some("shout"));
+        assert_eq!(picked("notes.txt", b"hi"), None);
+        assert_eq!(picked("up", b""), None);
+    }
+
+    #[test]
+    fn files_no_adapter_sniffs_are_read_as_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("adapters.json");
+        fs::write(&config, ADAPTERS).unwrap();
+        let capture = CaptureArgs {
+            adapters: Some(config),
+            ..CaptureArgs::default()
+        }
+        .capture()
+        .unwrap();
+        let read = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            capture.read(&path).unwrap().contents
+        };
+        assert_eq!(read("a.txt", "SHOUT this\n"), "SHOUT THIS\n");
+        assert_eq!(read("b.txt", "plain text\n"), "plain text\n");
+        assert_eq!(read("e.up", "quiet\n"), "QUIET\n");
+        assert_eq!(read("c.broken", "x\n"), "unreadable by broken: no\n");
+        assert_eq!(read("d.ipynb", r#"{"cells": []}"#), "notebook, no kernel\n");
+    }
+
+    #[test]
+    fn adapters_need_a_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("adapters.json");
+        assert!(CommandAdapter::load(&config).unwrap().is_empty());
+        fs::write(&config, r#"[{"name": "none", "command": []}]"#).unwrap();
+        let error = CommandAdapter::load(&config).err().unwrap();
+        assert!(error.to_string().ends_with("adapter `none` has no command"));
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "archive")]
+use std::{
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+#[cfg(feature = "archive")]
+use crate::rules::{self, Rules, MAX_HEX_SIZE};
+
+/// Members larger than this are described by size and hash alone.
+#[cfg(feature = "archive")]
+const MAX_TEXT_SIZE: u64 = 1 << 20;
+
+/// Indent of a member's contents under its line in a description.
+const CONTENTS_INDENT: &str = "    ";
+
+/// Whether `bytes` are a zip, tar or gzipped tar archive, going by their
+/// first bytes, decompressed if need be.
+#[cfg(feature = "archive")]
+pub fn is_archive(bytes: &[u8]) -> bool {
+    if bytes.starts_with(GZIP_MAGIC) {
+        let mut header = Vec::new();
+        // A truncated stream still yields the header if it was written.
+        let _ = flate2::read::GzDecoder::new(bytes)
+            .take(TAR_HEADER_SIZE)
+            .read_to_end(&mut header);
+        return is_tar(&header);
+    }
+    bytes.starts_with(ZIP_MAGIC) || is_tar(bytes)
+}
+
+/// Describes the archive `bytes`: one line per member with its size,
+/// followed by its contents indented if it is text, or by a hash if not.
+/// Members `rules` say to diff as bytes are followed by a hex dump instead.
+/// Archives are captured as this description, so a change to a member shows
+/// up in the diff as a change to its lines.
+#[cfg(feature = "archive")]
+pub fn describe(bytes: &[u8], rules: &Rules) -> String {
+    let (kind, members) = if bytes.starts_with(ZIP_MAGIC) {
+        ("zip", zip_members(bytes, rules))
+    } else if bytes.starts_with(GZIP_MAGIC) {
+        let mut tar = Vec::new();
+        let members = flate2::read::GzDecoder::new(bytes)
+            .read_to_end(&mut tar)
+            .and_then(|_| tar_members(&tar, rules));
+        ("tar.gz", members)
+    } else {
+        ("tar", tar_members(bytes, rules))
+    };
+    let members = match members {
+        Ok(members) => members,
+        // E.g. still being written.
+        Err(error) => return format!("{kind} archive, unreadable: {error}\n"),
+    };
+    let mut description = format!("{kind} archive\n");
+    for member in members {
+        description.push_str(&member);
+    }
+    description
+}
+
+#[cfg(feature = "archive")]
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+#[cfg(feature = "archive")]
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// Size of a tar header, which ends in the `ustar` magic.
+#[cfg(feature = "archive")]
+const TAR_HEADER_SIZE: u64 = 512;
+
+#[cfg(feature = "archive")]
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes.get(257..262) == Some(b"ustar")
+}
+
+#[cfg(feature = "archive")]
+fn zip_members(bytes: &[u8], rules: &Rules) -> io::Result<Vec<String>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    (0..archive.len())
+        .map(|i| {
+            let file = archive.by_index(i)?;
+            let name = file.name()?.into_owned();
+            let size = file.size();
+            describe_member(&name, size, file, rules)
+        })
+        .collect()
+}
+
+#[cfg(feature = "archive")]
+fn tar_members(bytes: &[u8], rules: &Rules) -> io::Result<Vec<String>> {
+    let mut members = Vec::new();
+    for entry in tar::Archive::new(bytes).entries()? {
+        let entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        // Archives of `.` name everything from `./`, itself included.
+        let mut name = path.strip_prefix("./").unwrap_or(&path).to_string();
+        if name.is_empty() {
+            continue;
+        }
+        if entry.header().entry_type().is_dir() && !name.ends_with('/') {
+            name.push('/');
+        }
+        let size = entry.size();
+        members.push(describe_member(&name, size, entry, rules)?);
+    }
+    Ok(members)
+}
+
+/// The line describing a member, tab-separated from its size so names can
+/// be told apart from sizes, and its contents.
+#[cfg(feature = "archive")]
+fn describe_member(
+    name: &str,
+    size: u64,
+    contents: impl Read,
+    rules: &Rules,
+) -> io::Result<String> {
+    if name.ends_with('/') {
+        return Ok(format!("{name}\n"));
+    }
+    let mut bytes = Vec::new();
+    contents.take(MAX_TEXT_SIZE + 1).read_to_end(&mut bytes)?;
+    let text = if bytes.len() <= MAX_HEX_SIZE && rules.is_hex(Path::new(name)) {
+        Some(rules::hex_dump(&bytes))
+    } else {
+        (bytes.len() as u64 <= MAX_TEXT_SIZE && !bytes.contains(&0))
+            .then(|| String::from_utf8(bytes.clone()).ok())
+            .flatten()
+    };
+    let mut description = format!("{name}\t{size} bytes\n");
+    match text {
+        Some(text) => {
+            for line in text.lines() {
+                description.push_str(&format!("{CONTENTS_INDENT}{line}\n"));
+            }
+        }
+        None => description.push_str(&format!(
+            "{CONTENTS_INDENT}binary, blake3 {}\n",
+            blake3::hash(&bytes).to_hex()
+        )),
+    }
+    Ok(description)
+}
+
+/// How a member differs between two versions of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberChange {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl MemberChange {
+    /// The letter `git status --short` would show for it.
+    pub fn symbol(self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Removed => 'D',
+            Self::Modified => 'M',
+        }
+    }
+}
+
+/// The members that differ between two archive descriptions, by name, or
+/// `None` unless both describe an archive.
+pub fn changed_members(old: &str, new: &str) -> Option<BTreeMap<String, MemberChange>> {
+    let (old, new) = (members(old)?, members(new)?);
+    let mut changes = BTreeMap::new();
+    for (name, contents) in &old {
+        match new.get(name) {
+            None => changes.insert(name.to_string(), MemberChange::Removed),
+            Some(new_contents) if new_contents != contents => {
+                changes.insert(name.to_string(), MemberChange::Modified)
+            }
+            Some(_) => None,
+        };
+    }
+    for name in new.keys().filter(|name| !old.contains_key(*name)) {
+        changes.insert(name.to_string(), MemberChange::Added);
+    }
+    Some(changes)
+}
+
+/// The contents of member `name` in an archive description, as text or
+/// as whatever stands in for it, or `None` if there is no such member.
+pub fn member_contents(description: &str, name: &str) -> Option<String> {
+    let members = members(description)?;
+    let lines = members.get(name)?;
+    Some(
+        lines
+            .iter()
+            .skip(1)
+            .map(|line| format!("{line}\n"))
+            .collect(),
+    )
+}
+
+/// Each member's lines in a description, by name.
+fn members(description: &str) -> Option<BTreeMap<&str, Vec<&str>>> {
+    let mut lines = description.lines();
+    lines.next().filter(|first| first.ends_with(" archive"))?;
+    let mut members: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut current = None;
+    for line in lines {
+        match line.strip_prefix(CONTENTS_INDENT) {
+            Some(contents) => {
+                if let Some(name) = current {
+                    members.entry(name).or_default().push(contents);
+                }
+            }
+            None => {
+                let name = line.split('\t').next().unwrap_or(line);
+                members.entry(name).or_default().push(line);
+                current = Some(name);
+            }
+        }
+    }
+    Some(members)
+}
+
+#[cfg(all(test, feature = "archive"))]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in members {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn tar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, contents) in members {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, *contents).unwrap();
+        }
+        tar.into_inner().unwrap()
+    }
+
+    fn changes(old: &[u8], new: &[u8]) -> Vec<(String, char)> {
+        let rules = Rules::new(&[]);
+        let (old, new) = (describe(old, &rules), describe(new, &rules));
+        changed_members(&old, &new)
+            .unwrap()
+            .into_iter()
+            .map(|(name, change)| (name, change.symbol()))
+            .collect()
+    }
+
+    #[test]
+    fn zip_members_are_described_and_compared() {
+        let old = zip(&[("README", b"hello\n"), ("src/lib.rs", b"fn a() {}\n")]);
+        let new = zip(&[("README", b"hello\nworld\n"), ("logo.png", b"\x89PNG\0")]);
+        assert!(is_archive(&old));
+        let description = describe(&new, &Rules::new(&[]));
+        assert!(description.starts_with("zip archive\nREADME\t12 bytes\n    hello\n    world\n"));
+        assert!(description.contains("logo.png\t5 bytes\n    binary, blake3 "));
+        assert_eq!(
+            member_contents(&description, "README").as_deref(),
+            Some("hello\nworld\n")
+        );
+        assert_eq!(
+            changes(&old, &new),
+            [
+                ("README".to_string(), 'M'),
+                ("logo.png".to_string(), 'A'),
+                ("src/lib.rs".to_string(), 'D'),
+            ]
+        );
+    }
+
+    #[test]
+    fn tar_members_are_described_and_compared() {
+        let old = tar(&[("./a.txt", b"one\n"), ("./b.txt", b"two\n")]);
+        let new = tar(&[("./a.txt", b"one\n"), ("./b.txt", b"2\n"), ("./c.txt", b"")]);
+        assert!(is_archive(&new));
+        assert_eq!(
+            describe(&old, &Rules::new(&[])),
+            "tar archive\na.txt\t4 bytes\n    one\nb.txt\t4 bytes\n    two\n"
+        );
+        assert_eq!(
+            changes(&old, &new),
+            [("b.txt".to_string(), 'M'), ("c.txt".to_string(), 'A')]
+        );
+
+        let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        gzipped.write_all(&new).unwrap();
+        let gzipped = gzipped.finish().unwrap();
+        assert!(is_archive(&gzipped));
+        assert!(describe(&gzipped, &Rules::new(&[])).starts_with("tar.gz archive\na.txt\t"));
+    }
+
+    #[test]
+    fn truncated_archives_are_unreadable_and_text_isnt_an_archive() {
+        let zipped = zip(&[("a.txt", b"one\n")]);
+        let description = describe(&zipped[..10], &Rules::new(&[]));
+        assert!(description.starts_with("zip archive, unreadable: "));
+        assert!(!is_archive(b"just text\n"));
+        assert!(changed_members("just text\n", "zip archive\n").is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Permission bits, ownership and extended attributes of a file.
+///
+/// POSIX ACLs and SELinux labels are stored by the kernel as extended
+/// attributes (`system.posix_acl_access`, `security.selinux`), so they are
+/// covered by `xattrs`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileAttributes {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl FileAttributes {
+    #[cfg(unix)]
+    pub fn read(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = path.metadata()?;
+        let mut xattrs = BTreeMap::new();
+        if xattr::SUPPORTED_PLATFORM {
+            for name in xattr::list(path)? {
+                if let Some(value) = xattr::get(path, &name)? {
+                    xattrs.insert(name.to_string_lossy().into_owned(), value);
+                }
+            }
+        }
+        Ok(Self {
+            mode: metadata.mode() & 0o7777,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            xattrs,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let metadata = path.metadata()?;
+        let mode = if metadata.permissions().readonly() {
+            0o444
+        } else {
+            0o644
+        };
+        Ok(Self {
+            mode,
+            ..Self::default()
+        })
+    }
+
+    /// One human readable line per attribute that differs from `old`.
+    pub fn changes_from(&self, old: &FileAttributes) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.mode != old.mode {
+            changes.push(format!("mode: {:o} -> {:o}", old.mode, self.mode));
+        }
+        if (self.uid, self.gid) != (old.uid, old.gid) {
+            changes.push(format!(
+                "owner: {}:{} -> {}:{}",
+                old.uid, old.gid, self.uid, self.gid
+            ));
+        }
+        for (name, value) in &self.xattrs {
+            match old.xattrs.get(name) {
+                None => changes.push(format!("xattr {name}: added {}", show(value))),
+                Some(old_value) if old_value != value => changes.push(format!(
+                    "xattr {name}: {} -> {}",
+                    show(old_value),
+                    show(value)
+                )),
+                Some(_) => {}
+            }
+        }
+        for (name, value) in &old.xattrs {
+            if !self.xattrs.contains_key(name) {
+                changes.push(format!("xattr {name}: removed {}", show(value)));
+            }
+        }
+        changes
+    }
+}
+
+/// Text values (SELinux labels, user attributes) as text, binary ones such
+/// as ACLs as hex.
+fn show(value: &[u8]) -> String {
+    let trimmed = value.strip_suffix(b"\0").unwrap_or(value);
+    match std::str::from_utf8(trimmed) {
+        Ok(text) if !text.chars().any(char::is_control) => format!("{text:?}"),
+        _ => value.iter().map(|b| format!("{b:02x}")).collect(),
+    }
+}
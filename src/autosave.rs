@@ -0,0 +1,81 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{history::FileVersion, session::Entry, version_store::VersionStore};
+
+/// The state of a TUI session, saved periodically and on exit so that
+/// `--restore-last` brings back the history and where the UI was in it.
+/// Unlike a session journal, which is appended to as versions arrive, it is
+/// rewritten whole, so it also holds what changed in the UI.
+#[derive(Serialize, Deserialize)]
+pub struct Autosave {
+    /// The watched file.
+    pub path: PathBuf,
+    versions: Vec<Entry>,
+    /// Position of the selected tab.
+    pub index: usize,
+    pub show_whitespace: bool,
+    pub bisect_good: Option<usize>,
+    pub bisect_bad: Option<usize>,
+    pub exported: Option<usize>,
+    #[serde(default)]
+    pub known_good: Option<usize>,
+}
+
+impl Autosave {
+    pub fn new(path: &Path, versions: &dyn VersionStore) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            versions: versions
+                .iter()
+                .map(|version| Ok(Entry::new(&version?)))
+                .collect::<io::Result<_>>()?,
+            index: 0,
+            show_whitespace: false,
+            bisect_good: None,
+            bisect_bad: None,
+            exported: None,
+            known_good: None,
+        })
+    }
+
+    /// Takes the saved versions, oldest first.
+    pub fn take_versions(&mut self) -> Vec<FileVersion> {
+        self.versions.drain(..).map(Entry::into_version).collect()
+    }
+
+    /// Loads the session saved in `path`, if there is one.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Saves the session to `path`, replacing the previous save only once
+    /// this one is complete, so a crash mid-write leaves that one intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec(self)?)?;
+        fs::rename(partial, path)
+    }
+
+    /// `$XDG_STATE_HOME/slip-diff/tui-session.json`, falling back to
+    /// `~/.local/state`.
+    pub fn default_path() -> Option<PathBuf> {
+        let state = env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+            })?;
+        Some(state.join("slip-diff").join("tui-session.json"))
+    }
+}
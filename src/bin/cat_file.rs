@@ -1,16 +1,18 @@
 use rand::Rng;
-use std::{error::Error, fs::OpenOptions, io::Write, thread, time};
+use std::{env, error::Error, fs::OpenOptions, io::Write, thread, time};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let append = "asdf asdflk";
-    let file_path = "test-file";
+    let file_path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "test-file".to_string());
 
     let mut rng = rand::thread_rng();
 
     let mut file = OpenOptions::new()
         .append(true)
         .create(true)
-        .open(file_path)?;
+        .open(&file_path)?;
 
     loop {
         let newline: bool = rng.gen();
@@ -18,7 +20,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         file.write_all(append.as_bytes())?;
 
         if newline {
-            file.write(b"\n")?;
+            file.write_all(b"\n")?;
         }
 
         file.flush()?;
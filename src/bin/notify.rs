@@ -2,16 +2,16 @@ use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 use std::path::Path;
 
 fn main() {
-    // env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    tracing_subscriber::fmt().with_target(false).init();
 
     let path = std::env::args()
         .nth(1)
         .expect("Argument 1 needs to be a path");
 
-    // log::info!("Watching {path}");
+    tracing::info!("Watching {path}");
 
     if let Err(error) = watch(path) {
-        println!("Error: {error:?}");
+        tracing::error!("{error}");
     }
 }
 
@@ -29,7 +29,7 @@ fn watch<P: AsRef<Path>>(path: P) -> notify::Result<()> {
     for res in rx {
         match res {
             Ok(event) => println!("Change: {event:?}"),
-            Err(error) => println!("Error: {error:?}"),
+            Err(error) => tracing::error!("{error}"),
         }
     }
 
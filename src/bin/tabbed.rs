@@ -1,92 +1,683 @@
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
-    fs, io,
-    path::PathBuf,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    cursor::MoveTo,
+    event::{
+        DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent,
+        KeyEventKind, KeyModifiers,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use futures_util::StreamExt;
 use ratatui::{prelude::*, widgets::*};
+use slip_diff::{
+    autosave::Autosave,
+    bisect::Bisect,
+    cache::WarmCache,
+    capture::CaptureArgs,
+    clock,
+    cursor::VersionCursor,
+    debounce::Debounce,
+    digest::HashAlgorithm,
+    events::{EventLog, LogKind},
+    export,
+    format::Format,
+    git_export,
+    graphics::Protocol,
+    history::{FileVersion, HistoryLimits},
+    hook::{Hook, HookOutcome},
+    images,
+    logging::{self, init_logging},
+    macros::Macros,
+    poll::PollArgs,
+    preserve,
+    render::{self, DiffRenderer, RenderArgs, RenderOptions},
+    replay::{Replay, ReplayArgs},
+    rules::Treatment,
+    session::{self, SessionArgs},
+    sink::{Sink, SinkFormat, SinkSpec},
+    stats::{KnownGood, LineStats},
+    store::{Persistence, StoreSpec},
+    validate::{CommandArgs, Validator},
+    version_store::{self, VersionStore},
+    watcher::{self, Watcher},
+};
+use tokio::time;
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct Args {
-    #[clap(short, long, required = true)]
-    pub file: PathBuf,
+    #[clap(short, long, required_unless_present_any = ["replay", "restore_last"])]
+    pub file: Option<PathBuf>,
+
+    /// Play back a recorded session file instead of watching a file
+    #[clap(long, conflicts_with = "file")]
+    pub replay: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub replay_args: ReplayArgs,
 
     #[clap(short, long)]
     pub clear: bool,
-}
 
-#[derive(Clone)]
-struct FileVersion {
-    pub contents: String,
-    pub at: Instant,
-}
+    /// Rhai script defining `on_change(event)`, returning `false` to skip the change, or `transform(contents)`, returning what to keep instead
+    #[clap(long)]
+    pub hook: Option<PathBuf>,
 
-impl FileVersion {
-    pub fn new_at_now(contents: String) -> Self {
-        let at = Instant::now();
-        Self { contents, at }
-    }
+    /// Shell command checking every new version, e.g. `nginx -t` or `yamllint {}` where `{}` is the file
+    #[clap(long, value_name = "COMMAND")]
+    pub validate: Option<String>,
+
+    #[clap(flatten)]
+    pub validate_command: CommandArgs,
+
+    #[clap(flatten)]
+    pub capture: CaptureArgs,
+
+    #[clap(flatten)]
+    pub poll: PollArgs,
+
+    #[clap(flatten)]
+    pub limits: HistoryLimits,
+
+    #[clap(flatten)]
+    pub session: SessionArgs,
+
+    /// Persist versions to a store, e.g. `sqlite:versions.db`
+    #[clap(long)]
+    pub store: Option<StoreSpec>,
+
+    /// Commit every captured version to a git repository in this directory
+    #[clap(long)]
+    pub export_git: Option<PathBuf>,
+
+    /// Write a unified-diff patch for every change to this directory, for `patch` or `git apply`
+    #[clap(long)]
+    pub patch_dir: Option<PathBuf>,
+
+    /// Commit every captured version to `refs/slip-diff/<path>` in the file's own git repository
+    #[clap(long)]
+    pub git_autocommit: bool,
+
+    /// Show the file as committed at this git ref next to the selected version
+    #[clap(long, value_name = "REF", num_args = 0..=1, default_missing_value = "HEAD", conflicts_with = "replay")]
+    pub vs_git: Option<String>,
+
+    /// File keyboard macros are saved to [default: ~/.config/slip-diff/macros.json]
+    #[clap(long)]
+    pub macros: Option<PathBuf>,
+
+    /// Don't remember the last version seen in the cache directory for the next run
+    #[clap(long)]
+    pub no_cache: bool,
+
+    /// Mark new versions pending until the file has been quiet this long
+    #[clap(long, value_parser = humantime::parse_duration, conflicts_with = "replay")]
+    pub confirm_window: Option<Duration>,
+
+    /// Also write every change to a FIFO or Unix socket, e.g. `fifo:/tmp/changes` or `unix:/run/dash.sock`, publish it to an MQTT topic with `mqtt://<host>/<topic>`, post it to a Slack or Discord webhook with `slack:<url>` or `discord:<url>`, at most every 10s or e.g. `slack/5m:<url>`, or mail an hourly digest with `smtp:smtp://<server>?to=<address>`, or e.g. `smtp/1d:…` daily
+    #[clap(long, conflicts_with = "replay")]
+    pub sink: Vec<SinkSpec>,
+
+    /// What to write to each --sink
+    #[clap(long, value_enum, default_value_t)]
+    pub sink_format: SinkFormat,
+
+    /// Directory the `e` key exports versions to [default: <FILE>.versions]
+    #[clap(long)]
+    pub export: Option<PathBuf>,
+
+    /// Content hash used to deduplicate versions and in stores, caches and manifests
+    #[clap(long, value_enum, default_value_t)]
+    pub hash: HashAlgorithm,
+
+    #[clap(flatten)]
+    pub render: RenderArgs,
+
+    /// Show thumbnails of watched images under their descriptions, in terminals supporting the kitty or iTerm2 graphics protocol
+    #[clap(long, conflicts_with = "replay")]
+    pub thumbnails: bool,
+
+    /// Bring back the session autosaved when the TUI last ran: its file, history and selected version
+    #[clap(long, conflicts_with_all = ["file", "replay", "resume"])]
+    pub restore_last: bool,
+
+    /// How often to autosave the session for --restore-last, besides on exit
+    #[clap(long, default_value = "5m", value_parser = humantime::parse_duration)]
+    pub autosave_interval: Duration,
+
+    /// Don't autosave the session
+    #[clap(long, conflicts_with = "restore_last")]
+    pub no_autosave: bool,
+
+    /// Log what happens while watching: `-v` for versions and re-watches, `-vv` for every event and debounce decision too
+    #[clap(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Write the log to this file, since the terminal is taken [default: $XDG_STATE_HOME/slip-diff/tabbed.log]
+    #[clap(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
 }
 
 struct App {
-    pub versions: Vec<FileVersion>,
-    pub index: usize,
+    pub versions: Box<dyn VersionStore>,
+    /// The selected version, whose change to the next one is shown.
+    pub cursor: VersionCursor,
+    pub path: PathBuf,
+    pub persistence: Persistence,
+    /// Watches the file for changes; not set up for replays.
+    pub watcher: Option<Watcher>,
+    /// Message shown in the tab bar title, e.g. the result of an export.
+    pub status: Option<String>,
+    pub hook: Option<Hook>,
+    pub validator: Option<Validator>,
+    /// Whether the latest capture had invalid UTF-8 replaced.
+    pub replaced_invalid: bool,
+    /// Version number waiting for a second `r` to confirm restoring it.
+    pub pending_restore: Option<usize>,
+    /// Git ref to compare against instead of the next version.
+    pub vs_git: Option<String>,
+    /// The file's contents at `vs_git`, refreshed on every change.
+    pub git_base: Option<Arc<str>>,
+    pub macros: Macros,
+    /// Set after `q` or `@` while waiting for the register key.
+    pub pending_macro: Option<KeyCode>,
+    /// How many macros are currently being played back, to stop a macro
+    /// that invokes itself.
+    pub macro_depth: usize,
+    /// Holds versions back as pending with `--confirm-window`.
+    pub debounce: Option<Debounce>,
+    /// Newest version that has outlasted the confirm window; later ones are
+    /// still pending.
+    pub confirmed: Option<usize>,
+    /// Newest version included in the last export.
+    pub exported: Option<usize>,
+    /// Set after Ctrl-C with unsaved history, until the next key either
+    /// exports it or quits anyway.
+    pub quitting: bool,
+    /// Renders the change from the selected version in the right pane.
+    pub renderer: Arc<dyn DiffRenderer>,
+    /// Lines of unchanged context the renderer shows around each hunk.
+    pub context: usize,
+    pub show_whitespace: bool,
+    /// The version marked known-good with `k`.
+    pub known_good: Option<KnownGood>,
+    /// How far the latest version drifted from the known-good one.
+    pub drift: Option<LineStats>,
+    /// Version numbers marked with `g` and `b` while bisecting.
+    pub bisect_good: Option<usize>,
+    pub bisect_bad: Option<usize>,
+    /// How to draw image thumbnails, if `--thumbnails` found a way.
+    pub thumbnails: Option<Protocol>,
+    /// The latest capture's bytes if the file is an image.
+    pub image: Option<Vec<u8>>,
+    /// PNG thumbnails of the versions that are images, by version number.
+    pub images: HashMap<usize, Vec<u8>>,
+    /// Which versions' thumbnails were last drawn, and where.
+    pub drawn_thumbnails: Option<((usize, Option<usize>), Rect)>,
+    /// Where the session is autosaved for `--restore-last`, unless turned
+    /// off.
+    pub autosave: Option<PathBuf>,
+    /// Raw watcher events and what was made of them, shown with `l`.
+    pub log: EventLog,
+    pub show_log: bool,
 }
 
 impl App {
-    fn new() -> App {
+    fn new(versions: Box<dyn VersionStore>, path: PathBuf) -> App {
         App {
-            versions: Vec::new(),
-            index: 0,
+            versions,
+            cursor: VersionCursor::default(),
+            path,
+            persistence: Persistence::default(),
+            watcher: None,
+            status: None,
+            hook: None,
+            validator: None,
+            replaced_invalid: false,
+            pending_restore: None,
+            vs_git: None,
+            git_base: None,
+            macros: Macros::default(),
+            pending_macro: None,
+            macro_depth: 0,
+            debounce: None,
+            confirmed: None,
+            exported: None,
+            quitting: false,
+            renderer: Arc::new(render::Builtin),
+            context: render::CONTEXT,
+            show_whitespace: false,
+            known_good: None,
+            drift: None,
+            log: EventLog::default(),
+            show_log: false,
+            bisect_good: None,
+            bisect_bad: None,
+            thumbnails: None,
+            image: None,
+            images: HashMap::new(),
+            drawn_thumbnails: None,
+            autosave: None,
         }
     }
 
     pub fn next(&mut self) {
-        let len = self.versions.len();
-        self.index = (self.index + 1) % (len - 1);
+        self.cursor.next(self.versions.len());
     }
 
     pub fn previous(&mut self) {
-        let len = self.versions.len();
-        if self.index > 0 {
-            self.index -= 1;
+        self.cursor.prev(self.versions.len());
+    }
+
+    pub fn current_contents(&self) -> io::Result<Arc<str>> {
+        Ok(self.versions.get(self.cursor.index())?.unwrap().contents)
+    }
+
+    pub fn next_contents(&self) -> io::Result<Option<Arc<str>>> {
+        Ok(self
+            .versions
+            .get(self.cursor.index() + 1)?
+            .map(|f| f.contents))
+    }
+
+    /// The change from the selected version to the next one, or to it from
+    /// the file at the `--vs-git` ref, rendered `width` columns wide, or
+    /// `None` if there is no next version. Redraws, e.g. after a resize or
+    /// flipping back to a tab, take it from the renderer's cache.
+    pub fn rendered_change(&self, width: u16) -> io::Result<Option<String>> {
+        let (old, new) = match &self.git_base {
+            Some(base) => (Arc::clone(base), self.current_contents()?),
+            None => match self.next_contents()? {
+                Some(next) => (self.current_contents()?, next),
+                None => return Ok(None),
+            },
+        };
+        let options = RenderOptions {
+            path: Some(&self.path),
+            width: Some(width.into()),
+            context: self.context,
+            show_whitespace: self.show_whitespace,
+            ..RenderOptions::default()
+        };
+        self.renderer.render(&old, &new, &options).map(Some)
+    }
+
+    pub fn read(&mut self) -> io::Result<String> {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return Err(io::Error::other("not watching the file"));
+        };
+        let snapshot = watcher.read()?;
+        self.replaced_invalid = snapshot.replaced_invalid;
+        self.image = snapshot.image;
+        match &self.hook {
+            Some(hook) => Ok(hook.transform(snapshot.contents)),
+            None => Ok(snapshot.contents),
+        }
+    }
+
+    /// Records freshly read contents as a new version if they differ from
+    /// the latest one and the hook doesn't suppress them.
+    pub fn changed(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
+        if self.versions.is_latest(&contents) {
+            let number = self.versions.last_info().unwrap().number;
+            self.log.push(
+                LogKind::Suppressed,
+                format!("contents unchanged since version {number}"),
+            );
+            return Ok(());
+        }
+        let prev = self.versions.last()?.unwrap();
+        let mut new = FileVersion::new_at_now(contents);
+        new.number = self.versions.next_number();
+        let outcome = match &self.hook {
+            Some(hook) => hook.on_change(&self.path, &prev.contents, &new),
+            None => HookOutcome::Show,
+        };
+        if outcome == HookOutcome::Suppress {
+            self.log.push(
+                LogKind::Suppressed,
+                format!("version {} suppressed by the hook", new.number),
+            );
         } else {
-            self.index = len - 2;
+            self.push_version(new)?;
         }
+        self.refresh_git_base()
     }
 
-    pub fn current_contents(&self) -> String {
-        self.versions[self.index].contents.clone()
+    /// Re-reads the file at the `--vs-git` ref, which may have moved since.
+    pub fn refresh_git_base(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(rev) = &self.vs_git {
+            self.git_base = Some(git_export::show(&self.path, rev)?.into());
+        }
+        Ok(())
     }
 
-    pub fn next_contents(&self) -> Option<String> {
-        self.versions
-            .get(self.index + 1)
-            .and_then(|f| Some(f.contents.clone()))
+    pub fn push_version(&mut self, mut version: FileVersion) -> Result<(), Box<dyn Error>> {
+        if let Some(validator) = &self.validator {
+            version.meta.validation = Some(validator.run(&self.path)?);
+        }
+        version.meta.replaced_invalid = self.replaced_invalid;
+        if let Some(debounce) = &mut self.debounce {
+            debounce.change();
+        }
+        if let Some(watcher) = &self.watcher {
+            self.versions.set_keep_whole(watcher.throttled());
+        }
+        let evicted = self.versions.push(version)?;
+        self.cursor.evicted(&evicted, self.versions.len());
+        let number = self.versions.last_info().unwrap().number;
+        self.log
+            .push(LogKind::Version, format!("recorded version {number}"));
+        if let Some(debounce) = &self.debounce {
+            self.log.push(
+                LogKind::Debounce,
+                format!(
+                    "version {number} pending until quiet for {}",
+                    humantime::format_duration(debounce.window())
+                ),
+            );
+        }
+        self.refresh_drift()?;
+        self.thumbnail_latest();
+        let latest = self.versions.last()?.unwrap();
+        // Pending versions reach the sinks together, once confirmed.
+        if self.debounce.is_none() {
+            self.persistence.send(&latest);
+        }
+        self.persistence.record(&self.path, &latest)
     }
 
-    pub fn push_version(&mut self, version: FileVersion) {
-        self.versions.push(version);
+    /// Keeps a thumbnail of the latest version if it is an image, and drops
+    /// those of versions no longer in the history.
+    fn thumbnail_latest(&mut self) {
+        if self.thumbnails.is_none() {
+            return;
+        }
+        let number = self.versions.last_info().unwrap().number;
+        if let Some(thumbnail) = self
+            .image
+            .as_deref()
+            .and_then(|bytes| images::thumbnail(bytes, THUMBNAIL_SIZE))
+        {
+            self.images.insert(number, thumbnail);
+        }
+        let numbers: HashSet<usize> = self.versions.infos().map(|v| v.number).collect();
+        self.images.retain(|number, _| numbers.contains(number));
     }
 
     pub fn push_contents(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
         let fv = FileVersion::new_at_now(contents);
-        self.versions.push(fv);
+        self.push_version(fv)
+    }
+
+    pub fn restore_version(&mut self, version: FileVersion) -> io::Result<()> {
+        let evicted = self.versions.restore(version)?;
+        self.cursor.evicted(&evicted, self.versions.len());
+        self.refresh_drift()?;
+        Ok(())
+    }
+
+    pub fn export(&mut self, dir: &Path) {
+        self.status = Some(
+            match export::export(self.versions.as_ref(), &self.path, dir) {
+                Ok(count) => {
+                    self.exported = self.versions.last_info().map(|v| v.number);
+                    format!(
+                        "Exported {} versions to {}",
+                        Format::new(false).count(count),
+                        dir.display()
+                    )
+                }
+                Err(error) => format!("Export failed: {error}"),
+            },
+        );
+    }
+
+    /// Whether quitting now would lose versions: nothing persists them and
+    /// they weren't all exported.
+    pub fn is_unsaved(&self) -> bool {
+        !self.persistence.keeps_history()
+            && self.exported != self.versions.last_info().map(|v| v.number)
+    }
+
+    /// Handles Ctrl-C, returning whether the app should quit. With unsaved
+    /// history it first asks whether to export it.
+    pub fn interrupt(&mut self) -> bool {
+        if self.quitting || !self.is_unsaved() {
+            return true;
+        }
+        self.quitting = true;
+        self.status =
+            Some("History isn't saved: e exports it and quits, Ctrl-C quits anyway".to_string());
+        false
+    }
+
+    /// Writes the selected version back to the watched file once confirmed
+    /// by pressing `r` twice, snapshotting what is on disk first.
+    pub fn restore_selected(&mut self) {
+        let index = self.cursor.index();
+        let number = self.versions.info(index).unwrap().number;
+        if self.pending_restore != Some(number) {
+            self.pending_restore = Some(number);
+            self.status = Some(format!(
+                "Press r again to restore version {number} to {}",
+                self.path.display()
+            ));
+            return;
+        }
+        self.pending_restore = None;
+
+        self.status = Some(match self.write_restored(index) {
+            Ok(warnings) if warnings.is_empty() => format!("Restored version {number}"),
+            Ok(warnings) => format!("Restored version {number}, but {}", warnings.join(", ")),
+            Err(error) => format!("Restore failed: {error}"),
+        });
+    }
+
+    /// Marks the selected version known-good, measuring every later version
+    /// against it too.
+    pub fn mark_known_good(&mut self) {
+        self.status = Some(match self.mark_selected_known_good() {
+            Ok(number) => format!("Marked version {number} known-good"),
+            Err(error) => format!("Marking known-good failed: {error}"),
+        });
+    }
+
+    fn mark_selected_known_good(&mut self) -> io::Result<usize> {
+        let version = self.versions.get(self.cursor.index())?.unwrap();
+        let number = version.number;
+        self.known_good = Some(KnownGood::new(number, version.contents));
+        self.refresh_drift()?;
+        Ok(number)
+    }
+
+    fn refresh_drift(&mut self) -> io::Result<()> {
+        self.drift = self
+            .known_good
+            .as_ref()
+            .zip(self.versions.last()?)
+            .map(|(good, latest)| good.drift(&latest.contents));
+        Ok(())
+    }
+
+    /// Marks the selected version good or bad and selects the next one to
+    /// test. Until both are marked, the first version counts as good and
+    /// the latest as bad.
+    pub fn mark_bisect(&mut self, good: bool) {
+        let number = self.versions.info(self.cursor.index()).unwrap().number;
+        match good {
+            true => self.bisect_good = Some(number),
+            false => self.bisect_bad = Some(number),
+        }
+        let position = |number: Option<usize>| {
+            number.and_then(|number| self.versions.infos().position(|v| v.number == number))
+        };
+        let good = position(self.bisect_good).unwrap_or(0);
+        let bad = position(self.bisect_bad).unwrap_or(self.versions.len() - 1);
+        let bisect = match Bisect::new(good, bad) {
+            Ok(bisect) => bisect,
+            Err(error) => {
+                self.status = Some(format!("Can't bisect: {error}"));
+                return;
+            }
+        };
+        if let Some(next) = bisect.next() {
+            self.cursor.jump(next, self.versions.len());
+            self.status = Some(format!(
+                "Bisecting: mark version {} with g or b, about {} more to test",
+                self.versions.info(next).unwrap().number,
+                bisect.steps()
+            ));
+            return;
+        }
+
+        // Show the change that made the first bad version.
+        let first = bisect.first_bad().unwrap();
+        self.cursor.jump(first - 1, self.versions.len());
+        self.status = Some(format!(
+            "Version {} is the first bad version",
+            self.versions.info(first).unwrap().number
+        ));
+        self.bisect_good = None;
+        self.bisect_bad = None;
+    }
+
+    /// Confirms the pending versions once the file has been quiet for the
+    /// whole confirm window.
+    pub fn confirm_pending(&mut self) {
+        let Some(debounce) = &mut self.debounce else {
+            return;
+        };
+        let latest = self.versions.last_info().map(|v| v.number);
+        if latest != self.confirmed && debounce.settled() {
+            debounce.release();
+            self.confirmed = latest;
+            match self.versions.last() {
+                Ok(Some(version)) => self.persistence.send(&version),
+                Ok(None) => {}
+                Err(error) => self.log.push(
+                    LogKind::Error,
+                    format!("sending the confirmed version: {error}"),
+                ),
+            }
+            if let Some(number) = latest {
+                self.log
+                    .push(LogKind::Debounce, format!("confirmed version {number}"));
+                self.status = Some(format!("Confirmed version {number}"));
+            }
+        }
+    }
+
+    /// When the pending versions are due to be confirmed, if there are any.
+    pub fn confirm_due(&self) -> Option<Instant> {
+        let debounce = self.debounce.as_ref()?;
+        let latest = self.versions.last_info().map(|v| v.number);
+        (latest != self.confirmed).then(|| debounce.due()).flatten()
+    }
+
+    /// Whether `number` is newer than the last confirmed version.
+    pub fn is_pending(&self, number: usize) -> bool {
+        self.debounce.is_some() && self.confirmed.is_none_or(|confirmed| number > confirmed)
+    }
+
+    /// Writes version `index` back, returning warnings about what it
+    /// differs in that doesn't show in a diff, kept as the file has it.
+    fn write_restored(&mut self, index: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        let version = self.versions.get(index)?.unwrap();
+        let (contents, attributes) = (version.contents, version.meta.attributes.clone());
+        let current = self.read()?;
+        self.changed(current)?;
+        Ok(preserve::restore(
+            &self.path,
+            &contents,
+            attributes.as_ref(),
+        )?)
+    }
+
+    /// Saves the history and where the UI is in it for `--restore-last`.
+    fn autosave(&self) -> io::Result<()> {
+        let Some(path) = &self.autosave else {
+            return Ok(());
+        };
+        let mut saved = Autosave::new(&self.path, self.versions.as_ref())?;
+        saved.index = self.cursor.index();
+        saved.show_whitespace = self.show_whitespace;
+        saved.bisect_good = self.bisect_good;
+        saved.bisect_bad = self.bisect_bad;
+        saved.exported = self.exported;
+        saved.known_good = self.known_good.as_ref().map(|good| good.number);
+        saved.save(path)
+    }
+
+    /// Takes up an autosaved session where it was left.
+    fn restore_autosave(&mut self, mut saved: Autosave) -> io::Result<()> {
+        for version in saved.take_versions() {
+            self.restore_version(version)?;
+        }
+        self.cursor.jump(saved.index, self.versions.len());
+        self.show_whitespace = saved.show_whitespace;
+        self.bisect_good = saved.bisect_good;
+        self.bisect_bad = saved.bisect_bad;
+        self.exported = saved.exported;
+        let known_good = saved
+            .known_good
+            .and_then(|number| self.versions.infos().position(|v| v.number == number));
+        self.known_good = match known_good {
+            Some(index) => {
+                let version = self.versions.get(index)?.unwrap();
+                Some(KnownGood::new(version.number, version.contents))
+            }
+            None => None,
+        };
+        self.refresh_drift()?;
         Ok(())
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
     let args = Args::parse();
+    let log_file = args.log_file.clone().or_else(logging::default_tui_log);
+    if let Some(dir) = log_file.as_deref().and_then(Path::parent) {
+        fs::create_dir_all(dir)?;
+    }
+    init_logging(args.verbose, log_file.as_deref())?;
+    let autosave = (!args.no_autosave).then(Autosave::default_path).flatten();
+    let restored = if args.restore_last {
+        let saved = match &autosave {
+            Some(path) => Autosave::load(path)?,
+            None => None,
+        };
+        Some(saved.ok_or("there is no autosaved session to restore")?)
+    } else {
+        None
+    };
+    let path = args
+        .file
+        .clone()
+        .or_else(|| args.replay.clone())
+        .or_else(|| restored.as_ref().map(|saved| saved.path.clone()))
+        .unwrap();
+    let mut app = App::new(version_store::open(args.limits.clone(), args.hash)?, path);
+    app.autosave = autosave;
+    let rules = args.capture.rules();
+    app.renderer = args.render.renderer(match rules.treatment(&app.path) {
+        Some(Treatment::Renderer(name)) => Some(name),
+        _ => None,
+    });
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -94,9 +685,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // create app and run it
-    let app = App::new();
-    let res = run_app(&mut terminal, app, &args);
+    // run the app
+    let res = run_app(&mut terminal, app, &args, restored).await;
 
     // restore terminal
     disable_raw_mode()?;
@@ -107,65 +697,180 @@ fn main() -> Result<(), Box<dyn Error>> {
     )?;
     terminal.show_cursor()?;
 
-    if let Err(err) = res {
-        println!("{err:?}");
+    if let Err(error) = res {
+        tracing::error!("{error}");
+        return Ok(ExitCode::FAILURE);
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-fn run_app<B: Backend>(
+async fn run_app<B: Backend + Write>(
     terminal: &mut Terminal<B>,
     mut app: App,
     args: &Args,
+    restored: Option<Autosave>,
 ) -> Result<(), Box<dyn Error>> {
-    let path = &args.file;
-    let zero = fs::read_to_string(&path)?;
-    app.push_contents(zero)?;
+    app.show_whitespace = args.render.show_whitespace;
+    app.context = args.render.context;
+    app.thumbnails = args.thumbnails.then(Protocol::detect).flatten();
+    if args.thumbnails && app.thumbnails.is_none() {
+        app.status = Some("This terminal can't show thumbnails".into());
+    }
+    if let Some(session) = &args.replay {
+        let replay = Replay::load(session, args.replay_args.clone())?;
+        // A replay is already saved.
+        app.autosave = None;
+        return run_replay(terminal, app, args, replay).await;
+    }
 
-    let (tx, rx) = std::sync::mpsc::channel();
+    let path = &app.path.clone();
+    let mut watcher = Watcher::new(path, args.capture.capture()?, &args.poll)?;
+    watcher.keep_log();
+    app.watcher = Some(watcher);
+    app.hook = args.hook.as_deref().map(Hook::load).transpose()?;
+    app.validator = args
+        .validate
+        .clone()
+        .map(|command| Validator::new(command, &args.validate_command));
+    app.vs_git.clone_from(&args.vs_git);
+    if let Some(path) = args.macros.clone().or_else(Macros::default_path) {
+        app.macros = Macros::load(&path)?;
+    }
+    app.refresh_git_base()?;
+    if let Some(saved) = restored {
+        app.restore_autosave(saved)?;
+    }
+    if let Some(session_path) = &args.session.session {
+        if args.session.resume {
+            for version in session::resume(session_path)? {
+                app.restore_version(version)?;
+            }
+        }
+    }
+    let cache_dir = (!args.no_cache).then(WarmCache::default_dir).flatten();
+    app.persistence = Persistence::open(
+        path,
+        args.session.session.as_deref(),
+        args.store.as_ref(),
+        args.export_git.as_deref(),
+        args.patch_dir.as_deref(),
+        args.git_autocommit,
+        cache_dir.as_deref(),
+    )?;
+    app.persistence.sinks = args
+        .sink
+        .iter()
+        .map(|spec| Sink::new(spec.clone(), args.sink_format, path, clock::system()))
+        .collect();
+    if let (true, Some(cache)) = (app.versions.is_empty(), &app.persistence.cache) {
+        if let Some(last) = cache.last(path)? {
+            app.restore_version(last)?;
+        }
+    }
 
-    // Automatically select the best implementation for your platform.
-    // You can also access each implementation directly e.g. INotifyWatcher.
-    let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
+    let zero = app.read()?;
+    if !app.versions.is_latest(&zero) {
+        app.push_contents(zero)?;
+    }
+    app.debounce = args
+        .confirm_window
+        .map(|window| Debounce::new(window, clock::system()));
+    app.confirmed = app.versions.last_info().map(|v| v.number);
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
+    // A zero interval would make the timer panic.
+    let autosave_interval = args.autosave_interval.max(Duration::from_secs(1));
+    let mut autosave =
+        time::interval_at(time::Instant::now() + autosave_interval, autosave_interval);
+    let mut keys = EventStream::new();
+    // Sleeps until something happens: the screen is only redrawn after a
+    // change, a key or resize, or pending versions coming due.
     loop {
-        while let Ok(res) = rx.try_recv() {
-            match res {
-                Ok(event) => match event.kind {
-                    notify::EventKind::Modify(event) => match event {
-                        notify::event::ModifyKind::Data(_) => {
-                            let prev = app.versions.last().unwrap();
-                            let contents = fs::read_to_string(&path)?;
-                            let new = FileVersion::new_at_now(contents);
-                            if prev.contents != new.contents {
-                                app.push_version(new);
+        app.confirm_pending();
+        terminal.draw(|f| ui(f, &app))?;
+        draw_thumbnails(terminal, &mut app)?;
+
+        let confirm_due = app.confirm_due();
+        let confirm = time::sleep_until(confirm_due.unwrap_or_else(Instant::now).into());
+        tokio::select! {
+            change = app.watcher.as_mut().unwrap().next() => {
+                app.log.append(&mut app.watcher.as_mut().unwrap().take_log());
+                match change {
+                    Ok(watcher::Change::Moved) if !app.path.exists() => {
+                        app.status = Some(format!("{} was moved away", app.path.display()));
+                    }
+                    Ok(change @ (watcher::Change::Contents | watcher::Change::Moved)) => {
+                        // Saving by renaming over the file leaves the watch
+                        // on the old one.
+                        if change == watcher::Change::Moved {
+                            if let Err(error) = app.watcher.as_mut().unwrap().rewatch() {
+                                app.status = Some(format!("Watch error: {error}"));
                             }
                         }
-                        _ => {}
-                    },
-                    notify::EventKind::Any => {}
-                    notify::EventKind::Access(_) => {}
-                    notify::EventKind::Create(_) => {}
-                    notify::EventKind::Remove(_) => {}
-                    notify::EventKind::Other => {}
-                },
-                Err(error) => println!("Error: {error:?}"),
+                        let contents = app.read()?;
+                        app.changed(contents)?;
+                    }
+                    Ok(watcher::Change::Metadata) => {
+                        app.log.push(LogKind::Suppressed, "only metadata changed");
+                    }
+                    Ok(watcher::Change::Ignored) => {}
+                    Err(error) => app.status = Some(format!("Watch error: {error}")),
+                }
+            }
+            Some(event) = keys.next() => {
+                if let Event::Key(key) = event? {
+                    let quit = match key {
+                        _ if key.kind != KeyEventKind::Press => false,
+                        _ if is_ctrl_c(key) => app.interrupt(),
+                        _ => handle_key(&mut app, args, key.code),
+                    };
+                    if quit {
+                        app.autosave()?;
+                        return Ok(());
+                    }
+                }
+            }
+            _ = confirm, if confirm_due.is_some() => {}
+            _ = autosave.tick(), if app.autosave.is_some() => {
+                if let Err(error) = app.autosave() {
+                    app.log.push(LogKind::Error, format!("autosave failed: {error}"));
+                    app.status = Some(format!("Autosave failed: {error}"));
+                }
             }
         }
+    }
+}
+
+fn is_ctrl_c(key: KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Shows the versions of a recorded session as they come due.
+async fn run_replay<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+    args: &Args,
+    mut replay: Replay,
+) -> Result<(), Box<dyn Error>> {
+    let mut keys = EventStream::new();
+    loop {
         terminal.draw(|f| ui(f, &app))?;
 
-        if let Ok(true) = event::poll(Duration::from_micros(1)) {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Esc => return Ok(()),
-                        KeyCode::Right => app.next(),
-                        KeyCode::Left => app.previous(),
-                        _ => {}
+        tokio::select! {
+            Some(version) = replay.next() => {
+                app.restore_version(version)?;
+                // Show everything that is due at once.
+                while let Some(version) = replay.poll() {
+                    app.restore_version(version)?;
+                }
+            }
+            Some(event) = keys.next() => {
+                if let Event::Key(key) = event? {
+                    // The replayed session is already saved, so Ctrl-C just quits.
+                    if key.kind == KeyEventKind::Press
+                        && (is_ctrl_c(key) || handle_key(&mut app, args, key.code))
+                    {
+                        return Ok(());
                     }
                 }
             }
@@ -173,25 +878,243 @@ fn run_app<B: Backend>(
     }
 }
 
-fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
-    let size = f.size();
+/// Width and height in pixels thumbnails are scaled to fit.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Draws the thumbnails of the versions on screen under their descriptions,
+/// once they or the screen's size change, since the terminal keeps showing
+/// an image until it is cleared.
+fn draw_thumbnails<B: Backend + Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> Result<(), Box<dyn Error>> {
+    let Some(protocol) = app.thumbnails else {
+        return Ok(());
+    };
+    let number = |index: usize| app.versions.info(index).map(|v| v.number);
+    let shown = match app.git_base {
+        // The left pane shows the file at the git ref, not a version.
+        Some(_) => None,
+        None => number(app.cursor.index()).map(|left| (left, number(app.cursor.index() + 1))),
+    };
+    let area = terminal.size()?;
+    let drawn = shown.map(|shown| (shown, area));
+    if drawn == app.drawn_thumbnails {
+        return Ok(());
+    }
+    if app.drawn_thumbnails.take().is_some() {
+        write!(terminal.backend_mut(), "{}", protocol.clear())?;
+        terminal.clear()?;
+        terminal.draw(|f| ui(f, app))?;
+    }
+    let Some(((left, right), _)) = drawn else {
+        return Ok(());
+    };
+    let (_, left_pane, right_pane, _) = layout(area, app.show_log);
+    for (number, pane) in [(Some(left), left_pane), (right, right_pane)] {
+        let Some(png) = number.and_then(|number| app.images.get(&number)) else {
+            continue;
+        };
+        let Some(index) = number.and_then(|n| app.versions.infos().position(|v| v.number == n))
+        else {
+            continue;
+        };
+        let Some(version) = app.versions.get(index)? else {
+            continue;
+        };
+        // The right pane shows the change to the version, not the version.
+        let text = match pane == right_pane {
+            true => app.rendered_change(pane.width)?.unwrap_or_default(),
+            false => version.contents.to_string(),
+        };
+        let lines = text.lines().count() as u16;
+        if lines >= pane.height {
+            continue;
+        }
+        let backend = terminal.backend_mut();
+        execute!(backend, MoveTo(pane.x, pane.y + lines))?;
+        write!(
+            backend,
+            "{}",
+            protocol.draw(png, pane.width, pane.height - lines)
+        )?;
+        Write::flush(backend)?;
+    }
+    app.drawn_thumbnails = drawn;
+    Ok(())
+}
+
+/// Maximum nesting of macros invoking other macros.
+const MAX_MACRO_DEPTH: usize = 8;
+
+/// Handles macro recording and playback keys, passing everything else on to
+/// [`apply_key`]. Returns whether the app should quit.
+fn handle_key(app: &mut App, args: &Args, code: KeyCode) -> bool {
+    if let Some(pending) = app.pending_macro.take() {
+        let KeyCode::Char(register) = code else {
+            app.status = None;
+            return false;
+        };
+        if pending == KeyCode::Char('q') {
+            app.macros.start(register);
+            app.status = Some(format!("Recording @{register}"));
+            return false;
+        }
+        if app.macro_depth == 0 {
+            app.macros.record(pending);
+            app.macros.record(code);
+        }
+        let Some(keys) = app.macros.get(register).map(<[KeyCode]>::to_vec) else {
+            app.status = Some(format!("Register @{register} is empty"));
+            return false;
+        };
+        if app.macro_depth >= MAX_MACRO_DEPTH {
+            app.status = Some(format!("Macros nested too deeply at @{register}"));
+            return false;
+        }
+        app.macro_depth += 1;
+        let quit = keys.into_iter().any(|key| handle_key(app, args, key));
+        app.macro_depth -= 1;
+        return quit;
+    }
+
+    match code {
+        KeyCode::Char('q') if app.macros.recording().is_some() => {
+            app.status = match app.macros.stop() {
+                Ok(Some(register)) => Some(format!("Recorded @{register}")),
+                Ok(None) => None,
+                Err(error) => Some(format!("Saving macros failed: {error}")),
+            };
+            false
+        }
+        KeyCode::Char('q' | '@') => {
+            app.pending_macro = Some(code);
+            false
+        }
+        _ => {
+            if app.macro_depth == 0 {
+                app.macros.record(code);
+            }
+            apply_key(app, args, code)
+        }
+    }
+}
+
+/// Applies a key press, returning whether the app should quit.
+fn apply_key(app: &mut App, args: &Args, code: KeyCode) -> bool {
+    if code != KeyCode::Char('r') {
+        app.pending_restore = None;
+    }
+    let quitting = std::mem::take(&mut app.quitting);
+    if quitting && code != KeyCode::Char('e') {
+        app.status = None;
+    }
+    match code {
+        KeyCode::Esc => return true,
+        KeyCode::Char('r') if args.replay.is_some() => {
+            app.status = Some("Restoring isn't available while replaying".to_string());
+        }
+        KeyCode::Char('r') => app.restore_selected(),
+        KeyCode::Char('e') => {
+            let dir = args
+                .export
+                .clone()
+                .unwrap_or_else(|| export::default_dir(&app.path));
+            app.export(&dir);
+            if quitting && !app.is_unsaved() {
+                return true;
+            }
+        }
+        KeyCode::Char('k') => app.mark_known_good(),
+        KeyCode::Char('l') => app.show_log = !app.show_log,
+        KeyCode::Char('g') => app.mark_bisect(true),
+        KeyCode::Char('b') => app.mark_bisect(false),
+        KeyCode::Right => app.next(),
+        KeyCode::Left => app.previous(),
+        _ => {}
+    }
+    false
+}
+
+/// Rows the log pane takes, borders included.
+const LOG_HEIGHT: u16 = 10;
+
+/// The tab bar, the two panes below it and the log pane under them if it is
+/// shown.
+fn layout(size: Rect, show_log: bool) -> (Rect, Rect, Rect, Option<Rect>) {
+    let log_height = if show_log { LOG_HEIGHT } else { 0 };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(log_height),
+            ]
+            .as_ref(),
+        )
         .split(size);
+    let split = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(chunks[1]);
+    (chunks[0], split[0], split[1], show_log.then_some(chunks[2]))
+}
+
+fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let size = f.size();
+    let (tab_bar, left, right, log) = layout(size, app.show_log);
 
     let block = Block::default().on_black().white();
     f.render_widget(block, size);
 
     let titles = app
         .versions
-        .iter()
-        .enumerate()
-        .map(|(i, _v)| Line::from(format!("{}", i)))
+        .infos()
+        .map(|v| {
+            let failed = v.meta.validation.as_ref().is_some_and(|v| !v.passed);
+            let mut title = v.number.to_string();
+            let mut style = Style::default();
+            if app.is_pending(v.number) {
+                title.push('*');
+                style = style.fg(Color::Yellow).add_modifier(Modifier::ITALIC);
+            }
+            if app
+                .known_good
+                .as_ref()
+                .is_some_and(|good| good.number == v.number)
+            {
+                title.push('✓');
+            }
+            if failed {
+                title.push('!');
+                style = style.fg(Color::Red);
+            }
+            Line::styled(title, style)
+        })
         .collect();
+    // Say why the selected version failed validation unless there is news.
+    let failure = app
+        .versions
+        .info(app.cursor.index())
+        .and_then(|v| v.meta.validation.clone())
+        .filter(|validation| !validation.passed)
+        .map(|validation| format!("Validation failed: {}", validation.headline()));
+    let mut title = app
+        .status
+        .as_deref()
+        .or(failure.as_deref())
+        .unwrap_or("Tabs")
+        .to_string();
+    if let (Some(good), Some(drift)) = (&app.known_good, app.drift) {
+        title.push_str(&format!(
+            " · +{} -{} since known-good {}",
+            drift.inserted, drift.deleted, good.number
+        ));
+    }
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title("Tabs"))
-        .select(app.index)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .select(app.cursor.index())
         .style(Style::default().fg(Color::Cyan))
         .highlight_style(
             Style::default()
@@ -199,17 +1122,132 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
                 .add_modifier(Modifier::BOLD)
                 .bg(Color::Black),
         );
-    f.render_widget(tabs, chunks[0]);
+    f.render_widget(tabs, tab_bar);
 
-    let contents = app.current_contents();
-    let split = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-        .split(chunks[1]);
-    let original = Paragraph::new(contents);
-    f.render_widget(original, split[0]);
+    let contents = match &app.git_base {
+        Some(base) => Ok(Arc::clone(base)),
+        None => app.current_contents(),
+    };
+    let original = Paragraph::new(match &contents {
+        Ok(text) => visible(text, app.show_whitespace),
+        Err(error) => format!("Can't read the version: {error}").into(),
+    });
+    f.render_widget(original, left);
+
+    let changed = Paragraph::new(match app.rendered_change(right.width) {
+        Ok(Some(diff)) => styled(&diff),
+        Ok(None) => "Nothing".into(),
+        Err(error) => format!("Can't render the change: {error}").into(),
+    });
+    f.render_widget(changed, right);
+
+    if let Some(area) = log {
+        f.render_widget(log_pane(&app.log, area.height), area);
+    }
+}
+
+/// The latest entries of `log` that fit in a pane `height` rows high,
+/// newest last.
+fn log_pane(log: &EventLog, height: u16) -> Paragraph<'_> {
+    let rows = usize::from(height.saturating_sub(2));
+    let mut lines: Vec<Line> = log
+        .iter()
+        .rev()
+        .take(rows)
+        .map(|entry| {
+            let color = match entry.kind {
+                LogKind::Event => Color::Gray,
+                LogKind::Version => Color::Green,
+                LogKind::Suppressed => Color::Yellow,
+                LogKind::Debounce => Color::Cyan,
+                LogKind::Error => Color::Red,
+            };
+            let at = humantime::format_rfc3339_seconds(entry.at);
+            Line::styled(
+                format!("{at} {}", entry.message),
+                Style::default().fg(color),
+            )
+        })
+        .collect();
+    lines.reverse();
+    Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Events"))
+}
 
-    let changed = Paragraph::new(app.next_contents().unwrap_or("Nothing".into()));
+/// `text` as shown in a pane, only copied to mark whitespace.
+fn visible(text: &str, show_whitespace: bool) -> Text<'_> {
+    match show_whitespace {
+        true => text
+            .split_inclusive('\n')
+            .map(|line| render::mark_whitespace(line) + "\n")
+            .collect::<String>()
+            .into(),
+        false => text.into(),
+    }
+}
 
-    f.render_widget(changed, split[1]);
+/// A rendered diff with its ANSI colours and emphasis turned into styles,
+/// and any other escape sequences dropped.
+fn styled(rendered: &str) -> Text<'static> {
+    let mut style = Style::default();
+    let mut lines = Vec::new();
+    for line in rendered.lines() {
+        let mut spans = Vec::new();
+        let mut rest = line;
+        while let Some(escape) = rest.find('\x1b') {
+            if escape > 0 {
+                spans.push(Span::styled(rest[..escape].to_string(), style));
+            }
+            rest = &rest[escape + 1..];
+            let Some(sequence) = rest.strip_prefix('[') else {
+                continue;
+            };
+            let end = sequence
+                .find(|c: char| c.is_ascii_alphabetic())
+                .unwrap_or(sequence.len());
+            if sequence[end..].starts_with('m') {
+                style = apply_sgr(style, &sequence[..end]);
+            }
+            rest = sequence.get(end + 1..).unwrap_or_default();
+        }
+        if !rest.is_empty() {
+            spans.push(Span::styled(rest.to_string(), style));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.into()
+}
+
+/// `style` changed by the `;`-separated parameters of an SGR sequence.
+fn apply_sgr(mut style: Style, parameters: &str) -> Style {
+    let mut codes = parameters.split(';').map(|code| code.parse().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            30..=37 => style.fg(Color::Indexed(code - 30)),
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(Color::Indexed(code - 40)),
+            49 => style.bg(Color::Reset),
+            90..=97 => style.fg(Color::Indexed(code - 90 + 8)),
+            38 | 48 => {
+                let color = match codes.next() {
+                    Some(5) => codes.next().map(Color::Indexed),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match (code, color) {
+                    (38, Some(color)) => style.fg(color),
+                    (_, Some(color)) => style.bg(color),
+                    (_, None) => style,
+                }
+            }
+            _ => style,
+        };
+    }
+    style
 }
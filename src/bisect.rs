@@ -0,0 +1,97 @@
+/// Narrows down the first bad version between a good one and a later bad
+/// one, like `git bisect` but over positions in a version history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bisect {
+    /// Newest position known to be good.
+    good: usize,
+    /// Oldest position known to be bad.
+    bad: usize,
+}
+
+impl Bisect {
+    /// Starts bisecting between positions `good` and `bad`, failing unless
+    /// the good one is older.
+    pub fn new(good: usize, bad: usize) -> Result<Self, String> {
+        if good >= bad {
+            return Err("the good version must be older than the bad one".to_string());
+        }
+        Ok(Self { good, bad })
+    }
+
+    /// The position to test next, or `None` once the first bad one is found.
+    pub fn next(&self) -> Option<usize> {
+        (self.remaining() > 0).then(|| self.good + (self.bad - self.good) / 2)
+    }
+
+    /// Records the outcome of testing `position`. Positions outside the
+    /// range still in question are ignored.
+    pub fn mark(&mut self, position: usize, good: bool) {
+        if position <= self.good || position >= self.bad {
+            return;
+        }
+        match good {
+            true => self.good = position,
+            false => self.bad = position,
+        }
+    }
+
+    /// The first bad position, once every version before it is known good.
+    pub fn first_bad(&self) -> Option<usize> {
+        (self.remaining() == 0).then_some(self.bad)
+    }
+
+    /// How many untested versions lie between the good and the bad one.
+    pub fn remaining(&self) -> usize {
+        self.bad - self.good - 1
+    }
+
+    /// Roughly how many more tests are needed.
+    pub fn steps(&self) -> usize {
+        (self.remaining() + 1).next_power_of_two().trailing_zeros() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_good_version_must_be_older() {
+        assert!(Bisect::new(3, 3).is_err());
+        assert!(Bisect::new(4, 3).is_err());
+        assert!(Bisect::new(3, 4).is_ok());
+    }
+
+    #[test]
+    fn marking_narrows_down_the_first_bad_version() {
+        let mut bisect = Bisect::new(0, 10).unwrap();
+        assert_eq!((bisect.remaining(), bisect.steps()), (9, 4));
+        let mut tested = Vec::new();
+        while let Some(position) = bisect.next() {
+            tested.push(position);
+            bisect.mark(position, position < 4);
+        }
+        assert_eq!(tested, [5, 2, 3, 4]);
+        assert_eq!(bisect.first_bad(), Some(4));
+        assert_eq!(bisect.steps(), 0);
+    }
+
+    #[test]
+    fn positions_outside_the_range_are_ignored() {
+        let mut bisect = Bisect::new(2, 8).unwrap();
+        for position in [0, 2, 8, 9] {
+            bisect.mark(position, true);
+            bisect.mark(position, false);
+        }
+        assert_eq!(bisect, Bisect::new(2, 8).unwrap());
+        assert_eq!(bisect.first_bad(), None);
+    }
+
+    #[test]
+    fn an_adjacent_bad_version_is_the_first_one() {
+        let bisect = Bisect::new(6, 7).unwrap();
+        assert_eq!(bisect.next(), None);
+        assert_eq!(bisect.first_bad(), Some(7));
+        assert_eq!((bisect.remaining(), bisect.steps()), (0, 0));
+    }
+}
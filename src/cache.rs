@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    digest::Digest,
+    fsck::Problem,
+    history::{FileVersion, Version},
+    remote,
+};
+
+/// The last version seen of one file.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    hash: String,
+    number: usize,
+    /// Milliseconds since the Unix epoch.
+    at: u64,
+}
+
+/// A small on-disk cache of the last version seen of every watched file, so
+/// a restart can diff against where the previous run left off without a
+/// session or store.
+///
+/// Contents are kept once per hash under `blobs/`, next to an `index.json`
+/// mapping canonical paths to their latest hash.
+pub struct WarmCache {
+    dir: PathBuf,
+    index: BTreeMap<String, Entry>,
+}
+
+impl WarmCache {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir.join("blobs"))?;
+        let index = match fs::read_to_string(dir.join("index.json")) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index,
+        })
+    }
+
+    /// `$XDG_CACHE_HOME/slip-diff`, falling back to `~/.cache`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(cache.join("slip-diff"))
+    }
+
+    /// The version of `file` the previous run saw last, if it is cached.
+    pub fn last(&self, file: &Path) -> io::Result<Option<FileVersion>> {
+        let Some(entry) = self.index.get(&key(file)?) else {
+            return Ok(None);
+        };
+        let contents = match fs::read_to_string(self.blob(&entry.hash)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Ok(Some(FileVersion {
+            contents: contents.into(),
+            at: UNIX_EPOCH + Duration::from_millis(entry.at),
+            number: entry.number,
+            meta: Default::default(),
+        }))
+    }
+
+    /// Makes `version` the cached one for `file`, storing its contents unless
+    /// a blob with the same hash is already there and dropping blobs no file
+    /// refers to anymore.
+    pub fn update(&mut self, file: &Path, version: &Version) -> io::Result<()> {
+        let hash = version.hash.to_string();
+        let blob = self.blob(&hash);
+        if !blob.exists() {
+            fs::write(&blob, version.contents.as_bytes())?;
+        }
+        let at = version
+            .at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let previous = self.index.insert(
+            key(file)?,
+            Entry {
+                hash: hash.clone(),
+                number: version.number,
+                at,
+            },
+        );
+        self.save()?;
+
+        if let Some(previous) = previous {
+            if previous.hash != hash && !self.index.values().any(|e| e.hash == previous.hash) {
+                fs::remove_file(self.blob(&previous.hash))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every indexed blob exists and matches its hash, and that
+    /// no blob or temporary index is left over. With `repair`, entries
+    /// without a sound blob are dropped and leftovers deleted.
+    pub fn check(&mut self, repair: bool) -> io::Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+        let mut broken = Vec::new();
+        for (path, entry) in &self.index {
+            let description = match entry.hash.parse::<Digest>() {
+                Err(error) => format!("cache entry for {path}: {error}"),
+                Ok(digest) => match fs::read(self.blob(&entry.hash)) {
+                    Ok(contents) if digest.matches(&contents) => continue,
+                    Ok(_) => format!("cached blob for {path} doesn't match its hash"),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        format!("cached blob for {path} is missing")
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+            problems.push(Problem::new(description, repair));
+            broken.push(path.clone());
+        }
+        if repair {
+            for path in broken {
+                let entry = self.index.remove(&path).unwrap();
+                if !self.index.values().any(|e| e.hash == entry.hash) {
+                    match fs::remove_file(self.blob(&entry.hash)) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                }
+            }
+            self.save()?;
+        }
+
+        let referenced: HashSet<&str> = self.index.values().map(|e| e.hash.as_str()).collect();
+        for blob in fs::read_dir(self.dir.join("blobs"))? {
+            let blob = blob?;
+            let name = blob.file_name().to_string_lossy().into_owned();
+            if !referenced.contains(name.as_str()) {
+                if repair {
+                    fs::remove_file(blob.path())?;
+                }
+                problems.push(Problem::new(
+                    format!("cached blob {name} is orphaned"),
+                    repair,
+                ));
+            }
+        }
+
+        let temp = self.dir.join("index.json.tmp");
+        if temp.exists() {
+            if repair {
+                fs::remove_file(&temp)?;
+            }
+            problems.push(Problem::new(
+                "a half-written cache index was left behind",
+                repair,
+            ));
+        }
+        Ok(problems)
+    }
+
+    fn blob(&self, hash: &str) -> PathBuf {
+        self.dir.join("blobs").join(hash)
+    }
+
+    /// Writes the index through a temporary file so a crash never leaves it
+    /// half written.
+    fn save(&self) -> io::Result<()> {
+        let path = self.dir.join("index.json");
+        let temp = self.dir.join("index.json.tmp");
+        fs::write(&temp, serde_json::to_string(&self.index)?)?;
+        fs::rename(temp, path)
+    }
+}
+
+fn key(file: &Path) -> io::Result<String> {
+    if remote::is_remote(file) {
+        return Ok(file.to_string_lossy().into_owned());
+    }
+    Ok(fs::canonicalize(file)?.to_string_lossy().into_owned())
+}
@@ -0,0 +1,270 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File, Metadata},
+    io::{self, Read},
+    path::Path,
+    path::PathBuf,
+};
+
+use crate::{
+    adapter::{self, CommandAdapter, ContentAdapter},
+    encoding::{self, TextEncoding},
+    extract::Extract,
+    remote::{HttpOptions, RemoteFile},
+    resolve,
+    rules::{Rule, Rules},
+};
+
+#[derive(Debug, Default, Clone, clap::Args)]
+pub struct CaptureArgs {
+    /// Expand include directives (nginx `include`, sshd `Include`, systemd
+    /// drop-ins) into one document before diffing, and watch the included
+    /// files too
+    #[clap(long)]
+    pub resolve_includes: bool,
+
+    /// Read device files, sockets and FIFOs anyway, at most 1 MiB per capture
+    #[clap(long)]
+    pub force: bool,
+
+    /// Character encoding of the file, e.g. `latin1` or `utf-16le` [default: detected]
+    #[clap(long)]
+    pub encoding: Option<TextEncoding>,
+
+    /// Replace invalid UTF-8 with U+FFFD instead of detecting another encoding, so a stray byte in a log doesn't garble or stop the capture
+    #[clap(long, conflicts_with = "encoding")]
+    pub lossy: bool,
+
+    /// Add a perceptual hash to the description of watched images, and show how different each version looks
+    #[clap(long)]
+    pub perceptual_hash: bool,
+
+    /// Diff the text of PDF and Word documents rather than their bytes
+    #[clap(long, value_enum, default_value_t)]
+    pub extract: Extract,
+
+    /// Tables to dump the rows of when the file is a SQLite database, which is diffed as its schema and rows [default: all]
+    #[clap(long, value_delimiter = ',')]
+    pub sqlite_tables: Vec<String>,
+
+    /// Keep the outputs and execution counts of Jupyter notebook cells, which are left out of their diffs by default
+    #[clap(long)]
+    pub notebook_outputs: bool,
+
+    /// File of adapter commands that turn proprietary formats into text to diff [default: ~/.config/slip-diff/adapters.json]
+    #[clap(long)]
+    pub adapters: Option<PathBuf>,
+
+    /// Read files an adapter would handle like any other file, e.g. `archives` or an adapter from --adapters
+    #[clap(long, value_name = "NAME")]
+    pub no_adapter: Vec<String>,
+
+    /// Diff the status and response headers of a URL along with its body, with names lowercased and sorted, and Date, Age and Expires left out
+    #[clap(long)]
+    pub headers: bool,
+
+    /// A response header to leave out of --headers, e.g. `set-cookie`
+    #[clap(long, value_name = "NAME", requires = "headers")]
+    pub ignore_header: Vec<String>,
+
+    /// Treat files, or archive members, matching a glob a certain way: `structured` diffs key paths, `hex` diffs a hex dump, and a renderer name such as `words` shows changes with it, e.g. `*.md=words` [defaults: *.json, *.yaml, *.yml, *.toml=structured, *.md, *.markdown=words, *.bin=hex]
+    #[clap(long = "rule", value_name = "GLOB=MODE")]
+    pub rules: Vec<Rule>,
+}
+
+/// How much of a special file is read per capture with `--force`, since it
+/// may never end.
+const SPECIAL_READ_LIMIT: u64 = 1 << 20;
+
+/// The text captured from the watched file at one moment.
+pub struct Snapshot {
+    pub contents: String,
+    /// Other files the contents were assembled from, which should be watched
+    /// as well.
+    pub includes: Vec<PathBuf>,
+    /// Whether invalid UTF-8 was replaced with U+FFFD.
+    pub replaced_invalid: bool,
+    /// The file's bytes if it is an image, which `contents` describes.
+    pub image: Option<Vec<u8>>,
+}
+
+impl CaptureArgs {
+    /// Sets up capturing with these arguments, loading the configured
+    /// adapters.
+    pub fn capture(&self) -> io::Result<Capture> {
+        let path = self.adapters.clone().or_else(CommandAdapter::default_path);
+        let configured = match path {
+            Some(path) => CommandAdapter::load(&path)?,
+            None => Vec::new(),
+        };
+        let mut adapters: Vec<Box<dyn ContentAdapter>> = configured
+            .into_iter()
+            .map(|adapter| Box::new(adapter) as Box<dyn ContentAdapter>)
+            .chain(self.builtin_adapters())
+            .collect();
+        for name in &self.no_adapter {
+            if !adapters.iter().any(|adapter| adapter.name() == name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("there is no adapter named `{name}`"),
+                ));
+            }
+        }
+        adapters.retain(|adapter| !self.no_adapter.iter().any(|name| name == adapter.name()));
+        Ok(Capture {
+            args: self.clone(),
+            adapters,
+        })
+    }
+
+    /// The `--rule`s and the default ones.
+    pub fn rules(&self) -> Rules {
+        Rules::new(&self.rules)
+    }
+
+    /// The adapters this build has, in the order they are tried.
+    fn builtin_adapters(&self) -> Vec<Box<dyn ContentAdapter>> {
+        #[allow(unused_mut)] // Without the optional formats.
+        let mut adapters: Vec<Box<dyn ContentAdapter>> = vec![
+            Box::new(adapter::Hex(self.rules())),
+            Box::new(adapter::Documents(self.extract)),
+            Box::new(adapter::Notebooks {
+                outputs: self.notebook_outputs,
+            }),
+        ];
+        #[cfg(feature = "sqlite")]
+        adapters.push(Box::new(adapter::Databases {
+            tables: self.sqlite_tables.clone(),
+        }));
+        #[cfg(feature = "archive")]
+        adapters.push(Box::new(adapter::Archives(self.rules())));
+        #[cfg(feature = "image")]
+        adapters.push(Box::new(adapter::Images {
+            perceptual: self.perceptual_hash,
+        }));
+        adapters
+    }
+}
+
+/// Captures the watched file: `CaptureArgs` with the adapters they select.
+pub struct Capture {
+    args: CaptureArgs,
+    adapters: Vec<Box<dyn ContentAdapter>>,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        let args = CaptureArgs::default();
+        Self {
+            adapters: args.builtin_adapters(),
+            args,
+        }
+    }
+}
+
+impl Capture {
+    pub fn args(&self) -> &CaptureArgs {
+        &self.args
+    }
+
+    /// Captures `path`, refusing special files unless `--force` was given.
+    pub fn read(&self, path: &Path) -> io::Result<Snapshot> {
+        if let Some(remote) = RemoteFile::from_path(path)? {
+            let http = HttpOptions {
+                headers: self.args.headers,
+                ignore_headers: self.args.ignore_header.clone(),
+            };
+            return self.decode(path, remote.read(&http)?);
+        }
+        if let Some(kind) = special_kind(&fs::metadata(path)?) {
+            if !self.args.force {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} is a {kind}; reading it could block or never end (use --force to read it anyway)",
+                        path.display()
+                    ),
+                ));
+            }
+            let mut bytes = Vec::new();
+            File::open(path)?
+                .take(SPECIAL_READ_LIMIT)
+                .read_to_end(&mut bytes)?;
+            return Ok(lossy(&bytes));
+        }
+        if self.args.resolve_includes {
+            let mut resolved = resolve::resolve(path)?;
+            resolved.files.retain(|file| file != path);
+            return Ok(Snapshot {
+                contents: resolved.text,
+                includes: resolved.files,
+                replaced_invalid: false,
+                image: None,
+            });
+        }
+        self.decode(path, fs::read(path)?)
+    }
+
+    /// Makes text of `path`'s `bytes` with the adapters or encoding.
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> io::Result<Snapshot> {
+        if let Some(adapter) = self
+            .adapters
+            .iter()
+            .find(|adapter| adapter.sniff(path, &bytes))
+        {
+            let adapted = adapter.adapt(path, &bytes)?;
+            return Ok(Snapshot {
+                contents: adapted.text,
+                includes: Vec::new(),
+                replaced_invalid: false,
+                image: adapted.image,
+            });
+        }
+        if self.args.lossy {
+            return Ok(lossy(&bytes));
+        }
+        Ok(Snapshot {
+            contents: encoding::decode(bytes, self.args.encoding)?,
+            includes: Vec::new(),
+            replaced_invalid: false,
+            image: None,
+        })
+    }
+}
+
+/// `bytes` as UTF-8 with anything invalid replaced by U+FFFD.
+fn lossy(bytes: &[u8]) -> Snapshot {
+    let contents = String::from_utf8_lossy(bytes);
+    Snapshot {
+        // Only borrowed when there was nothing to replace.
+        replaced_invalid: matches!(contents, Cow::Owned(_)),
+        contents: contents.into_owned(),
+        includes: Vec::new(),
+        image: None,
+    }
+}
+
+/// Names the kind of file `metadata` describes if it isn't a regular file
+/// or directory.
+#[cfg(unix)]
+fn special_kind(metadata: &Metadata) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = metadata.file_type();
+    if file_type.is_char_device() {
+        Some("character device")
+    } else if file_type.is_block_device() {
+        Some("block device")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_fifo() {
+        Some("FIFO")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_metadata: &Metadata) -> Option<&'static str> {
+    None
+}
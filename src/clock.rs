@@ -0,0 +1,72 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Where the time comes from: the system's clocks, or a [`MockClock`] that
+/// only moves when told to, so that debouncing, playback and timestamps can
+/// be tested without waiting.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Monotonic time, for measuring how long something took or waited.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for timestamping versions.
+    fn system_now(&self) -> SystemTime;
+}
+
+/// The system's clocks.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The system's clocks, shared.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// A clock standing still at a set time until [`MockClock::advance`]d.
+/// Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    /// The time it started at and how far it has been advanced since.
+    start: (Instant, SystemTime),
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    /// A clock whose wall-clock time starts at `at`.
+    pub fn new(at: SystemTime) -> Self {
+        Self {
+            start: (Instant::now(), at),
+            elapsed: Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start.0 + self.elapsed()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.start.1 + self.elapsed()
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+/// How comments are written in a kind of file, for `--ignore-comments`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// `# …` to the end of the line, after whitespace: shell, Python,
+    /// YAML, TOML and most configuration files.
+    Hash,
+    /// `// …` to the end of the line and `/* … */`: C, Rust, JavaScript,
+    /// Go, Java, CSS and the like.
+    CLike,
+}
+
+impl CommentStyle {
+    /// The style `path`'s extension implies. Files without one are taken
+    /// to be configuration files, commented with `#`.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let Some(extension) = path.extension() else {
+            return Some(Self::Hash);
+        };
+        match extension.to_str()?.to_ascii_lowercase().as_str() {
+            "sh" | "bash" | "zsh" | "fish" | "py" | "rb" | "pl" | "r" | "yaml" | "yml" | "toml"
+            | "conf" | "cfg" | "ini" | "env" | "properties" | "mk" | "cmake" | "nix" | "tf"
+            | "ps1" | "service" | "timer" | "socket" => Some(Self::Hash),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "rs" | "go" | "java" | "kt" | "kts"
+            | "scala" | "swift" | "cs" | "js" | "mjs" | "cjs" | "jsx" | "ts" | "tsx" | "dart"
+            | "css" | "scss" | "less" | "jsonc" | "json5" | "proto" | "groovy" | "gradle"
+            | "zig" => Some(Self::CLike),
+            _ => None,
+        }
+    }
+}
+
+/// What of `text` is significant with `style`'s comments left out, and
+/// indentation and runs of spaces between words too: every line trimmed,
+/// with single spaces between words, and blank lines dropped. Comment
+/// markers in quoted strings are left alone, as are comments if the style
+/// isn't known.
+pub fn significant(text: &str, style: Option<CommentStyle>) -> String {
+    let mut code = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut quote = None;
+    let mut in_block = false;
+    let mut prev = '\n';
+    while let Some(c) = chars.next() {
+        if in_block {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                in_block = false;
+                code.push(' ');
+            } else if c == '\n' {
+                code.push('\n');
+            }
+            prev = ' ';
+            continue;
+        }
+        if let Some(open) = quote {
+            code.push(c);
+            if c == '\\' && chars.peek().is_some_and(|&next| next != '\n') {
+                code.extend(chars.next());
+            } else if c == open || c == '\n' {
+                quote = None;
+            }
+            prev = c;
+            continue;
+        }
+        let line_comment = match (style, c) {
+            (Some(CommentStyle::Hash), '#') => prev.is_whitespace(),
+            (Some(CommentStyle::CLike), '/') => chars.peek() == Some(&'/'),
+            _ => false,
+        };
+        if line_comment {
+            while chars.next_if(|&next| next != '\n').is_some() {}
+            continue;
+        }
+        if style == Some(CommentStyle::CLike) && c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            in_block = true;
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            quote = Some(c);
+        }
+        code.push(c);
+        prev = c;
+    }
+    let lines: Vec<String> = code
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_extension() {
+        assert_eq!(
+            CommentStyle::detect(Path::new("nginx.conf")),
+            Some(CommentStyle::Hash)
+        );
+        assert_eq!(
+            CommentStyle::detect(Path::new("/etc/hosts")),
+            Some(CommentStyle::Hash)
+        );
+        assert_eq!(
+            CommentStyle::detect(Path::new("main.RS")),
+            Some(CommentStyle::CLike)
+        );
+        assert_eq!(CommentStyle::detect(Path::new("notes.md")), None);
+    }
+
+    #[test]
+    fn strips_hash_comments_outside_strings() {
+        let old = "# listen here\nport = 80\ncolor = \"#fff\" # white\n";
+        let new = "port   = 80   # the default\n\n    color = \"#fff\"\n";
+        let style = Some(CommentStyle::Hash);
+        assert_eq!(significant(old, style), "port = 80\ncolor = \"#fff\"");
+        assert_eq!(significant(old, style), significant(new, style));
+        assert_ne!(significant("a#b\n", style), "a");
+    }
+
+    #[test]
+    fn strips_c_comments_across_lines() {
+        let text = "int x = 1; // one\n/* a\n   b */ int y = 2;\nchar *url = \"http://x\";\n";
+        assert_eq!(
+            significant(text, Some(CommentStyle::CLike)),
+            "int x = 1;\nint y = 2;\nchar *url = \"http://x\";"
+        );
+    }
+
+    #[test]
+    fn only_whitespace_without_a_style() {
+        assert_eq!(significant("  a  # b\n\n", None), "a # b");
+    }
+}
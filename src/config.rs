@@ -0,0 +1,278 @@
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::{parser::ValueSource, Arg, ArgMatches, Command};
+
+use crate::rules::glob_matches;
+
+/// A flag set by the configuration file: `true` or `false` for flags that
+/// take no value, otherwise the values to pass it, one per occurrence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setting {
+    Flag(bool),
+    Values(Vec<String>),
+}
+
+/// The flags set for files matching a pattern.
+#[derive(Debug, Clone)]
+struct Profile {
+    pattern: glob::Pattern,
+    settings: Vec<(String, Setting)>,
+}
+
+/// Defaults for the command line, read from a TOML file.
+///
+/// Top-level keys are long flags without the dashes, e.g.
+/// `renderer = "side-by-side"` or `confirm-window = "2s"`, and every
+/// `[[profile]]` table sets more of them for the files its `path` glob
+/// matches, overriding the defaults and the profiles before it:
+///
+/// ```toml
+/// color = "always"
+///
+/// [[profile]]
+/// path = "*.json"
+/// json = true
+/// ```
+///
+/// Patterns are matched as by [`glob_matches`]. Flags given on the command line win over the
+/// file, as do flags they conflict with.
+#[derive(Debug, Clone)]
+pub struct Config {
+    path: PathBuf,
+    defaults: Vec<(String, Setting)>,
+    profiles: Vec<Profile>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/slip-diff/config.toml`, falling back to
+    /// `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("slip-diff").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        Ok(Self::parse(path, &text).map_err(|e| format!("{}: {e}", path.display()))?)
+    }
+
+    #[cfg(feature = "toml")]
+    fn parse(path: &Path, text: &str) -> Result<Self, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut config = Self {
+            path: path.to_path_buf(),
+            defaults: Vec::new(),
+            profiles: Vec::new(),
+        };
+        for (key, value) in table {
+            if key != "profile" {
+                config.defaults.push((key.clone(), setting(&key, value)?));
+                continue;
+            }
+            let toml::Value::Array(profiles) = value else {
+                return Err("`profile` isn't a list of [[profile]] tables".into());
+            };
+            for profile in profiles {
+                let toml::Value::Table(mut profile) = profile else {
+                    return Err("`profile` isn't a list of [[profile]] tables".into());
+                };
+                let Some(toml::Value::String(pattern)) = profile.remove("path") else {
+                    return Err("a [[profile]] is missing `path = \"<glob>\"`".into());
+                };
+                let pattern = glob::Pattern::new(&pattern)
+                    .map_err(|e| format!("`{pattern}` isn't a glob: {e}"))?;
+                let settings = profile
+                    .into_iter()
+                    .map(|(key, value)| Ok((key.clone(), setting(&key, value)?)))
+                    .collect::<Result<_, String>>()?;
+                config.profiles.push(Profile { pattern, settings });
+            }
+        }
+        Ok(config)
+    }
+
+    #[cfg(not(feature = "toml"))]
+    fn parse(_path: &Path, _text: &str) -> Result<Self, String> {
+        Err("slip-diff was built without the `toml` feature".into())
+    }
+
+    /// The flags set for `file`, or only the defaults without one.
+    pub fn settings(&self, file: Option<&Path>) -> Vec<(String, Setting)> {
+        let mut settings = self.defaults.clone();
+        let profiles = self
+            .profiles
+            .iter()
+            .filter(|profile| file.is_some_and(|file| glob_matches(&profile.pattern, file)));
+        for (key, setting) in profiles.flat_map(|profile| &profile.settings) {
+            match settings.iter_mut().find(|(set, _)| set == key) {
+                Some((_, old)) => *old = setting.clone(),
+                None => settings.push((key.clone(), setting.clone())),
+            }
+        }
+        settings
+    }
+
+    /// `args`, with the flags set for `file` put before the ones `matches`
+    /// was parsed from. Flags given there or conflicting with ones given
+    /// there are left out, as are flags a subcommand doesn't take.
+    pub fn merge(
+        &self,
+        command: &Command,
+        matches: &ArgMatches,
+        file: Option<&Path>,
+        mut args: Vec<OsString>,
+    ) -> Result<Vec<OsString>, String> {
+        let given: Vec<&Arg> = command
+            .get_arguments()
+            .filter(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+            .collect();
+        let mut flags = Vec::new();
+        for (key, setting) in self.settings(file) {
+            let Some(arg) = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key.as_str()))
+            else {
+                return Err(format!(
+                    "{}: `{key}` isn't a flag of {}",
+                    self.path.display(),
+                    command.get_name()
+                ));
+            };
+            let overridden = given
+                .iter()
+                .any(|given| given.get_id() == arg.get_id() || conflict(command, arg, given));
+            if overridden || (matches.subcommand().is_some() && !arg.is_global_set()) {
+                continue;
+            }
+            match setting {
+                Setting::Flag(true) => flags.push(OsString::from(format!("--{key}"))),
+                Setting::Flag(false) => {}
+                Setting::Values(values) => {
+                    flags.extend(values.iter().map(|value| format!("--{key}={value}").into()))
+                }
+            }
+        }
+        let at = args.len().min(1);
+        args.splice(at..at, flags);
+        Ok(args)
+    }
+}
+
+/// Whether either of `a` and `b` says it conflicts with the other.
+fn conflict(command: &Command, a: &Arg, b: &Arg) -> bool {
+    let with = |a: &Arg, b: &Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|conflict| conflict.get_id() == b.get_id())
+    };
+    with(a, b) || with(b, a)
+}
+
+#[cfg(feature = "toml")]
+fn setting(key: &str, value: toml::Value) -> Result<Setting, String> {
+    let scalar = |value: toml::Value| match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        _ => Err(format!(
+            "`{key}` isn't set to a string, number or list of them"
+        )),
+    };
+    match value {
+        toml::Value::Boolean(set) => Ok(Setting::Flag(set)),
+        toml::Value::Array(values) => Ok(Setting::Values(
+            values.into_iter().map(scalar).collect::<Result<_, _>>()?,
+        )),
+        value => Ok(Setting::Values(vec![scalar(value)?])),
+    }
+}
+
+#[cfg(all(test, feature = "toml"))]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct Args {
+        #[clap(long)]
+        file: Option<PathBuf>,
+        #[clap(long, default_value = "auto")]
+        renderer: String,
+        #[clap(long)]
+        json: bool,
+        #[clap(long, conflicts_with = "json")]
+        yaml: bool,
+        #[clap(long)]
+        sink: Vec<String>,
+    }
+
+    const CONFIG: &str = r#"
+        renderer = "builtin"
+        sink = ["fifo:/tmp/a", "fifo:/tmp/b"]
+
+        [[profile]]
+        path = "*.json"
+        json = true
+        renderer = "delta"
+
+        [[profile]]
+        path = "/etc/**/*.conf"
+        renderer = "unified"
+    "#;
+
+    fn parse(config: &str, args: &[&str]) -> Result<Args, String> {
+        let config = Config::parse(Path::new("config.toml"), config)?;
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        let command = Args::command();
+        let matches = command.clone().try_get_matches_from(&args).unwrap();
+        let file = matches.get_one::<PathBuf>("file").map(PathBuf::as_path);
+        let args = config.merge(&command, &matches, file, args)?;
+        Ok(Args::from_arg_matches(&command.get_matches_from(args)).unwrap())
+    }
+
+    #[test]
+    fn profiles_override_defaults() {
+        let args = parse(CONFIG, &["slip-diff", "--file", "data.json"]).unwrap();
+        assert_eq!((args.renderer.as_str(), args.json), ("delta", true));
+        assert_eq!(args.sink, ["fifo:/tmp/a", "fifo:/tmp/b"]);
+        let args = parse(CONFIG, &["slip-diff", "--file", "/etc/nginx/site.conf"]).unwrap();
+        assert_eq!((args.renderer.as_str(), args.json), ("unified", false));
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let args = parse(
+            CONFIG,
+            &[
+                "slip-diff",
+                "--file",
+                "data.json",
+                "--renderer",
+                "side-by-side",
+                "--yaml",
+            ],
+        )
+        .unwrap();
+        assert_eq!(args.renderer, "side-by-side");
+        assert!(args.yaml && !args.json);
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        assert!(parse("colour = \"never\"", &["slip-diff"]).is_err());
+        assert!(parse("[[profile]]\njson = true", &["slip-diff"]).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+/// The selected position in a version history, whose change to the next
+/// version is the one shown. Every position but the latest has a next
+/// version, so the cursor stays on the second-latest or before, or on the
+/// only version while there is just one.
+///
+/// Moves take the history's current length rather than the cursor keeping
+/// it, since the history grows and shrinks under it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VersionCursor {
+    index: usize,
+}
+
+impl VersionCursor {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Selects the next position, wrapping around to the first after the
+    /// last.
+    pub fn next(&mut self, len: usize) {
+        self.index = match self.index >= last(len) {
+            true => 0,
+            false => self.index + 1,
+        };
+    }
+
+    /// Selects the previous position, wrapping around to the last before the
+    /// first.
+    pub fn prev(&mut self, len: usize) {
+        self.index = match self.index.min(last(len)).checked_sub(1) {
+            Some(index) => index,
+            None => last(len),
+        };
+    }
+
+    /// Selects the latest change.
+    pub fn latest(&mut self, len: usize) {
+        self.index = last(len);
+    }
+
+    /// Selects position `index`, or the last one if it is past that.
+    pub fn jump(&mut self, index: usize, len: usize) {
+        self.index = index.min(last(len));
+    }
+
+    /// Follows the selected version as the positions in `evicted` are
+    /// removed from the history, in removal order, leaving `len` versions.
+    /// If the selected version itself was evicted, the one that took its
+    /// place is selected.
+    pub fn evicted(&mut self, evicted: &[usize], len: usize) {
+        for &position in evicted {
+            if self.index > position {
+                self.index -= 1;
+            }
+        }
+        self.index = self.index.min(last(len));
+    }
+}
+
+/// The last position that can be selected in a history of `len` versions.
+fn last(len: usize) -> usize {
+    len.saturating_sub(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(index: usize) -> VersionCursor {
+        VersionCursor { index }
+    }
+
+    #[test]
+    fn starts_at_the_first_version() {
+        assert_eq!(VersionCursor::default().index(), 0);
+    }
+
+    #[test]
+    fn next_and_prev_stay_put_without_a_change_to_show() {
+        for len in [0, 1, 2] {
+            let mut cursor = VersionCursor::default();
+            cursor.next(len);
+            assert_eq!(cursor.index(), 0, "next with {len} versions");
+            cursor.prev(len);
+            assert_eq!(cursor.index(), 0, "prev with {len} versions");
+        }
+    }
+
+    #[test]
+    fn next_visits_every_change_and_wraps() {
+        for len in 2..8 {
+            let mut cursor = VersionCursor::default();
+            let mut visited = vec![cursor.index()];
+            for _ in 0..len - 1 {
+                cursor.next(len);
+                visited.push(cursor.index());
+            }
+            let mut expected: Vec<_> = (0..len - 1).collect();
+            expected.push(0);
+            assert_eq!(visited, expected, "with {len} versions");
+        }
+    }
+
+    #[test]
+    fn prev_visits_every_change_and_wraps() {
+        for len in 2..8 {
+            let mut cursor = VersionCursor::default();
+            let mut visited = vec![cursor.index()];
+            for _ in 0..len - 1 {
+                cursor.prev(len);
+                visited.push(cursor.index());
+            }
+            let mut expected = vec![0];
+            expected.extend((0..len - 1).rev());
+            assert_eq!(visited, expected, "with {len} versions");
+        }
+    }
+
+    #[test]
+    fn prev_undoes_next() {
+        for len in 0..8 {
+            for index in 0..last(len) + 1 {
+                let mut cursor = at(index);
+                cursor.next(len);
+                cursor.prev(len);
+                assert_eq!(cursor, at(index), "from {index} of {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn moves_from_past_the_end_land_in_range() {
+        // E.g. after the history shrank without the cursor being told.
+        let mut cursor = at(10);
+        cursor.next(4);
+        assert_eq!(cursor.index(), 0);
+        let mut cursor = at(10);
+        cursor.prev(4);
+        assert_eq!(cursor.index(), 1);
+    }
+
+    #[test]
+    fn latest_selects_the_newest_change() {
+        for (len, expected) in [(0, 0), (1, 0), (2, 0), (3, 1), (10, 8)] {
+            let mut cursor = VersionCursor::default();
+            cursor.latest(len);
+            assert_eq!(cursor.index(), expected, "with {len} versions");
+        }
+    }
+
+    #[test]
+    fn jump_clamps_to_the_last_change() {
+        let mut cursor = VersionCursor::default();
+        cursor.jump(3, 10);
+        assert_eq!(cursor.index(), 3);
+        cursor.jump(8, 10);
+        assert_eq!(cursor.index(), 8);
+        cursor.jump(9, 10);
+        assert_eq!(cursor.index(), 8);
+        cursor.jump(usize::MAX, 10);
+        assert_eq!(cursor.index(), 8);
+        cursor.jump(5, 1);
+        assert_eq!(cursor.index(), 0);
+        cursor.jump(5, 0);
+        assert_eq!(cursor.index(), 0);
+    }
+
+    #[test]
+    fn eviction_before_the_cursor_keeps_the_same_version() {
+        let mut cursor = at(5);
+        cursor.evicted(&[0], 9);
+        assert_eq!(cursor.index(), 4);
+        cursor.evicted(&[1, 1], 7);
+        assert_eq!(cursor.index(), 2);
+    }
+
+    #[test]
+    fn eviction_after_the_cursor_leaves_it() {
+        let mut cursor = at(2);
+        cursor.evicted(&[3, 5], 8);
+        assert_eq!(cursor.index(), 2);
+    }
+
+    #[test]
+    fn eviction_of_the_selected_version_selects_its_successor() {
+        let mut cursor = at(3);
+        cursor.evicted(&[3], 9);
+        assert_eq!(cursor.index(), 3);
+    }
+
+    #[test]
+    fn eviction_of_the_first_version_under_the_cursor_stays_at_zero() {
+        let mut cursor = at(0);
+        cursor.evicted(&[0, 0, 0], 5);
+        assert_eq!(cursor.index(), 0);
+    }
+
+    #[test]
+    fn eviction_clamps_to_the_shrunk_history() {
+        // Keep-first eviction drops position 1, which the cursor is past.
+        let mut cursor = at(3);
+        cursor.evicted(&[], 3);
+        assert_eq!(cursor.index(), 1);
+        let mut cursor = at(1);
+        cursor.evicted(&[1], 1);
+        assert_eq!(cursor.index(), 0);
+    }
+
+    #[test]
+    fn no_move_leaves_the_selectable_range() {
+        for len in 0..6 {
+            for index in 0..8 {
+                let mut moves: Vec<(&str, VersionCursor)> = Vec::new();
+                let mut cursor = at(index);
+                cursor.next(len);
+                moves.push(("next", cursor));
+                let mut cursor = at(index);
+                cursor.prev(len);
+                moves.push(("prev", cursor));
+                let mut cursor = at(index);
+                cursor.latest(len);
+                moves.push(("latest", cursor));
+                let mut cursor = at(index);
+                cursor.jump(index, len);
+                moves.push(("jump", cursor));
+                let mut cursor = at(index);
+                cursor.evicted(&[0], len);
+                moves.push(("evicted", cursor));
+                for (name, cursor) in moves {
+                    assert!(
+                        cursor.index() <= last(len),
+                        "{name} from {index} of {len} went to {}",
+                        cursor.index()
+                    );
+                }
+            }
+        }
+    }
+}
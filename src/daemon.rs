@@ -0,0 +1,993 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream as StdUnixStream,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader},
+    net::{UnixListener, UnixStream},
+    sync::{broadcast, Notify},
+};
+
+use crate::{
+    access::{Access, AccessArgs, Peer},
+    capture::{Capture, CaptureArgs},
+    clock::{self, Clock},
+    digest::{Digest, HashAlgorithm},
+    drift::{DriftBase, ReportArgs, TimeOfDay},
+    history::{FileVersion, HistoryLimits, Version, VersionHistory},
+    poll::PollArgs,
+    report::{self, SessionReport},
+    sink::{Sink, SinkFormat, SinkSpec},
+    stats::LineStats,
+    version_store::HistoryBackend,
+    watcher::{ChangeEvent, Watcher},
+};
+
+/// How many updates an attached client may fall behind before it misses
+/// some.
+const UPDATE_BACKLOG: usize = 256;
+
+/// What `slip-diff ctl` asks the daemon, one JSON object per line. Every
+/// `file` is the absolute path of a watched file, or `None` for all of them.
+#[derive(Debug, Clone, Serialize, Deserialize, clap::Subcommand)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// List the watched files
+    List,
+    /// Show the latest changes
+    Diffs {
+        /// Only this file's
+        file: Option<PathBuf>,
+
+        /// How many changes to show per file
+        #[clap(short = 'n', long, default_value_t = 5)]
+        count: usize,
+    },
+    /// Stop recording changes until resumed
+    Pause { file: Option<PathBuf> },
+    /// Record changes again, starting with the file as it is now
+    Resume { file: Option<PathBuf> },
+    /// Record the file as it is now, even if no change was noticed
+    Snapshot { file: Option<PathBuf> },
+    /// Follow changes as they are recorded until interrupted
+    Attach { file: Option<PathBuf> },
+    /// Stop the daemon
+    Stop,
+}
+
+impl Request {
+    fn file(&self) -> Option<&Path> {
+        match self {
+            Self::Diffs { file, .. }
+            | Self::Pause { file }
+            | Self::Resume { file }
+            | Self::Snapshot { file }
+            | Self::Attach { file } => file.as_deref(),
+            Self::List | Self::Stop => None,
+        }
+    }
+
+    /// Whether the request changes what the daemon does, which only admins
+    /// may ask for.
+    fn is_control(&self) -> bool {
+        matches!(
+            self,
+            Self::Pause { .. } | Self::Resume { .. } | Self::Snapshot { .. } | Self::Stop
+        )
+    }
+
+    /// What the request asks for, for the audit log.
+    fn describe(&self) -> String {
+        let verb = match self {
+            Self::List => "list",
+            Self::Diffs { .. } => "diffs",
+            Self::Pause { .. } => "pause",
+            Self::Resume { .. } => "resume",
+            Self::Snapshot { .. } => "snapshot",
+            Self::Attach { .. } => "attach",
+            Self::Stop => "stop",
+        };
+        match self.file() {
+            Some(file) => format!("{verb} {}", file.display()),
+            None => verb.to_string(),
+        }
+    }
+
+    fn file_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Self::Diffs { file, .. }
+            | Self::Pause { file }
+            | Self::Resume { file }
+            | Self::Snapshot { file }
+            | Self::Attach { file } => file.as_mut(),
+            Self::List | Self::Stop => None,
+        }
+    }
+}
+
+/// What the daemon answers, one JSON object per line. Attaching answers
+/// with a `Change` or `Notice` for as long as the client stays connected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    Files {
+        files: Vec<FileStatus>,
+    },
+    Changes {
+        changes: Vec<ChangeRecord>,
+    },
+    Change(ChangeRecord),
+    /// Something happened to a watched file other than a change.
+    Notice {
+        path: PathBuf,
+        message: String,
+    },
+    Done {
+        message: String,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileStatus {
+    pub path: PathBuf,
+    pub versions: usize,
+    pub latest: Option<usize>,
+    pub paused: bool,
+}
+
+/// A recorded change and its unified diff.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    pub path: PathBuf,
+    pub number: usize,
+    pub at: SystemTime,
+    pub inserted: usize,
+    pub deleted: usize,
+    pub diff: String,
+}
+
+/// `$XDG_RUNTIME_DIR/slip-diff.sock`, falling back to
+/// `~/.local/state/slip-diff/daemon.sock`.
+pub fn default_socket() -> Option<PathBuf> {
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR") {
+        return Some(Path::new(&runtime).join("slip-diff.sock"));
+    }
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+        })?;
+    Some(state.join("slip-diff").join("daemon.sock"))
+}
+
+/// What a daemon does besides keeping history: where it sends changes and
+/// reports, and who may use it.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct DaemonArgs {
+    /// Also send every change to each file here, taking what a watch's --sink takes
+    #[clap(long)]
+    pub sink: Vec<SinkSpec>,
+
+    /// What to write to each --sink
+    #[clap(long, value_enum, default_value_t)]
+    pub sink_format: SinkFormat,
+
+    #[clap(flatten)]
+    pub report: ReportArgs,
+
+    /// When the daemon stops, write a report of each file's session here, as a watch's --exit-report does; with several files, each report is named after its file, e.g. report-app.conf.html
+    #[clap(long, value_name = "PATH")]
+    pub exit_report: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub access: AccessArgs,
+}
+
+/// Watches files in the background for `slip-diff daemon`, keeping each
+/// one's history in memory and answering [`Request`]s on a Unix socket.
+///
+/// Every change is also sent to the `--sink`s, and with `--report-at` each
+/// file's drift over the day is sent to them daily. With `--exit-report`,
+/// each file's session is summarized when the daemon stops.
+pub struct Daemon {
+    files: Arc<Mutex<Vec<Tracked>>>,
+    updates: broadcast::Sender<Reply>,
+    poll: PollArgs,
+    capture: CaptureArgs,
+    report_at: Option<TimeOfDay>,
+    report_against: DriftBase,
+    exit_report: Option<PathBuf>,
+    access: Access,
+    clock: Arc<dyn Clock>,
+    stop: Arc<Notify>,
+}
+
+struct Tracked {
+    path: PathBuf,
+    capture: Capture,
+    versions: VersionHistory,
+    outputs: Arc<Mutex<Outputs>>,
+    paused: bool,
+}
+
+/// Where a file's changes are delivered, behind a lock of its own so that
+/// diffing and sending them doesn't hold up the other files or `ctl`.
+struct Outputs {
+    sinks: Vec<Sink>,
+    report: Option<SessionReport>,
+}
+
+/// A version copied out of a file's history, to deliver once the files are
+/// unlocked. The contents are shared, not copied.
+struct Detached {
+    version: FileVersion,
+    hash: Digest,
+}
+
+impl Detached {
+    fn new(version: &Version) -> Self {
+        Self {
+            version: version.to_file_version(),
+            hash: version.hash,
+        }
+    }
+
+    fn version(&self) -> Version<'_> {
+        Version {
+            contents: Arc::clone(&self.version.contents),
+            hash: self.hash,
+            at: self.version.at,
+            number: self.version.number,
+            meta: &self.version.meta,
+        }
+    }
+}
+
+impl Daemon {
+    pub fn new(
+        files: &[PathBuf],
+        limits: &HistoryLimits,
+        algorithm: HashAlgorithm,
+        capture: &CaptureArgs,
+        poll: &PollArgs,
+        options: &DaemonArgs,
+    ) -> io::Result<Self> {
+        if !matches!(limits.history, HistoryBackend::Memory) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the daemon keeps history in memory only",
+            ));
+        }
+        let clock = clock::system();
+        let mut tracked: Vec<Tracked> = Vec::new();
+        for path in files {
+            let path = fs::canonicalize(path).map_err(|e| {
+                io::Error::new(e.kind(), format!("watching {}: {e}", path.display()))
+            })?;
+            if tracked.iter().any(|file| file.path == path) {
+                continue;
+            }
+            tracked.push(Tracked {
+                capture: capture.capture()?,
+                versions: VersionHistory::new(limits.clone(), algorithm),
+                outputs: Arc::new(Mutex::new(Outputs {
+                    sinks: options
+                        .sink
+                        .iter()
+                        .map(|spec| {
+                            Sink::new(spec.clone(), options.sink_format, &path, clock.clone())
+                        })
+                        .collect(),
+                    report: options
+                        .exit_report
+                        .as_ref()
+                        .map(|_| SessionReport::new(&path, clock.clone())),
+                })),
+                path,
+                paused: false,
+            });
+        }
+        let (updates, _) = broadcast::channel(UPDATE_BACKLOG);
+        Ok(Self {
+            files: Arc::new(Mutex::new(tracked)),
+            updates,
+            poll: poll.clone(),
+            capture: capture.clone(),
+            report_at: options.report.report_at,
+            report_against: options.report.report_against,
+            exit_report: options.exit_report.clone(),
+            access: Access::new(&options.access)?,
+            clock,
+            stop: Arc::new(Notify::new()),
+        })
+    }
+
+    /// Watches the files and answers requests on `socket` until asked to
+    /// stop, interrupted or terminated, then removes the socket.
+    pub async fn run(self, socket: &Path) -> io::Result<()> {
+        let listener = bind(socket, &self.access)?;
+        let daemon = Arc::new(self);
+        let paths: Vec<PathBuf> = daemon.lock().iter().map(|f| f.path.clone()).collect();
+        for (index, path) in paths.into_iter().enumerate() {
+            let watcher = Watcher::new(&path, daemon.capture.capture()?, &daemon.poll)
+                .map_err(io::Error::other)?;
+            let mut events = watcher.subscribe();
+            let daemon = Arc::clone(&daemon);
+            tokio::spawn(async move {
+                while let Some(event) = events.recv().await {
+                    daemon.handle(index, event);
+                }
+            });
+        }
+        if let Some(report_at) = daemon.report_at {
+            let daemon = Arc::clone(&daemon);
+            tokio::spawn(async move {
+                loop {
+                    let now = daemon.clock.system_now();
+                    let next = report_at.next_after(now);
+                    let wait = next.duration_since(now).unwrap_or_default();
+                    tokio::time::sleep(wait).await;
+                    daemon.report(next);
+                }
+            });
+        }
+
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let result = loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(Arc::clone(&daemon).serve(stream));
+                    }
+                    Err(error) => break Err(error),
+                },
+                _ = daemon.stop.notified() => break Ok(()),
+                _ = tokio::signal::ctrl_c() => break Ok(()),
+                _ = terminate.recv() => break Ok(()),
+            }
+        };
+        let _ = fs::remove_file(socket);
+        daemon.write_reports();
+        result
+    }
+
+    /// Writes each file's `--exit-report`, if asked for one.
+    fn write_reports(&self) {
+        let Some(path) = &self.exit_report else {
+            return;
+        };
+        let files = self.lock();
+        for file in files.iter() {
+            let outputs = file.outputs.lock().unwrap();
+            let Some(report) = &outputs.report else {
+                continue;
+            };
+            let path = match files.len() {
+                1 => path.clone(),
+                _ => report::path_for(path, &file.path),
+            };
+            let latest = file.versions.last().map(|v| v.contents).unwrap_or_default();
+            match report.write(&path, &latest) {
+                Ok(()) => tracing::info!("wrote the session report to {}", path.display()),
+                Err(error) => tracing::warn!("writing {}: {error}", path.display()),
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Tracked>> {
+        self.files.lock().unwrap()
+    }
+
+    fn handle(&self, index: usize, event: ChangeEvent) {
+        let notice = |message: String| {
+            let path = self.lock()[index].path.clone();
+            // Fails only while no client is attached.
+            let _ = self.updates.send(Reply::Notice { path, message });
+        };
+        match event {
+            ChangeEvent::VersionCaptured(version) => {
+                if !self.lock()[index].paused {
+                    self.record(index, version);
+                }
+            }
+            ChangeEvent::WatchLost => notice("removed or moved away".into()),
+            ChangeEvent::FileRotated => notice("replaced by another file".into()),
+            ChangeEvent::Error(error) => notice(error.to_string()),
+        }
+    }
+
+    /// Records `version` unless it's the same as the latest, returning its
+    /// number if it was new.
+    fn record(&self, index: usize, version: FileVersion) -> Option<usize> {
+        let mut files = self.lock();
+        let file = &mut files[index];
+        if file.versions.is_latest(&version.contents) {
+            return None;
+        }
+        let prev = file.versions.last().map(|prev| prev.contents);
+        file.versions.push(version);
+        let latest = Detached::new(&file.versions.last().unwrap());
+        let path = file.path.clone();
+        let outputs = Arc::clone(&file.outputs);
+        // Locked before the files are let go of, so that one file's changes
+        // are delivered in the order they were recorded.
+        let mut outputs = outputs.lock().unwrap();
+        drop(files);
+
+        let latest = latest.version();
+        if let Some(report) = &mut outputs.report {
+            let stats = prev
+                .as_ref()
+                .map(|prev| LineStats::between(prev, &latest.contents))
+                .unwrap_or_default();
+            report.record(
+                prev.as_ref(),
+                latest.number,
+                latest.at,
+                &latest.contents,
+                stats,
+            );
+        }
+        for sink in &mut outputs.sinks {
+            sink.send(&latest);
+        }
+        if let Some(prev) = prev {
+            let change = change_record(&path, &prev, &latest.contents, latest.number, latest.at);
+            // Fails only while no client is attached.
+            let _ = self.updates.send(Reply::Change(change));
+        }
+        Some(latest.number)
+    }
+
+    /// Sends each file's drift as of `at` to its sinks: the change since
+    /// the version `--report-against` picks. Files that haven't changed
+    /// since are left out.
+    fn report(&self, at: SystemTime) {
+        let mut drifted = Vec::new();
+        for file in self.lock().iter() {
+            let Some(since) = self.report_against.pick(file.versions.infos(), at) else {
+                continue;
+            };
+            let (Some(since), Some(latest)) = (file.versions.get(since), file.versions.last())
+            else {
+                continue;
+            };
+            if since.contents == latest.contents {
+                tracing::info!("{} hasn't drifted", file.path.display());
+                continue;
+            }
+            drifted.push((
+                Arc::clone(&file.outputs),
+                Detached::new(&since),
+                Detached::new(&latest),
+            ));
+        }
+        for (outputs, since, latest) in drifted {
+            for sink in &mut outputs.lock().unwrap().sinks {
+                sink.report(&since.version(), &latest.version());
+            }
+        }
+    }
+
+    /// Reads the file at position `index` now and records it.
+    fn snapshot(&self, index: usize) -> io::Result<Option<usize>> {
+        let snapshot = {
+            let files = self.lock();
+            files[index].capture.read(&files[index].path)?
+        };
+        let mut version = FileVersion::new_by(snapshot.contents, &*self.clock);
+        version.meta.replaced_invalid = snapshot.replaced_invalid;
+        Ok(self.record(index, version))
+    }
+
+    /// The positions of the files `file` selects: that one, or all of them.
+    fn select(&self, file: Option<&Path>) -> Result<Vec<usize>, String> {
+        let files = self.lock();
+        let Some(file) = file else {
+            return Ok((0..files.len()).collect());
+        };
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        match files.iter().position(|tracked| tracked.path == file) {
+            Some(index) => Ok(vec![index]),
+            None => Err(format!("{} isn't being watched", file.display())),
+        }
+    }
+
+    /// Answers the requests on one connection. Only admins may ask for
+    /// anything but to look, and what is asked for besides listing and
+    /// diffs is audited.
+    async fn serve(self: Arc<Self>, stream: UnixStream) {
+        let peer = match stream.peer_cred() {
+            Ok(cred) => Peer::new(cred.uid(), cred.gid()),
+            Err(error) => {
+                tracing::warn!("can't tell who connected: {error}");
+                return;
+            }
+        };
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = AsyncBufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let request = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => request,
+                Err(error) => {
+                    let error = format!("invalid request: {error}");
+                    if send(&mut writer, &Reply::Error { error }).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let what = request.describe();
+            if request.is_control() && !self.access.is_admin(&peer) {
+                self.audit(&peer, &format!("was refused {what}"));
+                let error = format!("only admins may {what}");
+                if send(&mut writer, &Reply::Error { error }).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            if let Request::Attach { file } = &request {
+                self.audit(&peer, &format!("asked to {what}"));
+                self.attach(file.as_deref(), &mut writer).await;
+                self.audit(&peer, "detached");
+                return;
+            }
+            if request.is_control() {
+                self.audit(&peer, &format!("asked to {what}"));
+            }
+            let reply = self.answer(&request);
+            if send(&mut writer, &reply).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn answer(&self, request: &Request) -> Reply {
+        let selected = match self.select(request.file()) {
+            Ok(selected) => selected,
+            Err(error) => return Reply::Error { error },
+        };
+        match request {
+            Request::List => Reply::Files {
+                files: self
+                    .lock()
+                    .iter()
+                    .map(|file| FileStatus {
+                        path: file.path.clone(),
+                        versions: file.versions.len(),
+                        latest: file.versions.last().map(|v| v.number),
+                        paused: file.paused,
+                    })
+                    .collect(),
+            },
+            Request::Diffs { count, .. } => {
+                let files = self.lock();
+                let mut selected_versions = Vec::new();
+                for index in selected {
+                    let file = &files[index];
+                    // Only the versions in the last `count` changes are rebuilt.
+                    let start = file.versions.len().saturating_sub(count.saturating_add(1));
+                    let versions: Vec<_> = (start..file.versions.len())
+                        .filter_map(|index| file.versions.get(index))
+                        .map(|version| Detached::new(&version))
+                        .collect();
+                    selected_versions.push((file.path.clone(), versions));
+                }
+                drop(files);
+                let mut changes = Vec::new();
+                for (path, versions) in selected_versions {
+                    for pair in versions.windows(2) {
+                        let (prev, latest) = (&pair[0].version, &pair[1].version);
+                        changes.push(change_record(
+                            &path,
+                            &prev.contents,
+                            &latest.contents,
+                            latest.number,
+                            latest.at,
+                        ));
+                    }
+                }
+                Reply::Changes { changes }
+            }
+            Request::Pause { .. } => {
+                for &index in &selected {
+                    self.lock()[index].paused = true;
+                }
+                Reply::Done {
+                    message: format!("paused {}", self.describe(&selected)),
+                }
+            }
+            Request::Resume { .. } => {
+                for &index in &selected {
+                    self.lock()[index].paused = false;
+                    // Catch up on what changed while paused.
+                    if let Err(error) = self.snapshot(index) {
+                        return Reply::Error {
+                            error: error.to_string(),
+                        };
+                    }
+                }
+                Reply::Done {
+                    message: format!("resumed {}", self.describe(&selected)),
+                }
+            }
+            Request::Snapshot { .. } => {
+                let mut recorded = Vec::new();
+                for &index in &selected {
+                    let path = self.lock()[index].path.clone();
+                    match self.snapshot(index) {
+                        Ok(Some(number)) => {
+                            recorded.push(format!("version {number} of {}", path.display()))
+                        }
+                        Ok(None) => recorded.push(format!("{} unchanged", path.display())),
+                        Err(error) => {
+                            return Reply::Error {
+                                error: format!("reading {}: {error}", path.display()),
+                            }
+                        }
+                    }
+                }
+                Reply::Done {
+                    message: recorded.join(", "),
+                }
+            }
+            Request::Stop => {
+                self.stop.notify_one();
+                Reply::Done {
+                    message: "stopping".into(),
+                }
+            }
+            Request::Attach { .. } => unreachable!("attaching streams replies"),
+        }
+    }
+
+    /// Forwards the changes to the files `file` selects until the client
+    /// goes away.
+    async fn attach(&self, file: Option<&Path>, writer: &mut (impl AsyncWriteExt + Unpin)) {
+        let selected: Vec<PathBuf> = match self.select(file) {
+            Ok(selected) => {
+                let files = self.lock();
+                selected.iter().map(|&i| files[i].path.clone()).collect()
+            }
+            Err(error) => {
+                let _ = send(writer, &Reply::Error { error }).await;
+                return;
+            }
+        };
+        let mut updates = self.updates.subscribe();
+        loop {
+            let reply = match updates.recv().await {
+                Ok(reply) => reply,
+                Err(broadcast::error::RecvError::Lagged(missed)) => Reply::Error {
+                    error: format!("fell behind and missed {missed} updates"),
+                },
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let path = match &reply {
+                Reply::Change(change) => Some(&change.path),
+                Reply::Notice { path, .. } => Some(path),
+                _ => None,
+            };
+            if path.is_some_and(|path| !selected.contains(path)) {
+                continue;
+            }
+            if send(writer, &reply).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn audit(&self, peer: &Peer, what: &str) {
+        self.access.audit(self.clock.system_now(), peer, what);
+    }
+
+    fn describe(&self, selected: &[usize]) -> String {
+        let files = self.lock();
+        if selected.len() == 1 {
+            return files[selected[0]].path.display().to_string();
+        }
+        format!("{} files", selected.len())
+    }
+}
+
+/// Listens on `socket`, replacing a stale one left by a daemon that didn't
+/// stop cleanly but refusing to take over from one that is running, for the
+/// users `access` lets in.
+fn bind(socket: &Path, access: &Access) -> io::Result<UnixListener> {
+    if StdUnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("a daemon is already listening on {}", socket.display()),
+        ));
+    }
+    if socket.exists() {
+        fs::remove_file(socket)?;
+    }
+    let dir = match socket.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    // Bound in a directory only the daemon's user can enter and moved into
+    // place once restricted, so nobody can connect before then.
+    let private = tempfile::Builder::new()
+        .prefix(".slip-diff-")
+        .tempdir_in(dir)?;
+    let bound = private.path().join("sock");
+    let listener = UnixListener::bind(&bound)?;
+    access.restrict(&bound)?;
+    fs::rename(&bound, socket)?;
+    Ok(listener)
+}
+
+async fn send(writer: &mut (impl AsyncWriteExt + Unpin), reply: &Reply) -> io::Result<()> {
+    let mut line = serde_json::to_vec(reply)?;
+    line.push(b'\n');
+    writer.write_all(&line).await
+}
+
+fn change_record(
+    path: &Path,
+    prev: &str,
+    contents: &str,
+    number: usize,
+    at: SystemTime,
+) -> ChangeRecord {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stats = LineStats::between(prev, contents);
+    ChangeRecord {
+        path: path.to_path_buf(),
+        number,
+        at,
+        inserted: stats.inserted,
+        deleted: stats.deleted,
+        diff: TextDiff::from_lines(prev, contents)
+            .unified_diff()
+            .header(&format!("a/{name}"), &format!("b/{name}"))
+            .to_string(),
+    }
+}
+
+/// A connection to a running daemon, for `slip-diff ctl`.
+pub struct Client {
+    reader: BufReader<StdUnixStream>,
+    writer: StdUnixStream,
+}
+
+impl Client {
+    pub fn connect(socket: &Path) -> io::Result<Self> {
+        let writer = StdUnixStream::connect(socket).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("no daemon is listening on {}: {e}", socket.display()),
+            )
+        })?;
+        Ok(Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    /// Sends `request`, with its file made absolute since the daemon has a
+    /// different working directory.
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        let mut request = request.clone();
+        if let Some(file) = request.file_mut() {
+            *file = std::path::absolute(&*file)?;
+        }
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)
+    }
+
+    /// The next reply, or `None` once the daemon hung up.
+    pub fn reply(&mut self) -> io::Result<Option<Reply>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&line)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime;
+
+    use super::*;
+
+    /// A daemon watching `app.conf`, holding "one\n", in a new directory.
+    fn daemon() -> (Daemon, PathBuf, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+        fs::write(&path, "one\n").unwrap();
+        let daemon = Daemon::new(
+            std::slice::from_ref(&path),
+            &HistoryLimits::default(),
+            HashAlgorithm::default(),
+            &CaptureArgs::default(),
+            &PollArgs::default(),
+            &DaemonArgs::default(),
+        )
+        .unwrap();
+        (daemon, fs::canonicalize(path).unwrap(), dir)
+    }
+
+    fn done(reply: Reply) -> String {
+        match reply {
+            Reply::Done { message } => message,
+            reply => panic!("expected done, got {reply:?}"),
+        }
+    }
+
+    fn error(reply: Reply) -> String {
+        match reply {
+            Reply::Error { error } => error,
+            reply => panic!("expected an error, got {reply:?}"),
+        }
+    }
+
+    #[test]
+    fn requests_are_parsed_from_tagged_json() {
+        let request: Request =
+            serde_json::from_str(r#"{"command":"diffs","file":"/etc/app.conf","count":2}"#)
+                .unwrap();
+        assert!(matches!(
+            &request,
+            Request::Diffs { file: Some(file), count: 2 } if file == Path::new("/etc/app.conf")
+        ));
+        assert_eq!(request.describe(), "diffs /etc/app.conf");
+        assert!(!request.is_control());
+
+        let request: Request = serde_json::from_str(r#"{"command":"pause","file":null}"#).unwrap();
+        assert_eq!(request.describe(), "pause");
+        assert!(request.is_control());
+        assert!(serde_json::from_str::<Request>(r#"{"command":"explode"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command":"diffs"}"#).is_err());
+    }
+
+    #[test]
+    fn requests_are_answered_for_the_files_they_select() {
+        let (daemon, path, _dir) = daemon();
+        let shown = path.display();
+        assert_eq!(
+            done(daemon.answer(&Request::Snapshot { file: None })),
+            format!("version 0 of {shown}")
+        );
+        fs::write(&path, "two\n").unwrap();
+        assert_eq!(
+            done(daemon.answer(&Request::Snapshot {
+                file: Some(path.clone())
+            })),
+            format!("version 1 of {shown}")
+        );
+        assert_eq!(
+            done(daemon.answer(&Request::Snapshot { file: None })),
+            format!("{shown} unchanged")
+        );
+
+        let Reply::Changes { changes } = daemon.answer(&Request::Diffs {
+            file: None,
+            count: 5,
+        }) else {
+            panic!("expected changes");
+        };
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].number, changes[0].inserted, changes[0].deleted),
+            (1, 1, 1)
+        );
+        assert!(changes[0].diff.contains("-one\n+two\n"));
+        // Asking for more changes than any client could want is all of them.
+        let Reply::Changes { changes } = daemon.answer(&Request::Diffs {
+            file: None,
+            count: usize::MAX,
+        }) else {
+            panic!("expected changes");
+        };
+        assert_eq!(changes.len(), 1);
+
+        assert_eq!(
+            done(daemon.answer(&Request::Pause { file: None })),
+            format!("paused {shown}")
+        );
+        let Reply::Files { files } = daemon.answer(&Request::List) else {
+            panic!("expected files");
+        };
+        assert_eq!(
+            (files[0].versions, files[0].latest, files[0].paused),
+            (2, Some(1), true)
+        );
+
+        // Resuming catches up on what changed while paused.
+        fs::write(&path, "three\n").unwrap();
+        done(daemon.answer(&Request::Resume { file: None }));
+        let Reply::Files { files } = daemon.answer(&Request::List) else {
+            panic!("expected files");
+        };
+        assert_eq!((files[0].latest, files[0].paused), (Some(2), false));
+
+        let unknown = path.with_file_name("other.conf");
+        assert_eq!(
+            error(daemon.answer(&Request::Pause {
+                file: Some(unknown.clone())
+            })),
+            format!("{} isn't being watched", unknown.display())
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(error(daemon.answer(&Request::Snapshot { file: None })).starts_with("reading "));
+    }
+
+    #[test]
+    fn connections_are_served_until_the_client_hangs_up() {
+        let (daemon, _path, _dir) = daemon();
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (client, server) = UnixStream::pair().unwrap();
+            let serving = tokio::spawn(Arc::new(daemon).serve(server));
+            let (reader, mut writer) = client.into_split();
+            let mut replies = AsyncBufReader::new(reader).lines();
+
+            writer.write_all(b"not json\n").await.unwrap();
+            let reply = replies.next_line().await.unwrap().unwrap();
+            let reply: Reply = serde_json::from_str(&reply).unwrap();
+            assert!(error(reply).starts_with("invalid request: "));
+
+            writer.write_all(b"{\"command\":\"list\"}\n").await.unwrap();
+            let reply = replies.next_line().await.unwrap().unwrap();
+            let reply: Reply = serde_json::from_str(&reply).unwrap();
+            assert!(matches!(reply, Reply::Files { files } if files.len() == 1));
+
+            drop(writer);
+            serving.await.unwrap();
+            assert_eq!(replies.next_line().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn sockets_are_only_for_the_daemons_user_by_default() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let access = Access::new(&AccessArgs::default()).unwrap();
+            let _listener = bind(&socket, &access).unwrap();
+            let mode = fs::metadata(&socket).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            assert!(Client::connect(&socket).is_ok());
+            // Nothing is left of where it was bound.
+            let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+            assert_eq!(entries.len(), 1);
+        });
+    }
+
+    #[test]
+    fn connecting_without_a_daemon_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        let error = Client::connect(&socket).err().unwrap();
+        assert!(error.to_string().starts_with("no daemon is listening on "));
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+#[cfg(feature = "sqlite")]
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+
+/// The first bytes of every SQLite database file.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+pub fn is_database(bytes: &[u8]) -> bool {
+    bytes.starts_with(SQLITE_HEADER)
+}
+
+/// Dumps the SQLite database at `path` as text: its schema, then the rows of
+/// every table in `tables`, or of all of them if empty, so a change to the
+/// application state kept in it diffs as the statements and rows that
+/// changed.
+///
+/// The dump is canonical: schema entries are sorted by name and rows by
+/// their values, one per line as a SQL tuple, with newlines and backslashes
+/// in text escaped, so it only changes when the data does.
+#[cfg(feature = "sqlite")]
+pub fn dump(path: &Path, tables: &[String]) -> String {
+    dump_tables(path, tables)
+        .unwrap_or_else(|error| format!("unreadable SQLite database: {error}\n"))
+}
+
+#[cfg(feature = "sqlite")]
+fn dump_tables(path: &Path, tables: &[String]) -> rusqlite::Result<String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    // Reading the schema and every table in one transaction sees them as of
+    // a single commit.
+    conn.execute_batch("BEGIN")?;
+
+    let mut dump = String::from("-- schema\n");
+    let mut names = Vec::new();
+    let mut statement = conn.prepare(
+        "SELECT type, name, sql FROM sqlite_schema
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+         ORDER BY name",
+    )?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let (kind, name, sql): (String, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+        dump.push_str(&format!("{sql};\n"));
+        if kind == "table" && (tables.is_empty() || tables.contains(&name)) {
+            names.push(name);
+        }
+    }
+    drop(rows);
+
+    for name in names {
+        let quoted = format!("\"{}\"", name.replace('"', "\"\""));
+        let columns = conn
+            .prepare(&format!("SELECT * FROM {quoted}"))?
+            .column_count();
+        let order: Vec<String> = (1..=columns).map(|i| i.to_string()).collect();
+        let mut statement = conn.prepare(&format!(
+            "SELECT * FROM {quoted} ORDER BY {}",
+            order.join(", ")
+        ))?;
+        let mut lines = Vec::new();
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let values = (0..columns)
+                .map(|i| row.get_ref(i).map(literal))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            lines.push(format!("({})\n", values.join(", ")));
+        }
+        dump.push_str(&format!("-- {name}\n"));
+        dump.extend(lines);
+    }
+    conn.execute_batch("COMMIT")?;
+    Ok(dump)
+}
+
+/// `value` written as a SQL literal, on one line.
+#[cfg(feature = "sqlite")]
+fn literal(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(integer) => integer.to_string(),
+        ValueRef::Real(real) => format!("{real:?}"),
+        ValueRef::Text(text) => {
+            let text = String::from_utf8_lossy(text)
+                .replace('\\', "\\\\")
+                .replace('\n', "\\n")
+                .replace('\'', "''");
+            format!("'{text}'")
+        }
+        ValueRef::Blob(blob) => {
+            let hex: String = blob.iter().map(|byte| format!("{byte:02X}")).collect();
+            format!("X'{hex}'")
+        }
+    }
+}
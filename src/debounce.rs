@@ -0,0 +1,105 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::clock::Clock;
+
+/// Holds changes back until the file has been quiet for a whole window, as
+/// `--confirm-window` does.
+pub struct Debounce {
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    /// The latest change held back, if any.
+    last_change: Option<Instant>,
+}
+
+impl Debounce {
+    pub fn new(window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            window,
+            clock,
+            last_change: None,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Holds a change back, restarting the window.
+    pub fn change(&mut self) {
+        self.last_change = Some(self.clock.now());
+    }
+
+    /// When the window ends, if a change is held back.
+    pub fn due(&self) -> Option<Instant> {
+        Some(self.last_change? + self.window)
+    }
+
+    /// Whether a change is held back and the window has passed since.
+    pub fn settled(&self) -> bool {
+        self.due().is_some_and(|due| self.clock.now() >= due)
+    }
+
+    /// Lets the changes held back go.
+    pub fn release(&mut self) {
+        self.last_change = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    const WINDOW: Duration = Duration::from_secs(2);
+
+    fn debounce() -> (Debounce, MockClock) {
+        let clock = MockClock::new(UNIX_EPOCH);
+        (Debounce::new(WINDOW, Arc::new(clock.clone())), clock)
+    }
+
+    #[test]
+    fn nothing_is_due_without_changes() {
+        let (debounce, clock) = debounce();
+        clock.advance(WINDOW * 10);
+        assert_eq!(debounce.due(), None);
+        assert!(!debounce.settled());
+    }
+
+    #[test]
+    fn a_change_settles_after_the_window() {
+        let (mut debounce, clock) = debounce();
+        debounce.change();
+        assert_eq!(debounce.due(), Some(clock.now() + WINDOW));
+        clock.advance(WINDOW - Duration::from_millis(1));
+        assert!(!debounce.settled());
+        clock.advance(Duration::from_millis(1));
+        assert!(debounce.settled());
+    }
+
+    #[test]
+    fn another_change_restarts_the_window() {
+        let (mut debounce, clock) = debounce();
+        debounce.change();
+        clock.advance(WINDOW / 2);
+        debounce.change();
+        clock.advance(WINDOW / 2);
+        assert!(!debounce.settled());
+        clock.advance(WINDOW / 2);
+        assert!(debounce.settled());
+    }
+
+    #[test]
+    fn releasing_clears_what_was_held_back() {
+        let (mut debounce, clock) = debounce();
+        debounce.change();
+        clock.advance(WINDOW);
+        debounce.release();
+        assert!(!debounce.settled());
+        assert_eq!(debounce.due(), None);
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::Range;
+
+use similar::{DiffOp, TextDiff};
+
+/// A line based edit script that rebuilds one text from another.
+#[derive(Debug, Clone)]
+pub struct Delta {
+    ops: Vec<DeltaOp>,
+}
+
+#[derive(Debug, Clone)]
+enum DeltaOp {
+    /// Copy this byte range of the base.
+    Copy(Range<usize>),
+    /// Insert text that isn't in the base.
+    Insert(String),
+}
+
+impl Delta {
+    /// Computes the edits turning `base` into `target`.
+    pub fn encode(base: &str, target: &str) -> Self {
+        let diff = TextDiff::from_lines(base, target);
+        let old_offsets = offsets(diff.old_slices());
+        let new_offsets = offsets(diff.new_slices());
+
+        let mut ops = Vec::new();
+        for op in diff.ops() {
+            match *op {
+                DiffOp::Equal { old_index, len, .. } => {
+                    ops.push(DeltaOp::Copy(
+                        old_offsets[old_index]..old_offsets[old_index + len],
+                    ));
+                }
+                DiffOp::Delete { .. } => {}
+                DiffOp::Insert {
+                    new_index, new_len, ..
+                }
+                | DiffOp::Replace {
+                    new_index, new_len, ..
+                } => {
+                    let range = new_offsets[new_index]..new_offsets[new_index + new_len];
+                    ops.push(DeltaOp::Insert(target[range].to_string()));
+                }
+            }
+        }
+        Self { ops }
+    }
+
+    /// Rebuilds the target from the `base` this delta was encoded against.
+    pub fn apply(&self, base: &str) -> String {
+        let mut out = String::with_capacity(base.len());
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy(range) => out.push_str(&base[range.clone()]),
+                DeltaOp::Insert(text) => out.push_str(text),
+            }
+        }
+        out
+    }
+
+    /// Approximate bytes held by this delta.
+    pub fn size(&self) -> usize {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Copy(_) => std::mem::size_of::<DeltaOp>(),
+                DeltaOp::Insert(text) => std::mem::size_of::<DeltaOp>() + text.len(),
+            })
+            .sum()
+    }
+}
+
+/// Byte offset of the start of each line, plus the total length.
+fn offsets(lines: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(lines.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for line in lines {
+        offset += line.len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuilds_the_target() {
+        for (base, target) in [
+            ("a\nb\nc\n", "a\nB\nc\n"),
+            ("a\nb\nc\n", "a\nc\n"),
+            ("a\nc\n", "a\nb\nc\nd\n"),
+            ("", "new\n"),
+            ("old\n", ""),
+            ("no newline", "no newline\nat the end"),
+            ("héllo\nwörld\n", "héllo\nwørld\n"),
+        ] {
+            assert_eq!(Delta::encode(base, target).apply(base), target);
+        }
+    }
+
+    #[test]
+    fn only_holds_what_was_inserted() {
+        let base: String = (0..1000).map(|i| format!("line {i}\n")).collect();
+        let target = base.replace("line 500\n", "changed\n");
+        let delta = Delta::encode(&base, &target);
+        assert_eq!(delta.ops.len(), 3);
+        assert!(delta.size() < 4 * std::mem::size_of::<DeltaOp>() + "changed\n".len());
+    }
+}
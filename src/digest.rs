@@ -0,0 +1,94 @@
+use std::{fmt, str::FromStr};
+
+use sha2::{Digest as _, Sha256};
+
+/// Which algorithm content hashes are computed with, as given to `--hash`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum HashAlgorithm {
+    /// Fast, and what earlier versions always used.
+    #[default]
+    Blake3,
+    /// For setups that require a FIPS-approved hash.
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn digest(self, bytes: &[u8]) -> Digest {
+        let bytes = match self {
+            Self::Blake3 => *blake3::hash(bytes).as_bytes(),
+            Self::Sha256 => Sha256::digest(bytes).into(),
+        };
+        Digest {
+            algorithm: self,
+            bytes,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Blake3 => "blake3",
+            Self::Sha256 => "sha256",
+        }
+    }
+}
+
+/// A content hash and the algorithm that computed it.
+///
+/// Written out, blake3 hashes are plain hex as they were before the algorithm
+/// could be chosen, and others are prefixed by the algorithm's name, e.g.
+/// `sha256:9f86…`, so stored hashes can always be verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest {
+    algorithm: HashAlgorithm,
+    bytes: [u8; 32],
+}
+
+impl Digest {
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// The hash as hex, without the algorithm prefix.
+    pub fn to_hex(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Whether `bytes` hash to this digest.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        self.algorithm.digest(bytes) == *self
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.algorithm {
+            HashAlgorithm::Blake3 => f.write_str(&self.to_hex()),
+            algorithm => write!(f, "{}:{}", algorithm.name(), self.to_hex()),
+        }
+    }
+}
+
+impl FromStr for Digest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, hex) = match s.split_once(':') {
+            Some(("sha256", hex)) => (HashAlgorithm::Sha256, hex),
+            Some((name, _)) => return Err(format!("unknown hash algorithm `{name}`")),
+            None => (HashAlgorithm::Blake3, s),
+        };
+        let invalid = || format!("`{s}` isn't a valid {} hash", algorithm.name());
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self { algorithm, bytes })
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::history::VersionInfo;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `--report-at`: when the daemon sends its daily drift report.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ReportArgs {
+    /// Every day at this local time, e.g. `06:00`, send each file's drift
+    /// through the --sinks: the net change since --report-against
+    #[clap(long, requires = "sink")]
+    pub report_at: Option<TimeOfDay>,
+
+    /// What the daily drift report compares each file against
+    #[clap(long, value_enum, default_value_t, requires = "report_at")]
+    pub report_against: DriftBase,
+}
+
+/// What a drift report shows each file's change since.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DriftBase {
+    /// The file as it was a day before the report
+    #[default]
+    Day,
+    /// The file as it was when the daemon started watching it
+    Start,
+}
+
+impl DriftBase {
+    /// Which of the versions described by `infos`, oldest first, a report
+    /// made `at` compares against: the latest one captured at least a day
+    /// before, or the oldest kept if none is that old.
+    pub fn pick<'a>(
+        self,
+        mut infos: impl Iterator<Item = VersionInfo<'a>>,
+        at: SystemTime,
+    ) -> Option<usize> {
+        match self {
+            Self::Start => infos.next().map(|_| 0),
+            Self::Day => {
+                let since = at.checked_sub(DAY).unwrap_or(UNIX_EPOCH);
+                let mut picked = None;
+                for (index, info) in infos.enumerate() {
+                    if picked.is_some() && info.at > since {
+                        break;
+                    }
+                    picked = Some(index);
+                }
+                picked
+            }
+        }
+    }
+}
+
+/// A time of day as `HH:MM`, in the local time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOfDay {
+    hour: u32,
+    minute: u32,
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time `{s}`, expected HH:MM such as 06:00");
+        let (hour, minute) = s.split_once(':').ok_or_else(invalid)?;
+        let hour: u32 = hour.parse().map_err(|_| invalid())?;
+        let minute: u32 = minute.parse().map_err(|_| invalid())?;
+        if hour > 23 || minute > 59 {
+            return Err(invalid());
+        }
+        Ok(Self { hour, minute })
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl TimeOfDay {
+    /// The first time after `now` that the local clock shows this time.
+    pub fn next_after(&self, now: SystemTime) -> SystemTime {
+        self.next_in(now, local_offset)
+    }
+
+    /// [`Self::next_after`] in the time zone that is `offset(at)` seconds
+    /// ahead of UTC at `at`.
+    fn next_in(&self, now: SystemTime, offset: impl Fn(SystemTime) -> i64) -> SystemTime {
+        let day = DAY.as_secs() as i64;
+        let utc = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        let local = utc + offset(now);
+        let wanted = i64::from(self.hour * 3600 + self.minute * 60);
+        let mut next = local - local.rem_euclid(day) + wanted;
+        if next <= local {
+            next += day;
+        }
+        let at = |secs: i64| UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64);
+        // Taking the offset then rather than now, in case daylight saving
+        // starts or ends in between.
+        at(next - offset(at(next - offset(now))))
+    }
+}
+
+/// How many seconds the local time zone is ahead of UTC at `at`.
+fn local_offset(at: SystemTime) -> i64 {
+    let secs = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as libc::time_t;
+    // SAFETY: `localtime_r` only writes to `tm`, which is plain data.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        match libc::localtime_r(&secs, &mut tm).is_null() {
+            true => 0,
+            false => tm.tm_gmtoff as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{digest::HashAlgorithm, history::VersionMeta};
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn parses_times_of_day() {
+        assert_eq!("06:00".parse(), Ok(TimeOfDay { hour: 6, minute: 0 }));
+        assert_eq!("23:59".parse::<TimeOfDay>().unwrap().to_string(), "23:59");
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("6".parse::<TimeOfDay>().is_err());
+    }
+
+    #[test]
+    fn next_is_later_today_or_tomorrow() {
+        let six: TimeOfDay = "06:00".parse().unwrap();
+        let utc = |_| 0;
+        assert_eq!(six.next_in(at(3600), utc), at(6 * 3600));
+        assert_eq!(six.next_in(at(6 * 3600), utc), at(30 * 3600));
+        // Two hours ahead of UTC, 06:00 local is 04:00 UTC.
+        assert_eq!(six.next_in(at(3600), |_| 7200), at(4 * 3600));
+    }
+
+    #[test]
+    fn compares_against_the_version_a_day_old() {
+        let meta = VersionMeta::default();
+        let hash = HashAlgorithm::default().digest(b"");
+        let infos = |times: &[u64]| {
+            times
+                .iter()
+                .enumerate()
+                .map(|(number, &seconds)| VersionInfo {
+                    hash,
+                    at: at(seconds),
+                    number,
+                    size: 0,
+                    meta: &meta,
+                })
+                .collect::<Vec<_>>()
+        };
+        let now = at(DAY.as_secs() + 100);
+        let versions = infos(&[0, 50, 200, 300]);
+        assert_eq!(DriftBase::Day.pick(versions.into_iter(), now), Some(1));
+        let versions = infos(&[0, 50, 200, 300]);
+        assert_eq!(DriftBase::Start.pick(versions.into_iter(), now), Some(0));
+        // Nothing that old is kept, so the oldest there is.
+        assert_eq!(
+            DriftBase::Day.pick(infos(&[500, 600]).into_iter(), now),
+            Some(0)
+        );
+        assert_eq!(DriftBase::Day.pick(infos(&[]).into_iter(), now), None);
+    }
+}
@@ -0,0 +1,62 @@
+use std::{io, str::FromStr};
+
+/// A character encoding given to `--encoding`, e.g. `latin1` or `utf-16le`.
+#[cfg(feature = "encoding")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEncoding(&'static encoding_rs::Encoding);
+
+#[cfg(feature = "encoding")]
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        encoding_rs::Encoding::for_label(s.as_bytes())
+            .map(Self)
+            .ok_or_else(|| format!("unknown encoding `{s}`"))
+    }
+}
+
+/// Decodes a file's bytes to text in `encoding`, or else the one its byte
+/// order mark names, UTF-8 if it is valid UTF-8, or failing all that the
+/// most likely legacy encoding. A byte order mark is dropped.
+#[cfg(feature = "encoding")]
+pub fn decode(bytes: Vec<u8>, encoding: Option<TextEncoding>) -> io::Result<String> {
+    let encoding = match encoding {
+        Some(TextEncoding(encoding)) => encoding,
+        None => match encoding_rs::Encoding::for_bom(&bytes) {
+            Some((encoding, _)) => encoding,
+            None => match String::from_utf8(bytes) {
+                Ok(text) => return Ok(text),
+                Err(error) => {
+                    let bytes = error.into_bytes();
+                    let mut detector = chardetng::EncodingDetector::new();
+                    detector.feed(&bytes, true);
+                    let encoding = detector.guess(None, true);
+                    return Ok(encoding.decode(&bytes).0.into_owned());
+                }
+            },
+        },
+    };
+    // A byte order mark overrides the given encoding.
+    Ok(encoding.decode(&bytes).0.into_owned())
+}
+
+/// Stand-in for builds without the `encoding` feature, where only UTF-8
+/// files can be read.
+#[cfg(not(feature = "encoding"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEncoding(std::convert::Infallible);
+
+#[cfg(not(feature = "encoding"))]
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(_s: &str) -> Result<Self, Self::Err> {
+        Err("slip-diff was built without the `encoding` feature".to_string())
+    }
+}
+
+#[cfg(not(feature = "encoding"))]
+pub fn decode(bytes: Vec<u8>, _encoding: Option<TextEncoding>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
@@ -0,0 +1,69 @@
+use std::{borrow::Cow, fmt};
+
+/// Which line endings a text uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEndings {
+    /// No line breaks at all.
+    None,
+    Lf,
+    Crlf,
+    /// Both, line by line.
+    Mixed,
+}
+
+impl LineEndings {
+    pub fn detect(text: &str) -> Self {
+        let lines = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        match (lines, crlf) {
+            (0, _) => Self::None,
+            (_, 0) => Self::Lf,
+            (lines, crlf) if lines == crlf => Self::Crlf,
+            _ => Self::Mixed,
+        }
+    }
+}
+
+impl fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Lf => "LF",
+            Self::Crlf => "CRLF",
+            Self::Mixed => "mixed",
+        })
+    }
+}
+
+/// `text` with every CRLF turned into LF, so files saved by editors that
+/// flip line endings diff only by what was actually edited.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    match text.contains("\r\n") {
+        true => Cow::Owned(text.replace("\r\n", "\n")),
+        false => Cow::Borrowed(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_endings_are_detected() {
+        assert_eq!(LineEndings::detect("no break"), LineEndings::None);
+        assert_eq!(LineEndings::detect("a\nb\n"), LineEndings::Lf);
+        assert_eq!(LineEndings::detect("a\r\nb\r\n"), LineEndings::Crlf);
+        assert_eq!(LineEndings::detect("a\r\nb\n"), LineEndings::Mixed);
+        // A lone carriage return doesn't end a line.
+        assert_eq!(LineEndings::detect("a\rb\n"), LineEndings::Lf);
+        assert_eq!(LineEndings::Crlf.to_string(), "CRLF");
+    }
+
+    #[test]
+    fn crlf_is_normalized_to_lf() {
+        assert!(matches!(normalize("a\nb\n"), Cow::Borrowed("a\nb\n")));
+        assert_eq!(normalize("a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(normalize("a\r\nb\nc\r"), "a\nb\nc\r");
+        assert_eq!(LineEndings::detect(&normalize("a\r\nb\n")), LineEndings::Lf);
+    }
+}
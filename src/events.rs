@@ -0,0 +1,142 @@
+use std::{collections::VecDeque, sync::Arc, time::SystemTime};
+
+use notify::{Config, Event, RecommendedWatcher, Watcher};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::clock::{self, Clock};
+
+/// A file watcher delivering its events on a channel that can be awaited,
+/// e.g. in a `tokio::select!` alongside keyboard input and timers. Files
+/// to watch are added through the returned watcher.
+pub fn watcher() -> notify::Result<(RecommendedWatcher, UnboundedReceiver<notify::Result<Event>>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    // notify calls this on its own thread; the receiver is only gone once
+    // watching has ended.
+    let watcher = RecommendedWatcher::new(
+        move |event| {
+            let _ = tx.send(event);
+        },
+        Config::default(),
+    )?;
+    Ok((watcher, rx))
+}
+
+/// How many entries an [`EventLog`] keeps before dropping the oldest.
+const LOG_CAPACITY: usize = 500;
+
+/// What happened while watching: raw events, what was made of them and what
+/// went wrong, so that when nothing seems to happen it can be told whether
+/// events aren't arriving or are being filtered out.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    entries: VecDeque<LogEntry>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            clock: clock::system(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub at: SystemTime,
+    pub kind: LogKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogKind {
+    /// An event from the OS or a poll tick.
+    Event,
+    /// A new version was recorded.
+    Version,
+    /// A change that wasn't recorded, e.g. contents identical to the latest
+    /// version or suppressed by a hook.
+    Suppressed,
+    /// Changes gathered or held back until things settle.
+    Debounce,
+    Error,
+}
+
+impl LogKind {
+    /// Whether entries of this kind are traced at the level tracing is on:
+    /// errors as warnings, what was made of changes as info and raw events
+    /// as debug.
+    pub fn traced(self) -> bool {
+        match self {
+            Self::Error => tracing::enabled!(tracing::Level::WARN),
+            Self::Version | Self::Suppressed => tracing::enabled!(tracing::Level::INFO),
+            Self::Event | Self::Debounce => tracing::enabled!(tracing::Level::DEBUG),
+        }
+    }
+
+    pub fn trace(self, message: &str) {
+        match self {
+            Self::Error => tracing::warn!("{message}"),
+            Self::Version | Self::Suppressed => tracing::info!("{message}"),
+            Self::Event | Self::Debounce => tracing::debug!("{message}"),
+        }
+    }
+}
+
+impl EventLog {
+    /// Timestamps entries by `clock` rather than the system's.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn push(&mut self, kind: LogKind, message: impl Into<String>) {
+        if self.entries.len() == LOG_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry {
+            at: self.clock.system_now(),
+            kind,
+            message: message.into(),
+        });
+    }
+
+    /// Moves the entries of `other` to the end of this log.
+    pub fn append(&mut self, other: &mut EventLog) {
+        for entry in other.entries.drain(..) {
+            if self.entries.len() == LOG_CAPACITY {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+        }
+    }
+
+    /// The entries, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn entries_are_timestamped_by_the_clock_and_capped() {
+        let clock = MockClock::new(UNIX_EPOCH);
+        let mut log = EventLog::default().with_clock(Arc::new(clock.clone()));
+        log.push(LogKind::Event, "modified");
+        clock.advance(Duration::from_secs(2));
+        for number in 0..LOG_CAPACITY {
+            log.push(LogKind::Version, format!("recorded version {number}"));
+        }
+        assert_eq!(log.iter().count(), LOG_CAPACITY);
+        let first = log.iter().next().unwrap();
+        assert_eq!(first.message, "recorded version 0");
+        assert_eq!(first.at, UNIX_EPOCH + Duration::from_secs(2));
+    }
+}
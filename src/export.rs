@@ -0,0 +1,61 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{listing, version_store::VersionStore};
+
+/// Writes every stored version of `file` into `dir` as `name.0001`,
+/// `name.0002`, … oldest first, along with a `manifest.tsv` listing each
+/// file's version number, capture time and hash, in a column named after the
+/// history's hash algorithm. Versions recorded with `--listing` also get
+/// their directory listings in `listings.html`, one collapsed section each.
+///
+/// Returns how many versions were written.
+pub fn export(history: &dyn VersionStore, file: &Path, dir: &Path) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "version".to_string());
+
+    let mut manifest = fs::File::create(dir.join("manifest.tsv"))?;
+    writeln!(
+        manifest,
+        "file\tnumber\ttimestamp\t{}",
+        history.algorithm().name()
+    )?;
+    let mut listings = Vec::new();
+    for (i, version) in history.iter().enumerate() {
+        let version = version?;
+        if let Some(listing) = &version.meta.listing {
+            listings.push((version.number, version.at, listing.clone()));
+        }
+        let file_name = format!("{name}.{:04}", i + 1);
+        fs::write(dir.join(&file_name), version.contents.as_bytes())?;
+        writeln!(
+            manifest,
+            "{file_name}\t{}\t{}\t{}",
+            version.number,
+            humantime::format_rfc3339_millis(version.at),
+            version.hash.to_hex()
+        )?;
+    }
+    if !listings.is_empty() {
+        let title = format!("Directory of {} at each version", file.display());
+        let listings = listings
+            .iter()
+            .map(|(number, at, listing)| (*number, *at, listing.as_slice()));
+        fs::write(dir.join("listings.html"), listing::html(&title, listings))?;
+    }
+    Ok(history.len())
+}
+
+/// Where to export to when no directory was given: `<file>.versions` next to
+/// the watched file.
+pub fn default_dir(file: &Path) -> PathBuf {
+    let mut dir = file.as_os_str().to_owned();
+    dir.push(".versions");
+    dir.into()
+}
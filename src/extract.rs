@@ -0,0 +1,131 @@
+#[cfg(feature = "docx")]
+use std::io::{Cursor, Read};
+
+/// Which documents `--extract` diffs the text of rather than their bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Extract {
+    /// PDF and Word documents, recognised by their contents
+    #[default]
+    Auto,
+    /// Treat the file as a PDF document
+    Pdf,
+    /// Treat the file as a Word document
+    Docx,
+    /// Read documents like any other file
+    Off,
+}
+
+/// The document formats text can be extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Document {
+    Pdf,
+    Docx,
+}
+
+impl Extract {
+    /// Whether `bytes` are a document this extracts from, which for `Auto`
+    /// only looks at their first bytes.
+    pub fn handles(self, bytes: &[u8]) -> bool {
+        match self {
+            Self::Auto => recognise(bytes).is_some(),
+            Self::Pdf | Self::Docx => true,
+            Self::Off => false,
+        }
+    }
+
+    /// The text of `bytes` if they are a document this extracts from. A
+    /// document too broken to read, e.g. one still being written, is
+    /// captured as the reason why, so the next complete version still diffs
+    /// against the last readable one's text in the history.
+    pub fn text(self, bytes: &[u8]) -> Option<String> {
+        let document = match self {
+            Self::Auto => recognise(bytes)?,
+            Self::Pdf => Document::Pdf,
+            Self::Docx => Document::Docx,
+            Self::Off => return None,
+        };
+        let (name, text) = match document {
+            Document::Pdf => ("PDF", pdf_text(bytes)),
+            Document::Docx => ("Word document", docx_text(bytes)),
+        };
+        Some(text.unwrap_or_else(|error| format!("unreadable {name}: {error}\n")))
+    }
+}
+
+fn recognise(bytes: &[u8]) -> Option<Document> {
+    if bytes.starts_with(b"%PDF-") {
+        return Some(Document::Pdf);
+    }
+    // A Word document is a zip archive, which names its parts in the clear.
+    let is_zip = bytes.starts_with(b"PK\x03\x04");
+    let has_body = bytes
+        .windows(DOCX_BODY.len())
+        .any(|window| window == DOCX_BODY.as_bytes());
+    (is_zip && has_body).then_some(Document::Docx)
+}
+
+/// The part of a Word document holding its body text.
+const DOCX_BODY: &str = "word/document.xml";
+
+#[cfg(feature = "pdf")]
+fn pdf_text(bytes: &[u8]) -> Result<String, String> {
+    // pdf-extract panics on some malformed documents rather than failing.
+    std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+        .map_err(|_| "the PDF parser gave up on it".to_string())?
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(not(feature = "pdf"))]
+fn pdf_text(_bytes: &[u8]) -> Result<String, String> {
+    Err("slip-diff was built without the `pdf` feature".to_string())
+}
+
+/// The paragraphs of a Word document, one per line, with tabs and line
+/// breaks kept.
+#[cfg(feature = "docx")]
+fn docx_text(bytes: &[u8]) -> Result<String, String> {
+    use quick_xml::{escape::resolve_predefined_entity, events::Event, Reader};
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let mut xml = String::new();
+    archive
+        .by_name(DOCX_BODY)
+        .map_err(|e| e.to_string())?
+        .read_to_string(&mut xml)
+        .map_err(|e| e.to_string())?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut text = String::new();
+    // Only `w:t` elements hold text; the rest is layout and formatting.
+    let mut in_text = false;
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) if e.name().as_ref() == "w:t" => in_text = true,
+            Event::End(e) => match e.name().as_ref() {
+                "w:t" => in_text = false,
+                "w:p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Empty(e) => match e.name().as_ref() {
+                "w:tab" => text.push('\t'),
+                "w:br" | "w:cr" => text.push('\n'),
+                "w:p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Text(e) if in_text => text.push_str(&e.xml10_content()),
+            Event::GeneralRef(e) if in_text => {
+                match e.resolve_char_ref().map_err(|e| e.to_string())? {
+                    Some(c) => text.push(c),
+                    None => text.push_str(resolve_predefined_entity(&e).unwrap_or_default()),
+                }
+            }
+            Event::Eof => return Ok(text),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(not(feature = "docx"))]
+fn docx_text(_bytes: &[u8]) -> Result<String, String> {
+    Err("slip-diff was built without the `docx` feature".to_string())
+}
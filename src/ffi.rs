@@ -0,0 +1,287 @@
+//! C bindings for embedding the watcher and renderers in editors and other
+//! non-Rust tools, built into the `cdylib`. See `include/slip_diff.h`.
+//!
+//! Strings passed in are NUL-terminated UTF-8. Strings passed out are owned
+//! by the caller and freed with [`slip_diff_string_free`].
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+    time::Duration,
+};
+
+use tokio::{
+    runtime::{self, Runtime},
+    sync::mpsc::UnboundedReceiver,
+};
+
+use crate::{
+    capture::Capture,
+    poll::PollArgs,
+    render::{RenderOptions, Renderers},
+    watcher::{ChangeEvent, Watcher},
+};
+
+pub const SLIP_DIFF_EVENT_VERSION: c_int = 1;
+pub const SLIP_DIFF_EVENT_WATCH_LOST: c_int = 2;
+pub const SLIP_DIFF_EVENT_FILE_ROTATED: c_int = 3;
+pub const SLIP_DIFF_EVENT_ERROR: c_int = 4;
+
+/// A watched file, opened with [`slip_diff_watch_open`].
+pub struct SlipDiffWatch {
+    runtime: Runtime,
+    events: UnboundedReceiver<ChangeEvent>,
+}
+
+/// An event filled in by [`slip_diff_next_event`].
+#[repr(C)]
+pub struct SlipDiffEvent {
+    /// One of the `SLIP_DIFF_EVENT_*` constants.
+    pub kind: c_int,
+    /// The version's number, for `SLIP_DIFF_EVENT_VERSION`.
+    pub number: usize,
+    /// The version's contents, or the error's message; NULL for the other
+    /// kinds. Contents with NUL bytes are cut at the first.
+    pub text: *mut c_char,
+}
+
+/// Starts watching the file at `path`, which is captured as the `slip-diff`
+/// command would by default. Returns NULL if `path` isn't valid UTF-8 or
+/// can't be watched.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn slip_diff_watch_open(path: *const c_char) -> *mut SlipDiffWatch {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return ptr::null_mut();
+    };
+    let Ok(runtime) = runtime::Builder::new_current_thread().enable_all().build() else {
+        return ptr::null_mut();
+    };
+    // The watcher's timers and the subscription's task belong to the
+    // runtime, which only runs while an event is waited for.
+    let events = {
+        let _runtime = runtime.enter();
+        match Watcher::new(path.as_ref(), Capture::default(), &PollArgs::default()) {
+            Ok(watcher) => watcher.subscribe(),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+    Box::into_raw(Box::new(SlipDiffWatch { runtime, events }))
+}
+
+/// Waits up to `timeout_ms` milliseconds, or for ever if it is negative, for
+/// the next event about the watched file and fills in `event`. The first
+/// event is the file's current version.
+///
+/// Returns 1 if `event` was filled in, 0 on timeout, -1 if the arguments are
+/// NULL and -2 once the watcher has stopped and no more events will come.
+///
+/// # Safety
+///
+/// `watch` must come from [`slip_diff_watch_open`] and not have been closed,
+/// and `event` must point to writable memory for a `SlipDiffEvent`.
+#[no_mangle]
+pub unsafe extern "C" fn slip_diff_next_event(
+    watch: *mut SlipDiffWatch,
+    timeout_ms: i64,
+    event: *mut SlipDiffEvent,
+) -> c_int {
+    let (Some(watch), false) = (watch.as_mut(), event.is_null()) else {
+        return -1;
+    };
+    let next = watch.runtime.block_on(async {
+        match u64::try_from(timeout_ms) {
+            Ok(ms) => tokio::time::timeout(Duration::from_millis(ms), watch.events.recv())
+                .await
+                .ok(),
+            Err(_) => Some(watch.events.recv().await),
+        }
+    });
+    let next = match next {
+        Some(Some(next)) => next,
+        Some(None) => return -2,
+        None => return 0,
+    };
+    let (kind, number, text) = match next {
+        ChangeEvent::VersionCaptured(version) => (
+            SLIP_DIFF_EVENT_VERSION,
+            version.number,
+            Some(version.contents.to_string()),
+        ),
+        ChangeEvent::WatchLost => (SLIP_DIFF_EVENT_WATCH_LOST, 0, None),
+        ChangeEvent::FileRotated => (SLIP_DIFF_EVENT_FILE_ROTATED, 0, None),
+        ChangeEvent::Error(error) => (SLIP_DIFF_EVENT_ERROR, 0, Some(error.to_string())),
+    };
+    event.write(SlipDiffEvent {
+        kind,
+        number,
+        text: text.map_or(ptr::null_mut(), into_c_string),
+    });
+    1
+}
+
+/// Stops watching and frees `watch`. NULL is ignored.
+///
+/// # Safety
+///
+/// `watch` must come from [`slip_diff_watch_open`] and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn slip_diff_watch_close(watch: *mut SlipDiffWatch) {
+    if !watch.is_null() {
+        drop(Box::from_raw(watch));
+    }
+}
+
+/// Renders the change from `old` to `new` with the renderer `--renderer`
+/// calls `renderer`, or as a plain unified diff if it is NULL. Returns NULL
+/// if there is no such renderer, the arguments aren't valid UTF-8 or
+/// rendering failed.
+///
+/// # Safety
+///
+/// `old` and `new`, and `renderer` unless NULL, must be valid NUL-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn slip_diff_render(
+    old: *const c_char,
+    new: *const c_char,
+    renderer: *const c_char,
+) -> *mut c_char {
+    if old.is_null() || new.is_null() {
+        return ptr::null_mut();
+    }
+    let (Ok(old), Ok(new)) = (CStr::from_ptr(old).to_str(), CStr::from_ptr(new).to_str()) else {
+        return ptr::null_mut();
+    };
+    let name = match renderer.is_null() {
+        true => Ok("unified"),
+        false => CStr::from_ptr(renderer).to_str(),
+    };
+    let Some(renderer) = name.ok().and_then(|name| Renderers::default().get(name)) else {
+        return ptr::null_mut();
+    };
+    match renderer.render(old, new, &RenderOptions::default()) {
+        Ok(rendered) => into_c_string(rendered),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a string returned by these functions. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must come from this library and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn slip_diff_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    let mut bytes = s.into_bytes();
+    if let Some(nul) = bytes.iter().position(|&b| b == 0) {
+        bytes.truncate(nul);
+    }
+    CString::new(bytes).unwrap_or_default().into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, ptr::null};
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn next_event(watch: &mut SlipDiffWatch, timeout_ms: i64) -> (c_int, SlipDiffEvent) {
+        let mut event = SlipDiffEvent {
+            kind: 0,
+            number: 0,
+            text: ptr::null_mut(),
+        };
+        let status = unsafe { slip_diff_next_event(watch, timeout_ms, &mut event) };
+        (status, event)
+    }
+
+    fn take_text(event: SlipDiffEvent) -> Option<String> {
+        (!event.text.is_null()).then(|| unsafe {
+            let text = CStr::from_ptr(event.text).to_str().unwrap().to_string();
+            slip_diff_string_free(event.text);
+            text
+        })
+    }
+
+    #[test]
+    fn events_are_reported_until_the_watcher_stops() {
+        let (sender, events) = mpsc::unbounded_channel();
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut watch = SlipDiffWatch { runtime, events };
+
+        assert_eq!(next_event(&mut watch, 0).0, 0);
+        sender.send(ChangeEvent::WatchLost).unwrap();
+        sender
+            .send(ChangeEvent::Error(io::Error::other("gone")))
+            .unwrap();
+        let (status, event) = next_event(&mut watch, -1);
+        assert_eq!((status, event.kind), (1, SLIP_DIFF_EVENT_WATCH_LOST));
+        assert_eq!(take_text(event), None);
+        let (status, event) = next_event(&mut watch, 0);
+        assert_eq!((status, event.kind), (1, SLIP_DIFF_EVENT_ERROR));
+        assert_eq!(take_text(event).as_deref(), Some("gone"));
+
+        drop(sender);
+        assert_eq!(next_event(&mut watch, 0).0, -2);
+        assert_eq!(next_event(&mut watch, -1).0, -2);
+        assert_eq!(
+            unsafe { slip_diff_next_event(&mut watch, 0, ptr::null_mut()) },
+            -1
+        );
+    }
+
+    #[test]
+    fn the_first_event_is_the_current_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("watched.txt");
+        std::fs::write(&path, "first\n").unwrap();
+        let path = CString::new(path.to_str().unwrap()).unwrap();
+        let watch = unsafe { slip_diff_watch_open(path.as_ptr()) };
+        assert!(!watch.is_null());
+
+        let (status, event) = next_event(unsafe { &mut *watch }, 5000);
+        assert_eq!(
+            (status, event.kind, event.number),
+            (1, SLIP_DIFF_EVENT_VERSION, 0)
+        );
+        assert_eq!(take_text(event).as_deref(), Some("first\n"));
+        unsafe { slip_diff_watch_close(watch) };
+        assert!(unsafe { slip_diff_watch_open(null()) }.is_null());
+    }
+
+    #[test]
+    fn changes_are_rendered_by_the_named_renderer() {
+        let (old, new) = (c"a\nb\n", c"a\nc\n");
+        let rendered = unsafe { slip_diff_render(old.as_ptr(), new.as_ptr(), null()) };
+        let rendered = take_text(SlipDiffEvent {
+            kind: 0,
+            number: 0,
+            text: rendered,
+        });
+        assert_eq!(
+            rendered.as_deref(),
+            Some("--- old\n+++ new\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n")
+        );
+        let unknown = unsafe { slip_diff_render(old.as_ptr(), new.as_ptr(), c"nope".as_ptr()) };
+        assert!(unknown.is_null());
+        assert!(unsafe { slip_diff_render(null(), new.as_ptr(), null()) }.is_null());
+    }
+}
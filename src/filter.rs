@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+use similar::{DiffOp, TextDiff};
+
+/// Picks the hunks of a diff worth showing by the lines they add or
+/// remove, as `--only` and `--ignore` do: a hunk is kept if one of its
+/// changed lines matches an `only` pattern, or there are none, and none of
+/// them matches an `ignore` pattern.
+#[derive(Debug, Clone, Default)]
+pub struct HunkFilter {
+    pub only: Vec<Regex>,
+    pub ignore: Vec<Regex>,
+}
+
+impl HunkFilter {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.ignore.is_empty()
+    }
+
+    /// `new` with only the kept hunks applied to `old`, so that the two
+    /// diff as the kept hunks alone, or `None` if no hunk was kept. Hunks
+    /// are split as they are shown with `context` lines around them.
+    pub fn apply(&self, old: &str, new: &str, context: usize) -> Option<String> {
+        let diff = TextDiff::from_lines(old, new);
+        let (old_lines, new_lines) = (diff.old_slices(), diff.new_slices());
+        let changed = |op: &DiffOp| {
+            let removed = old_lines[op.old_range()].iter();
+            let added = new_lines[op.new_range()].iter();
+            removed
+                .chain(added)
+                .map(|line| line.trim_end_matches(['\n', '\r']))
+        };
+        let kept: HashSet<(usize, usize)> = diff
+            .grouped_ops(context)
+            .iter()
+            .filter(|hunk| {
+                let changed = || {
+                    hunk.iter()
+                        .filter(|op| !matches!(op, DiffOp::Equal { .. }))
+                        .flat_map(changed)
+                };
+                let wanted = self.only.is_empty()
+                    || changed().any(|line| self.only.iter().any(|only| only.is_match(line)));
+                wanted
+                    && !changed().any(|line| self.ignore.iter().any(|ignore| ignore.is_match(line)))
+            })
+            .flatten()
+            .map(|op| (op.old_range().start, op.new_range().start))
+            .collect();
+        if kept.is_empty() {
+            return None;
+        }
+        let mut filtered = String::with_capacity(new.len());
+        for op in diff.ops() {
+            let lines = match op {
+                DiffOp::Equal { .. } => &old_lines[op.old_range()],
+                _ if kept.contains(&(op.old_range().start, op.new_range().start)) => {
+                    &new_lines[op.new_range()]
+                }
+                _ => &old_lines[op.old_range()],
+            };
+            filtered.extend(lines.iter().copied());
+        }
+        Some(filtered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::CONTEXT;
+
+    fn filter(only: &[&str], ignore: &[&str]) -> HunkFilter {
+        let regexes = |patterns: &[&str]| patterns.iter().map(|p| Regex::new(p).unwrap()).collect();
+        HunkFilter {
+            only: regexes(only),
+            ignore: regexes(ignore),
+        }
+    }
+
+    /// Two hunks, far enough apart not to be merged.
+    fn texts() -> (String, String) {
+        let middle: String = (0..10).map(|i| format!("same {i}\n")).collect();
+        let old = format!("timeout = 5\n{middle}host = a\n");
+        let new = format!("timeout = 10\n{middle}host = b\n");
+        (old, new)
+    }
+
+    #[test]
+    fn keeps_hunks_matching_only() {
+        let (old, new) = texts();
+        let filtered = filter(&["timeout"], &[])
+            .apply(&old, &new, CONTEXT)
+            .unwrap();
+        assert!(filtered.starts_with("timeout = 10\n"));
+        assert!(filtered.ends_with("host = a\n"));
+    }
+
+    #[test]
+    fn drops_hunks_matching_ignore() {
+        let (old, new) = texts();
+        let filtered = filter(&[], &["timeout"])
+            .apply(&old, &new, CONTEXT)
+            .unwrap();
+        assert!(filtered.starts_with("timeout = 5\n"));
+        assert!(filtered.ends_with("host = b\n"));
+        assert_eq!(filter(&["host"], &["b$"]).apply(&old, &new, CONTEXT), None);
+    }
+}
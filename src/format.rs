@@ -0,0 +1,115 @@
+use std::{env, time::SystemTime};
+
+/// How numbers, sizes and times are written in text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Grouped digits and binary-prefixed sizes following the locale, e.g.
+    /// `3,421` or `1.2 MiB`.
+    Human(NumberStyle),
+    /// Plain digits, byte counts and RFC 3339 UTC timestamps that don't
+    /// change with the locale, for scripts.
+    Porcelain,
+}
+
+/// Separators used for digits in the current locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberStyle {
+    pub group: Option<char>,
+    pub decimal: char,
+}
+
+impl NumberStyle {
+    /// Picks separators from `LC_ALL`, `LC_NUMERIC` or `LANG`, in that order.
+    /// The C/POSIX locale, or none at all, doesn't group digits.
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Self::for_locale(&locale)
+    }
+
+    pub fn for_locale(locale: &str) -> Self {
+        let language = locale
+            .split(['_', '.', '@', '-'])
+            .next()
+            .unwrap_or_default();
+        match language {
+            "" | "C" | "POSIX" => Self {
+                group: None,
+                decimal: '.',
+            },
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "da" | "tr" | "el" | "ro" => Self {
+                group: Some('.'),
+                decimal: ',',
+            },
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" | "bg" => {
+                Self {
+                    group: Some('\u{202f}'),
+                    decimal: ',',
+                }
+            }
+            _ => Self {
+                group: Some(','),
+                decimal: '.',
+            },
+        }
+    }
+}
+
+impl Format {
+    /// Porcelain if requested, otherwise human output in the user's locale.
+    pub fn new(porcelain: bool) -> Self {
+        if porcelain {
+            Self::Porcelain
+        } else {
+            Self::Human(NumberStyle::from_env())
+        }
+    }
+
+    pub fn count(&self, n: usize) -> String {
+        let digits = n.to_string();
+        let Self::Human(NumberStyle {
+            group: Some(separator),
+            ..
+        }) = self
+        else {
+            return digits;
+        };
+        let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(*separator);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    /// A byte count, e.g. `1.2 MiB` for humans and `1258291` for porcelain.
+    pub fn size(&self, bytes: usize) -> String {
+        let Self::Human(style) = self else {
+            return bytes.to_string();
+        };
+        const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+        if bytes < 1024 {
+            return format!("{} B", self.count(bytes));
+        }
+        let mut value = bytes as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit + 1 < UNITS.len() {
+            value /= 1024.0;
+            unit += 1;
+        }
+        let number = format!("{value:.1}").replace('.', &style.decimal.to_string());
+        format!("{number} {}", UNITS[unit])
+    }
+
+    pub fn timestamp(&self, at: SystemTime) -> String {
+        match self {
+            Self::Human(_) => humantime::format_rfc3339_seconds(at).to_string(),
+            Self::Porcelain => humantime::format_rfc3339_millis(at).to_string(),
+        }
+    }
+}
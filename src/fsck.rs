@@ -0,0 +1,28 @@
+use std::fmt;
+
+/// Something `slip-diff fsck` found wrong with a cache, store or journal.
+#[derive(Debug)]
+pub struct Problem {
+    pub description: String,
+    /// Whether `--repair` fixed it.
+    pub repaired: bool,
+}
+
+impl Problem {
+    pub fn new(description: impl Into<String>, repaired: bool) -> Self {
+        Self {
+            description: description.into(),
+            repaired,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description)?;
+        if self.repaired {
+            f.write_str(" (repaired)")?;
+        }
+        Ok(())
+    }
+}
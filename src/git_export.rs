@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::UNIX_EPOCH,
+};
+
+use crate::{history::Version, stats::LineStats};
+
+/// A git repository in which every captured version becomes a commit, so a
+/// session can be explored later with `git log -p`, bisect and blame.
+pub struct GitExport {
+    dir: PathBuf,
+    name: String,
+}
+
+impl GitExport {
+    /// Opens `dir` as a repository tracking `file`, running `git init` if it
+    /// isn't one yet.
+    pub fn open(dir: &Path, file: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        if !dir.join(".git").exists() {
+            git(dir, &["init", "-q"])?;
+        }
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_string());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name,
+        })
+    }
+
+    /// Commits `version`, dated with its capture time and with line stats
+    /// against the previously committed version in the message.
+    pub fn commit(&self, version: &Version) -> io::Result<()> {
+        let path = self.dir.join(&self.name);
+        let previous = match fs::read_to_string(&path) {
+            Ok(previous) => previous,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let stats = LineStats::between(&previous, &version.contents);
+
+        fs::write(&path, version.contents.as_bytes())?;
+        git(&self.dir, &["add", "--", &self.name])?;
+
+        let message = format!(
+            "{} version {}: +{} -{}",
+            self.name, version.number, stats.inserted, stats.deleted
+        );
+        let secs = version
+            .at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let date = format!("@{secs} +0000");
+        let status = Command::new("git")
+            .current_dir(&self.dir)
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .args([
+                "-c",
+                "user.name=slip-diff",
+                "-c",
+                "user.email=slip-diff@localhost",
+            ])
+            .args(["commit", "-q", "--allow-empty", "-m", &message])
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("git commit failed: {status}")));
+        }
+        Ok(())
+    }
+}
+
+/// Commits every captured version to `refs/slip-diff/<path>` in the
+/// repository the watched file lives in, leaving HEAD, the index and the
+/// working tree alone.
+pub struct ShadowRef {
+    top: PathBuf,
+    path: String,
+    reference: String,
+}
+
+impl ShadowRef {
+    /// Finds the repository containing `file`, failing if there is none.
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let file = fs::canonicalize(file)?;
+        let dir = file.parent().unwrap_or(Path::new("/"));
+        let top = PathBuf::from(git_output(dir, &["rev-parse", "--show-toplevel"], None)?);
+        let path = file
+            .strip_prefix(&top)
+            .map_err(|_| {
+                io::Error::other(format!("{} is outside {}", file.display(), top.display()))
+            })?
+            .to_string_lossy()
+            .into_owned();
+        let reference = format!("refs/slip-diff/{path}");
+        git(&top, &["check-ref-format", &reference])?;
+        Ok(Self {
+            top,
+            path,
+            reference,
+        })
+    }
+
+    /// The ref versions are committed to, e.g. `refs/slip-diff/etc/hosts`.
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+
+    /// Commits `version` on top of the shadow ref's current tip.
+    pub fn commit(&self, version: &Version) -> io::Result<()> {
+        let blob = git_output(
+            &self.top,
+            &["hash-object", "-w", "--stdin"],
+            Some(&version.contents),
+        )?;
+
+        // Build the tree in a throwaway index so the user's staging area is
+        // never touched.
+        let scratch = tempfile::tempdir()?;
+        let index_file = scratch.path().join("index");
+        let cacheinfo = format!("100644,{blob},{}", self.path);
+        let status = Command::new("git")
+            .current_dir(&self.top)
+            .env("GIT_INDEX_FILE", &index_file)
+            .args(["update-index", "--add", "--cacheinfo", &cacheinfo])
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "git update-index failed: {status}"
+            )));
+        }
+        let tree = Command::new("git")
+            .current_dir(&self.top)
+            .env("GIT_INDEX_FILE", &index_file)
+            .arg("write-tree")
+            .output()?;
+        if !tree.status.success() {
+            return Err(io::Error::other(format!(
+                "git write-tree failed: {}",
+                tree.status
+            )));
+        }
+        let tree = String::from_utf8_lossy(&tree.stdout).trim().to_string();
+
+        let parent = git_output(
+            &self.top,
+            &["rev-parse", "--verify", "-q", &self.reference],
+            None,
+        )
+        .ok();
+        let message = format!("{} version {}", self.path, version.number);
+        let mut args = vec!["commit-tree", &tree, "-m", &message];
+        if let Some(parent) = &parent {
+            args.extend(["-p", parent]);
+        }
+        let secs = version
+            .at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let date = format!("@{secs} +0000");
+        let output = Command::new("git")
+            .current_dir(&self.top)
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .args([
+                "-c",
+                "user.name=slip-diff",
+                "-c",
+                "user.email=slip-diff@localhost",
+            ])
+            .args(&args)
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "git commit-tree failed: {}",
+                output.status
+            )));
+        }
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        git(&self.top, &["update-ref", &self.reference, &commit])
+    }
+}
+
+/// Reads `file` as committed at `rev` in the repository it lives in, or an
+/// empty string if it isn't tracked there yet.
+pub fn show(file: &Path, rev: &str) -> io::Result<String> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    git_output(
+        dir,
+        &["rev-parse", "--verify", "-q", &format!("{rev}^{{commit}}")],
+        None,
+    )
+    .map_err(|_| io::Error::other(format!("`{rev}` is not a commit in {}", dir.display())))?;
+
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["show", &format!("{rev}:./{name}")])
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Ok(String::new());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs git and returns its trimmed stdout, feeding it `input` if given.
+fn git_output(dir: &Path, args: &[&str], input: Option<&str>) -> io::Result<String> {
+    let mut child = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    if let Some(input) = input {
+        stdin.write_all(input.as_bytes())?;
+    }
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            output.status
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn git(dir: &Path, args: &[&str]) -> io::Result<()> {
+    let status = Command::new("git").current_dir(dir).args(args).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {status}",
+            args.join(" ")
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::env;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// A terminal graphics protocol the TUI can draw image thumbnails with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// kitty's graphics protocol, also spoken by WezTerm and Konsole.
+    Kitty,
+    /// iTerm2's inline images.
+    Iterm,
+}
+
+/// Largest chunk of an image kitty accepts in one escape sequence.
+const KITTY_CHUNK: usize = 4096;
+
+impl Protocol {
+    /// The protocol the terminal we run in understands, going by the
+    /// environment variables it sets, if any.
+    pub fn detect() -> Option<Self> {
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || env::var("TERM").is_ok_and(|term| term.contains("kitty"))
+        {
+            return Some(Self::Kitty);
+        }
+        match env::var("TERM_PROGRAM").as_deref() {
+            Ok("iTerm.app" | "WezTerm") => Some(Self::Iterm),
+            _ => None,
+        }
+    }
+
+    /// The escape sequences drawing `png` at the cursor, scaled to fit in
+    /// `columns` by `rows` cells.
+    pub fn draw(self, png: &[u8], columns: u16, rows: u16) -> String {
+        let data = STANDARD.encode(png);
+        match self {
+            Self::Kitty => {
+                let chunks: Vec<&str> = data
+                    .as_bytes()
+                    .chunks(KITTY_CHUNK)
+                    .map(|chunk| std::str::from_utf8(chunk).unwrap())
+                    .collect();
+                let mut out = String::new();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    // `m=1` on every chunk but the last.
+                    let more = u8::from(i + 1 < chunks.len());
+                    match i {
+                        0 => out.push_str(&format!(
+                            "\x1b_Ga=T,f=100,q=2,c={columns},r={rows},m={more};{chunk}\x1b\\"
+                        )),
+                        _ => out.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\")),
+                    }
+                }
+                out
+            }
+            Self::Iterm => format!(
+                "\x1b]1337;File=inline=1;width={columns};height={rows};preserveAspectRatio=1:{data}\x07"
+            ),
+        }
+    }
+
+    /// The escape sequence removing every image drawn so far, which iTerm2
+    /// doesn't need since its images are overwritten like text.
+    pub fn clear(self) -> &'static str {
+        match self {
+            Self::Kitty => "\x1b_Ga=d,q=2\x1b\\",
+            Self::Iterm => "",
+        }
+    }
+}
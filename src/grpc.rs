@@ -0,0 +1,198 @@
+use std::{io, net::SocketAddr, path::Path};
+
+#[cfg(feature = "grpc")]
+use std::{convert::Infallible, path::PathBuf, time::UNIX_EPOCH};
+
+#[cfg(feature = "grpc")]
+use similar::TextDiff;
+#[cfg(feature = "grpc")]
+use tokio::sync::broadcast::{self, error::RecvError};
+#[cfg(feature = "grpc")]
+use tonic::{
+    codegen::{http, Body, BoxFuture, BoxStream, Context, Poll, Service, StdError},
+    server::{Grpc, NamedService, ServerStreamingService},
+    Status,
+};
+
+use crate::history::Version;
+#[cfg(feature = "grpc")]
+use crate::stats::LineStats;
+
+/// How many events a slow client may fall behind before it misses some.
+#[cfg(feature = "grpc")]
+const EVENT_BACKLOG: usize = 64;
+
+#[cfg(feature = "grpc")]
+const WATCH_PATH: &str = "/slip_diff.ChangeStream/Watch";
+
+/// Requests the stream of changes, see proto/slip_diff.proto.
+#[cfg(feature = "grpc")]
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WatchRequest {}
+
+/// A change to the watched file, see proto/slip_diff.proto.
+#[cfg(feature = "grpc")]
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChangeEvent {
+    #[prost(string, tag = "1")]
+    pub path: String,
+    #[prost(uint64, tag = "2")]
+    pub number: u64,
+    #[prost(uint64, tag = "3")]
+    pub at_ms: u64,
+    #[prost(uint64, tag = "4")]
+    pub inserted: u64,
+    #[prost(uint64, tag = "5")]
+    pub deleted: u64,
+    #[prost(string, tag = "6")]
+    pub diff: String,
+}
+
+/// The `--grpc` service: `slip_diff.ChangeStream/Watch` streams every change
+/// recorded after the call as a [`ChangeEvent`] with a unified diff.
+#[cfg(feature = "grpc")]
+pub struct GrpcServer {
+    file: PathBuf,
+    events: broadcast::Sender<ChangeEvent>,
+}
+
+#[cfg(feature = "grpc")]
+impl GrpcServer {
+    /// Starts serving on `addr` for the watch of `file`. Must be called
+    /// within a tokio runtime, which the server runs on.
+    pub fn bind(addr: SocketAddr, file: &Path) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+            .map_err(io::Error::other)?;
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let service = ChangeStream {
+            events: events.clone(),
+        };
+        tokio::spawn(async move {
+            // Serving only ends with the process.
+            let _ = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await;
+        });
+        Ok(Self {
+            file: file.to_path_buf(),
+            events,
+        })
+    }
+
+    /// Streams the change from `prev` to `version` to every client. The
+    /// first version has nothing to compare with and isn't sent.
+    pub fn publish(&self, prev: Option<&str>, version: &Version) {
+        let Some(prev) = prev else {
+            return;
+        };
+        let name = self
+            .file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let stats = LineStats::between(prev, &version.contents);
+        let diff = TextDiff::from_lines(prev, &*version.contents)
+            .unified_diff()
+            .header(&format!("a/{name}"), &format!("b/{name}"))
+            .to_string();
+        // Fails only while no client is connected.
+        let _ = self.events.send(ChangeEvent {
+            path: self.file.display().to_string(),
+            number: version.number as u64,
+            at_ms: version
+                .at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            inserted: stats.inserted as u64,
+            deleted: stats.deleted as u64,
+            diff,
+        });
+    }
+}
+
+/// The `slip_diff.ChangeStream` service, routing calls by hand as
+/// `tonic-build` would generate.
+#[cfg(feature = "grpc")]
+#[derive(Clone)]
+struct ChangeStream {
+    events: broadcast::Sender<ChangeEvent>,
+}
+
+#[cfg(feature = "grpc")]
+impl NamedService for ChangeStream {
+    const NAME: &'static str = "slip_diff.ChangeStream";
+}
+
+#[cfg(feature = "grpc")]
+impl<B> Service<http::Request<B>> for ChangeStream
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != WATCH_PATH {
+            return Box::pin(async { Ok(Status::unimplemented("").into_http()) });
+        }
+        let watch = Watch(self.events.clone());
+        Box::pin(async move {
+            let mut grpc = Grpc::new(tonic::codec::ProstCodec::default());
+            Ok(grpc.server_streaming(watch, request).await)
+        })
+    }
+}
+
+/// A `Watch` call.
+#[cfg(feature = "grpc")]
+struct Watch(broadcast::Sender<ChangeEvent>);
+
+#[cfg(feature = "grpc")]
+impl ServerStreamingService<WatchRequest> for Watch {
+    type Response = ChangeEvent;
+    type ResponseStream = BoxStream<ChangeEvent>;
+    type Future = std::future::Ready<Result<tonic::Response<Self::ResponseStream>, Status>>;
+
+    fn call(&mut self, _request: tonic::Request<WatchRequest>) -> Self::Future {
+        let events = self.0.subscribe();
+        let stream = futures_util::stream::unfold(events, |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((Ok(event), events)),
+                    // The oldest events are gone; carry on with the rest.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        std::future::ready(Ok(tonic::Response::new(Box::pin(stream))))
+    }
+}
+
+/// Stand-in for builds without the `grpc` feature, which can't serve.
+#[cfg(not(feature = "grpc"))]
+pub struct GrpcServer(std::convert::Infallible);
+
+#[cfg(not(feature = "grpc"))]
+impl GrpcServer {
+    pub fn bind(_addr: SocketAddr, _file: &Path) -> io::Result<Self> {
+        Err(io::Error::other(
+            "slip-diff was built without the `grpc` feature",
+        ))
+    }
+
+    pub fn publish(&self, _prev: Option<&str>, _version: &Version) {
+        match self.0 {}
+    }
+}
@@ -0,0 +1,564 @@
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attributes::FileAttributes,
+    clock::{Clock, SystemClock},
+    delta::Delta,
+    digest::{Digest, HashAlgorithm},
+    listing::ListingEntry,
+    provenance::Process,
+    validate::Validation,
+    version_store::HistoryBackend,
+};
+
+/// Every this many distinct contents one is kept in full, bounding how many
+/// deltas have to be applied to rebuild any version.
+const KEYFRAME_INTERVAL: usize = 16;
+
+/// A single captured state of the watched file.
+///
+/// Contents are shared rather than copied between the history, the renderer
+/// and everything a version is recorded to, since they may be large.
+#[derive(Clone)]
+pub struct FileVersion {
+    pub contents: Arc<str>,
+    pub at: SystemTime,
+    /// Sequence number assigned by [`VersionHistory::push`], stable across
+    /// evictions.
+    pub number: usize,
+    pub meta: VersionMeta,
+}
+
+/// Facts recorded alongside a version's contents.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionMeta {
+    /// Permissions and extended attributes, when `--attributes` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<FileAttributes>,
+    /// Processes that had the file open for writing when the change was
+    /// seen, when `--provenance` is on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub writers: Vec<Process>,
+    /// What the `--validate` command made of the version, if one is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<Validation>,
+    /// Whether invalid UTF-8 in the file was replaced with U+FFFD, with
+    /// `--lossy`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replaced_invalid: bool,
+    /// The directory the file is in, when `--listing` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listing: Option<Vec<ListingEntry>>,
+}
+
+impl FileVersion {
+    /// Borrows this version as if it were held by a [`VersionHistory`]
+    /// hashing with `algorithm`.
+    pub fn as_version(&self, algorithm: HashAlgorithm) -> Version<'_> {
+        Version {
+            contents: Arc::clone(&self.contents),
+            hash: algorithm.digest(self.contents.as_bytes()),
+            at: self.at,
+            number: self.number,
+            meta: &self.meta,
+        }
+    }
+
+    pub fn new_at_now(contents: impl Into<Arc<str>>) -> Self {
+        Self::new_by(contents, &SystemClock)
+    }
+
+    /// A version of `contents` captured at `clock`'s current time.
+    pub fn new_by(contents: impl Into<Arc<str>>, clock: &dyn Clock) -> Self {
+        let at = clock.system_now();
+        Self {
+            contents: contents.into(),
+            at,
+            number: 0,
+            meta: VersionMeta::default(),
+        }
+    }
+}
+
+/// A version held by a [`VersionHistory`], sharing its contents with the
+/// history's blob storage or rebuilding them from deltas.
+#[derive(Clone)]
+pub struct Version<'a> {
+    pub contents: Arc<str>,
+    pub hash: Digest,
+    pub at: SystemTime,
+    pub number: usize,
+    pub meta: &'a VersionMeta,
+}
+
+/// What is known about a version without its contents, which a history can
+/// tell without rebuilding or reading them.
+#[derive(Clone)]
+pub struct VersionInfo<'a> {
+    pub hash: Digest,
+    pub at: SystemTime,
+    pub number: usize,
+    /// Bytes of contents.
+    pub size: usize,
+    pub meta: &'a VersionMeta,
+}
+
+impl Version<'_> {
+    pub fn to_file_version(&self) -> FileVersion {
+        FileVersion {
+            contents: Arc::clone(&self.contents),
+            at: self.at,
+            number: self.number,
+            meta: self.meta.clone(),
+        }
+    }
+}
+
+/// Which versions to drop once the history grows past its limits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EvictionPolicy {
+    /// Drop the oldest version first.
+    #[default]
+    DropOldest,
+    /// Always keep the first version as a baseline and drop the oldest of
+    /// the rest.
+    KeepFirst,
+}
+
+#[derive(Debug, Default, Clone, clap::Args)]
+pub struct HistoryLimits {
+    /// Maximum number of versions to keep in memory
+    #[clap(long, value_parser = clap::value_parser!(u64).range(2..))]
+    pub max_versions: Option<u64>,
+
+    /// Maximum bytes of file contents to keep in memory (accepts K, M and G suffixes)
+    #[clap(long, value_parser = parse_size)]
+    pub max_memory: Option<usize>,
+
+    /// Which versions to drop once a limit is reached
+    #[clap(long, value_enum, default_value_t)]
+    pub eviction: EvictionPolicy,
+
+    /// Where to keep versions: memory, disk:<DIR> or sqlite:<PATH>
+    #[clap(long, default_value = "memory")]
+    pub history: HistoryBackend,
+}
+
+/// A version's place in the history; its contents live in the blob map.
+struct Entry {
+    hash: Digest,
+    at: SystemTime,
+    number: usize,
+    size: usize,
+    meta: VersionMeta,
+}
+
+impl Entry {
+    fn info(&self) -> VersionInfo<'_> {
+        VersionInfo {
+            hash: self.hash,
+            at: self.at,
+            number: self.number,
+            size: self.size,
+            meta: &self.meta,
+        }
+    }
+}
+
+/// Contents shared by every version with the same hash.
+struct Blob {
+    data: BlobData,
+    /// Versions referring to this blob.
+    refs: usize,
+    /// Blobs stored as a delta against this one.
+    dependents: usize,
+    /// Order in which distinct contents were first seen.
+    seq: usize,
+}
+
+enum BlobData {
+    Full(Arc<str>),
+    /// Rebuilt by applying `delta` to the contents of `base`, which is
+    /// always newer.
+    Delta {
+        base: Digest,
+        delta: Delta,
+    },
+}
+
+impl BlobData {
+    fn size(&self) -> usize {
+        match self {
+            BlobData::Full(contents) => contents.len(),
+            BlobData::Delta { delta, .. } => delta.size(),
+        }
+    }
+}
+
+/// Every version captured so far, bounded by [`HistoryLimits`].
+///
+/// Contents are stored once per distinct hash, so a file that keeps
+/// being rewritten with the same contents doesn't grow memory use. The newest
+/// contents are kept in full and older ones as reverse deltas against their
+/// successor, with a full keyframe every [`KEYFRAME_INTERVAL`] blobs.
+#[derive(Default)]
+pub struct VersionHistory {
+    entries: Vec<Entry>,
+    blobs: HashMap<Digest, Blob>,
+    limits: HistoryLimits,
+    algorithm: HashAlgorithm,
+    bytes: usize,
+    next_number: usize,
+    next_seq: usize,
+    /// Whether new versions are kept whole rather than compressing their
+    /// predecessor, to save the work.
+    keep_whole: bool,
+}
+
+impl VersionHistory {
+    pub fn new(limits: HistoryLimits, algorithm: HashAlgorithm) -> Self {
+        Self {
+            limits,
+            algorithm,
+            ..Self::default()
+        }
+    }
+
+    /// Stops or resumes delta-compressing older versions as new ones are
+    /// pushed. Versions pushed meanwhile stay whole.
+    pub fn set_keep_whole(&mut self, on: bool) {
+        self.keep_whole = on;
+    }
+
+    /// The algorithm versions are deduplicated by.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Appends a version and evicts older ones if a limit was exceeded.
+    ///
+    /// Returns the positions that were removed, in removal order, so callers
+    /// holding indices into the history can shift them.
+    pub fn push(&mut self, version: FileVersion) -> Vec<usize> {
+        let hash = self.algorithm.digest(version.contents.as_bytes());
+        let size = version.contents.len();
+        if let Some(blob) = self.blobs.get_mut(&hash) {
+            blob.refs += 1;
+        } else {
+            let previous = self.entries.last().map(|entry| entry.hash);
+            self.bytes += version.contents.len();
+            self.blobs.insert(
+                hash,
+                Blob {
+                    data: BlobData::Full(version.contents),
+                    refs: 1,
+                    dependents: 0,
+                    seq: self.next_seq,
+                },
+            );
+            self.next_seq += 1;
+            if let (Some(previous), false) = (previous, self.keep_whole) {
+                self.compress(previous, hash);
+            }
+        }
+        self.entries.push(Entry {
+            hash,
+            at: version.at,
+            number: self.next_number,
+            size,
+            meta: version.meta,
+        });
+        self.next_number += 1;
+
+        let mut evicted = Vec::new();
+        while self.over_limit() {
+            let Some(index) = self.eviction_index() else {
+                break;
+            };
+            self.evict(index);
+            evicted.push(index);
+        }
+        evicted
+    }
+
+    /// Drops the version at position `index`, and its contents unless other
+    /// versions share them.
+    pub fn evict(&mut self, index: usize) {
+        let removed = self.entries.remove(index);
+        self.blobs.get_mut(&removed.hash).unwrap().refs -= 1;
+        self.release(removed.hash);
+    }
+
+    /// Appends a version that was captured earlier, e.g. reloaded from a
+    /// session journal, keeping its original sequence number.
+    pub fn restore(&mut self, version: FileVersion) -> Vec<usize> {
+        let number = version.number;
+        let evicted = self.push(version);
+        if let Some(last) = self.entries.last_mut() {
+            last.number = number;
+        }
+        self.next_number = number + 1;
+        evicted
+    }
+
+    /// The sequence number the next pushed version will get.
+    pub fn next_number(&self) -> usize {
+        self.next_number
+    }
+
+    /// Whether `contents` are what the latest version holds, by hash, which
+    /// costs neither a comparison nor rebuilding the latest contents.
+    pub fn is_latest(&self, contents: &str) -> bool {
+        self.entries
+            .last()
+            .is_some_and(|entry| entry.hash == self.algorithm.digest(contents.as_bytes()))
+    }
+
+    pub fn get(&self, index: usize) -> Option<Version<'_>> {
+        self.entries.get(index).map(|entry| self.version(entry))
+    }
+
+    pub fn last(&self) -> Option<Version<'_>> {
+        self.entries.last().map(|entry| self.version(entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every version in order, rebuilding each one's contents; see
+    /// [`VersionHistory::infos`] for what takes none.
+    pub fn iter(&self) -> impl Iterator<Item = Version<'_>> {
+        self.entries.iter().map(|entry| self.version(entry))
+    }
+
+    pub fn info(&self, index: usize) -> Option<VersionInfo<'_>> {
+        self.entries.get(index).map(Entry::info)
+    }
+
+    /// What is known about every version in order, without rebuilding any
+    /// contents.
+    pub fn infos(&self) -> impl Iterator<Item = VersionInfo<'_>> {
+        self.entries.iter().map(Entry::info)
+    }
+
+    fn version<'a>(&'a self, entry: &'a Entry) -> Version<'a> {
+        Version {
+            contents: self.contents(&entry.hash),
+            hash: entry.hash,
+            at: entry.at,
+            number: entry.number,
+            meta: &entry.meta,
+        }
+    }
+
+    fn contents(&self, hash: &Digest) -> Arc<str> {
+        match &self.blobs[hash].data {
+            BlobData::Full(contents) => Arc::clone(contents),
+            BlobData::Delta { base, delta } => delta.apply(&self.contents(base)).into(),
+        }
+    }
+
+    /// Stores `previous` as a delta against the newer `base` unless it is a
+    /// keyframe, already a delta, or wouldn't get any smaller.
+    fn compress(&mut self, previous: Digest, base: Digest) {
+        let blob = &self.blobs[&previous];
+        let BlobData::Full(old) = &blob.data else {
+            return;
+        };
+        if blob.seq.is_multiple_of(KEYFRAME_INTERVAL) {
+            return;
+        }
+        let BlobData::Full(new) = &self.blobs[&base].data else {
+            return;
+        };
+        let delta = Delta::encode(new, old);
+        if delta.size() >= old.len() {
+            return;
+        }
+
+        self.bytes = self.bytes - old.len() + delta.size();
+        self.blobs.get_mut(&previous).unwrap().data = BlobData::Delta { base, delta };
+        self.blobs.get_mut(&base).unwrap().dependents += 1;
+    }
+
+    /// Drops a blob no version refers to anymore, first rebasing any deltas
+    /// that were encoded against it.
+    fn release(&mut self, hash: Digest) {
+        if self.blobs[&hash].refs > 0 {
+            return;
+        }
+        if self.blobs[&hash].dependents > 0 {
+            self.rebase_dependents(hash);
+        }
+
+        let blob = self.blobs.remove(&hash).unwrap();
+        self.bytes -= blob.data.size();
+        if let BlobData::Delta { base, .. } = blob.data {
+            self.blobs.get_mut(&base).unwrap().dependents -= 1;
+            self.release(base);
+        }
+    }
+
+    /// Re-encodes every delta based on `hash` against `hash`'s own base, or
+    /// stores it in full if `hash` was a full blob.
+    fn rebase_dependents(&mut self, hash: Digest) {
+        let dependents: Vec<Digest> = self
+            .blobs
+            .iter()
+            .filter(|(_, blob)| matches!(&blob.data, BlobData::Delta { base, .. } if *base == hash))
+            .map(|(dependent, _)| *dependent)
+            .collect();
+        let new_base = match &self.blobs[&hash].data {
+            BlobData::Delta { base, .. } => Some(*base),
+            BlobData::Full(_) => None,
+        };
+
+        for dependent in dependents {
+            let contents = self.contents(&dependent);
+            let data = match new_base {
+                Some(base) => BlobData::Delta {
+                    base,
+                    delta: Delta::encode(&self.contents(&base), &contents),
+                },
+                None => BlobData::Full(contents),
+            };
+            if let Some(base) = new_base {
+                self.blobs.get_mut(&base).unwrap().dependents += 1;
+            }
+            self.blobs.get_mut(&hash).unwrap().dependents -= 1;
+            let blob = self.blobs.get_mut(&dependent).unwrap();
+            self.bytes = self.bytes - blob.data.size() + data.size();
+            blob.data = data;
+        }
+    }
+
+    /// Rebuilds every version through its chain of deltas, returning the
+    /// numbers of those whose contents no longer match their hash.
+    pub fn verify(&self) -> Vec<usize> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.hash.matches(self.contents(&entry.hash).as_bytes()))
+            .map(|entry| entry.number)
+            .collect()
+    }
+
+    /// Bytes of distinct file contents currently held, after compression.
+    pub fn memory(&self) -> usize {
+        self.bytes
+    }
+
+    fn over_limit(&self) -> bool {
+        let too_many = self
+            .limits
+            .max_versions
+            .is_some_and(|max| self.entries.len() as u64 > max);
+        let too_big = self.limits.max_memory.is_some_and(|max| self.bytes > max);
+        too_many || too_big
+    }
+
+    /// The version to drop next, never the latest one.
+    fn eviction_index(&self) -> Option<usize> {
+        let index = match self.limits.eviction {
+            EvictionPolicy::DropOldest => 0,
+            EvictionPolicy::KeepFirst => 1,
+        };
+        (index + 1 < self.entries.len()).then_some(index)
+    }
+}
+
+/// Parses a byte count such as `4096`, `512K`, `64M` or `2G`.
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .trim()
+        .parse::<usize>()
+        .map(|n| n * multiplier)
+        .map_err(|e| format!("invalid size `{s}`: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distinct contents differing only in their last line, so that each
+    /// compresses well against the next.
+    fn contents(i: usize) -> String {
+        let mut text: String = (0..50).map(|line| format!("line {line}\n")).collect();
+        text.push_str(&format!("edit {i}\n"));
+        text
+    }
+
+    fn history(versions: usize) -> VersionHistory {
+        let mut history = VersionHistory::default();
+        for i in 0..versions {
+            history.push(FileVersion::new_at_now(contents(i)));
+        }
+        history
+    }
+
+    fn is_full(history: &VersionHistory, index: usize) -> bool {
+        let hash = history.info(index).unwrap().hash;
+        matches!(history.blobs[&hash].data, BlobData::Full(_))
+    }
+
+    #[test]
+    fn keeps_a_keyframe_every_interval() {
+        let history = history(2 * KEYFRAME_INTERVAL + 2);
+        let last = history.len() - 1;
+        for index in 0..history.len() {
+            let full = index % KEYFRAME_INTERVAL == 0 || index == last;
+            assert_eq!(is_full(&history, index), full, "version {index}");
+        }
+    }
+
+    #[test]
+    fn rebuilds_every_version_across_keyframes() {
+        let history = history(3 * KEYFRAME_INTERVAL);
+        for (i, version) in history.iter().enumerate() {
+            assert_eq!(&*version.contents, contents(i));
+        }
+        assert!(history.verify().is_empty());
+        assert!(history.memory() < 3 * KEYFRAME_INTERVAL * contents(0).len() / 4);
+    }
+
+    #[test]
+    fn stores_repeated_contents_once() {
+        let mut history = history(2);
+        let memory = history.memory();
+        history.push(FileVersion::new_at_now(contents(0)));
+        history.push(FileVersion::new_at_now(contents(1)));
+
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.blobs.len(), 2);
+        assert_eq!(history.memory(), memory);
+        let infos: Vec<_> = history.infos().collect();
+        assert_eq!(infos[0].hash, infos[2].hash);
+        assert_eq!(infos[3].size, contents(1).len());
+        assert_eq!(&*history.get(2).unwrap().contents, contents(0));
+    }
+
+    #[test]
+    fn rebuilds_versions_after_evicting_older_ones() {
+        let mut history = history(KEYFRAME_INTERVAL + 4);
+        let expected: Vec<_> = history.iter().skip(3).map(|v| v.contents).collect();
+        for _ in 0..3 {
+            history.evict(0);
+        }
+        let rebuilt: Vec<_> = history.iter().map(|v| v.contents).collect();
+        assert_eq!(rebuilt, expected);
+        assert!(history.verify().is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{error::Error, path::Path};
+
+use crate::history::FileVersion;
+#[cfg(feature = "hooks")]
+use crate::stats::LineStats;
+#[cfg(feature = "hooks")]
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+#[cfg(feature = "hooks")]
+use std::time::UNIX_EPOCH;
+
+/// How many operations a call into a script may take, so one that loops
+/// by accident gives up rather than holding up the watch.
+#[cfg(feature = "hooks")]
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// How deeply a script's functions may call each other.
+#[cfg(feature = "hooks")]
+const MAX_CALL_LEVELS: usize = 64;
+
+/// The largest string, in bytes, or array or map a script may build.
+#[cfg(feature = "hooks")]
+const MAX_SIZE: usize = 256 * 1024 * 1024;
+
+/// What a hook decided to do with a change.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookOutcome {
+    /// Show the change as usual.
+    Show,
+    /// Drop the change: it is neither recorded nor shown.
+    Suppress,
+    /// Show this text instead of the rendered diff.
+    Replace(String),
+}
+
+/// A Rhai script defining `on_change(event)`, `transform(contents)` or both.
+///
+/// `event` is a map with `path`, `number`, `timestamp` (seconds since the
+/// epoch), `old`, `new`, `inserted` and `deleted` (line counts). The function
+/// returns `false` to suppress the change, a string to print instead of the
+/// diff, or anything else to show the diff as usual. Anything else it wants
+/// to emit it can `print`.
+///
+/// `transform` gets the contents every time the file is read, the first
+/// time too, and returns what to keep instead, e.g. with secrets masked or
+/// timestamps stripped. A change that is gone once transformed is no change.
+///
+/// A script that fails, or runs into the engine's limits, is logged and the
+/// change handled as if there were no hook.
+#[cfg(feature = "hooks")]
+pub struct Hook {
+    engine: Engine,
+    ast: AST,
+    on_change: bool,
+    transform: bool,
+}
+
+#[cfg(feature = "hooks")]
+impl Hook {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_SIZE)
+            .set_max_array_size(MAX_SIZE)
+            .set_max_map_size(MAX_SIZE);
+        let ast = engine.compile_file(path.to_path_buf())?;
+        let defines = |name: &str| {
+            ast.iter_functions()
+                .any(|f| f.name == name && f.params.len() == 1)
+        };
+        let (on_change, transform) = (defines("on_change"), defines("transform"));
+        if !on_change && !transform {
+            return Err(format!(
+                "{} defines neither on_change(event) nor transform(contents)",
+                path.display()
+            )
+            .into());
+        }
+        Ok(Self {
+            engine,
+            ast,
+            on_change,
+            transform,
+        })
+    }
+
+    /// The contents to keep of what was just read, which are all of them if
+    /// `transform` fails.
+    pub fn transform(&self, contents: String) -> String {
+        if !self.transform {
+            return contents;
+        }
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(
+                &mut Scope::new(),
+                &self.ast,
+                "transform",
+                (contents.clone(),),
+            )
+            .map_err(|error| error.to_string())
+            .and_then(|result| {
+                result
+                    .into_string()
+                    .map_err(|kind| format!("returned a {kind}, not a string"))
+            });
+        match result {
+            Ok(transformed) => transformed,
+            Err(error) => {
+                tracing::warn!(
+                    "the hook's transform failed, keeping the contents as read: {error}"
+                );
+                contents
+            }
+        }
+    }
+
+    /// What `on_change` makes of the change from `old` to `new`, which is to
+    /// show it if `on_change` fails.
+    pub fn on_change(&self, path: &Path, old: &str, new: &FileVersion) -> HookOutcome {
+        if !self.on_change {
+            return HookOutcome::Show;
+        }
+        let stats = LineStats::between(old, &new.contents);
+        let timestamp = new
+            .at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut event = Map::new();
+        event.insert("path".into(), path.display().to_string().into());
+        event.insert("number".into(), (new.number as i64).into());
+        event.insert("timestamp".into(), timestamp.into());
+        event.insert("old".into(), old.to_string().into());
+        event.insert("new".into(), (&*new.contents).into());
+        event.insert("inserted".into(), (stats.inserted as i64).into());
+        event.insert("deleted".into(), (stats.deleted as i64).into());
+
+        let result = match self.engine.call_fn::<Dynamic>(
+            &mut Scope::new(),
+            &self.ast,
+            "on_change",
+            (event,),
+        ) {
+            Ok(result) => result,
+            Err(error) => {
+                tracing::warn!(
+                    "the hook's on_change failed for version {}, showing it: {error}",
+                    new.number
+                );
+                return HookOutcome::Show;
+            }
+        };
+        if result.as_bool() == Ok(false) {
+            return HookOutcome::Suppress;
+        }
+        match result.into_string() {
+            Ok(text) => HookOutcome::Replace(text),
+            Err(_) => HookOutcome::Show,
+        }
+    }
+}
+
+/// Stand-in for builds without the `hooks` feature, which fails to load.
+#[cfg(not(feature = "hooks"))]
+pub struct Hook(std::convert::Infallible);
+
+#[cfg(not(feature = "hooks"))]
+impl Hook {
+    pub fn load(_path: &Path) -> Result<Self, Box<dyn Error>> {
+        Err("slip-diff was built without the `hooks` feature".into())
+    }
+
+    pub fn transform(&self, _contents: String) -> String {
+        match self.0 {}
+    }
+
+    pub fn on_change(&self, _path: &Path, _old: &str, _new: &FileVersion) -> HookOutcome {
+        match self.0 {}
+    }
+}
+
+#[cfg(all(test, feature = "hooks"))]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn hook(script: &str) -> (Hook, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hook.rhai");
+        fs::write(&path, script).unwrap();
+        (Hook::load(&path).unwrap(), dir)
+    }
+
+    fn on_change(hook: &Hook, old: &str, new: &str) -> HookOutcome {
+        hook.on_change(Path::new("app.log"), old, &FileVersion::new_at_now(new))
+    }
+
+    #[test]
+    fn transform_keeps_what_the_script_returns() {
+        let (hook, _dir) =
+            hook(r#"fn transform(contents) { contents.replace("hunter2", "***"); contents }"#);
+        assert_eq!(
+            hook.transform("password hunter2\n".into()),
+            "password ***\n"
+        );
+        assert_eq!(on_change(&hook, "a\n", "b\n"), HookOutcome::Show);
+    }
+
+    #[test]
+    fn on_change_skips_shows_or_replaces() {
+        let (hook, _dir) = hook(
+            r#"
+            fn on_change(event) {
+                if event.inserted == 0 { return false; }
+                if event["new"].contains("alert") { return `ALERT in ${event.path}`; }
+            }
+            "#,
+        );
+        assert_eq!(on_change(&hook, "a\nb\n", "a\n"), HookOutcome::Suppress);
+        assert_eq!(on_change(&hook, "a\n", "a\nb\n"), HookOutcome::Show);
+        assert_eq!(
+            on_change(&hook, "a\n", "a\nalert\n"),
+            HookOutcome::Replace("ALERT in app.log".into())
+        );
+        assert_eq!(hook.transform("as read\n".into()), "as read\n");
+    }
+
+    #[test]
+    fn failing_scripts_change_nothing() {
+        let (hook, _dir) = hook(
+            r#"
+            fn on_change(event) { throw "boom"; }
+            fn transform(contents) { contents.no_such_method() }
+            "#,
+        );
+        assert_eq!(on_change(&hook, "a\n", "b\n"), HookOutcome::Show);
+        assert_eq!(hook.transform("as read\n".into()), "as read\n");
+    }
+
+    #[test]
+    fn scripts_that_never_return_are_stopped() {
+        let (hook, _dir) = hook("fn on_change(event) { loop {} }");
+        assert_eq!(on_change(&hook, "a\n", "b\n"), HookOutcome::Show);
+    }
+}
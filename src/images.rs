@@ -0,0 +1,105 @@
+#[cfg(feature = "image")]
+use std::io::Cursor;
+
+#[cfg(feature = "image")]
+use image::{imageops::FilterType, ImageDecoder, ImageFormat, ImageReader};
+
+/// Prefix of the description line holding the perceptual hash.
+const PERCEPTUAL_HASH: &str = "perceptual hash: ";
+
+/// The format of `bytes` and its name if they are a PNG, JPEG, GIF, WebP or
+/// BMP image.
+#[cfg(feature = "image")]
+fn format(bytes: &[u8]) -> Option<(ImageFormat, &'static str)> {
+    let format = image::guess_format(bytes).ok()?;
+    let name = match format {
+        ImageFormat::Png => "PNG",
+        ImageFormat::Jpeg => "JPEG",
+        ImageFormat::Gif => "GIF",
+        ImageFormat::WebP => "WebP",
+        ImageFormat::Bmp => "BMP",
+        _ => return None,
+    };
+    Some((format, name))
+}
+
+#[cfg(feature = "image")]
+pub fn is_image(bytes: &[u8]) -> bool {
+    format(bytes).is_some()
+}
+
+/// Describes the image `bytes` by format, dimensions, colour type and size,
+/// and with `perceptual` a hash of what it looks like. Images are captured as
+/// this description rather than their bytes, so a change shows up in the
+/// diff as e.g. `-dimensions: 640x480` / `+dimensions: 800x600`. An image too
+/// broken to decode, e.g. one still being written, is described by format and
+/// size.
+#[cfg(feature = "image")]
+pub fn describe(bytes: &[u8], perceptual: bool) -> String {
+    let Some((format, name)) = format(bytes) else {
+        return format!("unrecognised image, bytes: {}\n", bytes.len());
+    };
+    let mut description = format!("format: {name}\n");
+    match ImageReader::with_format(Cursor::new(bytes), format).into_decoder() {
+        Ok(decoder) => {
+            let (width, height) = decoder.dimensions();
+            description.push_str(&format!("dimensions: {width}x{height}\n"));
+            description.push_str(&format!("color: {:?}\n", decoder.color_type()));
+        }
+        Err(error) => description.push_str(&format!("unreadable: {error}\n")),
+    }
+    description.push_str(&format!("bytes: {}\n", bytes.len()));
+    if let Some(hash) = perceptual.then(|| perceptual_hash(bytes)).flatten() {
+        description.push_str(&format!("{PERCEPTUAL_HASH}{hash:016x}\n"));
+    }
+    description
+}
+
+/// A difference hash: one bit per neighbouring pair of pixels in a 9x8
+/// grayscale copy, set where brightness increases to the right. Images that
+/// look alike have hashes a few bits apart, however they were scaled or
+/// re-encoded.
+#[cfg(feature = "image")]
+fn perceptual_hash(bytes: &[u8]) -> Option<u64> {
+    let image = image::load_from_memory(bytes).ok()?;
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x + 1, y)[0] > small.get_pixel(x, y)[0];
+            hash = hash << 1 | u64::from(brighter);
+        }
+    }
+    Some(hash)
+}
+
+/// `bytes` scaled to fit in `size` pixels square and encoded as PNG, if
+/// they are an image.
+#[cfg(feature = "image")]
+pub fn thumbnail(bytes: &[u8], size: u32) -> Option<Vec<u8>> {
+    let image = image::load_from_memory(bytes).ok()?.thumbnail(size, size);
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+/// Stand-in for builds without the `image` feature, which have no
+/// thumbnails to show.
+#[cfg(not(feature = "image"))]
+pub fn thumbnail(_bytes: &[u8], _size: u32) -> Option<Vec<u8>> {
+    None
+}
+
+/// How many of the 64 perceptual hash bits differ between two image
+/// descriptions, if both have one.
+pub fn perceptual_distance(old: &str, new: &str) -> Option<u32> {
+    let hash = |description: &str| {
+        description
+            .lines()
+            .find_map(|line| line.strip_prefix(PERCEPTUAL_HASH))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+    };
+    Some((hash(old)? ^ hash(new)?).count_ones())
+}
@@ -0,0 +1,185 @@
+/// How many bytes are compared at a time while looking for where two texts
+/// start or stop differing, before narrowing down to the byte.
+const CHUNK: usize = 64;
+
+/// The lines two texts differ in, with as many unchanged lines around them as
+/// the diff shows for context, which diff the same as the whole texts.
+pub struct Window<'a> {
+    pub old: &'a str,
+    pub new: &'a str,
+    /// How many lines come before the window, the same in both texts.
+    pub skipped: usize,
+}
+
+/// Narrows each diff between consecutive versions down to a [`Window`]
+/// around the lines that changed, so the diff itself, its line counts and
+/// its highlighting cost time in proportion to the change rather than the
+/// file.
+///
+/// The unchanged start and end of the texts are found by comparing their
+/// bytes, a chunk at a time, and then moved to the nearest line boundary,
+/// so nothing is hashed or split into lines outside the window.
+#[derive(Default)]
+pub struct IncrementalDiff;
+
+impl IncrementalDiff {
+    pub fn window<'a>(&mut self, old: &'a str, new: &'a str, context: usize) -> Window<'a> {
+        let (a, b) = (old.as_bytes(), new.as_bytes());
+
+        // The whole lines both texts start with.
+        let prefix = match a[..common_prefix(a, b)].iter().rposition(|&c| c == b'\n') {
+            Some(newline) => newline + 1,
+            None => 0,
+        };
+        // The whole lines both end with, after those.
+        let shortest = a.len().min(b.len()) - prefix;
+        let mut suffix = common_suffix(a, b).min(shortest);
+        let starts_line = |text: &[u8], at: usize| at == prefix || text[at - 1] == b'\n';
+        if !starts_line(a, a.len() - suffix) || !starts_line(b, b.len() - suffix) {
+            let tail = &a[a.len() - suffix..];
+            suffix = match tail.iter().position(|&c| c == b'\n') {
+                Some(newline) => tail.len() - newline - 1,
+                None => 0,
+            };
+        }
+
+        let start = lines_back(&a[..prefix], context);
+        let end = a.len() - suffix + lines_forward(&a[a.len() - suffix..], context);
+        let skipped = a[..start].iter().filter(|&&c| c == b'\n').count();
+        Window {
+            old: &old[start..end],
+            new: &new[start..b.len() - (a.len() - end)],
+            skipped,
+        }
+    }
+}
+
+/// How many bytes `a` and `b` start with that are the same.
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    let chunks = a
+        .chunks(CHUNK)
+        .zip(b.chunks(CHUNK))
+        .take_while(|(a, b)| a == b)
+        .count();
+    let same = (chunks * CHUNK).min(a.len()).min(b.len());
+    same + a[same..]
+        .iter()
+        .zip(&b[same..])
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// How many bytes `a` and `b` end with that are the same.
+fn common_suffix(a: &[u8], b: &[u8]) -> usize {
+    let chunks = a
+        .rchunks(CHUNK)
+        .zip(b.rchunks(CHUNK))
+        .take_while(|(a, b)| a == b)
+        .count();
+    let same = (chunks * CHUNK).min(a.len()).min(b.len());
+    same + a[..a.len() - same]
+        .iter()
+        .rev()
+        .zip(b[..b.len() - same].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Where the `lines` whole lines before the end of `text` start.
+fn lines_back(text: &[u8], lines: usize) -> usize {
+    let mut start = text.len();
+    for _ in 0..lines {
+        if start == 0 {
+            break;
+        }
+        start = text[..start - 1]
+            .iter()
+            .rposition(|&c| c == b'\n')
+            .map_or(0, |newline| newline + 1);
+    }
+    start
+}
+
+/// Where the first `lines` lines of `text` end.
+fn lines_forward(text: &[u8], lines: usize) -> usize {
+    let mut end = 0;
+    for _ in 0..lines {
+        match text[end..].iter().position(|&c| c == b'\n') {
+            Some(newline) => end += newline + 1,
+            None => return text.len(),
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render::CONTEXT, stats::LineStats};
+
+    fn window<'a>(old: &'a str, new: &'a str) -> (&'a str, &'a str, usize) {
+        let window = IncrementalDiff.window(old, new, CONTEXT);
+        (window.old, window.new, window.skipped)
+    }
+
+    fn numbered(lines: std::ops::Range<usize>) -> String {
+        lines.map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn narrows_down_to_the_changed_lines_and_their_context() {
+        let old = numbered(0..20);
+        let new = old.replace("line 10\n", "line ten\n");
+        let (old_window, new_window, skipped) = window(&old, &new);
+        assert_eq!(skipped, 7);
+        assert_eq!(old_window, numbered(7..14));
+        assert_eq!(
+            new_window,
+            numbered(7..14).replace("line 10\n", "line ten\n")
+        );
+    }
+
+    #[test]
+    fn changes_within_a_line_take_the_whole_line() {
+        // The bytes before and after the change are the same, but not the
+        // lines they are in.
+        assert_eq!(
+            window("a\nbcd\ne\n", "a\nbXd\ne\n"),
+            ("a\nbcd\ne\n", "a\nbXd\ne\n", 0)
+        );
+        assert_eq!(window("ab", "abc"), ("ab", "abc", 0));
+        assert_eq!(window("x\nab", "x\nab\n"), ("x\nab", "x\nab\n", 0));
+    }
+
+    #[test]
+    fn repeated_lines_are_not_counted_twice() {
+        let same = |times| {
+            format!(
+                "{}{}{}",
+                numbered(0..10),
+                "same\n".repeat(times),
+                numbered(10..20)
+            )
+        };
+        let (old, new) = (same(3), same(5));
+        let (old_window, new_window, skipped) = window(&old, &new);
+        assert_eq!(skipped, 10);
+        assert_eq!(
+            LineStats::between(old_window, new_window),
+            LineStats::between(&old, &new)
+        );
+        assert_eq!(window("same\n", "same\n"), ("same\n", "same\n", 0));
+    }
+
+    #[test]
+    fn finds_differences_across_chunks() {
+        let old = "x".repeat(200);
+        let mut new = old.clone();
+        new.replace_range(130..131, "y");
+        assert_eq!(common_prefix(old.as_bytes(), new.as_bytes()), 130);
+        assert_eq!(common_suffix(old.as_bytes(), new.as_bytes()), 69);
+        assert_eq!(common_prefix(b"abc", b"abcdef"), 3);
+        assert_eq!(common_suffix(b"def", b"abcdef"), 3);
+        assert_eq!(common_prefix(b"same", b"same"), 4);
+    }
+}
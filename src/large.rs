@@ -0,0 +1,258 @@
+use std::{fs::File, io, path::Path};
+
+use memmap2::Mmap;
+
+/// How much of the file each hash covers. Smaller blocks narrow the changed
+/// region down further at the cost of more hashes to keep.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Changed regions larger than this on either side are only reported, since
+/// diffing them could take minutes, e.g. when the file is read halfway
+/// through being rewritten.
+const MAX_DIFF_SIZE: usize = 1 << 20;
+
+/// Reads a file too large to re-read into a `String` on every change.
+///
+/// The file is memory-mapped and hashed block by block, both from its start
+/// and from its end, so that an edit anywhere only costs diffing the blocks
+/// around it even if it shifted everything after it. Only the bytes last
+/// seen are kept, not a copy per version.
+pub struct LargeFile {
+    contents: Vec<u8>,
+    /// Hashes of consecutive blocks from the start of `contents`.
+    head: Vec<blake3::Hash>,
+    /// Hashes of consecutive blocks from the end of `contents`, last first.
+    tail: Vec<blake3::Hash>,
+}
+
+/// The region of a large file that changed in one read.
+pub struct Changed {
+    /// 1-based number of the first changed line.
+    pub first_line: usize,
+    pub lines: ChangedLines,
+}
+
+pub enum ChangedLines {
+    Diffable {
+        /// The lines as they were before.
+        before: String,
+        /// The lines that replaced them.
+        after: String,
+    },
+    /// Sizes of a region too large to diff, in bytes.
+    TooLarge { before: usize, after: usize },
+}
+
+impl LargeFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let contents = map(path)?.map_or_else(Vec::new, |mmap| mmap.to_vec());
+        Ok(Self {
+            head: head_blocks(&contents).map(blake3::hash).collect(),
+            tail: tail_blocks(&contents).map(blake3::hash).collect(),
+            contents,
+        })
+    }
+
+    /// Reads `path` again, returning the lines that changed since the last
+    /// read, or `None` if nothing did.
+    pub fn read_changed(&mut self, path: &Path) -> io::Result<Option<Changed>> {
+        let mmap = map(path)?;
+        let new = mmap.as_deref().unwrap_or_default();
+        let (start, end) = self.changed_blocks(new);
+        let old_end = self.contents.len() - (new.len() - end);
+        let (old, changed) = (&self.contents[start..old_end], &new[start..end]);
+        if old == changed {
+            return Ok(None);
+        }
+
+        // Narrow the blocks down to the bytes that differ.
+        let prefix = old.iter().zip(changed).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(changed[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (start, end, old_end) = snap_to_lines(
+            &self.contents,
+            new,
+            start + prefix,
+            end - suffix,
+            old_end - suffix,
+        );
+
+        let (before, after) = (&self.contents[start..old_end], &new[start..end]);
+        let lines = match before.len().max(after.len()) > MAX_DIFF_SIZE {
+            true => ChangedLines::TooLarge {
+                before: before.len(),
+                after: after.len(),
+            },
+            false => ChangedLines::Diffable {
+                before: String::from_utf8_lossy(before).into_owned(),
+                after: String::from_utf8_lossy(after).into_owned(),
+            },
+        };
+        let changed = Changed {
+            first_line: count_lines(&new[..start]) + 1,
+            lines,
+        };
+        self.contents
+            .splice(start..old_end, new[start..end].iter().copied());
+        self.rehash(start, new.len() - end);
+        Ok(Some(changed))
+    }
+
+    /// Where `new` starts and stops differing from the last read, to the
+    /// nearest block: it matches up to the first offset and from the second
+    /// one on, counted in `new`.
+    fn changed_blocks(&self, new: &[u8]) -> (usize, usize) {
+        let shorter = new.len().min(self.contents.len());
+        let head = head_blocks(new)
+            .zip(&self.head)
+            .take_while(|(block, hash)| blake3::hash(block) == **hash)
+            .count();
+        let start = head * BLOCK_SIZE;
+        // The matching end mustn't overlap the matching start in either
+        // version.
+        let tail = tail_blocks(new)
+            .zip(&self.tail)
+            .take_while(|(block, hash)| blake3::hash(block) == **hash)
+            .take((shorter - start) / BLOCK_SIZE)
+            .count();
+        (start, new.len() - tail * BLOCK_SIZE)
+    }
+
+    /// Updates the block hashes after everything from `start` up to the last
+    /// `unchanged_tail` bytes was replaced.
+    fn rehash(&mut self, start: usize, unchanged_tail: usize) {
+        self.head.truncate(start / BLOCK_SIZE);
+        self.head.extend(
+            head_blocks(&self.contents)
+                .skip(self.head.len())
+                .map(blake3::hash),
+        );
+        self.tail.truncate(unchanged_tail / BLOCK_SIZE);
+        self.tail.extend(
+            tail_blocks(&self.contents)
+                .skip(self.tail.len())
+                .map(blake3::hash),
+        );
+    }
+}
+
+/// Maps `path` into memory, or `None` if it is empty, which can't be mapped.
+fn map(path: &Path) -> io::Result<Option<Mmap>> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // SAFETY: another process may write to the file while it is mapped. That
+    // can at worst make one diff show a half-written change, or fault if the
+    // file is truncated mid-read, the price of not copying it all.
+    unsafe { Mmap::map(&file) }.map(Some)
+}
+
+/// The full blocks of `bytes` from its start.
+fn head_blocks(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes.chunks_exact(BLOCK_SIZE)
+}
+
+/// The full blocks of `bytes` from its end, last first.
+fn tail_blocks(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes.rchunks_exact(BLOCK_SIZE)
+}
+
+/// Widens the changed range `start..old_end` of `old`, `start..end` of
+/// `new`, to whole lines. Both share everything before `start` and after
+/// their ends, so the nearest newlines are the same in either.
+fn snap_to_lines(
+    old: &[u8],
+    new: &[u8],
+    start: usize,
+    end: usize,
+    old_end: usize,
+) -> (usize, usize, usize) {
+    let line_start = |bytes: &[u8], at: usize| at == 0 || bytes[at - 1] == b'\n';
+    let start = new[..start]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    // Already at the end of a line in both when whole lines were inserted or
+    // removed.
+    let to_newline = match line_start(old, old_end) && line_start(new, end) {
+        true => 0,
+        false => new[end..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(new.len() - end, |newline| newline + 1),
+    };
+    (start, end + to_newline, old_end + to_newline)
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&byte| byte == b'\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Several blocks' worth of numbered lines.
+    fn numbered() -> String {
+        (0..40_000).map(|i| format!("line {i}\n")).collect()
+    }
+
+    fn diffable(changed: Changed) -> (usize, String, String) {
+        match changed.lines {
+            ChangedLines::Diffable { before, after } => (changed.first_line, before, after),
+            ChangedLines::TooLarge { .. } => panic!("expected a diffable change"),
+        }
+    }
+
+    #[test]
+    fn finds_the_changed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big");
+        let text = numbered();
+        fs::write(&path, &text).unwrap();
+        let mut file = LargeFile::open(&path).unwrap();
+        assert!(file.read_changed(&path).unwrap().is_none());
+
+        let text = text.replace("line 20000\n", "line twenty thousand\n");
+        fs::write(&path, &text).unwrap();
+        let changed = file.read_changed(&path).unwrap().unwrap();
+        assert_eq!(
+            diffable(changed),
+            (
+                20001,
+                "line 20000\n".into(),
+                "line twenty thousand\n".into()
+            )
+        );
+
+        // Inserting shifts every block after it.
+        let text = text.replace("line 100\n", "line 100\ninserted\n");
+        fs::write(&path, &text).unwrap();
+        let changed = file.read_changed(&path).unwrap().unwrap();
+        assert_eq!(diffable(changed), (102, String::new(), "inserted\n".into()));
+        assert_eq!(file.contents, text.as_bytes());
+    }
+
+    #[test]
+    fn only_reports_the_size_of_huge_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big");
+        fs::write(&path, "").unwrap();
+        let mut file = LargeFile::open(&path).unwrap();
+        let huge = "x\n".repeat(MAX_DIFF_SIZE);
+        fs::write(&path, &huge).unwrap();
+        let changed = file.read_changed(&path).unwrap().unwrap();
+        assert_eq!(changed.first_line, 1);
+        assert!(matches!(
+            changed.lines,
+            ChangedLines::TooLarge { before: 0, after } if after == huge.len()
+        ));
+    }
+}
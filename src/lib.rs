@@ -0,0 +1,99 @@
+//! The engine behind the `slip-diff` and `tabbed` binaries, for embedding:
+//! a [`Watcher`] notices a file changing and captures it as text, a
+//! [`VersionHistory`] keeps the versions it went through, and a
+//! [`DiffRenderer`] shows what changed between two of them.
+
+#[cfg(unix)]
+pub mod access;
+pub mod adapter;
+pub mod archive;
+pub mod attributes;
+#[cfg(feature = "tui")]
+pub mod autosave;
+pub mod bisect;
+pub mod cache;
+pub mod capture;
+pub mod clock;
+pub mod comments;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+pub mod cursor;
+#[cfg(unix)]
+pub mod daemon;
+pub mod database;
+pub mod debounce;
+pub mod delta;
+pub mod digest;
+#[cfg(unix)]
+pub mod drift;
+pub mod encoding;
+pub mod eol;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod events;
+pub mod export;
+pub mod extract;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ffi;
+pub mod filter;
+pub mod format;
+pub mod fsck;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod git_export;
+#[cfg(feature = "tui")]
+pub mod graphics;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod grpc;
+pub mod history;
+pub mod hook;
+pub mod images;
+pub mod incremental;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod large;
+pub mod listing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logging;
+#[cfg(feature = "tui")]
+pub mod macros;
+pub mod mail;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod materialize;
+pub mod merge;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mirror;
+pub mod mqtt;
+pub mod notebook;
+pub mod patch;
+pub mod poll;
+pub mod power;
+pub mod preserve;
+pub mod provenance;
+pub mod remote;
+pub mod render;
+pub mod replay;
+pub mod report;
+pub mod resolve;
+pub mod rules;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod serve;
+pub mod session;
+pub mod sink;
+pub mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod store;
+pub mod structured;
+pub mod summary;
+pub mod system_log;
+pub mod table;
+pub mod tail;
+pub mod validate;
+pub mod version_store;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watcher;
+pub mod webhook;
+
+pub use history::VersionHistory;
+pub use render::{DiffRenderer, Renderers};
+#[cfg(not(target_arch = "wasm32"))]
+pub use watcher::Watcher;
@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::html_escape;
+
+/// One entry of the directory a file is watched in, as `--listing` records
+/// it with each version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListingEntry {
+    pub name: String,
+    pub size: u64,
+    pub modified: SystemTime,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_dir: bool,
+}
+
+/// The entries of `dir`, without descending into subdirectories, sorted by
+/// name.
+pub fn read(dir: &Path) -> io::Result<Vec<ListingEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Entries can go between listing the directory and looking at them.
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        entries.push(ListingEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            is_dir: metadata.is_dir(),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// How an entry differs from the listing recorded with the version before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryChange {
+    Appeared,
+    Disappeared,
+    Modified,
+    Unchanged,
+}
+
+impl EntryChange {
+    fn class(self) -> &'static str {
+        match self {
+            Self::Appeared => "insert",
+            Self::Disappeared => "delete",
+            Self::Modified => "modified",
+            Self::Unchanged => "",
+        }
+    }
+}
+
+/// `new` merged with `old` by name, each entry marked with how it changed.
+pub fn compare<'a>(
+    old: &'a [ListingEntry],
+    new: &'a [ListingEntry],
+) -> Vec<(&'a ListingEntry, EntryChange)> {
+    let old: BTreeMap<&str, &ListingEntry> = old.iter().map(|e| (e.name.as_str(), e)).collect();
+    let mut merged: BTreeMap<&str, (&ListingEntry, EntryChange)> = new
+        .iter()
+        .map(|entry| {
+            let change = match old.get(entry.name.as_str()) {
+                None => EntryChange::Appeared,
+                Some(&before) if before != entry => EntryChange::Modified,
+                Some(_) => EntryChange::Unchanged,
+            };
+            (entry.name.as_str(), (entry, change))
+        })
+        .collect();
+    for (name, entry) in old {
+        merged
+            .entry(name)
+            .or_insert((entry, EntryChange::Disappeared));
+    }
+    merged.into_values().collect()
+}
+
+/// An HTML page with a collapsed section for each version that has a
+/// listing, summarizing what appeared and disappeared since the listing
+/// before it.
+pub fn html<'a>(
+    title: &str,
+    listings: impl IntoIterator<Item = (usize, SystemTime, &'a [ListingEntry])>,
+) -> String {
+    let title = html_escape(title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; }}\nth, td {{ text-align: left; padding-right: 1em; }}\n\
+         .insert {{ color: #116329; }}\n.delete {{ color: #82071e; text-decoration: line-through; }}\n\
+         .modified {{ color: #953800; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    let mut previous = None;
+    for (number, at, listing) in listings {
+        // The first listing is only shown, there being nothing to compare.
+        let entries = compare(previous.unwrap_or(listing), listing);
+        let count = |wanted| entries.iter().filter(|(_, c)| *c == wanted).count();
+        let (appeared, disappeared) = (
+            count(EntryChange::Appeared),
+            count(EntryChange::Disappeared),
+        );
+        let _ = writeln!(
+            out,
+            "<details id=\"v{number}\">\n<summary>Version {number} at {}: {} entries, {appeared} appeared, {disappeared} disappeared</summary>\n<table>",
+            humantime::format_rfc3339_seconds(at),
+            listing.len(),
+        );
+        for (entry, change) in entries {
+            let slash = if entry.is_dir { "/" } else { "" };
+            let _ = writeln!(
+                out,
+                "<tr class=\"{}\"><td>{}{slash}</td><td>{}</td><td>{}</td></tr>",
+                change.class(),
+                html_escape(&entry.name),
+                entry.size,
+                humantime::format_rfc3339_seconds(entry.modified),
+            );
+        }
+        out.push_str("</table>\n</details>\n");
+        previous = Some(listing);
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry(name: &str, size: u64) -> ListingEntry {
+        ListingEntry {
+            name: name.into(),
+            size,
+            modified: UNIX_EPOCH + Duration::from_secs(60),
+            is_dir: false,
+        }
+    }
+
+    #[test]
+    fn compares_listings_by_name() {
+        let old = [entry("a.txt", 1), entry("b.lock", 0), entry("c.txt", 3)];
+        let new = [entry("a.txt", 1), entry("c.txt", 4), entry("d.tmp", 9)];
+        let changes: Vec<_> = compare(&old, &new)
+            .into_iter()
+            .map(|(entry, change)| (entry.name.as_str(), change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("a.txt", EntryChange::Unchanged),
+                ("b.lock", EntryChange::Disappeared),
+                ("c.txt", EntryChange::Modified),
+                ("d.tmp", EntryChange::Appeared),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_a_directory_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b"), "bb").unwrap();
+        fs::write(dir.path().join("a"), "a").unwrap();
+        fs::create_dir(dir.path().join("c")).unwrap();
+        let listing = read(dir.path()).unwrap();
+        let names: Vec<_> = listing
+            .iter()
+            .map(|e| (e.name.as_str(), e.size, e.is_dir))
+            .collect();
+        assert_eq!(names, [("a", 1, false), ("b", 2, false), ("c", 0, true)]);
+    }
+
+    #[test]
+    fn html_collapses_each_listing() {
+        let (first, second) = ([entry("a", 1)], [entry("a", 1), entry("<b>", 2)]);
+        let html = html(
+            "notes.txt",
+            [(0, UNIX_EPOCH, &first[..]), (1, UNIX_EPOCH, &second[..])],
+        );
+        assert_eq!(html.matches("<details").count(), 2);
+        assert!(html.contains("2 entries, 1 appeared, 0 disappeared"));
+        assert!(html.contains("<tr class=\"insert\"><td>&lt;b&gt;</td>"));
+    }
+}
@@ -0,0 +1,42 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Logs warnings and errors, and more with every `-v`, to stderr or
+/// `log_file`.
+pub fn init_logging(verbose: u8, log_file: Option<&Path>) -> io::Result<()> {
+    let level = match verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    let logger = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false);
+    match log_file {
+        Some(path) => {
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            logger.with_ansi(false).with_writer(Arc::new(file)).init()
+        }
+        None => logger.with_writer(io::stderr).init(),
+    }
+    Ok(())
+}
+
+/// Where the TUI logs to unless told otherwise, since it draws over the
+/// whole terminal: `$XDG_STATE_HOME/slip-diff/tabbed.log`, falling back to
+/// `~/.local/state`.
+pub fn default_tui_log() -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+        })?;
+    Some(state.join("slip-diff").join("tabbed.log"))
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::KeyCode;
+
+/// Keyboard macros for the TUI, recorded with `q<register>` … `q` and played
+/// back with `@<register>`, persisted as JSON between runs.
+#[derive(Default)]
+pub struct Macros {
+    registers: BTreeMap<char, Vec<KeyCode>>,
+    recording: Option<(char, Vec<KeyCode>)>,
+    path: Option<PathBuf>,
+}
+
+impl Macros {
+    /// Loads the registers saved in `path`, if it exists.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let registers = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            registers,
+            recording: None,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// `$XDG_CONFIG_HOME/slip-diff/macros.json`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("slip-diff").join("macros.json"))
+    }
+
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Adds a key to the macro being recorded, if any.
+    pub fn record(&mut self, key: KeyCode) {
+        if let Some((_, keys)) = self.recording.as_mut() {
+            keys.push(key);
+        }
+    }
+
+    /// Stores the macro being recorded in its register and saves all
+    /// registers. Returns the register that was recorded.
+    pub fn stop(&mut self) -> io::Result<Option<char>> {
+        let Some((register, keys)) = self.recording.take() else {
+            return Ok(None);
+        };
+        self.registers.insert(register, keys);
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, serde_json::to_string_pretty(&self.registers)?)?;
+        }
+        Ok(Some(register))
+    }
+
+    pub fn get(&self, register: char) -> Option<&[KeyCode]> {
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+}
@@ -1,110 +1,2463 @@
 use std::{
+    borrow::Cow,
+    collections::BTreeMap,
     error::Error,
-    fs::{self},
-    path::PathBuf,
-    process::Command,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use clap::Parser;
-use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use similar::{ChangeTag, DiffableStr, TextDiff};
+use clap::{ArgAction, ColorChoice, CommandFactory, FromArgMatches};
+use clap_complete::Shell;
+use regex::Regex;
+use signal_hook::consts::SIGINT;
+
+#[cfg(unix)]
+use slip_diff::daemon::{self, ChangeRecord, Client, Daemon, DaemonArgs, Reply, Request};
+use slip_diff::{
+    archive::{self, MemberChange},
+    attributes::FileAttributes,
+    bisect::Bisect,
+    cache::WarmCache,
+    capture::CaptureArgs,
+    clock::{self, Clock},
+    comments::{self, CommentStyle},
+    config::Config,
+    debounce::Debounce,
+    digest::HashAlgorithm,
+    eol::{self, LineEndings},
+    export,
+    filter::HunkFilter,
+    format::Format,
+    fsck::Problem,
+    git_export,
+    grpc::GrpcServer,
+    history::{FileVersion, HistoryLimits, VersionHistory},
+    hook::{Hook, HookOutcome},
+    images,
+    incremental::IncrementalDiff,
+    large::{ChangedLines, LargeFile},
+    listing::{self, ListingEntry},
+    logging::init_logging,
+    materialize::{self, Materialized},
+    merge::{Choice, Merge},
+    mirror::{Mirror, MirrorSpec},
+    notebook,
+    poll::PollArgs,
+    preserve, provenance, remote,
+    render::{DiffRenderer, RenderArgs, RenderOptions, Renderers, Unified},
+    replay::{Replay, ReplayArgs},
+    report::SessionReport,
+    rules::{Rules, Treatment},
+    serve::Server,
+    session::{self, SessionArgs},
+    sink::{Sink, SinkFormat, SinkSpec},
+    stats::{KnownGood, LineStats},
+    store::{Persistence, SqliteStore, StoreSpec},
+    structured::{self, Change, Syntax},
+    summary,
+    system_log::{LogTarget, SystemLog},
+    table::{self, Dialect},
+    tail::{AppendReader, TailReader},
+    validate::{CommandArgs, Validation, Validator},
+    version_store::{self, VersionStore},
+    watcher::{self, Watcher},
+};
+use tokio::time;
+
+/// How long `slip-diff daemon` waits for the daemon it started to listen.
+#[cfg(unix)]
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, clap::Parser)]
 #[clap(author, version, about)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    #[clap(short, long, required = true)]
-    pub file: PathBuf,
+    #[clap(subcommand)]
+    pub command: Option<Commands>,
+
+    /// The file to watch, `ssh://[user@]host[:port]/path` to poll one on
+    /// another machine over SSH, or `docker://container:/path` to poll one
+    /// inside a running container
+    #[clap(short, long, required_unless_present = "url")]
+    pub file: Option<PathBuf>,
+
+    /// Fetch an `http://` or `https://` URL every --interval and watch the
+    /// response body like a file
+    #[clap(long, conflicts_with = "file")]
+    pub url: Option<String>,
 
     #[clap(short, long)]
     pub clear: bool,
+
+    /// Stable, locale-independent text output for scripts: plain numbers, unified diffs, no colour
+    #[clap(long, global = true)]
+    pub porcelain: bool,
+
+    /// When to colour the output
+    #[clap(long, value_enum, default_value_t, global = true)]
+    pub color: ColorChoice,
+
+    /// Log what happens while watching to stderr: `-v` for versions and re-watches, `-vv` for every event and debounce decision too
+    #[clap(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Write the log to this file instead of stderr
+    #[clap(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// TOML file setting defaults for these flags, and more of them for files matching a `[[profile]]`'s `path` glob [default: ~/.config/slip-diff/config.toml]
+    #[clap(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub render: RenderArgs,
+
+    /// Content hash used to deduplicate versions and in stores, caches and manifests
+    #[clap(long, value_enum, default_value_t, global = true)]
+    pub hash: HashAlgorithm,
+    /// Serve a live web UI of the versions and their diffs on this address, e.g. `127.0.0.1:8080`, with the history as JSON under `/files`
+    #[clap(long, value_name = "ADDR", conflicts_with_all = ["tail", "large_file"])]
+    pub serve: Option<SocketAddr>,
+
+    /// Stream every change as a gRPC `slip_diff.ChangeStream` on this address, e.g. `127.0.0.1:50051`; see proto/slip_diff.proto
+    #[clap(long, value_name = "ADDR", conflicts_with_all = ["tail", "large_file"])]
+    pub grpc: Option<SocketAddr>,
+
+    /// Also show how far every new version drifted from this known-good version [default: the first one captured]
+    #[clap(long, value_name = "VERSION", num_args = 0..=1, conflicts_with_all = ["tail", "large_file"])]
+    pub known_good: Option<Option<usize>>,
+
+    /// Treat the file as append-only and only read and diff what was added, which must be UTF-8 unless --lossy is given
+    #[clap(short, long, conflicts_with = "session")]
+    pub tail: bool,
+
+    /// Memory-map the file and only diff the blocks that changed, for files too large to re-read on every change. Like --tail, changes are shown but not recorded
+    #[clap(long, conflicts_with_all = [
+        "tail", "session", "hook", "validate", "store", "export_git", "patch_dir",
+        "git_autocommit", "vs_git", "summarize", "confirm_window", "sink", "export",
+        "resolve_includes", "only", "ignore", "ignore_comments",
+    ])]
+    pub large_file: bool,
+
+    /// Treat the file as append-only but record every version in full, reading only the bytes added since the last read unless what was read before changed
+    #[clap(long, conflicts_with_all = ["tail", "large_file", "resolve_includes", "encoding", "lossy"])]
+    pub append_only: bool,
+
+    /// Record permissions, ownership, xattrs, ACLs and SELinux labels and report changes to them
+    #[clap(short, long)]
+    pub attributes: bool,
+
+    /// Record the names, sizes and modification times of everything in the file's directory with each version, to see what else appeared or disappeared at the same moment; --export writes them to listings.html
+    #[clap(long)]
+    pub listing: bool,
+
+    /// Look up which processes had the file open for writing when it changed (via lsof/fuser)
+    #[clap(long)]
+    pub provenance: bool,
+
+    /// Rhai script defining `on_change(event)`, run for every change to suppress it or print something else, and `transform(contents)`, run on every read to keep something else, e.g. with secrets masked
+    #[clap(long, conflicts_with = "tail")]
+    pub hook: Option<PathBuf>,
+
+    /// Shell command checking every new version, e.g. `nginx -t` or `yamllint {}` where `{}` is the file
+    #[clap(long, value_name = "COMMAND", conflicts_with = "tail")]
+    pub validate: Option<String>,
+
+    #[clap(flatten)]
+    pub validate_command: CommandArgs,
+
+    #[clap(flatten)]
+    pub capture: CaptureArgs,
+
+    #[clap(flatten)]
+    pub poll: PollArgs,
+
+    #[clap(flatten)]
+    pub limits: HistoryLimits,
+
+    #[clap(flatten)]
+    pub session: SessionArgs,
+
+    /// Persist versions to a store, e.g. `sqlite:versions.db`
+    #[clap(long, conflicts_with = "tail")]
+    pub store: Option<StoreSpec>,
+
+    /// Commit every captured version to a git repository in this directory
+    #[clap(long, conflicts_with = "tail")]
+    pub export_git: Option<PathBuf>,
+
+    /// Write a unified-diff patch for every change to this directory, for `patch` or `git apply`
+    #[clap(long, conflicts_with = "tail")]
+    pub patch_dir: Option<PathBuf>,
+
+    /// Commit every captured version to `refs/slip-diff/<path>` in the file's own git repository
+    #[clap(long, conflicts_with = "tail")]
+    pub git_autocommit: bool,
+
+    /// Diff each version against the file as committed at this git ref instead of the previous version
+    #[clap(long, value_name = "REF", num_args = 0..=1, default_missing_value = "HEAD", conflicts_with = "tail")]
+    pub vs_git: Option<String>,
+
+    /// Only show hunks adding or removing a line that matches this regex, e.g. `timeout`; may be given more than once
+    #[clap(long, value_name = "REGEX", conflicts_with = "tail")]
+    pub only: Vec<Regex>,
+
+    /// Don't show hunks adding or removing a line that matches this regex; may be given more than once
+    #[clap(long, value_name = "REGEX", conflicts_with = "tail")]
+    pub ignore: Vec<Regex>,
+
+    /// Don't show changes that only touch comments (`#`, `//` or `/* */`, by the file's extension), indentation or spacing; they are recorded and marked trivial
+    #[clap(long, conflicts_with = "tail")]
+    pub ignore_comments: bool,
+
+    /// Diff the file as JSON by key path, even if it doesn't end in `.json`
+    #[clap(long)]
+    pub json: bool,
+
+    /// Diff the file as YAML by key path, even if it doesn't end in `.yaml` or `.yml`
+    #[clap(long, conflicts_with = "json")]
+    pub yaml: bool,
+
+    /// Diff the file as CSV cell by cell, even if it doesn't end in `.csv`
+    #[clap(long, conflicts_with_all = ["json", "yaml", "toml"])]
+    pub csv: bool,
+
+    /// Diff the file as TSV cell by cell, even if it doesn't end in `.tsv`
+    #[clap(long, conflicts_with_all = ["json", "yaml", "toml", "csv"])]
+    pub tsv: bool,
+
+    /// Column (header name or 1-based number) identifying rows in CSV/TSV diffs, instead of row position
+    #[clap(long)]
+    pub key_column: Option<String>,
+
+    /// Diff the file as TOML by key path, even if it doesn't end in `.toml`
+    #[clap(long, conflicts_with_all = ["json", "yaml"])]
+    pub toml: bool,
+
+    /// Don't remember the last version seen in the cache directory for the next run
+    #[clap(long)]
+    pub no_cache: bool,
+
+    /// Diff CRLF and LF line endings alike, reporting a switch between them in one line
+    #[clap(long)]
+    pub normalize_eol: bool,
+
+    /// Print a one-line plain-language summary of every change above its diff
+    #[clap(long, conflicts_with = "tail")]
+    pub summarize: bool,
+
+    /// Hold changes back until the file has been quiet this long, then show them as one diff
+    #[clap(long, value_parser = humantime::parse_duration, conflicts_with = "tail")]
+    pub confirm_window: Option<Duration>,
+
+    /// Also write every change to a FIFO or Unix socket, e.g. `fifo:/tmp/changes` or `unix:/run/dash.sock`, publish it to an MQTT topic with `mqtt://<host>/<topic>`, post it to a Slack or Discord webhook with `slack:<url>` or `discord:<url>`, at most every 10s or e.g. `slack/5m:<url>`, or mail an hourly digest with `smtp:smtp://<server>?to=<address>`, or e.g. `smtp/1d:…` daily
+    #[clap(long, conflicts_with = "tail")]
+    pub sink: Vec<SinkSpec>,
+
+    /// What to write to each --sink
+    #[clap(long, value_enum, default_value_t)]
+    pub sink_format: SinkFormat,
+
+    /// Also record every change in syslog or the systemd journal, with the version and line counts as fields
+    #[clap(long, value_enum, conflicts_with = "tail")]
+    pub log_to: Option<LogTarget>,
+
+    /// Include the diff in every --log-to record
+    #[clap(long, requires = "log_to")]
+    pub log_diff: bool,
+
+    /// Also show the output on another terminal or in a file, e.g. `/dev/pts/3`; prefix with `plain:` or `ansi:` to drop or keep colours, kept by default only on terminals
+    #[clap(long)]
+    pub mirror: Vec<MirrorSpec>,
+
+    /// When watching ends, write every stored version to this directory as numbered files
+    #[clap(long, conflicts_with = "tail")]
+    pub export: Option<PathBuf>,
+
+    /// When watching ends, write a report of the session's changes here: how much changed, the busiest minutes, the biggest change and the net diff. HTML if the path ends in .html, text otherwise
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["tail", "large_file"])]
+    pub exit_report: Option<PathBuf>,
+
+    /// Stop watching after this long, e.g. `2h`, as if interrupted with Ctrl-C
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
 }
 
-fn main() {
-    let args = Args::parse();
-    if let Err(error) = watch(&args.file) {
-        println!("Error: {error:?}");
-    }
-}
-
-fn watch(path: &PathBuf) -> Result<(), Box<dyn Error>> {
-    let mut versions: Vec<String> = Vec::new();
-    let zero = fs::read_to_string(path)?;
-    versions.push(zero);
-    let (tx, rx) = std::sync::mpsc::channel();
-
-    // Automatically select the best implementation for your platform.
-    // You can also access each implementation directly e.g. INotifyWatcher.
-    let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
-
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
-
-    while let Ok(res) = rx.try_recv() {
-        match res {
-            Ok(event) => match event.kind {
-                notify::EventKind::Modify(event) => {
-                    match event {
-                        notify::event::ModifyKind::Data(_) => {
-                            let prev = versions.last().unwrap().clone();
-                            let contents = fs::read_to_string(path)?;
-                            if prev != contents {
-                                versions.push(contents);
-                                let len = versions.len();
-                                // print_diff(&versions[len - 2], &versions[len - 1]);
-                                print_diff_delta(&versions[len - 2], &versions[len - 1], false);
-                            }
-                        }
-                        _ => {}
+impl Args {
+    /// The structured format to diff the file as, if any.
+    fn syntax(&self) -> Option<Syntax> {
+        if self.json {
+            return Some(Syntax::Json);
+        }
+        if self.yaml {
+            return Some(Syntax::Yaml);
+        }
+        if self.toml {
+            return Some(Syntax::Toml);
+        }
+        Syntax::detect(self.file())
+    }
+
+    /// Where to keep the warm-start cache, unless it was turned off.
+    fn cache_dir(&self) -> Option<PathBuf> {
+        if self.no_cache || self.tail || self.large_file {
+            return None;
+        }
+        WarmCache::default_dir()
+    }
+
+    /// The tabular dialect to diff the file as, if any.
+    fn dialect(&self) -> Option<Dialect> {
+        if self.csv {
+            return Some(Dialect::Csv);
+        }
+        if self.tsv {
+            return Some(Dialect::Tsv);
+        }
+        if self.json || self.yaml || self.toml {
+            return None;
+        }
+        Dialect::detect(self.file())
+    }
+
+    /// The renderer `--renderer` picked, or the `--rule` for `path` when it
+    /// is left to pick, behind the `--render-cache`. Make one per file and
+    /// keep it, so that the cache is shared between its changes.
+    fn renderer(&self, path: Option<&Path>) -> Arc<dyn DiffRenderer> {
+        let rules = self.capture.rules();
+        let rule = match path.and_then(|path| rules.treatment(path)) {
+            Some(Treatment::Renderer(name)) => Some(name.as_str()),
+            _ => None,
+        };
+        self.render.renderer(rule)
+    }
+
+    fn format(&self) -> Format {
+        Format::new(self.porcelain)
+    }
+
+    /// The watched file, always present unless a subcommand was given.
+    fn file(&self) -> &Path {
+        self.file
+            .as_deref()
+            .or(self.url.as_deref().map(Path::new))
+            .expect("--file or --url is required when watching")
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Commands {
+    /// Play back the versions recorded in a session file
+    Replay {
+        session: PathBuf,
+
+        #[clap(flatten)]
+        replay: ReplayArgs,
+
+        #[clap(short, long)]
+        clear: bool,
+    },
+    /// Write a stored version back to the watched file, snapshotting the
+    /// current contents first
+    Restore {
+        /// The file to restore
+        file: PathBuf,
+
+        /// Sequence number of the version to restore
+        #[clap(long)]
+        version: usize,
+
+        /// Session journal the version was recorded in
+        #[clap(long, required_unless_present = "store", conflicts_with = "store")]
+        session: Option<PathBuf>,
+
+        /// Store the version was recorded in, e.g. `sqlite:versions.db`
+        #[clap(long)]
+        store: Option<StoreSpec>,
+
+        /// Don't ask for confirmation
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Find the first recorded version a test command fails on, like
+    /// `git bisect`
+    Bisect {
+        /// The file the versions were recorded from
+        file: PathBuf,
+
+        /// Shell command testing a version, where `{}` is a temporary copy of it, e.g. `yamllint {}`; exiting with 0 means good
+        #[clap(long, value_name = "COMMAND")]
+        test: String,
+
+        /// Sequence number of a version known to be good [default: the first]
+        #[clap(long)]
+        good: Option<usize>,
+
+        /// Sequence number of a version known to be bad [default: the latest]
+        #[clap(long)]
+        bad: Option<usize>,
+
+        /// Session journal the versions were recorded in
+        #[clap(long, required_unless_present = "store", conflicts_with = "store")]
+        session: Option<PathBuf>,
+
+        /// Store the versions were recorded in, e.g. `sqlite:versions.db`
+        #[clap(long)]
+        store: Option<StoreSpec>,
+    },
+    /// Verify the warm cache, and a store or session journal if given,
+    /// against their hashes
+    Fsck {
+        /// Session journal to check, rebuilding every version it records
+        #[clap(long)]
+        session: Option<PathBuf>,
+
+        /// Store to check, e.g. `sqlite:versions.db`
+        #[clap(long)]
+        store: Option<StoreSpec>,
+
+        /// Fix what can be fixed: drop broken cache entries and leftover blobs, rebuild store indexes and cut off a half-written last journal line
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Write out every file in a store as it was at a given time, e.g. to
+    /// see what was on disk when something broke
+    Materialize {
+        /// When, e.g. `14:32`, `2024-05-01 14:32`, `15m ago` or an RFC 3339 timestamp
+        #[clap(long, value_parser = materialize::parse_time)]
+        at: SystemTime,
+
+        /// Directory to write the files into, below their own paths
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// Store the files were recorded in, e.g. `sqlite:versions.db`
+        #[clap(long)]
+        store: StoreSpec,
+    },
+    /// Watch two copies of a file and, whenever both change differently
+    /// from what they last had in common, pick between their changes side by
+    /// side and write the merged file
+    Sync {
+        /// Our copy, shown on the left
+        ours: PathBuf,
+
+        /// Their copy, shown on the right
+        theirs: PathBuf,
+
+        /// Where to write each merged file; conflicts left unpicked are marked like `git merge` does
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// Wait until both copies have been quiet this long before comparing them, so a copy half-way through being written isn't taken for a change
+        #[clap(long, value_parser = humantime::parse_duration, default_value = "500ms")]
+        settle: Duration,
+
+        #[clap(flatten)]
+        capture: CaptureArgs,
+
+        #[clap(flatten)]
+        poll: PollArgs,
+    },
+    /// Watch a scratch file that the simulator keeps appending to, in the TUI
+    Demo,
+    /// Print a completion script for a shell, e.g.
+    /// `slip-diff completions bash > /etc/bash_completion.d/slip-diff`
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page, e.g. `slip-diff manpage > slip-diff.1`
+    Manpage,
+    /// Open the `tabbed` TUI with the remaining arguments, e.g.
+    /// `slip-diff tui --restore-last`
+    Tui {
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+    /// Watch files in the background, detached from the terminal, and
+    /// control it with `slip-diff ctl`
+    #[cfg(unix)]
+    Daemon {
+        /// The files to watch
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Socket to take `slip-diff ctl` requests on [default: $XDG_RUNTIME_DIR/slip-diff.sock]
+        #[clap(long)]
+        socket: Option<PathBuf>,
+
+        /// Stay in the foreground instead of detaching
+        #[clap(long)]
+        foreground: bool,
+
+        #[clap(flatten)]
+        options: Box<DaemonArgs>,
+
+        #[clap(flatten)]
+        limits: HistoryLimits,
+
+        #[clap(flatten)]
+        capture: CaptureArgs,
+
+        #[clap(flatten)]
+        poll: PollArgs,
+    },
+    /// Control a running `slip-diff daemon`
+    #[cfg(unix)]
+    Ctl {
+        /// Socket the daemon listens on [default: $XDG_RUNTIME_DIR/slip-diff.sock]
+        #[clap(long)]
+        socket: Option<PathBuf>,
+
+        #[clap(subcommand)]
+        request: Request,
+    },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = match configured_args() {
+        Ok(args) => args,
+        Err(error) => {
+            let _ = init_logging(0, None);
+            tracing::error!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(error) = init_logging(args.verbose, args.log_file.as_deref()) {
+        eprintln!("Error: opening the log file: {error}");
+        return ExitCode::FAILURE;
+    }
+    match args.color {
+        ColorChoice::Auto => {}
+        color => {
+            console::set_colors_enabled(color == ColorChoice::Always);
+            console::set_colors_enabled_stderr(color == ColorChoice::Always);
+        }
+    }
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            tracing::error!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the subcommand given, or watches the file if there was none.
+async fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let format = args.format();
+    match &args.command {
+        Some(Commands::Replay {
+            session,
+            replay,
+            clear,
+        }) => {
+            let renderer = args.renderer(None);
+            let options = args.render.options(None);
+            replay_session(
+                session,
+                replay.clone(),
+                *clear,
+                format,
+                &*renderer,
+                &options,
+            )
+            .await
+        }
+        Some(Commands::Restore {
+            file,
+            version,
+            session,
+            store,
+            yes,
+        }) => restore(
+            file,
+            *version,
+            session.as_deref(),
+            store.as_ref(),
+            *yes,
+            args,
+        ),
+        Some(Commands::Bisect {
+            file,
+            test,
+            good,
+            bad,
+            session,
+            store,
+        }) => bisect(
+            file,
+            test,
+            (*good, *bad),
+            session.as_deref(),
+            store.as_ref(),
+            args,
+        ),
+        Some(Commands::Fsck {
+            session,
+            store,
+            repair,
+        }) => fsck(session.as_deref(), store.as_ref(), *repair, args),
+        Some(Commands::Materialize { at, output, store }) => {
+            materialize_store(store, *at, output, format)
+        }
+        Some(Commands::Sync {
+            ours,
+            theirs,
+            output,
+            settle,
+            capture,
+            poll,
+        }) => sync([ours, theirs], output, *settle, capture, poll).await,
+        Some(Commands::Demo) => demo(),
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Args::command(), "slip-diff", &mut io::stdout());
+            Ok(())
+        }
+        Some(Commands::Manpage) => clap_mangen::Man::new(Args::command())
+            .render(&mut io::stdout())
+            .map_err(Into::into),
+        Some(Commands::Tui { args }) => tui(args),
+        #[cfg(unix)]
+        Some(Commands::Daemon {
+            files,
+            socket,
+            foreground: false,
+            ..
+        }) => start_daemon(files, socket.as_deref()),
+        #[cfg(unix)]
+        Some(Commands::Daemon {
+            files,
+            socket,
+            foreground: true,
+            options,
+            limits,
+            capture,
+            poll,
+        }) => {
+            run_daemon(
+                files,
+                socket.as_deref(),
+                options,
+                limits,
+                capture,
+                poll,
+                args,
+            )
+            .await
+        }
+        #[cfg(unix)]
+        Some(Commands::Ctl { socket, request }) => ctl(socket.as_deref(), request, format),
+        None => watch(args).await,
+    }
+}
+
+/// The command line, with the flags the configuration file sets merged in.
+fn configured_args() -> Result<Args, Box<dyn Error>> {
+    let command = Args::command();
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let matches = command.clone().get_matches_from(&args);
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None => Config::default_path().filter(|path| path.exists()),
+    };
+    let Some(path) = path else {
+        return Ok(Args::from_arg_matches(&matches)?);
+    };
+    let file = matches
+        .get_one::<PathBuf>("file")
+        .cloned()
+        .or_else(|| matches.get_one::<String>("url").map(PathBuf::from));
+    let args = Config::load(&path)?.merge(&command, &matches, file.as_deref(), args)?;
+    Ok(Args::from_arg_matches(&command.get_matches_from(args))?)
+}
+
+async fn replay_session(
+    path: &Path,
+    args: ReplayArgs,
+    clear: bool,
+    format: Format,
+    renderer: &dyn DiffRenderer,
+    options: &RenderOptions<'_>,
+) -> Result<(), Box<dyn Error>> {
+    let mut replay = Replay::load(path, args)?;
+    let mut prev: Option<FileVersion> = None;
+    while let Some(version) = replay.next().await {
+        print_separator(clear, format);
+        let old = prev.as_ref().map_or("", |prev| &*prev.contents);
+        print_version_header(&version, old, &version.contents, format);
+        if let Some(prev) = &prev {
+            print_change(&prev.contents, &version.contents, format, renderer, options);
+        }
+        prev = Some(version);
+    }
+    Ok(())
+}
+
+fn restore(
+    path: &Path,
+    number: usize,
+    session: Option<&Path>,
+    store: Option<&StoreSpec>,
+    yes: bool,
+    args: &Args,
+) -> Result<(), Box<dyn Error>> {
+    let format = args.format();
+    let renderer = args.renderer(Some(path));
+    let mut persistence = Persistence::open(path, session, store, None, None, false, None)?;
+    let (version, next_number) = match (session, &persistence.store) {
+        (Some(session), _) => {
+            let versions = session::load(session)?;
+            let next_number = versions.last().map_or(0, |last| last.number + 1);
+            let version = versions.into_iter().find(|v| v.number == number);
+            (version, next_number)
+        }
+        (None, Some(store)) => {
+            let next_number = store.latest(path)?.map_or(0, |last| last.number + 1);
+            (store.version(path, number)?, next_number)
+        }
+        (None, None) => unreachable!("clap requires --session or --store"),
+    };
+    let Some(version) = version else {
+        return Err(format!("no version {number} of {} was recorded", path.display()).into());
+    };
+
+    let current = fs::read_to_string(path)?;
+    if *current == *version.contents {
+        println!(
+            "{} already matches version {}",
+            path.display(),
+            format.count(number)
+        );
+        return Ok(());
+    }
+    let options = args.render.options(Some(path));
+    print_change(&current, &version.contents, format, &*renderer, &options);
+    if !yes && !confirm(&format!("Restore {} to version {number}?", path.display()))? {
+        return Ok(());
+    }
+
+    let mut snapshot = FileVersion::new_at_now(current);
+    snapshot.number = next_number;
+    persistence.record(path, &snapshot.as_version(args.hash))?;
+    let attributes = version.meta.attributes.as_ref();
+    for warning in preserve::restore(path, &version.contents, attributes)? {
+        tracing::warn!("{}: {warning}", path.display());
+    }
+    println!(
+        "Restored version {}, previous contents saved as version {}",
+        format.count(number),
+        format.count(next_number)
+    );
+    Ok(())
+}
+
+/// Tests versions of `file` between the `good` and `bad` ones with `test`
+/// until the first bad one is found, then shows what changed in it.
+fn bisect(
+    file: &Path,
+    test: &str,
+    (good, bad): (Option<usize>, Option<usize>),
+    session: Option<&Path>,
+    store: Option<&StoreSpec>,
+    args: &Args,
+) -> Result<(), Box<dyn Error>> {
+    let versions = match (session, store) {
+        (Some(session), _) => session::load(session)?,
+        (None, Some(StoreSpec::Sqlite(path))) => {
+            SqliteStore::open(path)?.between(file, UNIX_EPOCH, SystemTime::now())?
+        }
+        (None, None) => unreachable!("clap requires --session or --store"),
+    };
+    let position = |number: usize| {
+        versions
+            .iter()
+            .position(|v| v.number == number)
+            .ok_or_else(|| format!("no version {number} of {} was recorded", file.display()))
+    };
+    let good = good.map(position).transpose()?.unwrap_or(0);
+    let bad = match bad {
+        Some(number) => position(number)?,
+        None => versions.len().saturating_sub(1),
+    };
+    let mut bisect = Bisect::new(good, bad)?;
+
+    // Test a copy with the same name, so tools that go by extension work.
+    let dir = tempfile::tempdir()?;
+    let copy = dir
+        .path()
+        .join(file.file_name().unwrap_or("version".as_ref()));
+    let validator = Validator::new(test.to_string(), &args.validate_command);
+    let format = args.format();
+    while let Some(position) = bisect.next() {
+        let version = &versions[position];
+        fs::write(&copy, version.contents.as_bytes())?;
+        let passed = validator.run(&copy)?.passed;
+        bisect.mark(position, passed);
+        let verdict = if passed { "good" } else { "bad" };
+        match format {
+            Format::Human(_) => println!(
+                "version {} is {verdict}, about {} more to test",
+                format.count(version.number),
+                format.count(bisect.steps())
+            ),
+            Format::Porcelain => println!("tested\t{}\t{verdict}", version.number),
+        }
+    }
+
+    let first = bisect.first_bad().unwrap();
+    let version = &versions[first];
+    match format {
+        Format::Human(_) => println!(
+            "version {} from {} is the first bad version",
+            format.count(version.number),
+            format.timestamp(version.at)
+        ),
+        Format::Porcelain => println!(
+            "first-bad\t{}\t{}",
+            version.number,
+            format.timestamp(version.at)
+        ),
+    }
+    print_change(
+        &versions[first - 1].contents,
+        &version.contents,
+        format,
+        &*args.renderer(Some(file)),
+        &args.render.options(Some(file)),
+    );
+    Ok(())
+}
+
+/// Checks the warm cache and the given store and journal, printing every
+/// problem found and failing if any are left unrepaired.
+fn fsck(
+    session: Option<&Path>,
+    store: Option<&StoreSpec>,
+    repair: bool,
+    args: &Args,
+) -> Result<(), Box<dyn Error>> {
+    let mut problems = Vec::new();
+    if let Some(dir) = WarmCache::default_dir().filter(|dir| dir.exists()) {
+        problems.extend(WarmCache::open(&dir)?.check(repair)?);
+    }
+    if let Some(StoreSpec::Sqlite(path)) = store {
+        problems.extend(SqliteStore::open(path)?.check(repair)?);
+    }
+    if let Some(path) = session {
+        problems.extend(session::check(path, repair)?);
+        // Rebuild every version through the delta chains the history would
+        // keep, unless the journal is too damaged to load.
+        if let Ok(versions) = session::load(path) {
+            let mut history = VersionHistory::new(HistoryLimits::default(), args.hash);
+            for version in versions {
+                history.restore(version);
+            }
+            for number in history.verify() {
+                let description = format!("version {number} doesn't rebuild from its deltas");
+                problems.push(Problem::new(description, false));
+            }
+        }
+    }
+
+    let format = args.format();
+    for problem in &problems {
+        match format {
+            Format::Human(_) => println!("{problem}"),
+            Format::Porcelain => println!(
+                "problem\t{}\t{}",
+                if problem.repaired {
+                    "repaired"
+                } else {
+                    "unrepaired"
+                },
+                problem.description
+            ),
+        }
+    }
+    let unrepaired = problems.iter().filter(|p| !p.repaired).count();
+    if let Format::Human(_) = format {
+        println!(
+            "{} problems found, {} repaired",
+            format.count(problems.len()),
+            format.count(problems.len() - unrepaired)
+        );
+    }
+    if unrepaired > 0 {
+        return Err(format!("{unrepaired} problems left unrepaired").into());
+    }
+    Ok(())
+}
+
+/// Writes the files in `store` as they were `at` into `dir`, listing which
+/// version each one came from.
+fn materialize_store(
+    store: &StoreSpec,
+    at: SystemTime,
+    dir: &Path,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
+    let StoreSpec::Sqlite(path) = store;
+    let store = SqliteStore::open(path)?;
+    for result in materialize::materialize(&store, at, dir)? {
+        match (result, format) {
+            (Materialized::Written(path, version), Format::Human(_)) => println!(
+                "{}: version {} from {}",
+                path.display(),
+                format.count(version.number),
+                format.timestamp(version.at)
+            ),
+            (Materialized::Written(path, version), Format::Porcelain) => println!(
+                "materialized\t{}\t{}\t{}",
+                path.display(),
+                version.number,
+                format.timestamp(version.at)
+            ),
+            (Materialized::NotYetCaptured(path), Format::Human(_)) => {
+                println!("{}: not captured yet", path.display())
+            }
+            (Materialized::NotYetCaptured(path), Format::Porcelain) => {
+                println!("missing\t{}", path.display())
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `cat_file` simulator against a file in a temporary directory and
+/// opens it in the `tabbed` TUI, both found next to this executable.
+fn demo() -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+    let bin_dir = exe.parent().ok_or("can't locate the slip-diff binaries")?;
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("demo.txt");
+    fs::write(&file, "")?;
+
+    let mut simulator = Command::new(bin_dir.join("cat_file")).arg(&file).spawn()?;
+    let status = Command::new(bin_dir.join("tabbed"))
+        .arg("--no-cache")
+        .arg("--file")
+        .arg(&file)
+        .status();
+    simulator.kill()?;
+    simulator.wait()?;
+
+    let status = status?;
+    if !status.success() {
+        return Err(format!("tabbed exited with {status}").into());
+    }
+    Ok(())
+}
+
+/// Runs the `tabbed` TUI found next to this executable with `args`.
+fn tui(args: &[OsString]) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+    let bin_dir = exe.parent().ok_or("can't locate the slip-diff binaries")?;
+    let status = Command::new(bin_dir.join("tabbed")).args(args).status()?;
+    if !status.success() {
+        return Err(format!("tabbed exited with {status}").into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn daemon_socket(socket: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
+    match socket {
+        Some(socket) => Ok(socket.to_path_buf()),
+        None => daemon::default_socket()
+            .ok_or_else(|| "no place for the daemon's socket, set --socket or HOME".into()),
+    }
+}
+
+/// Starts this executable again as a daemon in a session of its own, so it
+/// outlives the terminal, and waits until it takes requests. What it prints
+/// goes to a log next to its socket.
+#[cfg(unix)]
+fn start_daemon(files: &[PathBuf], socket: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::process::CommandExt;
+
+    let socket = daemon_socket(socket)?;
+    if Client::connect(&socket).is_ok() {
+        return Err(format!("a daemon is already listening on {}", socket.display()).into());
+    }
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let log_path = socket.with_extension("log");
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)?;
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg("--foreground")
+        .stdin(std::process::Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // SAFETY: setsid is async-signal-safe.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    let started = Instant::now();
+    while started.elapsed() < DAEMON_START_TIMEOUT {
+        if Client::connect(&socket).is_ok() {
+            println!(
+                "Watching {} files in the background as process {}, see `slip-diff ctl`",
+                files.len(),
+                child.id()
+            );
+            return Ok(());
+        }
+        if let Some(status) = child.try_wait()? {
+            return Err(format!(
+                "the daemon exited with {status}, see {}",
+                log_path.display()
+            )
+            .into());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Err(format!(
+        "the daemon didn't start listening on {}, see {}",
+        socket.display(),
+        log_path.display()
+    )
+    .into())
+}
+
+#[cfg(unix)]
+async fn run_daemon(
+    files: &[PathBuf],
+    socket: Option<&Path>,
+    options: &DaemonArgs,
+    limits: &HistoryLimits,
+    capture: &CaptureArgs,
+    poll: &PollArgs,
+    args: &Args,
+) -> Result<(), Box<dyn Error>> {
+    let socket = daemon_socket(socket)?;
+    let daemon = Daemon::new(files, limits, args.hash, capture, poll, options)?;
+    daemon.run(&socket).await?;
+    Ok(())
+}
+
+/// Sends `request` to the daemon and prints its replies.
+#[cfg(unix)]
+fn ctl(socket: Option<&Path>, request: &Request, format: Format) -> Result<(), Box<dyn Error>> {
+    let socket = daemon_socket(socket)?;
+    let mut client = Client::connect(&socket)?;
+    client.send(request)?;
+    while let Some(reply) = client.reply()? {
+        match reply {
+            Reply::Files { files } => {
+                for file in files {
+                    let latest = file.latest.map_or("-".into(), |n| n.to_string());
+                    match format {
+                        Format::Human(_) => println!(
+                            "{}: {} versions, latest {latest}{}",
+                            file.path.display(),
+                            format.count(file.versions),
+                            if file.paused { ", paused" } else { "" }
+                        ),
+                        Format::Porcelain => println!(
+                            "{}\t{}\t{latest}\t{}",
+                            file.path.display(),
+                            file.versions,
+                            if file.paused { "paused" } else { "watching" }
+                        ),
                     }
                 }
-                notify::EventKind::Any => {}
-                notify::EventKind::Access(_) => {}
-                notify::EventKind::Create(_) => {}
-                notify::EventKind::Remove(_) => {}
-                notify::EventKind::Other => {}
+            }
+            Reply::Changes { changes } => {
+                for change in &changes {
+                    print_change_record(change, format);
+                }
+            }
+            Reply::Change(change) => print_change_record(&change, format),
+            Reply::Notice { path, message } => match format {
+                Format::Human(_) => println!("{}: {message}", path.display()),
+                Format::Porcelain => println!("notice\t{}\t{message}", path.display()),
             },
-            Err(error) => println!("Error: {error:?}"),
+            Reply::Done { message } => println!("{message}"),
+            Reply::Error { error } => return Err(error.into()),
+        }
+        if !matches!(request, Request::Attach { .. }) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn print_change_record(change: &ChangeRecord, format: Format) {
+    print_separator(false, format);
+    match format {
+        Format::Human(_) => println!(
+            "{} version {} at {}: +{} -{} lines",
+            change.path.display(),
+            format.count(change.number),
+            format.timestamp(change.at),
+            format.count(change.inserted),
+            format.count(change.deleted)
+        ),
+        Format::Porcelain => println!(
+            "version\t{}\t{}\t{}\t{}\t{}",
+            change.path.display(),
+            change.number,
+            format.timestamp(change.at),
+            change.inserted,
+            change.deleted
+        ),
+    }
+    for line in change.diff.lines() {
+        match (format, line.as_bytes().first()) {
+            (Format::Porcelain, _) => println!("{line}"),
+            _ if line.starts_with("+++") || line.starts_with("---") => {
+                println!("{}", console::style(line).bold())
+            }
+            (_, Some(b'+')) => println!("{}", console::style(line).green()),
+            (_, Some(b'-')) => println!("{}", console::style(line).red()),
+            (_, Some(b'@')) => println!("{}", console::style(line).cyan()),
+            _ => println!("{line}"),
+        }
+    }
+}
+
+fn confirm(question: &str) -> io::Result<bool> {
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Watches two copies of a file and, whenever both have changed
+/// differently since they were last the same, asks which changes to keep and
+/// writes the merged file to `output`. Changes are only looked at once
+/// both copies have been quiet for `settle`.
+async fn sync(
+    paths: [&Path; 2],
+    output: &Path,
+    settle: Duration,
+    capture: &CaptureArgs,
+    poll: &PollArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mut watchers = [
+        Watcher::new(paths[0], capture.capture()?, poll)?,
+        Watcher::new(paths[1], capture.capture()?, poll)?,
+    ];
+    let [ours, theirs] = paths.map(|path| path.display().to_string());
+    let mut base = watchers[0].read()?.contents;
+    if base != watchers[1].read()?.contents {
+        tracing::warn!("{ours} and {theirs} already differ, so {ours} is taken as how they were");
+    }
+    // The copies last merged, not to be asked about again.
+    let mut merged = None;
+    let mut debounce = Debounce::new(settle, clock::system());
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let due = debounce.due();
+        let settled = time::sleep_until(due.unwrap_or_else(Instant::now).into());
+        let [first, second] = &mut watchers;
+        let (side, change) = tokio::select! {
+            _ = &mut ctrl_c => return Ok(()),
+            change = first.next() => (0, change?),
+            change = second.next() => (1, change?),
+            _ = settled, if due.is_some() => (2, watcher::Change::Contents),
+        };
+        match change {
+            watcher::Change::Metadata | watcher::Change::Ignored => continue,
+            _ if side == 2 => debounce.release(),
+            watcher::Change::Moved if !paths[side].exists() => continue,
+            watcher::Change::Moved => {
+                if let Err(error) = watchers[side].rewatch() {
+                    tracing::warn!("watching {}: {error}", paths[side].display());
+                }
+                debounce.change();
+                continue;
+            }
+            watcher::Change::Contents => {
+                debounce.change();
+                continue;
+            }
+        }
+        let copies = (watchers[0].read()?.contents, watchers[1].read()?.contents);
+        if copies.0 == copies.1 {
+            base = copies.0;
+            continue;
         }
+        if copies.0 == base || copies.1 == base || merged.as_ref() == Some(&copies) {
+            continue;
+        }
+        let mut merge = Merge::new(&base, &copies.0, &copies.1);
+        println!(
+            "{}",
+            console::style(format!("{ours} and {theirs} have both changed")).bold()
+        );
+        pick_sides(&mut merge, (&ours, &theirs))?;
+        fs::write(output, merge.result((&ours, &theirs)))?;
+        match merge.conflicts().filter(|c| c.choice.is_none()).count() {
+            0 => println!("Wrote the merged file to {}", output.display()),
+            left => println!(
+                "Wrote the merged file to {}, with {left} conflicts marked",
+                output.display()
+            ),
+        }
+        merged = Some(copies);
     }
+}
 
+/// Shows each conflict of `merge` as three panes, ours, what the copies had
+/// in common and theirs, and asks which to keep until told to quit.
+fn pick_sides(merge: &mut Merge, names: (&str, &str)) -> io::Result<()> {
+    let total = merge.conflicts().count();
+    let width = (console::Term::stdout().size().1 as usize).saturating_sub(6) / 3;
+    for (i, conflict) in merge.conflicts_mut().enumerate() {
+        println!("Conflict {} of {total}:", i + 1);
+        print_panes(
+            [
+                (names.0, &conflict.ours),
+                ("before", &conflict.base),
+                (names.1, &conflict.theirs),
+            ],
+            width.max(10),
+        );
+        conflict.choice = loop {
+            print!(
+                "Keep [o]urs, [t]heirs, [b]oth, what was [B]efore, or [q]uit and mark the rest? "
+            );
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer)? == 0 {
+                return Ok(());
+            }
+            match answer.trim() {
+                "o" => break Some(Choice::Ours),
+                "t" => break Some(Choice::Theirs),
+                "b" => break Some(Choice::Both),
+                "B" => break Some(Choice::Base),
+                "q" => return Ok(()),
+                _ => {}
+            }
+        };
+    }
     Ok(())
 }
 
-fn print_diff(old: &str, new: &str) {
-    println!("OLD: \n{old}\n NEW: \n{new}");
+/// Prints `panes` next to each other, each `width` columns wide and headed
+/// by its name.
+fn print_panes(panes: [(&str, &str); 3], width: usize) {
+    let pad = |text: &str| {
+        let text = text.replace('\t', "    ");
+        console::pad_str(&text, width, console::Alignment::Left, Some("…")).into_owned()
+    };
+    let header = panes.map(|(name, _)| pad(name));
+    println!("{}", console::style(header.join(" │ ")).bold());
+    let lines = panes.map(|(_, text)| text.lines().collect::<Vec<_>>());
+    let rows = lines.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..rows {
+        let cells = lines
+            .each_ref()
+            .map(|lines| pad(lines.get(row).copied().unwrap_or("")));
+        println!("{}", cells.join(" │ "));
+    }
+}
 
-    let diff = TextDiff::from_lines(old, new);
+async fn watch(args: &Args) -> Result<(), Box<dyn Error>> {
+    let path = args.file();
+    if remote::is_remote(path) {
+        if let Some(flag) = local_only(args) {
+            return Err(format!("{flag} needs a local file, not {}", path.display()).into());
+        }
+    }
+    let _mirror = match args.mirror.is_empty() {
+        true => None,
+        false => Some(Mirror::start(&args.mirror)?),
+    };
+    let mut watch = Watch::new(args)?;
 
-    for op in diff.ops() {
-        for change in diff.iter_changes(op) {
-            let (sign, style) = match change.tag() {
-                ChangeTag::Delete => ("-", console::Style::new().red()),
-                ChangeTag::Insert => ("+", console::Style::new().green()),
-                ChangeTag::Equal => (" ", console::Style::new()),
-            };
-            print!("{}{}", style.apply_to(sign).bold(), style.apply_to(change));
+    // Ctrl-C ends the session with a summary; a second one exits right away,
+    // e.g. while a large diff is still being rendered.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 130, Arc::clone(&interrupted))?;
+    signal_hook::flag::register(SIGINT, Arc::clone(&interrupted))?;
+
+    let mut partial = None;
+    if args.tail {
+        partial = Some(PartialReader::Tail(TailReader::at_end(
+            path,
+            args.capture.lossy,
+        )?));
+    } else if args.large_file {
+        partial = Some(PartialReader::Large(LargeFile::open(path)?));
+    } else {
+        let zero = watch.read()?;
+        if watch.versions.is_empty() {
+            let mut version = FileVersion::new_at_now(zero);
+            version.meta.attributes = read_attributes(args)?;
+            version.meta.listing = read_listing(args)?;
+            version.meta.validation = watch.validate()?;
+            version.meta.replaced_invalid = watch.replaced_invalid;
+            if let Some(validation) = &version.meta.validation {
+                print_validation(validation, args.format());
+            }
+            watch.record(version)?;
+        } else {
+            // Show what happened while nobody was watching.
+            watch.changed(zero)?;
+        }
+    }
+    watch.attributes = read_attributes(args)?;
+    if let Some(number) = args.known_good {
+        watch.mark_known_good(number)?;
+    }
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let timeout = args.timeout.map(|timeout| watch.clock.now() + timeout);
+    let timed_out = time::sleep_until(timeout.unwrap_or_else(Instant::now).into());
+    tokio::pin!(timed_out);
+
+    while !interrupted.load(Ordering::Relaxed) {
+        let pending_due = watch.pending_due();
+        let flush = time::sleep_until(pending_due.unwrap_or_else(Instant::now).into());
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            _ = &mut timed_out, if timeout.is_some() => break,
+            change = watch.watcher.next() => match change {
+                Ok(watcher::Change::Metadata) => watch.metadata_changed()?,
+                Ok(watcher::Change::Moved) if !path.exists() => {
+                    tracing::info!("{} was moved away", path.display())
+                }
+                Ok(watcher::Change::Ignored) => {}
+                Ok(change @ (watcher::Change::Contents | watcher::Change::Moved)) => {
+                    // Saving by renaming over the file leaves the watch on
+                    // the old one.
+                    if change == watcher::Change::Moved {
+                        tracing::info!("{} was replaced, watching it afresh", path.display());
+                        if let Err(error) = watch.watcher.rewatch() {
+                            tracing::warn!("watching {}: {error}", path.display());
+                        }
+                    }
+                    if let Some(partial) = partial.as_mut() {
+                        partial.show_changes(args, &*watch.renderer)?;
+                    } else {
+                        let contents = watch.read()?;
+                        watch.changed(contents)?;
+                    }
+                }
+                Err(error) => tracing::warn!("watching {}: {error}", args.file().display()),
+            },
+            _ = flush, if pending_due.is_some() => {}
+        }
+        watch.flush_pending(false)?;
+    }
+    watch.finish(true)
+}
+
+/// Reads only the part of the file that changed, for `--tail` and
+/// `--large-file`, showing changes without recording versions.
+enum PartialReader {
+    Tail(TailReader),
+    Large(LargeFile),
+}
+
+impl PartialReader {
+    /// Prints whatever changed since the last read.
+    fn show_changes(&mut self, args: &Args, renderer: &dyn DiffRenderer) -> io::Result<()> {
+        let path = args.file();
+        let format = args.format();
+        let (before, after, skipped) = match self {
+            Self::Tail(tail) => match tail.read_appended(path)? {
+                Some(appended) => {
+                    print_separator(args.clear, format);
+                    if appended.replaced_invalid {
+                        match format {
+                            Format::Human(_) => println!("invalid UTF-8 replaced with U+FFFD"),
+                            Format::Porcelain => println!("lossy"),
+                        }
+                    }
+                    (appended.before, appended.after, 0)
+                }
+                None => return Ok(()),
+            },
+            Self::Large(large) => {
+                let Some(changed) = large.read_changed(path)? else {
+                    return Ok(());
+                };
+                print_separator(args.clear, format);
+                let line = changed.first_line;
+                match changed.lines {
+                    ChangedLines::Diffable { before, after } => (before, after, line - 1),
+                    ChangedLines::TooLarge { before, after } => {
+                        match format {
+                            Format::Human(_) => println!(
+                                "changed from line {}: {} replaced by {}, too much to diff",
+                                format.count(line),
+                                format.size(before),
+                                format.size(after)
+                            ),
+                            Format::Porcelain => println!("too-large\t{line}\t{before}\t{after}"),
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        let options = RenderOptions {
+            skipped,
+            ..args.render.options(Some(path))
+        };
+        print_change(&before, &after, format, renderer, &options);
+        Ok(())
+    }
+}
+
+/// State of a running watch over one file.
+struct Watch<'a> {
+    args: &'a Args,
+    watcher: Watcher,
+    /// Whether the latest capture had invalid UTF-8 replaced.
+    replaced_invalid: bool,
+    versions: Box<dyn VersionStore>,
+    persistence: Persistence,
+    hook: Option<Hook>,
+    validator: Option<Validator>,
+    /// Hunks to show with `--only` and `--ignore`.
+    filter: HunkFilter,
+    /// Attributes last seen on disk, to report metadata-only changes.
+    attributes: Option<FileAttributes>,
+    /// Changes held back by `--confirm-window`.
+    pending: Option<Pending>,
+    debounce: Option<Debounce>,
+    /// Versions recorded since watching started.
+    captured: usize,
+    /// Lines added and removed across all recorded versions.
+    churn: LineStats,
+    diffs: IncrementalDiff,
+    /// Continues from the last read with `--append-only`.
+    appends: Option<AppendReader>,
+    /// Marked with `--known-good`.
+    known_good: Option<KnownGood>,
+    /// Serves the web UI with `--serve`.
+    server: Option<Server>,
+    /// Streams changes with `--grpc`.
+    grpc: Option<GrpcServer>,
+    /// Written when watching ends with `--exit-report`.
+    report: Option<SessionReport>,
+    /// Renders every change to the file, sharing the `--render-cache`.
+    renderer: Arc<dyn DiffRenderer>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Changes seen during a `--confirm-window` that haven't been shown yet.
+struct Pending {
+    /// Contents before the first held-back change.
+    base: Arc<str>,
+    attributes: Option<FileAttributes>,
+    count: usize,
+}
+
+impl<'a> Watch<'a> {
+    fn new(args: &'a Args) -> Result<Self, Box<dyn Error>> {
+        let mut limits = args.limits.clone();
+        if args.store.is_some() {
+            // The store holds the full history, only the latest version is
+            // needed here to diff against.
+            limits.max_versions.get_or_insert(2);
+        }
+        let mut versions = version_store::open(limits, args.hash)?;
+        let clock = clock::system();
+        let hook = args.hook.as_deref().map(Hook::load).transpose()?;
+        let mut persistence = Persistence::open(
+            args.file(),
+            args.session.session.as_deref(),
+            args.store.as_ref(),
+            args.export_git.as_deref(),
+            args.patch_dir.as_deref(),
+            args.git_autocommit,
+            args.cache_dir().as_deref(),
+        )?;
+        persistence.sinks = args
+            .sink
+            .iter()
+            .map(|spec| Sink::new(spec.clone(), args.sink_format, args.file(), clock::system()))
+            .collect();
+        if let Some(target) = args.log_to {
+            let log = SystemLog::connect(target, args.log_diff)?;
+            persistence
+                .sinks
+                .push(Sink::system_log(target, log, args.file()));
+        }
+        if args.session.resume {
+            for version in session::resume(args.session.session.as_ref().unwrap())? {
+                versions.restore(version)?;
+            }
+        }
+        if let (true, Some(cache)) = (versions.is_empty(), &persistence.cache) {
+            if let Some(last) = cache.last(args.file())? {
+                versions.restore(last)?;
+            }
+        }
+        let server = args
+            .serve
+            .map(|addr| Server::bind(addr, args.file(), args.limits.max_versions))
+            .transpose()?;
+        if let Some(server) = &server {
+            let mut prev: Option<Arc<str>> = None;
+            for version in versions.iter() {
+                let version = version?;
+                server.publish(prev.as_deref(), &version);
+                prev = Some(version.contents);
+            }
+        }
+        let grpc = args
+            .grpc
+            .map(|addr| GrpcServer::bind(addr, args.file()))
+            .transpose()?;
+        Ok(Self {
+            args,
+            watcher: Watcher::new(args.file(), args.capture.capture()?, &args.poll)?,
+            server,
+            grpc,
+            replaced_invalid: false,
+            versions,
+            persistence,
+            hook,
+            validator: args
+                .validate
+                .clone()
+                .map(|command| Validator::new(command, &args.validate_command)),
+            filter: HunkFilter {
+                only: args.only.clone(),
+                ignore: args.ignore.clone(),
+            },
+            attributes: None,
+            pending: None,
+            debounce: args
+                .confirm_window
+                .map(|window| Debounce::new(window, Arc::clone(&clock))),
+            captured: 0,
+            churn: LineStats::default(),
+            diffs: IncrementalDiff,
+            appends: None,
+            known_good: None,
+            report: args
+                .exit_report
+                .as_ref()
+                .map(|_| SessionReport::new(args.file(), Arc::clone(&clock))),
+            renderer: args.renderer(Some(args.file())),
+            clock,
+        })
+    }
+
+    fn read(&mut self) -> Result<String, Box<dyn Error>> {
+        let contents = self.read_file()?;
+        Ok(match &self.hook {
+            Some(hook) => hook.transform(contents),
+            None => contents,
+        })
+    }
+
+    fn read_file(&mut self) -> io::Result<String> {
+        if let Some(appends) = self.appends.as_mut() {
+            if let Some(contents) = appends.read_appended(self.args.file())? {
+                return Ok(contents);
+            }
+        }
+        let snapshot = self.watcher.read()?;
+        self.replaced_invalid = snapshot.replaced_invalid;
+        // An image is described rather than read, so can't be appended to.
+        self.appends = (self.args.append_only && snapshot.image.is_none())
+            .then(|| AppendReader::new(snapshot.contents.clone()));
+        Ok(snapshot.contents)
+    }
+
+    /// Handles freshly read contents, showing and recording them if they
+    /// differ from the latest version.
+    fn changed(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        if self.versions.is_latest(&contents) {
+            return Ok(());
+        }
+        let prev = Arc::clone(&self.versions.last()?.unwrap().contents);
+
+        let mut version = FileVersion::new_at_now(contents);
+        version.number = self.versions.next_number();
+        version.meta.attributes = read_attributes(args)?;
+        version.meta.listing = read_listing(args)?;
+        if args.provenance {
+            version.meta.writers = provenance::writers(args.file());
+        }
+        version.meta.validation = self.validate()?;
+        version.meta.replaced_invalid = self.replaced_invalid;
+        let outcome = match &self.hook {
+            Some(hook) => hook.on_change(args.file(), &prev, &version),
+            None => HookOutcome::Show,
+        };
+        match outcome {
+            HookOutcome::Suppress => {
+                tracing::info!("the hook suppressed version {}", version.number);
+                return Ok(());
+            }
+            HookOutcome::Replace(text) => println!("{text}"),
+            HookOutcome::Show
+                if self.pending.is_none() && self.trivial(&prev, &version.contents) =>
+            {
+                tracing::info!(
+                    "version {} only changed comments or formatting",
+                    version.number
+                );
+                print_separator(args.clear, args.format());
+                print_trivial(&version, args.format());
+            }
+            HookOutcome::Show if self.debounce.is_some() => {
+                if let Some(pending) = self.pending.as_mut() {
+                    pending.count += 1;
+                } else {
+                    self.pending = Some(Pending {
+                        base: prev,
+                        attributes: self.attributes.clone(),
+                        count: 1,
+                    });
+                }
+                self.debounce.as_mut().unwrap().change();
+                tracing::debug!(
+                    "holding version {} back until the file is quiet",
+                    version.number
+                );
+            }
+            HookOutcome::Show => {
+                let attributes = self.attributes.clone();
+                self.show(&prev, attributes.as_ref(), &version, 1)?
+            }
+        }
+        self.attributes.clone_from(&version.meta.attributes);
+        tracing::info!("recorded version {}", version.number);
+        self.record(version)
+    }
+
+    /// Marks version `number`, or the first one in the history, known-good.
+    fn mark_known_good(&mut self, number: Option<usize>) -> Result<(), Box<dyn Error>> {
+        let version = match number {
+            Some(number) => self
+                .versions
+                .infos()
+                .position(|v| v.number == number)
+                .map(|index| self.versions.get(index))
+                .transpose()?
+                .flatten(),
+            None => self.versions.get(0)?,
+        };
+        let Some(version) = version else {
+            let number = number.map_or("the first".to_string(), |n| n.to_string());
+            return Err(format!("version {number} isn't in the history").into());
+        };
+        self.known_good = Some(KnownGood::new(version.number, version.contents));
+        Ok(())
+    }
+
+    /// Prints a change from `prev` to `version`, which stands for `count`
+    /// coalesced changes.
+    fn show(
+        &mut self,
+        prev: &str,
+        prev_attributes: Option<&FileAttributes>,
+        version: &FileVersion,
+        count: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        let format = args.format();
+        let git_base = args
+            .vs_git
+            .as_ref()
+            .map(|rev| git_export::show(args.file(), rev))
+            .transpose()?;
+        let base = git_base.as_deref().unwrap_or(prev);
+        let (base, contents) = match args.normalize_eol {
+            true => (eol::normalize(base), eol::normalize(&version.contents)),
+            false => (Cow::from(base), Cow::from(&*version.contents)),
+        };
+        let contents = match self.filter.is_empty() {
+            true => contents,
+            false => match self.filter.apply(&base, &contents, args.render.context) {
+                Some(filtered) => Cow::Owned(filtered),
+                None => {
+                    tracing::info!(
+                        "--only and --ignore left nothing of version {}",
+                        version.number
+                    );
+                    return Ok(());
+                }
+            },
+        };
+        // Counting and rendering lines only needs the ones that changed.
+        let window = self.diffs.window(&base, &contents, args.render.context);
+        print_separator(args.clear, format);
+        print_version_header(version, window.old, window.new, format);
+        match format {
+            _ if count == 1 => {}
+            Format::Human(_) => println!("coalesced {} changes", format.count(count)),
+            Format::Porcelain => println!("coalesced\t{count}"),
+        }
+        if !version.meta.writers.is_empty() {
+            let writers: Vec<String> = version.meta.writers.iter().map(|w| w.to_string()).collect();
+            println!("modified by {}", writers.join(", "));
+        }
+        if version.meta.replaced_invalid {
+            match format {
+                Format::Human(_) => println!("invalid UTF-8 replaced with U+FFFD"),
+                Format::Porcelain => println!("lossy"),
+            }
+        }
+        if let Some(changes) = archive::changed_members(&base, &contents) {
+            print_member_changes(&changes, format);
+            if let Format::Human(_) = format {
+                let rules = args.capture.rules();
+                let options = args.render.options(None);
+                print_member_details(&changes, &base, &contents, &rules, format, &options);
+            }
+        }
+        if let Some(changes) = notebook::changed_cells(&base, &contents) {
+            for (change, id) in changes {
+                match format {
+                    Format::Human(_) => println!("{} cell {id}", change.symbol()),
+                    Format::Porcelain => println!("cell\t{}\t{id}", change.symbol()),
+                }
+            }
+        }
+        if let Some(distance) = images::perceptual_distance(&base, &contents) {
+            match format {
+                Format::Human(_) => println!("perceptual distance {distance}/64"),
+                Format::Porcelain => println!("perceptual-distance\t{distance}"),
+            }
+        }
+        if args.normalize_eol {
+            let (old, new) = (
+                LineEndings::detect(prev),
+                LineEndings::detect(&version.contents),
+            );
+            match format {
+                _ if old == new => {}
+                Format::Human(_) => println!("line endings changed from {old} to {new}"),
+                Format::Porcelain => println!("eol\t{old}\t{new}"),
+            }
+        }
+        if args.summarize {
+            let summary = summarize(args, &base, &contents);
+            match format {
+                Format::Human(_) => println!("{}", console::style(summary).bold()),
+                Format::Porcelain => println!("summary\t{summary}"),
+            }
+        }
+        if let Some(good) = &self.known_good {
+            print_drift(args, good, &version.contents);
+        }
+        let key = args.key_column.as_deref();
+        let syntax = args.syntax().or_else(|| {
+            let rules = args.capture.rules();
+            match rules.treatment(args.file()) {
+                Some(Treatment::Structured) => Syntax::guess(&contents),
+                _ => None,
+            }
+        });
+        if !print_table(args.dialect(), key, &base, &contents, format)
+            && !print_structured(syntax, &base, &contents, format)
+        {
+            let renderer = &*self.renderer;
+            let options = args.render.options(Some(args.file()));
+            match format {
+                Format::Human(_) if renderer.needs_whole() => {
+                    print_change(&base, &contents, format, renderer, &options)
+                }
+                _ => {
+                    let options = RenderOptions {
+                        skipped: window.skipped,
+                        ..options
+                    };
+                    print_change(window.old, window.new, format, renderer, &options)
+                }
+            }
+        }
+        if let Some(validation) = &version.meta.validation {
+            print_validation(validation, format);
+        }
+        if let Some(current) = &version.meta.attributes {
+            report_attributes(prev_attributes, current);
+        }
+        Ok(())
+    }
+
+    /// Shows the changes held back by `--confirm-window` once the file has
+    /// been quiet for the whole window, or right away if `force` is set.
+    fn flush_pending(&mut self, force: bool) -> Result<(), Box<dyn Error>> {
+        let (Some(debounce), Some(_)) = (&mut self.debounce, &self.pending) else {
+            return Ok(());
+        };
+        if !force && !debounce.settled() {
+            return Ok(());
+        }
+        debounce.release();
+        let pending = self.pending.take().unwrap();
+        let latest = self.versions.last()?.unwrap();
+        if latest.contents == pending.base {
+            // Written back to where it started: nothing to report.
+            tracing::debug!("{} held back changes undid each other", pending.count);
+            return Ok(());
+        }
+        tracing::debug!("showing {} held back changes", pending.count);
+        self.persistence.send(&latest);
+        let latest = latest.to_file_version();
+        self.show(
+            &pending.base,
+            pending.attributes.as_ref(),
+            &latest,
+            pending.count,
+        )
+    }
+
+    /// Whether `--ignore-comments` is on and `old` and `new` only differ in
+    /// comments and whitespace.
+    fn trivial(&self, old: &str, new: &str) -> bool {
+        if !self.args.ignore_comments {
+            return false;
+        }
+        let style = CommentStyle::detect(self.args.file());
+        comments::significant(old, style) == comments::significant(new, style)
+    }
+
+    /// When the changes held back by `--confirm-window` are due to be shown,
+    /// if there are any.
+    fn pending_due(&self) -> Option<Instant> {
+        self.pending.as_ref()?;
+        self.debounce.as_ref()?.due()
+    }
+
+    /// Runs the `--validate` command on the file as it is now, if one is set.
+    fn validate(&self) -> io::Result<Option<Validation>> {
+        self.validator
+            .as_ref()
+            .map(|validator| validator.run(self.args.file()))
+            .transpose()
+    }
+
+    fn metadata_changed(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(current) = read_attributes(self.args)? {
+            report_attributes(self.attributes.as_ref(), &current);
+            self.attributes = Some(current);
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, version: FileVersion) -> Result<(), Box<dyn Error>> {
+        let prev = self.versions.last()?.map(|prev| prev.contents);
+        let mut stats = LineStats::default();
+        if let Some(prev) = &prev {
+            let window = self
+                .diffs
+                .window(prev, &version.contents, self.args.render.context);
+            stats = LineStats::between(window.old, window.new);
+            self.churn.inserted += stats.inserted;
+            self.churn.deleted += stats.deleted;
+        }
+        self.captured += 1;
+        self.versions.set_keep_whole(self.watcher.throttled());
+        self.versions.push(version)?;
+        let latest = self.versions.last()?.unwrap();
+        if let Some(report) = &mut self.report {
+            report.record(
+                prev.as_ref(),
+                latest.number,
+                latest.at,
+                &latest.contents,
+                stats,
+            );
+        }
+        if let Some(server) = &self.server {
+            server.publish(prev.as_deref(), &latest);
+        }
+        if let Some(grpc) = &self.grpc {
+            grpc.publish(prev.as_deref(), &latest);
+        }
+        if self.pending.is_none() {
+            self.persistence.send(&latest);
+        }
+        self.persistence.record(self.args.file(), &latest)
+    }
+
+    /// Shows any held-back changes and runs `--export` once watching ends,
+    /// summarizing the session if it was ended by Ctrl-C.
+    fn finish(&mut self, interrupted: bool) -> Result<(), Box<dyn Error>> {
+        self.flush_pending(true)?;
+
+        let args = self.args;
+        let format = args.format();
+        if let Some(dir) = &args.export {
+            let count = export::export(self.versions.as_ref(), args.file(), dir)?;
+            println!(
+                "Exported {} versions to {}",
+                format.count(count),
+                dir.display()
+            );
+        }
+        if let (Some(report), Some(path)) = (&self.report, &args.exit_report) {
+            let latest = self
+                .versions
+                .last()?
+                .map(|v| v.contents)
+                .unwrap_or_default();
+            report.write(path, &latest)?;
+            println!("Wrote the session report to {}", path.display());
+        }
+        // --tail and --large-file show changes without recording them.
+        if interrupted && !args.tail && !args.large_file {
+            self.print_summary()?;
+        }
+        Ok(())
+    }
+
+    /// Prints how many versions were captured, how much they changed and
+    /// where they were kept.
+    fn print_summary(&self) -> io::Result<()> {
+        let args = self.args;
+        let format = args.format();
+        let mut kept = Vec::new();
+        if let Some(path) = &args.session.session {
+            kept.push(("journal", path.display().to_string()));
+        }
+        if let Some(StoreSpec::Sqlite(path)) = &args.store {
+            kept.push(("store", path.display().to_string()));
+        }
+        if let Some(dir) = &args.export_git {
+            kept.push(("git", dir.display().to_string()));
+        }
+        if let Some(dir) = &args.patch_dir {
+            kept.push(("patches", dir.display().to_string()));
+        }
+        if let Some(shadow) = &self.persistence.shadow {
+            kept.push(("ref", shadow.reference().to_string()));
+        }
+        if let Some(dir) = &args.export {
+            kept.push(("export", dir.display().to_string()));
         }
+
+        match format {
+            Format::Human(_) => {
+                println!(
+                    "captured {} versions of {}: +{} -{} lines",
+                    format.count(self.captured),
+                    args.file().display(),
+                    format.count(self.churn.inserted),
+                    format.count(self.churn.deleted)
+                );
+                if let (Some(good), Some(latest)) = (&self.known_good, self.versions.last()?) {
+                    let drift = good.drift(&latest.contents);
+                    println!(
+                        "drifted +{} -{} lines from known-good version {}",
+                        format.count(drift.inserted),
+                        format.count(drift.deleted),
+                        format.count(good.number)
+                    );
+                }
+                if kept.is_empty() {
+                    println!("history was only kept in memory and is gone");
+                }
+                for (kind, location) in kept {
+                    println!("  {kind}: {location}");
+                }
+            }
+            Format::Porcelain => {
+                println!(
+                    "captured\t{}\t{}\t{}",
+                    self.captured, self.churn.inserted, self.churn.deleted
+                );
+                if let (Some(good), Some(latest)) = (&self.known_good, self.versions.last()?) {
+                    let drift = good.drift(&latest.contents);
+                    println!(
+                        "drift\t{}\t{}\t{}",
+                        good.number, drift.inserted, drift.deleted
+                    );
+                }
+                for (kind, location) in kept {
+                    println!("kept\t{kind}\t{location}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints how far `contents` drifted from the known-good version, and with
+/// `--summarize` what changed since.
+fn print_drift(args: &Args, good: &KnownGood, contents: &str) {
+    let format = args.format();
+    let drift = good.drift(contents);
+    match format {
+        Format::Human(_) => println!(
+            "+{} -{} lines from known-good version {}",
+            format.count(drift.inserted),
+            format.count(drift.deleted),
+            format.count(good.number)
+        ),
+        Format::Porcelain => println!(
+            "drift\t{}\t{}\t{}",
+            good.number, drift.inserted, drift.deleted
+        ),
+    }
+    if args.summarize && drift != LineStats::default() {
+        let summary = summarize(args, good.contents(), contents);
+        match format {
+            Format::Human(_) => println!("since known-good: {summary}"),
+            Format::Porcelain => println!("drift-summary\t{summary}"),
+        }
+    }
+}
+
+/// The first option given that only works on a local file.
+fn local_only(args: &Args) -> Option<&'static str> {
+    [
+        (args.tail, "--tail"),
+        (args.large_file, "--large-file"),
+        (args.append_only, "--append-only"),
+        (args.attributes, "--attributes"),
+        (args.listing, "--listing"),
+        (args.provenance, "--provenance"),
+        (args.capture.resolve_includes, "--resolve-includes"),
+        (args.vs_git.is_some(), "--vs-git"),
+    ]
+    .into_iter()
+    .find_map(|(given, flag)| given.then_some(flag))
+}
+
+fn read_attributes(args: &Args) -> std::io::Result<Option<FileAttributes>> {
+    if !args.attributes {
+        return Ok(None);
+    }
+    FileAttributes::read(args.file()).map(Some)
+}
+
+fn read_listing(args: &Args) -> std::io::Result<Option<Vec<ListingEntry>>> {
+    if !args.listing {
+        return Ok(None);
+    }
+    let dir = match args.file().parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    listing::read(dir).map(Some)
+}
+
+/// Prints changed members as a tree of the directories they are in, e.g.
+///
+/// ```text
+/// dist/
+///   M app.js
+///   A app.js.map
+/// D README
+/// ```
+fn print_member_changes(changes: &BTreeMap<String, MemberChange>, format: Format) {
+    let mut open: Vec<&str> = Vec::new();
+    for (name, change) in changes {
+        if format == Format::Porcelain {
+            println!("member\t{}\t{name}", change.symbol());
+            continue;
+        }
+        let mut parts: Vec<&str> = name.split_inclusive('/').collect();
+        let file = parts.pop().unwrap_or_default();
+        let shared = open.iter().zip(&parts).take_while(|(a, b)| a == b).count();
+        open.truncate(shared);
+        for dir in &parts[shared..] {
+            println!("{}{dir}", "  ".repeat(open.len()));
+            open.push(dir);
+        }
+        println!("{}{} {file}", "  ".repeat(open.len()), change.symbol());
+    }
+}
+
+/// Shows what changed in each modified member that `rules` give a diff mode
+/// or renderer of its own, e.g. key paths for a JSON file in a zip.
+fn print_member_details(
+    changes: &BTreeMap<String, MemberChange>,
+    old: &str,
+    new: &str,
+    rules: &Rules,
+    format: Format,
+    options: &RenderOptions,
+) {
+    let modified = changes
+        .iter()
+        .filter(|(_, change)| **change == MemberChange::Modified);
+    for (name, _) in modified {
+        let treatment = rules.treatment(Path::new(name));
+        if matches!(treatment, None | Some(Treatment::Hex)) {
+            continue;
+        }
+        let (Some(old), Some(new)) = (
+            archive::member_contents(old, name),
+            archive::member_contents(new, name),
+        ) else {
+            continue;
+        };
+        println!("{}", console::style(format!("{name}:")).bold());
+        match treatment {
+            Some(Treatment::Structured) => {
+                let syntax = Syntax::detect(Path::new(name)).or_else(|| Syntax::guess(&new));
+                if !print_structured(syntax, &old, &new, format) {
+                    println!("  not parseable");
+                }
+            }
+            Some(Treatment::Renderer(renderer)) => {
+                let renderer = Renderers::default()
+                    .get(renderer)
+                    .expect("rules are checked when parsed");
+                let options = RenderOptions {
+                    path: Some(Path::new(name)),
+                    ..*options
+                };
+                print_change(&old, &new, format, &*renderer, &options);
+            }
+            None | Some(Treatment::Hex) => unreachable!(),
+        }
+    }
+}
+
+fn print_validation(validation: &Validation, format: Format) {
+    match format {
+        Format::Human(_) if validation.passed => {
+            println!("{}", console::style("validation passed").green())
+        }
+        Format::Human(_) => {
+            println!("{}", console::style("validation failed").red().bold());
+            for line in validation.output.lines() {
+                println!("  {line}");
+            }
+        }
+        Format::Porcelain => println!(
+            "validation\t{}\t{}",
+            if validation.passed {
+                "passed"
+            } else {
+                "failed"
+            },
+            validation.headline()
+        ),
+    }
+}
+
+fn report_attributes(old: Option<&FileAttributes>, new: &FileAttributes) {
+    let Some(old) = old else {
+        return;
+    };
+    for change in new.changes_from(old) {
+        println!("{}", console::style(change).yellow());
     }
 }
 
-fn print_diff_delta(old: &str, new: &str, clear: bool) {
-    let old_file = tempfile::NamedTempFile::new().unwrap();
-    let new_file = tempfile::NamedTempFile::new().unwrap();
-    let _ = std::fs::write(old_file.path(), old);
-    let _ = std::fs::write(new_file.path(), new);
+/// Prints the version's number, capture time, line counts and size.
+/// Line counts are of `new` against `old`, which may be normalized versions
+/// of the contents.
+fn print_version_header(version: &FileVersion, old: &str, new: &str, format: Format) {
+    let stats = LineStats::between(old, new);
+    let size = version.contents.len();
+    match format {
+        Format::Human(_) => println!(
+            "version {} at {}: +{} -{} lines, {}",
+            format.count(version.number),
+            format.timestamp(version.at),
+            format.count(stats.inserted),
+            format.count(stats.deleted),
+            format.size(size)
+        ),
+        Format::Porcelain => println!(
+            "version\t{}\t{}\t{}\t{}\t{}",
+            version.number,
+            format.timestamp(version.at),
+            stats.inserted,
+            stats.deleted,
+            size
+        ),
+    }
+}
+
+fn print_trivial(version: &FileVersion, format: Format) {
+    match format {
+        Format::Human(_) => println!(
+            "version {} at {}: only comments or formatting changed",
+            format.count(version.number),
+            format.timestamp(version.at),
+        ),
+        Format::Porcelain => println!(
+            "trivial\t{}\t{}",
+            version.number,
+            format.timestamp(version.at)
+        ),
+    }
+}
 
+fn print_separator(clear: bool, format: Format) {
+    if format == Format::Porcelain {
+        return;
+    }
     // clear screen
     if clear {
         print!("\x1B[2J\x1B[1;1H");
     } else {
         println!("----------------------------------------------------------------");
     }
+}
+
+/// Describes a change in a sentence, from the table or key-path diff when
+/// the file parses as one and from its lines otherwise.
+fn summarize(args: &Args, old: &str, new: &str) -> String {
+    if let Some(dialect) = args.dialect() {
+        if let (Some(old), Some(new)) = (dialect.parse(old), dialect.parse(new)) {
+            return summary::table(&table::diff(&old, &new, args.key_column.as_deref()));
+        }
+    }
+    if let Some(syntax) = args.syntax() {
+        if let (Some(old), Some(new)) = (syntax.parse(old), syntax.parse(new)) {
+            return summary::structured(&structured::diff(&old, &new));
+        }
+    }
+    summary::lines(old, new)
+}
 
-    let output = Command::new("delta")
-        .arg(old_file.path().to_string_lossy().as_str().unwrap())
-        .arg(new_file.path().to_string_lossy().as_str().unwrap())
-        .output()
+/// Prints added and removed columns and rows and changed cells if both
+/// versions parse as `dialect`, returning false so the caller can fall back
+/// to a line diff otherwise.
+fn print_table(
+    dialect: Option<Dialect>,
+    key_column: Option<&str>,
+    old: &str,
+    new: &str,
+    format: Format,
+) -> bool {
+    let Some(dialect) = dialect else {
+        return false;
+    };
+    let (Some(old), Some(new)) = (dialect.parse(old), dialect.parse(new)) else {
+        return false;
+    };
+    let diff = table::diff(&old, &new, key_column);
+
+    if format == Format::Porcelain {
+        for column in &diff.added_columns {
+            println!("added-column\t{column}");
+        }
+        for column in &diff.removed_columns {
+            println!("removed-column\t{column}");
+        }
+        for (key, row) in &diff.removed_rows {
+            println!("removed-row\t{key}\t{}", row.join("\t"));
+        }
+        for (key, row) in &diff.added_rows {
+            println!("added-row\t{key}\t{}", row.join("\t"));
+        }
+        for change in &diff.changed_cells {
+            println!(
+                "changed\t{}\t{}\t{}\t{}",
+                change.row, change.column, change.old, change.new
+            );
+        }
+        return true;
+    }
+
+    if !diff.added_columns.is_empty() {
+        let added = format!("columns added: {}", diff.added_columns.join(", "));
+        println!("{}", console::style(added).green());
+    }
+    if !diff.removed_columns.is_empty() {
+        let removed = format!("columns removed: {}", diff.removed_columns.join(", "));
+        println!("{}", console::style(removed).red());
+    }
+    let mut rows = vec![vec![
+        String::new(),
+        "row".to_string(),
+        "column".to_string(),
+        "old".to_string(),
+        "new".to_string(),
+    ]];
+    for (key, row) in &diff.removed_rows {
+        rows.push(vec![
+            "-".into(),
+            key.clone(),
+            String::new(),
+            row.join(", "),
+            String::new(),
+        ]);
+    }
+    for (key, row) in &diff.added_rows {
+        rows.push(vec![
+            "+".into(),
+            key.clone(),
+            String::new(),
+            String::new(),
+            row.join(", "),
+        ]);
+    }
+    for change in &diff.changed_cells {
+        rows.push(vec![
+            "~".into(),
+            change.row.clone(),
+            change.column.clone(),
+            change.old.clone(),
+            change.new.clone(),
+        ]);
+    }
+    if rows.len() > 1 {
+        for (line, row) in table::align(&rows).into_iter().zip(&rows) {
+            let style = match row[0].as_str() {
+                "-" => console::Style::new().red(),
+                "+" => console::Style::new().green(),
+                "~" => console::Style::new().yellow(),
+                _ => console::Style::new().bold(),
+            };
+            println!("{}", style.apply_to(line));
+        }
+    }
+    true
+}
+
+/// Prints the key paths that changed if both versions parse as `syntax`,
+/// returning false so the caller can fall back to a line diff otherwise.
+fn print_structured(syntax: Option<Syntax>, old: &str, new: &str, format: Format) -> bool {
+    let Some(syntax) = syntax else {
+        return false;
+    };
+    let (Some(old), Some(new)) = (syntax.parse(old), syntax.parse(new)) else {
+        return false;
+    };
+    for change in structured::diff(&old, &new) {
+        match (format, &change) {
+            (Format::Porcelain, Change::Added { path, value }) => {
+                println!("added\t{path}\t{value}")
+            }
+            (Format::Porcelain, Change::Removed { path, value }) => {
+                println!("removed\t{path}\t{value}")
+            }
+            (Format::Porcelain, Change::Changed { path, old, new }) => {
+                println!("changed\t{path}\t{old}\t{new}")
+            }
+            (Format::Human(_), Change::Added { .. }) => {
+                println!("{}", console::style(change).green())
+            }
+            (Format::Human(_), Change::Removed { .. }) => {
+                println!("{}", console::style(change).red())
+            }
+            (Format::Human(_), Change::Changed { .. }) => {
+                println!("{}", console::style(change).yellow())
+            }
+        }
+    }
+    true
+}
+
+/// Shows a change through `renderer`, or as a plain unified diff for
+/// porcelain output.
+fn print_change(
+    old: &str,
+    new: &str,
+    format: Format,
+    renderer: &dyn DiffRenderer,
+    options: &RenderOptions,
+) {
+    let renderer = match format {
+        Format::Human(_) => renderer,
+        Format::Porcelain => &Unified,
+    };
+    match renderer.render(old, new, options) {
+        Ok(diff) => print!("{diff}"),
+        Err(error) => tracing::error!("rendering the diff: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use slip_diff::session::SessionJournal;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn replaying_needs_no_watched_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let mut journal = SessionJournal::open(&path).unwrap();
+        for (number, contents) in ["one\n", "two\n"].into_iter().enumerate() {
+            let mut version = FileVersion::new_at_now(contents);
+            version.number = number;
+            journal
+                .append(&version.as_version(HashAlgorithm::default()))
+                .unwrap();
+        }
+
+        let args =
+            Args::try_parse_from([OsString::from("slip-diff"), "replay".into(), path.into()])
+                .unwrap();
+        assert!(args.file.is_none());
+        run(&args).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn held_back_changes_reach_sinks_as_one() {
+        use std::{io::Read, os::unix::net::UnixListener};
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        fs::write(&file, "a\n").unwrap();
+        let socket = dir.path().join("changes.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let args = Args::try_parse_from([
+            OsString::from("slip-diff"),
+            "--no-cache".into(),
+            "--confirm-window".into(),
+            "1h".into(),
+            "--sink".into(),
+            format!("unix:{}", socket.display()).into(),
+            "--file".into(),
+            file.into(),
+        ])
         .unwrap();
-    print!("{}", std::str::from_utf8(output.stdout.as_slice()).unwrap());
+
+        let mut watch = Watch::new(&args).unwrap();
+        let zero = watch.read().unwrap();
+        watch.record(FileVersion::new_at_now(zero)).unwrap();
+        // Flapping within the window goes out once, when it closes.
+        for contents in ["b\n", "a\n", "b\n"] {
+            watch.changed(contents.into()).unwrap();
+        }
+        watch.flush_pending(true).unwrap();
+        drop(watch);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut events = String::new();
+        stream.read_to_string(&mut events).unwrap();
+        let events: Vec<serde_json::Value> = events
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["number"], 3);
+        assert!(events[0]["diff"].as_str().unwrap().contains("-a\n+b\n"));
+    }
 }
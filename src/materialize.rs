@@ -0,0 +1,166 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    history::FileVersion,
+    store::SqliteStore,
+};
+
+/// Parses a point in time for `--at`: an RFC 3339 timestamp as slip-diff
+/// prints them, `HH:MM[:SS]` today or `YYYY-MM-DD HH:MM[:SS]` in local time,
+/// or a duration before now such as `15m ago`.
+pub fn parse_time(s: &str) -> Result<SystemTime, String> {
+    parse_time_by(s, &SystemClock)
+}
+
+/// Parses a point in time like [`parse_time`], taking now from `clock`.
+pub fn parse_time_by(s: &str, clock: &dyn Clock) -> Result<SystemTime, String> {
+    let s = s.trim();
+    let now = clock.system_now();
+    if let Some(duration) = s.strip_suffix("ago") {
+        let duration = humantime::parse_duration(duration.trim()).map_err(|e| e.to_string())?;
+        return now
+            .checked_sub(duration)
+            .ok_or_else(|| format!("`{s}` is before the epoch"));
+    }
+    if let Ok(at) = humantime::parse_rfc3339(s) {
+        return Ok(at);
+    }
+
+    let invalid = || format!("`{s}` isn't a time like 14:32, 2024-05-01 14:32 or 15m ago");
+    let (date, time) = match s.split_once([' ', 'T']) {
+        Some((date, time)) => (Some(date), time),
+        None => (None, s),
+    };
+    let date = match date {
+        Some(date) => {
+            let fields = parse_fields(date, '-').ok_or_else(invalid)?;
+            let [year, month, day] = fields[..] else {
+                return Err(invalid());
+            };
+            (year, month, day)
+        }
+        None => today(now),
+    };
+    let time = parse_fields(time, ':').ok_or_else(invalid)?;
+    let (hour, minute, second) = match time[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return Err(invalid()),
+    };
+    local_time(date, (hour, minute, second)).ok_or_else(invalid)
+}
+
+fn parse_fields(s: &str, separator: char) -> Option<Vec<i32>> {
+    s.split(separator).map(|field| field.parse().ok()).collect()
+}
+
+/// The date of `now` in the local time zone.
+fn today(now: SystemTime) -> (i32, i32, i32) {
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as libc::time_t;
+    // SAFETY: localtime_r only writes to the `tm` it is given.
+    let tm = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    (tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday)
+}
+
+/// The instant a local wall-clock time stands for.
+fn local_time(
+    (year, month, day): (i32, i32, i32),
+    (hour, minute, second): (i32, i32, i32),
+) -> Option<SystemTime> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // SAFETY: mktime only reads and normalizes the `tm` it is given.
+    let seconds = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        tm.tm_year = year - 1900;
+        tm.tm_mon = month - 1;
+        tm.tm_mday = day;
+        tm.tm_hour = hour;
+        tm.tm_min = minute;
+        tm.tm_sec = second;
+        // Let the time zone database decide whether DST applies.
+        tm.tm_isdst = -1;
+        libc::mktime(&mut tm)
+    };
+    let seconds = u64::try_from(seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// What [`materialize`] did for one file in the store.
+pub enum Materialized {
+    /// The version that was on disk at the time, written out.
+    Written(PathBuf, FileVersion),
+    /// The file's first version was captured later.
+    NotYetCaptured(PathBuf),
+}
+
+/// Writes the newest version of every file in `store` captured at or before
+/// `at` into `dir`, each at its own path below `dir`, e.g.
+/// `dir/etc/nginx/nginx.conf`.
+pub fn materialize(
+    store: &SqliteStore,
+    at: SystemTime,
+    dir: &Path,
+) -> Result<Vec<Materialized>, Box<dyn Error>> {
+    let mut results = Vec::new();
+    for path in store.paths()? {
+        let Some(version) = store.at(&path, at)? else {
+            results.push(Materialized::NotYetCaptured(path));
+            continue;
+        };
+        let target = dir.join(relative(&path));
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, version.contents.as_bytes())?;
+        results.push(Materialized::Written(path, version));
+    }
+    Ok(results)
+}
+
+/// `path` without its root or any `..`, so it stays inside the output
+/// directory.
+fn relative(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn times_ago_count_back_from_the_clock() {
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(3600));
+        assert_eq!(
+            parse_time_by("15m ago", &clock),
+            Ok(UNIX_EPOCH + Duration::from_secs(2700))
+        );
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(
+            parse_time_by(" 1h ago ", &clock),
+            Ok(UNIX_EPOCH + Duration::from_secs(60))
+        );
+        assert_eq!(
+            parse_time_by("1970-01-01T00:10:00Z", &clock),
+            Ok(UNIX_EPOCH + Duration::from_secs(600))
+        );
+        assert!(parse_time_by("teatime", &clock).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use similar::{DiffOp, TextDiff};
+
+/// Which side of a [`Conflict`] goes into the merged result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Ours,
+    Theirs,
+    /// Ours, then theirs.
+    Both,
+    /// Neither change: the common ancestor's lines.
+    Base,
+}
+
+/// Lines that two copies changed differently since their common ancestor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+    /// What was picked for the result, if anything yet.
+    pub choice: Option<Choice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Chunk {
+    /// Lines both copies agree on, whether changed or not.
+    Merged(String),
+    Conflict(Conflict),
+}
+
+/// A diff3-style merge of two copies of a file, `ours` and `theirs`, that
+/// both changed since `base`. Changes made on one side only, or the same on
+/// both, merge by themselves; the rest are [`Conflict`]s to pick a side of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    chunks: Vec<Chunk>,
+}
+
+impl Merge {
+    pub fn new(base: &str, ours: &str, theirs: &str) -> Self {
+        let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+        let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+        let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+        let ours_at = matching(base, ours, base_lines.len());
+        let theirs_at = matching(base, theirs, base_lines.len());
+
+        let mut merge = Self { chunks: Vec::new() };
+        let (mut b, mut o, mut t) = (0, 0, 0);
+        loop {
+            // Lines neither side touched.
+            while b < base_lines.len() && ours_at[b] == Some(o) && theirs_at[b] == Some(t) {
+                merge.push_merged(base_lines[b]);
+                (b, o, t) = (b + 1, o + 1, t + 1);
+            }
+            if b == base_lines.len() && o == our_lines.len() && t == their_lines.len() {
+                return merge;
+            }
+            // Up to the next line both sides kept.
+            let (b_end, o_end, t_end) = (b..base_lines.len())
+                .find_map(|i| Some((i, ours_at[i]?, theirs_at[i]?)))
+                .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+            let base = base_lines[b..b_end].concat();
+            let ours = our_lines[o..o_end].concat();
+            let theirs = their_lines[t..t_end].concat();
+            if ours == base || ours == theirs {
+                merge.push_merged(&theirs);
+            } else if theirs == base {
+                merge.push_merged(&ours);
+            } else {
+                merge.chunks.push(Chunk::Conflict(Conflict {
+                    base,
+                    ours,
+                    theirs,
+                    choice: None,
+                }));
+            }
+            (b, o, t) = (b_end, o_end, t_end);
+        }
+    }
+
+    fn push_merged(&mut self, text: &str) {
+        match self.chunks.last_mut() {
+            Some(Chunk::Merged(merged)) => merged.push_str(text),
+            _ => self.chunks.push(Chunk::Merged(text.to_string())),
+        }
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = &Conflict> {
+        self.chunks.iter().filter_map(|chunk| match chunk {
+            Chunk::Conflict(conflict) => Some(conflict),
+            Chunk::Merged(_) => None,
+        })
+    }
+
+    pub fn conflicts_mut(&mut self) -> impl Iterator<Item = &mut Conflict> {
+        self.chunks.iter_mut().filter_map(|chunk| match chunk {
+            Chunk::Conflict(conflict) => Some(conflict),
+            Chunk::Merged(_) => None,
+        })
+    }
+
+    /// The merged file, with every conflict left unpicked marked like
+    /// `git merge` does, with `names` for the two sides.
+    pub fn result(&self, names: (&str, &str)) -> String {
+        let mut result = String::new();
+        for chunk in &self.chunks {
+            let conflict = match chunk {
+                Chunk::Merged(text) => {
+                    result.push_str(text);
+                    continue;
+                }
+                Chunk::Conflict(conflict) => conflict,
+            };
+            match conflict.choice {
+                Some(Choice::Ours) => result.push_str(&conflict.ours),
+                Some(Choice::Theirs) => result.push_str(&conflict.theirs),
+                Some(Choice::Base) => result.push_str(&conflict.base),
+                Some(Choice::Both) => {
+                    push_lines(&mut result, &conflict.ours);
+                    result.push_str(&conflict.theirs);
+                }
+                None => {
+                    push_lines(&mut result, &format!("<<<<<<< {}\n", names.0));
+                    push_lines(&mut result, &conflict.ours);
+                    push_lines(&mut result, "||||||| base\n");
+                    push_lines(&mut result, &conflict.base);
+                    push_lines(&mut result, "=======\n");
+                    push_lines(&mut result, &conflict.theirs);
+                    push_lines(&mut result, &format!(">>>>>>> {}\n", names.1));
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Pushes `text` onto `result` as whole lines, even if it is the end of a
+/// file without a final newline.
+fn push_lines(result: &mut String, text: &str) {
+    result.push_str(text);
+    if !text.is_empty() && !text.ends_with('\n') {
+        result.push('\n');
+    }
+}
+
+/// For each of the `len` lines of `base`, the line of `other` it was kept
+/// as, if it was.
+fn matching(base: &str, other: &str, len: usize) -> Vec<Option<usize>> {
+    let mut at = vec![None; len];
+    for op in TextDiff::from_lines(base, other).ops() {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = *op
+        {
+            for i in 0..len {
+                at[old_index + i] = Some(new_index + i);
+            }
+        }
+    }
+    at
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_on_one_side_or_the_same_on_both_merge() {
+        let merge = Merge::new("a\nb\nc\nd\ne\n", "A\nb\nc\nd\nE\n", "a\nb\nC\nd\nE\n");
+        assert_eq!(merge.conflicts().count(), 0);
+        assert_eq!(merge.result(("ours", "theirs")), "A\nb\nC\nd\nE\n");
+    }
+
+    #[test]
+    fn different_changes_to_the_same_lines_conflict() {
+        let mut merge = Merge::new("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+        let conflict = merge.conflicts().next().unwrap();
+        assert_eq!(
+            (&*conflict.base, &*conflict.ours, &*conflict.theirs),
+            ("b\n", "ours\n", "theirs\n")
+        );
+        assert_eq!(
+            merge.result(("x", "y")),
+            "a\n<<<<<<< x\nours\n||||||| base\nb\n=======\ntheirs\n>>>>>>> y\nc\n"
+        );
+        for (choice, result) in [
+            (Choice::Ours, "a\nours\nc\n"),
+            (Choice::Theirs, "a\ntheirs\nc\n"),
+            (Choice::Both, "a\nours\ntheirs\nc\n"),
+            (Choice::Base, "a\nb\nc\n"),
+        ] {
+            merge.conflicts_mut().next().unwrap().choice = Some(choice);
+            assert_eq!(merge.result(("x", "y")), result);
+        }
+    }
+
+    #[test]
+    fn additions_at_the_end_conflict() {
+        let merge = Merge::new("a\n", "a\nb\n", "a\nc\n");
+        assert_eq!(merge.conflicts().count(), 1);
+    }
+}
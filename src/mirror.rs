@@ -0,0 +1,184 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    path::PathBuf,
+    str::FromStr,
+    thread::JoinHandle,
+};
+
+/// Another terminal or a file that output is duplicated to, as given to
+/// `--mirror`.
+#[derive(Debug, Clone)]
+pub struct MirrorSpec {
+    pub path: PathBuf,
+    /// Whether colours and other escape sequences are kept, or `None` to keep
+    /// them only if the target is a terminal.
+    pub ansi: Option<bool>,
+}
+
+impl FromStr for MirrorSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ansi, path) = match s.split_once(':') {
+            Some(("ansi", path)) => (Some(true), path),
+            Some(("plain", path)) => (Some(false), path),
+            _ => (None, s),
+        };
+        if path.is_empty() {
+            return Err(format!("`{s}` is missing the path to mirror to"));
+        }
+        Ok(Self {
+            path: path.into(),
+            ansi,
+        })
+    }
+}
+
+/// A mirror target, opened.
+struct Target {
+    file: File,
+    ansi: bool,
+    /// The start of a line not written yet, to strip escape sequences from
+    /// whole lines only.
+    partial: Vec<u8>,
+}
+
+impl Target {
+    fn open(spec: &MirrorSpec) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&spec.path)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("opening {} to mirror to: {e}", spec.path.display()),
+                )
+            })?;
+        let ansi = spec.ansi.unwrap_or_else(|| file.is_terminal());
+        Ok(Self {
+            file,
+            ansi,
+            partial: Vec::new(),
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.ansi {
+            return self.file.write_all(bytes);
+        }
+        self.partial.extend_from_slice(bytes);
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Ok(());
+        };
+        let rest = self.partial.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.partial, rest);
+        self.write_plain(&lines)
+    }
+
+    fn write_plain(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let text = String::from_utf8_lossy(bytes);
+        self.file
+            .write_all(console::strip_ansi_codes(&text).as_bytes())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let partial = std::mem::take(&mut self.partial);
+        self.write_plain(&partial)
+    }
+}
+
+/// Duplicates everything written to stdout, by this process and the
+/// programs it runs, to the `--mirror` targets until dropped.
+///
+/// Stdout is replaced with a pipe that a thread copies to the original
+/// stdout and every target. A target that can't be written to any more, e.g.
+/// because its terminal was closed, is dropped without failing the watch.
+/// Colours are decided against the original stdout before it is replaced, so
+/// output looks the same as without mirroring.
+pub struct Mirror {
+    /// The original stdout, to put back.
+    #[cfg(unix)]
+    stdout: std::os::fd::OwnedFd,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Mirror {
+    #[cfg(unix)]
+    pub fn start(specs: &[MirrorSpec]) -> io::Result<Self> {
+        use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+
+        let targets = specs.iter().map(Target::open).collect::<io::Result<_>>()?;
+        console::set_colors_enabled(console::colors_enabled());
+        io::stdout().flush()?;
+
+        let stdout = io::stdout().as_fd().try_clone_to_owned()?;
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors pipe writes.
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: pipe succeeded, so both are open and owned by nobody else.
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        for fd in [&read, &write] {
+            // SAFETY: setting a flag on a descriptor owned here.
+            unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+
+        let original = File::from(stdout.try_clone()?);
+        let thread = std::thread::spawn(move || copy(File::from(read), original, targets));
+        // SAFETY: both descriptors are open; dup2 replaces stdout atomically.
+        if unsafe { libc::dup2(write.as_raw_fd(), libc::STDOUT_FILENO) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            stdout,
+            thread: Some(thread),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start(_specs: &[MirrorSpec]) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--mirror is not supported on this platform",
+        ))
+    }
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+
+            let _ = io::stdout().flush();
+            // Putting the original stdout back closes the pipe, which ends
+            // the copying once everything written is through.
+            // SAFETY: both descriptors are open.
+            unsafe { libc::dup2(self.stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Copies `pipe` to `stdout` and `targets` until it is closed.
+fn copy(mut pipe: File, mut stdout: File, mut targets: Vec<Target>) {
+    let mut buf = [0; 8192];
+    loop {
+        let n = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let _ = stdout.write_all(&buf[..n]);
+        targets.retain_mut(|target| target.write(&buf[..n]).is_ok());
+    }
+    for target in targets {
+        let _ = target.finish();
+    }
+}
@@ -0,0 +1,194 @@
+#[cfg(feature = "mqtt")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[cfg(feature = "mqtt")]
+use rumqttc::{Client, ConnectionError, Event, MqttOptions, Outgoing, QoS};
+
+/// The port brokers listen on without TLS.
+const DEFAULT_PORT: u16 = 1883;
+
+/// How many changes are queued while the broker is unreachable before the
+/// newest are dropped.
+#[cfg(feature = "mqtt")]
+const QUEUE: usize = 64;
+
+/// How long to wait before connecting again after the broker went away.
+#[cfg(feature = "mqtt")]
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A broker and topic changes are published to, as given to `--sink`:
+/// `mqtt://[user:password@]host[:port]/topic`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttSpec {
+    pub host: String,
+    pub port: u16,
+    /// Where the topic starts after the host, e.g. `home/files/notes`.
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl MqttSpec {
+    /// Parses the `//…` after `mqtt:`.
+    pub fn parse(rest: &str) -> Result<Self, String> {
+        let invalid = || format!("`mqtt:{rest}` isn't like mqtt://host[:port]/topic");
+        let rest = rest.strip_prefix("//").ok_or_else(invalid)?;
+        let (authority, topic) = rest.split_once('/').ok_or_else(invalid)?;
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(format!("`{topic}` isn't a topic to publish to"));
+        }
+        let (credentials, address) = match authority.rsplit_once('@') {
+            Some((credentials, address)) => (Some(credentials), address),
+            None => (None, authority),
+        };
+        let (username, password) = match credentials.map(|c| c.split_once(':')) {
+            Some(Some((username, password))) => (Some(username), Some(password)),
+            Some(None) => (credentials, None),
+            None => (None, None),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("`{port}` in `mqtt:{rest}` isn't a port"))?,
+            ),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        if !cfg!(feature = "mqtt") {
+            return Err("slip-diff was built without the `mqtt` feature".into());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            topic: topic.to_string(),
+            username: username.map(String::from),
+            password: password.map(String::from),
+        })
+    }
+}
+
+/// Publishes every change to a topic on an MQTT broker, at least once, for
+/// automations to act on.
+///
+/// The connection is kept by a thread and made again whenever the broker
+/// goes away. Like the other sinks it never fails the watch: changes that
+/// can't be queued are dropped.
+#[cfg(feature = "mqtt")]
+pub struct Mqtt {
+    client: Client,
+    topic: String,
+    stopping: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "mqtt")]
+impl Mqtt {
+    pub fn start(spec: &MqttSpec) -> Self {
+        let id = format!("slip-diff-{}", std::process::id());
+        let mut options = MqttOptions::new(id, &spec.host, spec.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &spec.username {
+            options.set_credentials(username, spec.password.as_deref().unwrap_or_default());
+        }
+        let (client, mut connection) = Client::new(options, QUEUE);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stopping);
+        let thread = thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(ConnectionError::RequestsDone) => break,
+                    // Whatever is still queued can't be sent.
+                    Err(_) if stopped.load(Ordering::Relaxed) => break,
+                    Err(_) => thread::sleep(RECONNECT_DELAY),
+                }
+            }
+        });
+        Self {
+            client,
+            topic: spec.topic.clone(),
+            stopping,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn publish(&self, payload: &str) {
+        let _ = self
+            .client
+            .try_publish(&self.topic, QoS::AtLeastOnce, false, payload.as_bytes());
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl Drop for Mqtt {
+    /// Sends what is queued, if the broker is there, before returning.
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        let _ = self.client.disconnect();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Stand-in for builds without the `mqtt` feature, whose sinks can't be
+/// parsed.
+#[cfg(not(feature = "mqtt"))]
+pub struct Mqtt(std::convert::Infallible);
+
+#[cfg(not(feature = "mqtt"))]
+impl Mqtt {
+    pub fn start(_spec: &MqttSpec) -> Self {
+        unreachable!("mqtt sinks can't be parsed without the `mqtt` feature")
+    }
+
+    pub fn publish(&self, _payload: &str) {
+        match self.0 {}
+    }
+}
+
+#[cfg(all(test, feature = "mqtt"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_broker_and_topic() {
+        assert_eq!(
+            MqttSpec::parse("//user:secret@broker.lan:8883/home/files/notes").unwrap(),
+            MqttSpec {
+                host: "broker.lan".into(),
+                port: 8883,
+                topic: "home/files/notes".into(),
+                username: Some("user".into()),
+                password: Some("secret".into()),
+            }
+        );
+        let spec = MqttSpec::parse("//localhost/changes").unwrap();
+        assert_eq!((spec.port, spec.username), (DEFAULT_PORT, None));
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_topic() {
+        for rest in [
+            "localhost/changes",
+            "//localhost",
+            "//localhost/",
+            "///changes",
+            "//localhost/home/+",
+            "//localhost:port/changes",
+        ] {
+            assert!(MqttSpec::parse(rest).is_err(), "{rest}");
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use serde_json::Value;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+/// Indent of a cell's source under its line in a description.
+const SOURCE_INDENT: &str = "    ";
+/// Prefix of a cell's output lines, under its source.
+const OUTPUT_PREFIX: &str = "    > ";
+
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "ipynb")
+}
+
+/// Describes the Jupyter notebook `bytes` as one line per cell naming its
+/// identity and type followed by its source, indented. Outputs and execution
+/// counts change on every run, so they are left out unless `outputs` is set.
+/// Cells without an identity, from notebooks older than format 4.5, are named
+/// by position.
+pub fn describe(bytes: &[u8], outputs: bool) -> String {
+    let notebook: Value = match serde_json::from_slice(bytes) {
+        Ok(notebook) => notebook,
+        // E.g. still being saved.
+        Err(error) => return format!("notebook, unreadable: {error}\n"),
+    };
+    let kernel = notebook["metadata"]["kernelspec"]["name"]
+        .as_str()
+        .unwrap_or("no kernel");
+    let mut description = format!("notebook, {kernel}\n");
+    let cells = notebook["cells"].as_array().map_or(&[][..], Vec::as_slice);
+    for (index, cell) in cells.iter().enumerate() {
+        let id = cell["id"]
+            .as_str()
+            .map_or_else(|| format!("#{}", index + 1), str::to_string);
+        let kind = cell["cell_type"].as_str().unwrap_or("unknown");
+        description.push_str(&format!("cell {id} {kind}"));
+        if let (true, Some(count)) = (outputs, cell["execution_count"].as_u64()) {
+            description.push_str(&format!(" [{count}]"));
+        }
+        description.push('\n');
+        for line in text(&cell["source"]).lines() {
+            description.push_str(&format!("{SOURCE_INDENT}{line}\n"));
+        }
+        if !outputs {
+            continue;
+        }
+        for output in cell["outputs"].as_array().map_or(&[][..], Vec::as_slice) {
+            for line in output_text(output).lines() {
+                description.push_str(&format!("{OUTPUT_PREFIX}{line}\n"));
+            }
+        }
+    }
+    description
+}
+
+/// Notebook text, stored either as one string or as a list of lines.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn output_text(output: &Value) -> String {
+    match output["output_type"].as_str() {
+        Some("stream") => text(&output["text"]),
+        Some("error") => format!(
+            "{}: {}",
+            output["ename"].as_str().unwrap_or_default(),
+            output["evalue"].as_str().unwrap_or_default()
+        ),
+        _ => match &output["data"] {
+            Value::Object(data) if data.contains_key("text/plain") => text(&data["text/plain"]),
+            Value::Object(data) => data
+                .keys()
+                .map(|mime| format!("[{mime} output]\n"))
+                .collect(),
+            _ => String::new(),
+        },
+    }
+}
+
+/// How a cell differs between two versions of a notebook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellChange {
+    Added,
+    Removed,
+    Modified,
+    /// Moved to another position, unchanged otherwise.
+    Moved,
+}
+
+impl CellChange {
+    /// The letter `git status --short` would show for it.
+    pub fn symbol(self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Removed => 'D',
+            Self::Modified => 'M',
+            Self::Moved => 'R',
+        }
+    }
+}
+
+/// The cells that differ between two notebook descriptions, matched by
+/// identity rather than position, in the order they appear in the new
+/// notebook followed by removed ones, or `None` unless both describe a
+/// notebook.
+pub fn changed_cells(old: &str, new: &str) -> Option<Vec<(CellChange, String)>> {
+    let (old, new) = (cells(old)?, cells(new)?);
+    let old_positions: HashMap<&str, usize> = old
+        .iter()
+        .enumerate()
+        .map(|(position, (id, _))| (*id, position))
+        .collect();
+    // Of the cells both versions have, the fewest that could have been
+    // moved to get from the old order to the new one count as moved.
+    let kept: Vec<&str> = new
+        .iter()
+        .map(|(id, _)| *id)
+        .filter(|id| old_positions.contains_key(id))
+        .collect();
+    let mut kept_old: Vec<&str> = old.iter().map(|(id, _)| *id).collect();
+    kept_old.retain(|id| kept.contains(id));
+    let moved: HashSet<&str> = capture_diff_slices(Algorithm::Myers, &kept_old, &kept)
+        .iter()
+        .flat_map(|op| match *op {
+            DiffOp::Insert {
+                new_index, new_len, ..
+            }
+            | DiffOp::Replace {
+                new_index, new_len, ..
+            } => &kept[new_index..new_index + new_len],
+            _ => &[],
+        })
+        .copied()
+        .collect();
+
+    let mut changes = Vec::new();
+    for (id, lines) in &new {
+        let change = match old_positions.get(id) {
+            None => Some(CellChange::Added),
+            Some(&position) if old[position].1 != *lines => Some(CellChange::Modified),
+            Some(_) => moved.contains(id).then_some(CellChange::Moved),
+        };
+        if let Some(change) = change {
+            changes.push((change, id.to_string()));
+        }
+    }
+    let new_ids: Vec<&str> = new.iter().map(|(id, _)| *id).collect();
+    for (id, _) in &old {
+        if !new_ids.contains(id) {
+            changes.push((CellChange::Removed, id.to_string()));
+        }
+    }
+    Some(changes)
+}
+
+/// Each cell's identity and lines in a description, in order.
+fn cells(description: &str) -> Option<Vec<(&str, Vec<&str>)>> {
+    let mut lines = description.lines();
+    lines
+        .next()
+        .filter(|first| first.starts_with("notebook, "))?;
+    let mut cells: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in lines {
+        match line.strip_prefix("cell ") {
+            Some(header) => {
+                let id = header.split(' ').next().unwrap_or(header);
+                cells.push((id, vec![line]));
+            }
+            None => {
+                if let Some((_, lines)) = cells.last_mut() {
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    Some(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r##"{
+        "metadata": {"kernelspec": {"name": "python3"}},
+        "cells": [
+            {"id": "intro", "cell_type": "markdown", "source": "# Sales"},
+            {"id": "load", "cell_type": "code", "execution_count": 1,
+             "source": ["import pandas\n", "df = pandas.read_csv('a.csv')"],
+             "outputs": [{"output_type": "stream", "text": "loaded\n"}]},
+            {"id": "plot", "cell_type": "code", "execution_count": 2,
+             "source": "df.plot()", "outputs": []}
+        ]
+    }"##;
+
+    /// `OLD` run again, with `load` edited, `plot` moved before it and a
+    /// cell added.
+    const NEW: &str = r##"{
+        "metadata": {"kernelspec": {"name": "python3"}},
+        "cells": [
+            {"id": "intro", "cell_type": "markdown", "source": "# Sales"},
+            {"id": "plot", "cell_type": "code", "execution_count": 9,
+             "source": "df.plot()",
+             "outputs": [{"output_type": "display_data", "data": {"image/png": "..."}}]},
+            {"id": "load", "cell_type": "code", "execution_count": 8,
+             "source": ["import pandas\n", "df = pandas.read_csv('b.csv')"],
+             "outputs": [{"output_type": "error", "ename": "FileNotFoundError",
+                          "evalue": "b.csv"}]},
+            {"id": "total", "cell_type": "code", "execution_count": null,
+             "source": "df.sum()", "outputs": []}
+        ]
+    }"##;
+
+    #[test]
+    fn outputs_and_execution_counts_are_ignored() {
+        assert_eq!(
+            describe(OLD.as_bytes(), false),
+            "notebook, python3\ncell intro markdown\n    # Sales\n\
+             cell load code\n    import pandas\n    df = pandas.read_csv('a.csv')\n\
+             cell plot code\n    df.plot()\n"
+        );
+        let rerun = OLD.replace("\"execution_count\": 1", "\"execution_count\": 7");
+        let rerun = rerun.replace("loaded", "loaded again");
+        assert_eq!(
+            describe(rerun.as_bytes(), false),
+            describe(OLD.as_bytes(), false)
+        );
+
+        let with_outputs = describe(NEW.as_bytes(), true);
+        assert!(
+            with_outputs.contains("cell plot code [9]\n    df.plot()\n    > [image/png output]\n")
+        );
+        assert!(with_outputs.contains("    > FileNotFoundError: b.csv\n"));
+    }
+
+    #[test]
+    fn cells_are_matched_by_identity() {
+        let (old, new) = (
+            describe(OLD.as_bytes(), false),
+            describe(NEW.as_bytes(), false),
+        );
+        let changes: Vec<(char, String)> = changed_cells(&old, &new)
+            .unwrap()
+            .into_iter()
+            .map(|(change, id)| (change.symbol(), id))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ('R', "plot".to_string()),
+                ('M', "load".to_string()),
+                ('A', "total".to_string()),
+            ]
+        );
+        assert_eq!(changed_cells(&new, &new), Some(Vec::new()));
+        assert!(changed_cells("plain text\n", &new).is_none());
+    }
+
+    #[test]
+    fn cells_without_identity_are_named_by_position() {
+        let notebook = r#"{"cells": [{"cell_type": "code", "source": "1 + 1"}]}"#;
+        assert_eq!(
+            describe(notebook.as_bytes(), false),
+            "notebook, no kernel\ncell #1 code\n    1 + 1\n"
+        );
+        assert!(describe(b"{\"cells\": [", false).starts_with("notebook, unreadable: "));
+        assert!(is_notebook(Path::new("analysis.ipynb")));
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use similar::TextDiff;
+
+use crate::history::Version;
+
+/// A directory receiving one unified-diff patch per change, applyable to the
+/// watched file with `patch -p1` or `git apply` (or reverted with `-R`).
+pub struct PatchDir {
+    dir: PathBuf,
+    name: String,
+    previous: Option<Arc<str>>,
+}
+
+impl PatchDir {
+    pub fn open(dir: &Path, file: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_string());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name,
+            previous: None,
+        })
+    }
+
+    /// Writes the patch from the previously recorded version to `version`
+    /// as `<number>-<timestamp>.patch`. The first version only becomes the
+    /// baseline.
+    pub fn write(&mut self, version: &Version) -> io::Result<()> {
+        let Some(previous) = self.previous.replace(Arc::clone(&version.contents)) else {
+            return Ok(());
+        };
+        if previous == version.contents {
+            return Ok(());
+        }
+
+        let old = format!("a/{}", self.name);
+        let new = format!("b/{}", self.name);
+        let diff = TextDiff::from_lines(&*previous, &*version.contents);
+        let patch = diff.unified_diff().header(&old, &new).to_string();
+
+        let timestamp = humantime::format_rfc3339_seconds(version.at)
+            .to_string()
+            .replace(':', "-");
+        let path = self
+            .dir
+            .join(format!("{:04}-{timestamp}.patch", version.number));
+        fs::File::create(path)?.write_all(patch.as_bytes())
+    }
+}
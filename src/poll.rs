@@ -0,0 +1,40 @@
+use std::{path::Path, time::Duration};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct PollArgs {
+    /// Poll and re-read the file instead of waiting for change events, for
+    /// virtual files such as /proc and /sys that report size 0 and never emit
+    /// events (on by default for paths under /proc and /sys)
+    #[clap(long = "virtual")]
+    pub virtual_file: bool,
+
+    /// How often to re-read the file when polling, e.g. `500ms` or `5s`
+    #[clap(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    pub interval: Duration,
+
+    /// While on battery, poll less often, read a burst of changes once, and keep versions whole instead of delta-compressing them
+    #[clap(long)]
+    pub low_power: bool,
+}
+
+impl Default for PollArgs {
+    /// What the flags default to: events where the OS sends them, and
+    /// polling every second where it doesn't.
+    fn default() -> Self {
+        Self {
+            virtual_file: false,
+            interval: Duration::from_secs(1),
+            low_power: false,
+        }
+    }
+}
+
+impl PollArgs {
+    /// Whether `path` should be polled rather than watched for events.
+    pub fn should_poll(&self, path: &Path) -> bool {
+        self.virtual_file
+            || path.starts_with("/proc")
+            || path.starts_with("/sys")
+            || crate::remote::is_remote(path)
+    }
+}
@@ -0,0 +1,47 @@
+/// Whether the machine is running on battery, as upower would report it.
+/// Machines without a battery, or whose power source can't be told, are
+/// taken to be on mains power.
+#[cfg(target_os = "linux")]
+pub fn on_battery() -> bool {
+    use std::fs;
+
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        let read = |name: &str| fs::read_to_string(path.join(name)).unwrap_or_default();
+        match read("type").trim() {
+            "Mains" | "USB" if read("online").trim() == "1" => return false,
+            "Battery" => discharging |= read("status").trim() == "Discharging",
+            _ => {}
+        }
+    }
+    discharging
+}
+
+#[cfg(target_os = "macos")]
+pub fn on_battery() -> bool {
+    std::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+}
+
+#[cfg(windows)]
+pub fn on_battery() -> bool {
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    // SAFETY: the status is plain data, filled in by the call.
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    // SAFETY: `status` is a valid, writable SYSTEM_POWER_STATUS.
+    let ok = unsafe { GetSystemPowerStatus(&mut status) } != 0;
+    // 0 is offline, 1 online and 255 unknown.
+    ok && status.ACLineStatus == 0
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn on_battery() -> bool {
+    false
+}
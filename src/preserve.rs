@@ -0,0 +1,168 @@
+use std::{fs, io, path::Path};
+
+use crate::{attributes::FileAttributes, eol::LineEndings};
+
+const BOM: char = '\u{feff}';
+
+/// What a diff doesn't show about a file but tools reading it care about,
+/// which restoring an older version keeps as the file on disk has it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invisible {
+    pub bom: bool,
+    /// The `#!` line, without its line ending.
+    pub shebang: Option<String>,
+    pub line_endings: LineEndings,
+}
+
+impl Invisible {
+    pub fn of(text: &str) -> Self {
+        let bom = text.starts_with(BOM);
+        let text = text.strip_prefix(BOM).unwrap_or(text);
+        Self {
+            bom,
+            shebang: shebang(text).map(str::to_string),
+            line_endings: LineEndings::detect(text),
+        }
+    }
+}
+
+/// The first line of `text` if it is a `#!` line, without its line ending.
+fn shebang(text: &str) -> Option<&str> {
+    let line = text.lines().next()?;
+    line.starts_with("#!").then(|| line.trim_end_matches('\r'))
+}
+
+/// Writes `contents`, a version recorded with `attributes` if they were
+/// read, over the file at `path`, keeping the byte order mark, shebang line,
+/// line endings and executable bit the file has now. Returns a warning for
+/// each of these the version differs in.
+pub fn restore(
+    path: &Path,
+    contents: &str,
+    attributes: Option<&FileAttributes>,
+) -> io::Result<Vec<String>> {
+    let current = match fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::write(path, contents)?;
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e),
+    };
+    let (restored, mut warnings) = preserving(&current, contents);
+    fs::write(path, restored)?;
+    if let Some(warning) = keep_executable(path, attributes)? {
+        warnings.push(warning);
+    }
+    Ok(warnings)
+}
+
+/// `contents` with the byte order mark, shebang line and line endings of
+/// `current`, and a warning for each that differed.
+pub fn preserving(current: &str, contents: &str) -> (String, Vec<String>) {
+    let on_disk = Invisible::of(current);
+    let restored = Invisible::of(contents);
+    let mut warnings = Vec::new();
+    let mut text = contents.strip_prefix(BOM).unwrap_or(contents).to_string();
+
+    if on_disk.bom != restored.bom {
+        warnings.push(match on_disk.bom {
+            true => "the version has no byte order mark; kept the file's".to_string(),
+            false => "the version has a byte order mark; left it out like the file".to_string(),
+        });
+    }
+    match (on_disk.line_endings, restored.line_endings) {
+        (LineEndings::Lf, LineEndings::Crlf) => {
+            warnings.push("the version has CRLF line endings; kept the file's LF".to_string());
+            text = text.replace("\r\n", "\n");
+        }
+        (LineEndings::Crlf, LineEndings::Lf) => {
+            warnings.push("the version has LF line endings; kept the file's CRLF".to_string());
+            text = text.replace('\n', "\r\n");
+        }
+        _ => {}
+    }
+    if on_disk.shebang != restored.shebang {
+        let first_line = text.find('\n').map_or(text.len(), |end| end + 1);
+        let line_ending = match on_disk.line_endings {
+            LineEndings::Crlf => "\r\n",
+            _ => "\n",
+        };
+        match (&on_disk.shebang, &restored.shebang) {
+            (Some(kept), Some(shebang)) => {
+                warnings.push(format!(
+                    "the version starts with `{shebang}`; kept `{kept}`"
+                ));
+                text.replace_range(..first_line, &format!("{kept}{line_ending}"));
+            }
+            (Some(kept), None) => {
+                warnings.push(format!("the version has no shebang line; kept `{kept}`"));
+                text.insert_str(0, &format!("{kept}{line_ending}"));
+            }
+            (None, Some(shebang)) => {
+                warnings.push(format!(
+                    "the version starts with `{shebang}`, which the file doesn't have"
+                ));
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    if on_disk.bom {
+        text.insert(0, BOM);
+    }
+    (text, warnings)
+}
+
+/// Warns if the version was recorded with a different executable bit than
+/// the file has, which writing over it in place has kept.
+#[cfg(unix)]
+fn keep_executable(path: &Path, recorded: Option<&FileAttributes>) -> io::Result<Option<String>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let Some(recorded) = recorded else {
+        return Ok(None);
+    };
+    let executable = fs::metadata(path)?.permissions().mode() & 0o111 != 0;
+    Ok(match (executable, recorded.mode & 0o111 != 0) {
+        (true, false) => Some("the version wasn't executable; kept the file executable".into()),
+        (false, true) => Some("the version was executable; left the file as it was".into()),
+        _ => None,
+    })
+}
+
+#[cfg(not(unix))]
+fn keep_executable(_path: &Path, _recorded: Option<&FileAttributes>) -> io::Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_files_bom_and_line_endings() {
+        let (text, warnings) = preserving("\u{feff}a\r\nb\r\n", "a\nc\n");
+        assert_eq!(text, "\u{feff}a\r\nc\r\n");
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn keeps_the_files_shebang() {
+        let (text, warnings) = preserving("#!/bin/bash\necho a\n", "#!/bin/sh\necho b\n");
+        assert_eq!(text, "#!/bin/bash\necho b\n");
+        assert_eq!(
+            warnings,
+            ["the version starts with `#!/bin/sh`; kept `#!/bin/bash`"]
+        );
+
+        let (text, _) = preserving("#!/bin/sh\necho a\n", "echo b\n");
+        assert_eq!(text, "#!/bin/sh\necho b\n");
+    }
+
+    #[test]
+    fn matching_versions_are_restored_as_they_are() {
+        let (text, warnings) = preserving("#!/bin/sh\r\na\r\n", "#!/bin/sh\r\nb\r\n");
+        assert_eq!(text, "#!/bin/sh\r\nb\r\n");
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use std::{fmt, path::Path, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+/// A process that had a file open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Process {
+    pub pid: u32,
+    pub command: String,
+}
+
+impl fmt::Display for Process {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.command, self.pid)
+    }
+}
+
+/// Best-effort list of processes that have `path` open for writing right
+/// now, via `lsof`, falling back to every process `fuser` reports holding it
+/// open. Returns nothing if neither tool is available or the writer already
+/// closed the file.
+pub fn writers(path: &Path) -> Vec<Process> {
+    if cfg!(windows) {
+        return Vec::new();
+    }
+    lsof_writers(path)
+        .or_else(|| fuser_users(path))
+        .unwrap_or_default()
+}
+
+/// Parses `lsof -F` output: a `p<pid>` line starts each process, followed by
+/// `c<command>` and, per open descriptor, `f<fd>` and `a<mode>` lines.
+fn lsof_writers(path: &Path) -> Option<Vec<Process>> {
+    let output = Command::new("lsof")
+        .arg("-Fpcfa")
+        .arg("--")
+        .arg(path)
+        .output()
+        .ok()?;
+    // lsof exits with 1 when nothing has the file open.
+    if !output.status.success() && !output.stdout.is_empty() {
+        return None;
+    }
+
+    let mut writers: Vec<Process> = Vec::new();
+    let mut current: Option<Process> = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let (field, value) = line.split_at(line.len().min(1));
+        match field {
+            "p" => {
+                current = value.parse().ok().map(|pid| Process {
+                    pid,
+                    command: String::new(),
+                })
+            }
+            "c" => {
+                if let Some(process) = current.as_mut() {
+                    process.command = value.to_string();
+                }
+            }
+            "a" if value == "w" || value == "u" => {
+                if let Some(process) = &current {
+                    if !writers.contains(process) {
+                        writers.push(process.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(writers)
+}
+
+/// `fuser` prints the pids on stdout and the path on stderr, and can't tell
+/// readers from writers.
+fn fuser_users(path: &Path) -> Option<Vec<Process>> {
+    let output = Command::new("fuser").arg(path).output().ok()?;
+    let users = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .filter_map(|pid| pid.trim_end_matches(char::is_alphabetic).parse().ok())
+        .map(|pid: u32| Process {
+            pid,
+            command: std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                .map(|comm| comm.trim().to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+    Some(users)
+}
@@ -0,0 +1,671 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::digest::HashAlgorithm;
+
+const SSH: &str = "ssh://";
+const DOCKER: &str = "docker://";
+const KUBERNETES: &str = "k8s://";
+const SCHEMES: [&str; 5] = [SSH, DOCKER, KUBERNETES, "http://", "https://"];
+
+/// How long to wait before watching a Kubernetes object again after
+/// `kubectl` stopped, e.g. because the API server ended the watch.
+const REWATCH_DELAY: Duration = Duration::from_secs(1);
+
+/// Response headers that change on every request, left out of `--headers`.
+const VOLATILE_HEADERS: [&str; 3] = ["date", "age", "expires"];
+
+/// A file somewhere else, given in place of a local path as a URL, read by
+/// running a command that prints it. Most are polled since there are no
+/// change events. Nothing needs installing on the other side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteFile {
+    /// A file on another machine, as `ssh://[user@]host[:port]/path`, or
+    /// `ssh://host/~/path` relative to the remote home directory, read with
+    /// `cat` over `ssh`. Reads reuse one connection, kept open for a minute
+    /// after the last, and never prompt: the host needs to be reachable with
+    /// keys or an agent.
+    Ssh {
+        /// `[user@]host`, as `ssh` takes it.
+        destination: String,
+        port: Option<u16>,
+        /// Relative to the home directory if it doesn't start with `/`.
+        path: String,
+    },
+    /// A file in a running container, as `docker://container:/path`, read
+    /// with `docker exec` so it needn't be bind-mounted.
+    Docker { container: String, path: String },
+    /// The body of an `http://` or `https://` URL, fetched with `curl`
+    /// following redirects.
+    Http { url: String },
+    /// The data of a ConfigMap or Secret, as `k8s://namespace/kind/name`,
+    /// read with `kubectl` in its current context. It isn't polled: the
+    /// object is read again whenever the watch API reports a new revision.
+    /// Each key is a line, or an indented block for multi-line values;
+    /// Secret and binary values are only shown as digests, so they don't end
+    /// up in the history. Secret digests are keyed at random for each run,
+    /// so that short values can't be guessed back from them; restarting
+    /// shows every Secret value as changed.
+    Kubernetes {
+        namespace: String,
+        kind: ObjectKind,
+        name: String,
+    },
+}
+
+/// The kinds of Kubernetes object whose data can be watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    ConfigMap,
+    Secret,
+}
+
+impl ObjectKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ConfigMap => "configmap",
+            Self::Secret => "secret",
+        }
+    }
+}
+
+/// How the response to an HTTP(S) URL is captured.
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    /// Whether the status and response headers come before the body, with
+    /// names lowercased, sorted, and [`VOLATILE_HEADERS`] left out.
+    pub headers: bool,
+    /// More headers to leave out, in any case.
+    pub ignore_headers: Vec<String>,
+}
+
+/// Whether `path` names a remote file rather than a local one.
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+impl RemoteFile {
+    /// The remote file `path` names, or `None` for a local path.
+    pub fn from_path(path: &Path) -> io::Result<Option<Self>> {
+        if !is_remote(path) {
+            return Ok(None);
+        }
+        let url = path.to_str().unwrap_or_default();
+        url.parse()
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+    }
+
+    /// Reads the file's bytes.
+    pub fn read(&self, http: &HttpOptions) -> io::Result<Vec<u8>> {
+        let mut command = self.command(http);
+        let output = command.output().map_err(|e| {
+            let program = command.get_program().to_string_lossy().into_owned();
+            io::Error::new(e.kind(), format!("running {program}: {e}"))
+        })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let kind = match stderr.contains("No such file")
+                || stderr.contains("error: 404")
+                || stderr.contains("(NotFound)")
+            {
+                true => io::ErrorKind::NotFound,
+                false => io::ErrorKind::Other,
+            };
+            return Err(io::Error::new(
+                kind,
+                format!("reading {self}: {}", stderr.trim()),
+            ));
+        }
+        Ok(match self {
+            Self::Http { .. } if http.headers => normalize_response(&output.stdout, http),
+            Self::Kubernetes { kind, .. } => render_object(&output.stdout, *kind)?,
+            _ => output.stdout,
+        })
+    }
+
+    /// Starts watching for changes to the file if it can tell when it
+    /// changes, rather than having to be polled.
+    pub fn watch(&self) -> io::Result<Option<RemoteWatch>> {
+        let Self::Kubernetes {
+            namespace,
+            kind,
+            name,
+        } = self
+        else {
+            return Ok(None);
+        };
+        let mut command = Command::new("kubectl");
+        command
+            .args([
+                "get",
+                "--namespace",
+                namespace,
+                "--watch",
+                "--output",
+                "name",
+            ])
+            .args([kind.name(), name])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        RemoteWatch::start(command).map(Some)
+    }
+
+    /// The command that prints the file.
+    fn command(&self, http: &HttpOptions) -> Command {
+        match self {
+            Self::Ssh {
+                destination,
+                port,
+                path,
+            } => {
+                let mut command = Command::new("ssh");
+                command.args([
+                    "-o",
+                    "BatchMode=yes",
+                    "-o",
+                    "ControlMaster=auto",
+                    "-o",
+                    "ControlPath=~/.ssh/slip-diff-%C",
+                    "-o",
+                    "ControlPersist=60",
+                ]);
+                if let Some(port) = port {
+                    command.arg("-p").arg(port.to_string());
+                }
+                command
+                    .arg("--")
+                    .arg(destination)
+                    .arg(format!("cat -- {}", shell_quote(path)));
+                command
+            }
+            Self::Docker { container, path } => {
+                let mut command = Command::new("docker");
+                command
+                    .args(["exec", "--", container, "cat", "--"])
+                    .arg(path);
+                command
+            }
+            Self::Http { url } => {
+                let mut command = Command::new("curl");
+                command.args(["--silent", "--show-error", "--fail", "--location"]);
+                if http.headers {
+                    command.arg("--include");
+                }
+                command.arg("--url").arg(url);
+                command
+            }
+            Self::Kubernetes {
+                namespace,
+                kind,
+                name,
+            } => {
+                let mut command = Command::new("kubectl");
+                command
+                    .args(["get", "--namespace", namespace, "--output", "json"])
+                    .args([kind.name(), name]);
+                command
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for RemoteFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "`{s}` isn't a remote file like ssh://host/path, docker://container:/path, \
+                 k8s://namespace/configmap/name or https://host/path"
+            )
+        };
+        if let Some(rest) = s.strip_prefix(KUBERNETES) {
+            let mut parts = rest.split('/');
+            let (Some(namespace), Some(kind), Some(name), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                return Err(invalid());
+            };
+            let kind = match kind {
+                "configmap" | "configmaps" | "cm" => ObjectKind::ConfigMap,
+                "secret" | "secrets" => ObjectKind::Secret,
+                _ => return Err(format!("`{kind}` in `{s}` isn't `configmap` or `secret`")),
+            };
+            if [namespace, name]
+                .iter()
+                .any(|part| part.is_empty() || part.starts_with('-'))
+            {
+                return Err(invalid());
+            }
+            return Ok(Self::Kubernetes {
+                namespace: namespace.to_string(),
+                kind,
+                name: name.to_string(),
+            });
+        }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Self::Http { url: s.to_string() });
+        }
+        if let Some(rest) = s.strip_prefix(DOCKER) {
+            let (container, path) = rest.split_once(':').ok_or_else(invalid)?;
+            if container.is_empty() || container.starts_with('-') || !path.starts_with('/') {
+                return Err(invalid());
+            }
+            return Ok(Self::Docker {
+                container: container.to_string(),
+                path: path.to_string(),
+            });
+        }
+        let rest = s.strip_prefix(SSH).ok_or_else(invalid)?;
+        let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (destination, port) = match authority.rsplit_once(':') {
+            Some((destination, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("`{port}` in `{s}` isn't a port"))?;
+                (destination, Some(port))
+            }
+            None => (authority, None),
+        };
+        let host = destination.rsplit('@').next().unwrap_or_default();
+        if host.is_empty() || path.is_empty() || destination.starts_with('-') {
+            return Err(invalid());
+        }
+        let path = match path.strip_prefix("~/") {
+            Some(relative) if !relative.is_empty() => relative.to_string(),
+            Some(_) => return Err(invalid()),
+            None => format!("/{path}"),
+        };
+        Ok(Self::Ssh {
+            destination: destination.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+impl std::fmt::Display for RemoteFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ssh {
+                destination,
+                port,
+                path,
+            } => {
+                write!(f, "{SSH}{destination}")?;
+                if let Some(port) = port {
+                    write!(f, ":{port}")?;
+                }
+                match path.starts_with('/') {
+                    true => write!(f, "{path}"),
+                    false => write!(f, "/~/{path}"),
+                }
+            }
+            Self::Docker { container, path } => write!(f, "{DOCKER}{container}:{path}"),
+            Self::Http { url } => write!(f, "{url}"),
+            Self::Kubernetes {
+                namespace,
+                kind,
+                name,
+            } => write!(f, "{KUBERNETES}{namespace}/{}/{name}", kind.name()),
+        }
+    }
+}
+
+/// Tells when a [`RemoteFile`] changes, from a command that prints a line
+/// each time, which is run again if it stops. The command is killed once
+/// this is dropped.
+pub struct RemoteWatch {
+    changes: UnboundedReceiver<()>,
+    child: Arc<Mutex<Option<Child>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl RemoteWatch {
+    fn start(mut command: Command) -> io::Result<Self> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let first = command
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("running {program}: {e}")))?;
+        let child = Arc::new(Mutex::new(Some(first)));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (sender, changes) = mpsc::unbounded_channel();
+        let (running, stopping) = (Arc::clone(&child), Arc::clone(&stopped));
+        thread::spawn(move || loop {
+            let stdout = running
+                .lock()
+                .unwrap()
+                .as_mut()
+                .and_then(|child| child.stdout.take());
+            if let Some(stdout) = stdout {
+                for line in BufReader::new(stdout).lines() {
+                    if line.is_err() || sender.send(()).is_err() {
+                        break;
+                    }
+                }
+            }
+            if let Some(mut child) = running.lock().unwrap().take() {
+                let _ = child.wait();
+            }
+            if stopping.load(Ordering::Relaxed) || sender.is_closed() {
+                return;
+            }
+            thread::sleep(REWATCH_DELAY);
+            // Checked again under the lock, so a drop meanwhile can't miss
+            // the new child.
+            let mut slot = running.lock().unwrap();
+            if stopping.load(Ordering::Relaxed) {
+                return;
+            }
+            *slot = command.spawn().ok();
+        });
+        Ok(Self {
+            changes,
+            child,
+            stopped,
+        })
+    }
+
+    /// Waits until the file may have changed. This is cancel-safe.
+    pub async fn changed(&mut self) {
+        if self.changes.recv().await.is_none() {
+            // Only once the command can't be run again; nothing more will
+            // be reported.
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl Drop for RemoteWatch {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(child) = self.child.lock().unwrap().as_mut() {
+            // Ends the output the thread is reading.
+            let _ = child.kill();
+        }
+    }
+}
+
+/// The `data` and `binaryData` of a ConfigMap or Secret, as printed by
+/// `kubectl get --output json`, one key per line in order.
+fn render_object(json: &[u8], kind: ObjectKind) -> io::Result<Vec<u8>> {
+    let object: serde_json::Value = serde_json::from_slice(json)?;
+    let entries = |field: &str| -> BTreeMap<String, String> {
+        object[field]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.as_str().unwrap_or_default().to_string()))
+            .collect()
+    };
+    let digest = |value: &str| {
+        let hex = HashAlgorithm::default().digest(value.as_bytes()).to_hex();
+        hex[..16].to_string()
+    };
+    let mut values: BTreeMap<String, String> = BTreeMap::new();
+    for (key, value) in entries("data") {
+        let value = match kind {
+            ObjectKind::ConfigMap => value,
+            ObjectKind::Secret => format!("<secret {}>", secret_digest(&value)),
+        };
+        values.insert(key, value);
+    }
+    for (key, value) in entries("binaryData") {
+        values.insert(key, format!("<binary {}>", digest(&value)));
+    }
+    let mut out = String::new();
+    for (key, value) in values {
+        if value.contains('\n') {
+            out.push_str(&format!("{key}: |\n"));
+            for line in value.lines() {
+                out.push_str(&format!("  {line}\n"));
+            }
+        } else {
+            out.push_str(&format!("{key}: {value}\n"));
+        }
+    }
+    Ok(out.into_bytes())
+}
+
+/// A digest of a Secret value telling whether it changed during this run,
+/// keyed at random so it can't be checked against guesses.
+fn secret_digest(value: &str) -> String {
+    static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+    let key = KEY.get_or_init(random_key);
+    let hex = blake3::keyed_hash(key, value.as_bytes()).to_hex();
+    hex[..16].to_string()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn random_key() -> [u8; 32] {
+    rand::random()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn random_key() -> [u8; 32] {
+    unreachable!("WebAssembly can't run kubectl, so never reads a Secret")
+}
+
+/// `s` quoted for a POSIX shell, which runs the remote command.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// The status and headers of the last response in `curl --include` output,
+/// one `name: value` per line, then a blank line and the body.
+fn normalize_response(output: &[u8], http: &HttpOptions) -> Vec<u8> {
+    // Redirects and `100 Continue` come first, each headers only.
+    let mut rest = output;
+    let mut head: &[u8] = &[];
+    while rest.starts_with(b"HTTP/") {
+        let Some(end) = rest.windows(4).position(|w| w == b"\r\n\r\n") else {
+            break;
+        };
+        head = &rest[..end];
+        rest = &rest[end + 4..];
+    }
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+    let mut headers: Vec<(String, &str)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+        .filter(|(name, _)| {
+            !VOLATILE_HEADERS.contains(&name.as_str())
+                && !http
+                    .ignore_headers
+                    .iter()
+                    .any(|ignored| ignored.eq_ignore_ascii_case(name))
+        })
+        .collect();
+    // Stable, so repeated headers keep their order.
+    headers.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut normalized = format!("status: {status}\n");
+    for (name, value) in headers {
+        normalized.push_str(&format!("{name}: {value}\n"));
+    }
+    normalized.push('\n');
+    let mut normalized = normalized.into_bytes();
+    normalized.extend_from_slice(rest);
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<RemoteFile, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_host_and_absolute_path() {
+        assert_eq!(
+            parse("ssh://web1/etc/nginx/nginx.conf").unwrap(),
+            RemoteFile::Ssh {
+                destination: "web1".into(),
+                port: None,
+                path: "/etc/nginx/nginx.conf".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_user_and_port() {
+        assert_eq!(
+            parse("ssh://deploy@10.0.0.5:2222/srv/app.env").unwrap(),
+            RemoteFile::Ssh {
+                destination: "deploy@10.0.0.5".into(),
+                port: Some(2222),
+                path: "/srv/app.env".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_container_and_path() {
+        assert_eq!(
+            parse("docker://api-1:/etc/app/config.yml").unwrap(),
+            RemoteFile::Docker {
+                container: "api-1".into(),
+                path: "/etc/app/config.yml".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_namespace_kind_and_name() {
+        assert_eq!(
+            parse("k8s://prod/cm/api-config").unwrap(),
+            RemoteFile::Kubernetes {
+                namespace: "prod".into(),
+                kind: ObjectKind::ConfigMap,
+                name: "api-config".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn tilde_is_relative_to_the_remote_home() {
+        let remote = parse("ssh://web1/~/notes.txt").unwrap();
+        assert!(matches!(remote, RemoteFile::Ssh { path, .. } if path == "notes.txt"));
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_remote_file() {
+        for s in [
+            "/etc/hosts",
+            "ssh://web1",
+            "ssh://web1/",
+            "ssh:///etc/hosts",
+            "ssh://user@/etc/hosts",
+            "ssh://web1:http/etc/hosts",
+            "ssh://web1/~/",
+            "ssh://-oProxyCommand=x/etc/hosts",
+            "docker://api-1",
+            "docker://:/etc/hosts",
+            "docker://api-1:etc/hosts",
+            "docker://api-1/etc/hosts",
+            "docker://--privileged:/etc/hosts",
+            "k8s://prod/configmap",
+            "k8s://prod/deployment/api",
+            "k8s:///configmap/api",
+            "k8s://prod/secret/api/extra",
+        ] {
+            assert!(parse(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn displays_as_given() {
+        for s in [
+            "ssh://web1/etc/hosts",
+            "ssh://deploy@web1:2222/srv/app.env",
+            "ssh://web1/~/notes.txt",
+            "docker://api-1:/etc/app/config.yml",
+            "k8s://prod/secret/api-credentials",
+            "https://example.com/status?verbose=1",
+        ] {
+            assert_eq!(parse(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn quotes_for_the_remote_shell() {
+        assert_eq!(shell_quote("/etc/hosts"), "'/etc/hosts'");
+        assert_eq!(shell_quote("it's; rm -rf ~"), r"'it'\''s; rm -rf ~'");
+    }
+
+    #[test]
+    fn only_urls_are_remote() {
+        assert!(is_remote(Path::new("ssh://web1/etc/hosts")));
+        assert!(is_remote(Path::new("docker://api-1:/etc/hosts")));
+        assert!(is_remote(Path::new("http://localhost:8080/")));
+        assert!(is_remote(Path::new("k8s://prod/configmap/api")));
+        assert!(!is_remote(Path::new("/etc/hosts")));
+        assert!(!is_remote(Path::new("ssh:/web1/etc/hosts")));
+    }
+
+    #[test]
+    fn normalizes_the_last_response() {
+        let output = b"HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\n\r\n\
+            HTTP/1.1 200 OK\r\nX-Trace: 1\r\nDate: Mon, 01 Jan 2024 00:00:00 GMT\r\n\
+            Content-Type: text/plain\r\nVary: Accept\r\nvary: Origin\r\n\r\nhello\n";
+        let http = HttpOptions {
+            headers: true,
+            ignore_headers: vec!["x-TRACE".into()],
+        };
+        assert_eq!(
+            String::from_utf8(normalize_response(output, &http)).unwrap(),
+            "status: 200\ncontent-type: text/plain\nvary: Accept\nvary: Origin\n\nhello\n"
+        );
+    }
+
+    #[test]
+    fn renders_configmap_keys_in_order() {
+        let json = br#"{"kind": "ConfigMap", "data": {
+            "mode": "debug",
+            "app.properties": "a=1\nb=2\n"
+        }, "binaryData": {"logo.png": "iVBORw0K"}}"#;
+        assert_eq!(
+            String::from_utf8(render_object(json, ObjectKind::ConfigMap).unwrap()).unwrap(),
+            format!(
+                "app.properties: |\n  a=1\n  b=2\nlogo.png: <binary {}>\nmode: debug\n",
+                &HashAlgorithm::default().digest(b"iVBORw0K").to_hex()[..16]
+            )
+        );
+    }
+
+    #[test]
+    fn hides_secret_values() {
+        let json = br#"{"kind": "Secret", "data": {"password": "aHVudGVyMg=="}}"#;
+        let rendered = String::from_utf8(render_object(json, ObjectKind::Secret).unwrap()).unwrap();
+        assert!(rendered.starts_with("password: <secret "));
+        assert!(!rendered.contains("aHVudGVyMg=="));
+        // Not the plain digest, which could be checked against guesses.
+        let plain = HashAlgorithm::default().digest(b"aHVudGVyMg==").to_hex();
+        assert!(!rendered.contains(&plain[..16]));
+        // But the same for the same value, so changes still show up.
+        let again = String::from_utf8(render_object(json, ObjectKind::Secret).unwrap()).unwrap();
+        assert_eq!(rendered, again);
+    }
+}
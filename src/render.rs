@@ -0,0 +1,1131 @@
+use std::{
+    collections::HashMap,
+    env, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use similar::{udiff::UnifiedHunkHeader, Change, ChangeTag, DiffOp, InlineChange, TextDiff};
+
+use crate::{
+    digest::{Digest, HashAlgorithm},
+    history::parse_size,
+};
+
+/// Options a [`DiffRenderer`] renders with.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions<'a> {
+    /// The file, whose extension picks the syntax highlighting.
+    pub path: Option<&'a Path>,
+    /// Lines before the window `old` and `new` are into larger texts, which
+    /// line numbers and headers are offset by.
+    pub skipped: usize,
+    /// Columns to fill [default: the terminal's width].
+    pub width: Option<usize>,
+    /// Lines of unchanged context shown around each hunk.
+    pub context: usize,
+    /// Whether the built-in renderers mark tabs, trailing spaces and
+    /// carriage returns.
+    pub show_whitespace: bool,
+}
+
+impl Default for RenderOptions<'_> {
+    fn default() -> Self {
+        Self {
+            path: None,
+            skipped: 0,
+            width: None,
+            context: CONTEXT,
+            show_whitespace: false,
+        }
+    }
+}
+
+/// The flags picking a renderer and its options, shared by both binaries.
+#[derive(Debug, Clone, clap::Args)]
+pub struct RenderArgs {
+    /// How to render line diffs: `auto` (delta if it is on the PATH), `builtin`, `side-by-side`, `delta` or `unified`
+    #[clap(long, default_value = AUTO, value_parser = parse_renderer, global = true)]
+    pub renderer: String,
+
+    /// Show old and new next to each other, short for `--renderer side-by-side`
+    #[clap(long, global = true)]
+    pub side_by_side: bool,
+
+    /// Lines of unchanged context around each hunk in the built-in renderers and porcelain diffs
+    #[clap(short = 'U', long, value_name = "N", default_value_t = CONTEXT, global = true)]
+    pub context: usize,
+
+    /// Bytes of rendered diffs to keep for changes that come up again (accepts K, M and G suffixes; 0 turns it off)
+    #[clap(long, default_value = "16M", value_parser = parse_size, global = true)]
+    pub render_cache: usize,
+
+    /// Mark tabs, trailing spaces and carriage returns in the built-in renderers
+    #[clap(long, global = true)]
+    pub show_whitespace: bool,
+}
+
+impl RenderArgs {
+    /// The renderer these flags pick, or `auto`, e.g. a `--rule`'s, when
+    /// `--renderer` was left at [`AUTO`], behind a `--render-cache` of its
+    /// own.
+    pub fn renderer(&self, auto: Option<&str>) -> Arc<dyn DiffRenderer> {
+        let name = match auto {
+            _ if self.side_by_side => "side-by-side",
+            Some(name) if self.renderer == AUTO => name,
+            _ => &self.renderer,
+        };
+        let renderer = Renderers::default()
+            .get(name)
+            .expect("--renderer is checked when parsed");
+        match self.render_cache {
+            0 => renderer,
+            size => Arc::new(Cached::new(renderer, size)),
+        }
+    }
+
+    /// Options for rendering changes to `path` as these flags ask.
+    pub fn options<'a>(&self, path: Option<&'a Path>) -> RenderOptions<'a> {
+        RenderOptions {
+            path,
+            context: self.context,
+            show_whitespace: self.show_whitespace,
+            ..RenderOptions::default()
+        }
+    }
+}
+
+/// Checks that `--renderer` names a registered renderer.
+fn parse_renderer(name: &str) -> Result<String, String> {
+    let renderers = Renderers::default();
+    if renderers.get(name).is_none() {
+        let names: Vec<&str> = renderers.names().collect();
+        return Err(format!("expected one of {}", names.join(", ")));
+    }
+    Ok(name.to_string())
+}
+
+/// Renders the change between two versions as text to show.
+pub trait DiffRenderer: Send + Sync {
+    /// What `--renderer` calls it.
+    fn name(&self) -> &str;
+
+    /// Whether it needs the whole texts rather than the window around what
+    /// changed, e.g. because it numbers lines itself.
+    fn needs_whole(&self) -> bool {
+        false
+    }
+
+    fn render(&self, old: &str, new: &str, options: &RenderOptions) -> io::Result<String>;
+}
+
+/// The in-process renderer, highlighting syntax by file extension. See
+/// [`render`].
+pub struct Builtin;
+
+impl DiffRenderer for Builtin {
+    fn name(&self) -> &str {
+        "builtin"
+    }
+
+    fn render(&self, old: &str, new: &str, options: &RenderOptions) -> io::Result<String> {
+        Ok(render(old, new, options))
+    }
+}
+
+/// Old and new next to each other in two columns. See [`side_by_side`].
+pub struct SideBySide;
+
+impl DiffRenderer for SideBySide {
+    fn name(&self) -> &str {
+        "side-by-side"
+    }
+
+    fn render(&self, old: &str, new: &str, options: &RenderOptions) -> io::Result<String> {
+        let width = options.width.unwrap_or_else(terminal_width);
+        Ok(side_by_side(old, new, width, options))
+    }
+}
+
+/// Shells out to `delta`, which diffs whole files.
+pub struct Delta;
+
+impl DiffRenderer for Delta {
+    fn name(&self) -> &str {
+        "delta"
+    }
+
+    fn needs_whole(&self) -> bool {
+        true
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn render(&self, _old: &str, _new: &str, _options: &RenderOptions) -> io::Result<String> {
+        Err(io::Error::other("delta can't be run from WebAssembly"))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn render(&self, old: &str, new: &str, _options: &RenderOptions) -> io::Result<String> {
+        let old_file = tempfile::NamedTempFile::new()?;
+        let new_file = tempfile::NamedTempFile::new()?;
+        std::fs::write(old_file.path(), old)?;
+        std::fs::write(new_file.path(), new)?;
+        let output = std::process::Command::new("delta")
+            .arg(old_file.path())
+            .arg(new_file.path())
+            .output()?;
+        delta_output(output)
+    }
+}
+
+/// What delta printed, or what it complained about if it failed. Like
+/// `diff`, it exits with 1 when the files differ.
+#[cfg(not(target_arch = "wasm32"))]
+fn delta_output(output: std::process::Output) -> io::Result<String> {
+    match output.status.code() {
+        Some(0 | 1) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => Err(io::Error::other(format!(
+            "delta {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ))),
+    }
+}
+
+/// A plain unified diff without colours, which porcelain output shows
+/// changes as.
+pub struct Unified;
+
+impl DiffRenderer for Unified {
+    fn name(&self) -> &str {
+        "unified"
+    }
+
+    fn render(&self, old: &str, new: &str, options: &RenderOptions) -> io::Result<String> {
+        let diff = TextDiff::from_lines(old, new);
+        let mut unified = String::new();
+        let hunks = diff
+            .unified_diff()
+            .context_radius(options.context)
+            .iter_hunks();
+        for (i, hunk) in hunks.enumerate() {
+            if i == 0 {
+                unified.push_str("--- old\n+++ new\n");
+            }
+            let header = hunk_header(hunk.ops(), options.skipped);
+            let hunk = hunk.to_string();
+            let (_, changes) = hunk.split_once('\n').unwrap_or_default();
+            unified.push_str(&format!("{header}\n{changes}"));
+        }
+        Ok(unified)
+    }
+}
+
+/// Hunks with the words that changed marked inline, like `git diff
+/// --word-diff`: `[-removed-]{+added+}`, in colour when colours are on.
+pub struct Words;
+
+impl DiffRenderer for Words {
+    fn name(&self) -> &str {
+        "words"
+    }
+
+    fn render(&self, old: &str, new: &str, options: &RenderOptions) -> io::Result<String> {
+        Ok(word_diff(old, new, options, console::colors_enabled()))
+    }
+}
+
+/// See [`Words`].
+pub fn word_diff(old: &str, new: &str, options: &RenderOptions, colors: bool) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut out = String::new();
+    for group in diff.grouped_ops(options.context) {
+        out.push_str(&hunk_header(&group, options.skipped));
+        out.push('\n');
+        for op in &group {
+            let (old_range, new_range) = (op.old_range(), op.new_range());
+            let (old_lines, new_lines) =
+                (&diff.old_slices()[old_range], &diff.new_slices()[new_range]);
+            if let DiffOp::Equal { .. } = op {
+                old_lines.iter().for_each(|line| out.push_str(line));
+                continue;
+            }
+            let (old_text, new_text) = (old_lines.concat(), new_lines.concat());
+            for change in TextDiff::from_words(&old_text, &new_text).iter_all_changes() {
+                let value = change.value();
+                // Whitespace is laid out as in the new text, unmarked.
+                let (open, close, color) = match change.tag() {
+                    ChangeTag::Equal => {
+                        out.push_str(value);
+                        continue;
+                    }
+                    ChangeTag::Delete if value.trim().is_empty() => continue,
+                    ChangeTag::Insert if value.trim().is_empty() => {
+                        out.push_str(value);
+                        continue;
+                    }
+                    ChangeTag::Delete => ("[-", "-]", "\x1b[31m"),
+                    ChangeTag::Insert => ("{+", "+}", "\x1b[32m"),
+                };
+                match colors {
+                    true => out.push_str(&format!("{color}{open}{value}{close}\x1b[0m")),
+                    false => out.push_str(&format!("{open}{value}{close}")),
+                }
+            }
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// The renderer name that picks delta if it is on the PATH and the built-in
+/// renderer otherwise.
+pub const AUTO: &str = "auto";
+
+/// Renderers by name for `--renderer` to pick from, starting with the
+/// built-in ones. Library users can register their own.
+#[derive(Clone)]
+pub struct Renderers {
+    renderers: Vec<Arc<dyn DiffRenderer>>,
+}
+
+impl Default for Renderers {
+    fn default() -> Self {
+        Self {
+            renderers: vec![
+                Arc::new(Builtin),
+                Arc::new(SideBySide),
+                Arc::new(Delta),
+                Arc::new(Unified),
+                Arc::new(Words),
+            ],
+        }
+    }
+}
+
+impl Renderers {
+    /// Adds `renderer`, replacing any registered under the same name.
+    pub fn register(&mut self, renderer: Arc<dyn DiffRenderer>) {
+        self.renderers
+            .retain(|registered| registered.name() != renderer.name());
+        self.renderers.push(renderer);
+    }
+
+    /// The renderer registered as `name`, or picked by [`AUTO`].
+    pub fn get(&self, name: &str) -> Option<Arc<dyn DiffRenderer>> {
+        static DELTA_FOUND: OnceLock<bool> = OnceLock::new();
+        let name = match name {
+            AUTO if *DELTA_FOUND.get_or_init(|| on_path("delta")) => "delta",
+            AUTO => "builtin",
+            name => name,
+        };
+        self.renderers
+            .iter()
+            .find(|renderer| renderer.name() == name)
+            .cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(AUTO).chain(self.renderers.iter().map(|renderer| renderer.name()))
+    }
+}
+
+/// Wraps a renderer to keep what it rendered, so a change that comes up
+/// again, e.g. a file flipping between two states or an embedder redrawing,
+/// isn't diffed and highlighted again. Changing an option, the terminal's
+/// width, colours or whitespace marking renders afresh. The least recently
+/// used diffs are dropped once they take more than the cache's size.
+pub struct Cached {
+    renderer: Arc<dyn DiffRenderer>,
+    max_bytes: usize,
+    cache: Mutex<RenderCache>,
+}
+
+#[derive(Default)]
+struct RenderCache {
+    diffs: HashMap<CacheKey, CachedDiff>,
+    bytes: usize,
+    /// Counts lookups, to tell which diff was used least recently.
+    clock: u64,
+}
+
+/// Everything a rendered diff depends on.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    old: Digest,
+    new: Digest,
+    path: Option<PathBuf>,
+    skipped: usize,
+    width: usize,
+    colors: bool,
+    show_whitespace: bool,
+    context: usize,
+}
+
+struct CachedDiff {
+    diff: String,
+    used: u64,
+}
+
+impl Cached {
+    /// Caches up to `max_bytes` of what `renderer` renders.
+    pub fn new(renderer: Arc<dyn DiffRenderer>, max_bytes: usize) -> Self {
+        Self {
+            renderer,
+            max_bytes,
+            cache: Mutex::default(),
+        }
+    }
+
+    fn key(old: &str, new: &str, options: &RenderOptions) -> CacheKey {
+        let hash = HashAlgorithm::default();
+        let width = options.width.unwrap_or_else(terminal_width);
+        CacheKey {
+            old: hash.digest(old.as_bytes()),
+            new: hash.digest(new.as_bytes()),
+            path: options.path.map(Path::to_path_buf),
+            skipped: options.skipped,
+            width,
+            colors: console::colors_enabled(),
+            show_whitespace: options.show_whitespace,
+            context: options.context,
+        }
+    }
+}
+
+impl DiffRenderer for Cached {
+    fn name(&self) -> &str {
+        self.renderer.name()
+    }
+
+    fn needs_whole(&self) -> bool {
+        self.renderer.needs_whole()
+    }
+
+    fn render(&self, old: &str, new: &str, options: &RenderOptions) -> io::Result<String> {
+        let key = Self::key(old, new, options);
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.clock += 1;
+            let clock = cache.clock;
+            if let Some(cached) = cache.diffs.get_mut(&key) {
+                cached.used = clock;
+                return Ok(cached.diff.clone());
+            }
+        }
+        // Rendered unlocked, since it may shell out.
+        let diff = self.renderer.render(old, new, options)?;
+        if diff.len() <= self.max_bytes {
+            let mut cache = self.cache.lock().unwrap();
+            cache.bytes += diff.len();
+            let used = cache.clock;
+            let replaced = cache.diffs.insert(
+                key,
+                CachedDiff {
+                    diff: diff.clone(),
+                    used,
+                },
+            );
+            if let Some(replaced) = replaced {
+                cache.bytes -= replaced.diff.len();
+            }
+            while cache.bytes > self.max_bytes {
+                let Some(oldest) = cache
+                    .diffs
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.used)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                let removed = cache.diffs.remove(&oldest).unwrap();
+                cache.bytes -= removed.diff.len();
+            }
+        }
+        Ok(diff)
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// The terminal's width in columns, from stderr when stdout isn't one, e.g.
+/// while `--mirror` has it going through a pipe.
+fn terminal_width() -> usize {
+    let (_, width) = console::Term::stdout()
+        .size_checked()
+        .unwrap_or_else(|| console::Term::stderr().size());
+    width.into()
+}
+
+const TAB_MARKER: &str = "→   ";
+const SPACE_MARKER: char = '·';
+const CR_MARKER: char = '␍';
+
+/// Replaces every tab in `line`, the spaces it ends with and a carriage
+/// return before its line ending with visible markers, expanding tabs to
+/// four columns.
+pub fn mark_whitespace(line: &str) -> String {
+    let (content, cr) = split_whitespace_end(line);
+    let mut marked = mark_segment(content, 0, content.trim_end_matches([' ', '\t']).len());
+    if cr {
+        marked.push(CR_MARKER);
+    }
+    marked
+}
+
+/// Strips the line ending off `line`, also returning whether it had a
+/// carriage return.
+fn split_whitespace_end(line: &str) -> (&str, bool) {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    match line.strip_suffix('\r') {
+        Some(line) => (line, true),
+        None => (line, false),
+    }
+}
+
+/// Marks the whitespace in `segment`, which starts `at` bytes into a line
+/// whose trailing whitespace starts at `trailing`.
+fn mark_segment(segment: &str, at: usize, trailing: usize) -> String {
+    let mut marked = String::with_capacity(segment.len());
+    for (i, c) in segment.char_indices() {
+        match c {
+            '\t' => marked.push_str(TAB_MARKER),
+            ' ' if at + i >= trailing => marked.push(SPACE_MARKER),
+            '\r' => marked.push(CR_MARKER),
+            c => marked.push(c),
+        }
+    }
+    marked
+}
+
+type Rgb = (u8, u8, u8);
+
+const INSERTED_BG: Rgb = (0x00, 0x3f, 0x1f);
+const DELETED_BG: Rgb = (0x3f, 0x00, 0x10);
+/// Backgrounds of the words that changed within a modified line.
+const INSERTED_EMPHASIS_BG: Rgb = (0x00, 0x70, 0x38);
+const DELETED_EMPHASIS_BG: Rgb = (0x80, 0x00, 0x28);
+
+/// A run of text within a line and its syntax colour, if highlighted.
+type Span = (Option<Rgb>, String);
+
+/// Lines of unchanged context shown around each hunk by default.
+pub const CONTEXT: usize = 3;
+
+/// What stands for the unchanged lines folded away between two hunks.
+const FOLD: &str = "…";
+
+/// Renders the hunks of `new` against `old`, each under a `@@ -a,b +c,d @@`
+/// header and with old and new line numbers in a gutter, highlighting the
+/// syntax `path`'s extension implies underneath the added and removed line
+/// backgrounds. Words that changed within a modified line get a brighter
+/// background. Without colours every line is plainly prefixed by `+`/`-`/` `.
+/// Whitespace is marked if the options ask for it, and the lines between
+/// hunks beyond their context are folded into a `…` line.
+///
+/// `old` and `new` may be a window into larger texts starting after
+/// `options.skipped` lines, which line numbers and headers are offset by.
+pub fn render(old: &str, new: &str, options: &RenderOptions) -> String {
+    let (path, skipped) = (options.path, options.skipped);
+    let colors = console::colors_enabled();
+    let old_lines = highlight(old, path, colors);
+    let new_lines = highlight(new, path, colors);
+    let lines = old_lines.len().max(new_lines.len()) + skipped;
+    let width = lines.max(1).ilog10() as usize + 1;
+    let gutter_style = console::Style::new().dim();
+    let header_style = console::Style::new().cyan();
+    let number =
+        |index: Option<usize>| index.map_or(String::new(), |i| (skipped + i + 1).to_string());
+
+    let diff = TextDiff::from_lines(old, new);
+    let mut out = String::new();
+    for (i, group) in diff.grouped_ops(options.context).iter().enumerate() {
+        if i > 0 {
+            out.push_str(&format!("{}\n", gutter_style.apply_to(FOLD)));
+        }
+        let header = hunk_header(group, skipped);
+        out.push_str(&format!("{}\n", header_style.apply_to(header)));
+        for change in group.iter().flat_map(|op| diff.iter_inline_changes(op)) {
+            let gutter = format!(
+                "{:>width$} {:>width$} │",
+                number(change.old_index()),
+                number(change.new_index())
+            );
+            out.push_str(&gutter_style.apply_to(gutter).to_string());
+            let lines = (&old_lines[..], &new_lines[..]);
+            render_line(&mut out, &change, lines, colors, options.show_whitespace);
+        }
+    }
+    out
+}
+
+fn render_line(
+    out: &mut String,
+    change: &InlineChange<str>,
+    (old_lines, new_lines): (&[Vec<Span>], &[Vec<Span>]),
+    colors: bool,
+    whitespace: bool,
+) {
+    let (sign, spans, background, emphasis) = match change.tag() {
+        ChangeTag::Delete => (
+            "-",
+            &old_lines[change.old_index().unwrap()],
+            Some(DELETED_BG),
+            DELETED_EMPHASIS_BG,
+        ),
+        ChangeTag::Insert => (
+            "+",
+            &new_lines[change.new_index().unwrap()],
+            Some(INSERTED_BG),
+            INSERTED_EMPHASIS_BG,
+        ),
+        ChangeTag::Equal => (
+            " ",
+            &new_lines[change.new_index().unwrap()],
+            None,
+            Default::default(),
+        ),
+    };
+    let line: String = change.iter_strings_lossy().map(|(_, s)| s).collect();
+    if !colors {
+        out.push_str(sign);
+        match whitespace {
+            true => out.push_str(&mark_whitespace(&line)),
+            false => spans.iter().for_each(|(_, text)| out.push_str(text)),
+        }
+        out.push('\n');
+        return;
+    }
+    let (content, cr) = split_whitespace_end(&line);
+    let trailing = content.trim_end_matches([' ', '\t']).len();
+
+    let sign_style = match change.tag() {
+        ChangeTag::Delete => console::Style::new().red().bold(),
+        ChangeTag::Insert => console::Style::new().green().bold(),
+        ChangeTag::Equal => console::Style::new(),
+    };
+    out.push_str(&sign_style.apply_to(sign).to_string());
+    let emphasized = emphasized_ranges(change);
+    let mut offset = 0;
+    for (color, text) in spans {
+        match (color, change.tag()) {
+            (Some((r, g, b)), _) => out.push_str(&format!("\x1b[38;2;{r};{g};{b}m")),
+            (None, ChangeTag::Delete) => out.push_str("\x1b[31m"),
+            (None, ChangeTag::Insert) => out.push_str("\x1b[32m"),
+            (None, ChangeTag::Equal) => {}
+        }
+        // Split the span wherever emphasis starts or ends inside it.
+        let mut start = 0;
+        while start < text.len() {
+            let at = offset + start;
+            let range = emphasized.iter().find(|range| range.end > at);
+            let (end, bright) = match range {
+                Some(range) if range.start <= at => (range.end - offset, true),
+                Some(range) => (range.start - offset, false),
+                None => (text.len(), false),
+            };
+            let end = end.min(text.len());
+            if let Some((r, g, b)) = if bright { Some(emphasis) } else { background } {
+                out.push_str(&format!("\x1b[48;2;{r};{g};{b}m"));
+            }
+            match whitespace {
+                true => out.push_str(&mark_segment(&text[start..end], offset + start, trailing)),
+                false => out.push_str(&text[start..end]),
+            }
+            start = end;
+        }
+        offset += text.len();
+    }
+    if whitespace && cr {
+        out.push(CR_MARKER);
+    }
+    // Extend the background to the end of the terminal line.
+    if background.is_some() {
+        out.push_str("\x1b[K");
+    }
+    out.push_str("\x1b[0m\n");
+}
+
+/// Byte ranges of the words that changed within a modified line, empty if
+/// the line has no counterpart close enough to compare against.
+fn emphasized_ranges(change: &InlineChange<str>) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    for (emphasized, value) in change.iter_strings_lossy() {
+        let value = trim_line_ending(&value);
+        if emphasized && !value.is_empty() {
+            ranges.push(offset..offset + value.len());
+        }
+        offset += value.len();
+    }
+    ranges
+}
+
+/// Splits `text` into lines of coloured spans, without line endings.
+fn highlight(text: &str, path: Option<&Path>, colors: bool) -> Vec<Vec<Span>> {
+    if colors {
+        if let Some(lines) = syntax_highlight(text, path) {
+            return lines;
+        }
+    }
+    text.split_inclusive('\n')
+        .map(|line| vec![(None, trim_line_ending(line).to_string())])
+        .collect()
+}
+
+#[cfg(feature = "highlight")]
+fn syntax_highlight(text: &str, path: Option<&Path>) -> Option<Vec<Vec<Span>>> {
+    use syntect::{easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet};
+
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+    let themes = THEMES.get_or_init(ThemeSet::load_defaults);
+
+    let extension = path.and_then(Path::extension).and_then(|e| e.to_str());
+    let name = path.and_then(Path::file_name).and_then(|n| n.to_str());
+    let syntax = extension
+        .and_then(|extension| syntaxes.find_syntax_by_extension(extension))
+        .or_else(|| name.and_then(|name| syntaxes.find_syntax_by_extension(name)))
+        .or_else(|| syntaxes.find_syntax_by_first_line(text.lines().next()?))?;
+    let mut highlighter = HighlightLines::new(syntax, &themes.themes["base16-ocean.dark"]);
+
+    let mut lines = Vec::new();
+    for line in text.split_inclusive('\n') {
+        let ranges = highlighter.highlight_line(line, syntaxes).ok()?;
+        let spans = ranges
+            .into_iter()
+            .map(|(style, piece)| {
+                let color = style.foreground;
+                (
+                    Some((color.r, color.g, color.b)),
+                    trim_line_ending(piece).to_string(),
+                )
+            })
+            .collect();
+        lines.push(spans);
+    }
+    Some(lines)
+}
+
+/// Without the `highlight` feature every line is shown in plain diff colours.
+#[cfg(not(feature = "highlight"))]
+fn syntax_highlight(_text: &str, _path: Option<&Path>) -> Option<Vec<Vec<Span>>> {
+    None
+}
+
+/// The `@@ -a,b +c,d @@` header of a hunk of `ops` between texts that
+/// start after `skipped` lines.
+pub fn hunk_header(ops: &[DiffOp], skipped: usize) -> String {
+    let shifted: Vec<DiffOp> = ops
+        .iter()
+        .map(|op| match *op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => DiffOp::Equal {
+                old_index: old_index + skipped,
+                new_index: new_index + skipped,
+                len,
+            },
+            DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            } => DiffOp::Delete {
+                old_index: old_index + skipped,
+                old_len,
+                new_index: new_index + skipped,
+            },
+            DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            } => DiffOp::Insert {
+                old_index: old_index + skipped,
+                new_index: new_index + skipped,
+                new_len,
+            },
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => DiffOp::Replace {
+                old_index: old_index + skipped,
+                old_len,
+                new_index: new_index + skipped,
+                new_len,
+            },
+        })
+        .collect();
+    UnifiedHunkHeader::new(&shifted).to_string()
+}
+
+fn trim_line_ending(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+/// Renders the hunks of `old` and `new` in two aligned columns filling
+/// `width` terminal columns, with line numbers on each side. Long lines wrap
+/// onto continuation rows. Whitespace is marked and the lines between hunks
+/// folded like in [`render`], and line numbers offset by `options.skipped`
+/// like there too.
+pub fn side_by_side(old: &str, new: &str, width: usize, options: &RenderOptions) -> String {
+    let skipped = options.skipped;
+    let diff = TextDiff::from_lines(old, new);
+    let lines = old.lines().count().max(new.lines().count()) + skipped;
+    let lines = lines.max(1);
+    let number_width = lines.ilog10() as usize + 1;
+    // Two gutters of "N " plus the " │ " between the columns.
+    let column = (width.saturating_sub(2 * (number_width + 1) + 3) / 2).max(8);
+    let header_style = console::Style::new().cyan();
+    let gutter_style = console::Style::new().dim();
+    let old_index = |change: &Change<&str>| change.old_index().map(|i| skipped + i);
+    let new_index = |change: &Change<&str>| change.new_index().map(|i| skipped + i);
+
+    let mut out = String::new();
+    for (i, group) in diff.grouped_ops(options.context).iter().enumerate() {
+        if i > 0 {
+            out.push_str(&format!("{}\n", gutter_style.apply_to(FOLD)));
+        }
+        let header = hunk_header(group, skipped);
+        out.push_str(&format!("{}\n", header_style.apply_to(header)));
+        for op in group {
+            let mut left = Vec::new();
+            let mut right = Vec::new();
+            for change in diff.iter_changes(op) {
+                let text = match options.show_whitespace {
+                    true => mark_whitespace(change.value()),
+                    false => expand_tabs(trim_line_ending(change.value())),
+                };
+                match change.tag() {
+                    ChangeTag::Delete => left.push((old_index(&change), text, change.tag())),
+                    ChangeTag::Insert => right.push((new_index(&change), text, change.tag())),
+                    ChangeTag::Equal => {
+                        left.push((old_index(&change), text.clone(), change.tag()));
+                        right.push((new_index(&change), text, change.tag()));
+                    }
+                }
+            }
+            for row in 0..left.len().max(right.len()) {
+                let left = wrap(left.get(row), column);
+                let right = wrap(right.get(row), column);
+                for part in 0..left.len().max(right.len()) {
+                    let cell = |side: &[Cell], pad: bool| {
+                        let (number, text, tag) = side.get(part).cloned().unwrap_or_default();
+                        let padded = match pad {
+                            true => console::pad_str(&text, column, console::Alignment::Left, None)
+                                .into_owned(),
+                            false => text,
+                        };
+                        let text = match tag {
+                            Some(ChangeTag::Delete) => console::style(padded).red().to_string(),
+                            Some(ChangeTag::Insert) => console::style(padded).green().to_string(),
+                            _ => padded,
+                        };
+                        let number = format!("{number:>number_width$}");
+                        format!("{} {text}", gutter_style.apply_to(number))
+                    };
+                    out.push_str(&format!(
+                        "{} {} {}\n",
+                        cell(&left, true),
+                        gutter_style.apply_to("│"),
+                        cell(&right, false)
+                    ));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// One row of one side-by-side column: line number, text and change kind.
+type Cell = (String, String, Option<ChangeTag>);
+
+/// Splits one side's line into rows at most `width` terminal columns wide,
+/// numbering only the first. Wide characters that don't fit at the end of a
+/// row start the next one. A missing line is a single empty row.
+fn wrap(line: Option<&(Option<usize>, String, ChangeTag)>, width: usize) -> Vec<Cell> {
+    let Some((index, text, tag)) = line else {
+        return vec![Default::default()];
+    };
+    let mut rows = vec![String::new()];
+    let mut used = 0;
+    for c in text.chars() {
+        let columns = console::measure_text_width(c.encode_utf8(&mut [0; 4]));
+        if used + columns > width && used > 0 {
+            rows.push(String::new());
+            used = 0;
+        }
+        rows.last_mut().unwrap().push(c);
+        used += columns;
+    }
+    let mut rows: Vec<Cell> = rows
+        .into_iter()
+        .map(|row| (String::new(), row, Some(*tag)))
+        .collect();
+    rows[0].0 = index.map_or(String::new(), |i| (i + 1).to_string());
+    rows
+}
+
+fn expand_tabs(line: &str) -> String {
+    line.replace('\t', "    ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn delta_failing_is_an_error_but_finding_differences_is_not() {
+        use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+        let output = |code: i32, stdout: &str, stderr: &str| std::process::Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.into(),
+            stderr: stderr.into(),
+        };
+        assert_eq!(delta_output(output(0, "", "")).unwrap(), "");
+        assert_eq!(delta_output(output(1, "-a\n+b\n", "")).unwrap(), "-a\n+b\n");
+        let error = delta_output(output(2, "", "error: unknown option --frobnicate\n"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "delta exit status: 2: error: unknown option --frobnicate"
+        );
+    }
+
+    #[test]
+    fn word_diff_marks_the_words_that_changed() {
+        let diff = word_diff(
+            "# Title\nThe quick fox\n",
+            "# Title\nThe slow fox\njumps\n",
+            &RenderOptions::default(),
+            false,
+        );
+        assert_eq!(
+            diff,
+            "@@ -1,2 +1,3 @@\n# Title\nThe [-quick-]{+slow+} fox\n{+jumps+}\n"
+        );
+    }
+
+    /// Renders `old` and `new` next to each other, counting how often.
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    impl Counting {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl DiffRenderer for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn render(&self, old: &str, new: &str, _options: &RenderOptions) -> io::Result<String> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(format!("{old}{new}"))
+        }
+    }
+
+    fn options() -> RenderOptions<'static> {
+        RenderOptions {
+            width: Some(80),
+            ..RenderOptions::default()
+        }
+    }
+
+    #[test]
+    fn cached_renders_each_change_once_per_options() {
+        let counting = Arc::new(Counting::default());
+        let cached = Cached::new(counting.clone(), 1024);
+        for _ in 0..3 {
+            assert_eq!(cached.render("a", "b", &options()).unwrap(), "ab");
+        }
+        assert_eq!(counting.count(), 1);
+        cached.render("b", "a", &options()).unwrap();
+        assert_eq!(counting.count(), 2);
+
+        let changed = [
+            RenderOptions {
+                width: Some(40),
+                ..options()
+            },
+            RenderOptions {
+                context: 1,
+                ..options()
+            },
+            RenderOptions {
+                show_whitespace: true,
+                ..options()
+            },
+            RenderOptions {
+                path: Some(Path::new("a.rs")),
+                ..options()
+            },
+        ];
+        for (i, options) in changed.iter().enumerate() {
+            cached.render("a", "b", options).unwrap();
+            assert_eq!(counting.count(), 3 + i);
+        }
+    }
+
+    #[test]
+    fn cached_drops_the_least_recently_used_beyond_its_size() {
+        let counting = Arc::new(Counting::default());
+        // Room for two of the two-byte diffs.
+        let cached = Cached::new(counting.clone(), 4);
+        for (old, new) in [("a", "b"), ("c", "d"), ("a", "b"), ("e", "f")] {
+            cached.render(old, new, &options()).unwrap();
+        }
+        assert_eq!(counting.count(), 3);
+        assert_eq!(cached.cache.lock().unwrap().bytes, 4);
+
+        // `c` to `d` was used least recently, so it went.
+        cached.render("a", "b", &options()).unwrap();
+        assert_eq!(counting.count(), 3);
+        cached.render("c", "d", &options()).unwrap();
+        assert_eq!(counting.count(), 4);
+
+        // A diff larger than the cache isn't kept at all.
+        cached.render("long", "er", &options()).unwrap();
+        cached.render("long", "er", &options()).unwrap();
+        assert_eq!(counting.count(), 6);
+        assert!(cached.cache.lock().unwrap().bytes <= 4);
+    }
+
+    fn numbered(lines: Range<usize>) -> String {
+        lines.map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn gutters_fit_the_widest_line_number() {
+        // Nine lines becoming ten take two columns on both sides.
+        let diff = render(&numbered(1..10), &numbered(1..11), &options());
+        assert_eq!(
+            diff,
+            "@@ -7,3 +7,4 @@\n 7  7 │ line 7\n 8  8 │ line 8\n 9  9 │ line 9\n   10 │+line 10\n"
+        );
+
+        // Numbers are counted from the start of the whole text.
+        let new = numbered(1..10).replace("line 5\n", "five\n");
+        let options = RenderOptions {
+            skipped: 95,
+            ..options()
+        };
+        let diff = render(&numbered(1..10), &new, &options);
+        assert!(diff.starts_with("@@ -97,7 +97,7 @@\n 97  97 │ line 2\n"));
+        assert!(diff.contains("\n100     │-line 5\n    100 │+five\n101 101 │ line 6\n"));
+    }
+
+    #[test]
+    fn side_by_side_splits_the_width_and_wraps_long_lines() {
+        let diff = side_by_side(
+            "short\nsame\n",
+            "a much longer line that wraps\nsame\n",
+            40,
+            &options(),
+        );
+        let rows = [
+            "@@ -1,2 +1,2 @@",
+            "1 short            │ 1 a much longer li",
+            "                   │   ne that wraps",
+            "2 same             │ 2 same",
+        ];
+        assert_eq!(diff.lines().collect::<Vec<_>>(), rows);
+    }
+
+    #[test]
+    fn side_by_side_keeps_wide_characters_within_their_column() {
+        let diff = side_by_side("漢字漢字漢字漢字漢字漢字\n", "漢字\n", 30, &options());
+        // Columns of 11 fit five of the two-column characters a row.
+        let rows: Vec<_> = diff.lines().skip(1).collect();
+        assert_eq!(rows.len(), 3);
+        for row in rows {
+            let (left, _) = row.split_once(" │ ").unwrap();
+            assert_eq!(console::measure_text_width(left), "1 ".len() + 11);
+        }
+    }
+
+    #[test]
+    fn only_the_changed_words_of_a_line_are_emphasized() {
+        let (old, new) = ("timeout = 30 # seconds\n", "timeout = 60 # seconds\n");
+        let diff = TextDiff::from_lines(old, new);
+        let changes: Vec<_> = diff
+            .ops()
+            .iter()
+            .flat_map(|op| diff.iter_inline_changes(op))
+            .collect();
+        let ranges: Vec<_> = changes.iter().map(emphasized_ranges).collect();
+        assert_eq!(ranges.concat(), [10..12, 10..12]);
+
+        let (old_lines, new_lines) = (highlight(old, None, false), highlight(new, None, false));
+        let mut out = String::new();
+        render_line(&mut out, &changes[1], (&old_lines, &new_lines), true, false);
+        assert!(out.ends_with(
+            "\x1b[32m\x1b[48;2;0;63;31mtimeout = \x1b[48;2;0;112;56m60\x1b[48;2;0;63;31m # seconds\x1b[K\x1b[0m\n"
+        ));
+    }
+
+    #[test]
+    fn unchanged_lines_beyond_the_context_are_folded() {
+        let old = numbered(1..21);
+        let new = old
+            .replace("line 1\n", "one\n")
+            .replace("line 20\n", "twenty\n");
+        let one_line = RenderOptions {
+            context: 1,
+            ..options()
+        };
+        assert_eq!(
+            render(&old, &new, &one_line),
+            "@@ -1,2 +1,2 @@\n 1    │-line 1\n    1 │+one\n 2  2 │ line 2\n…\n\
+             @@ -19,2 +19,2 @@\n19 19 │ line 19\n20    │-line 20\n   20 │+twenty\n"
+        );
+        for renderer in [&Unified as &dyn DiffRenderer, &Words, &SideBySide] {
+            let diff = renderer.render(&old, &new, &one_line).unwrap();
+            assert_eq!(diff.matches("@@ -").count(), 2, "{}", renderer.name());
+            assert!(!diff.contains("line 10"), "{}", renderer.name());
+        }
+
+        // Hunks whose context would meet are shown as one.
+        let nine_lines = RenderOptions {
+            context: 9,
+            ..options()
+        };
+        let diff = render(&old, &new, &nine_lines);
+        assert!(diff.starts_with("@@ -1,20 +1,20 @@\n"));
+        assert!(!diff.contains(FOLD));
+    }
+
+    #[test]
+    fn trailing_spaces_tabs_and_carriage_returns_are_marked() {
+        assert_eq!(mark_whitespace("a\tb c  \r\n"), "a→   b c··␍");
+        assert_eq!(mark_whitespace("  indented\n"), "  indented");
+
+        // A change to the line ending alone is visible once marked.
+        let marked = RenderOptions {
+            show_whitespace: true,
+            ..options()
+        };
+        assert_eq!(
+            render("a\tb  \nc\n", "a\tb  \r\nc\n", &marked),
+            "@@ -1,2 +1,2 @@\n1   │-a→   b··\n  1 │+a→   b··␍\n2 2 │ c\n"
+        );
+        assert!(!render("a\tb  \nc\n", "a\tb  \r\nc\n", &options()).contains('␍'));
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{self, Clock},
+    history::FileVersion,
+    session,
+};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReplayArgs {
+    /// Honor the original time between versions instead of playing back as
+    /// fast as possible
+    #[clap(long)]
+    pub realtime: bool,
+
+    /// Playback speed multiplier when honoring original timing
+    #[clap(long, default_value_t = 1.0, requires = "realtime", value_parser = parse_speed)]
+    pub speed: f64,
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err(format!("expected a speed above 0, got `{s}`")),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Plays back the versions recorded in a session journal.
+pub struct Replay {
+    versions: std::vec::IntoIter<FileVersion>,
+    pending: Option<FileVersion>,
+    /// When playback started and the capture time of the first version, to
+    /// schedule the rest relative to.
+    origin: Option<(Instant, std::time::SystemTime)>,
+    args: ReplayArgs,
+    clock: Arc<dyn Clock>,
+}
+
+impl Replay {
+    pub fn load(path: &Path, args: ReplayArgs) -> io::Result<Self> {
+        Ok(Self::new(session::load(path)?, args))
+    }
+
+    /// Plays back `versions`, oldest first.
+    pub fn new(versions: Vec<FileVersion>, args: ReplayArgs) -> Self {
+        let mut versions = versions.into_iter();
+        let pending = versions.next();
+        Self {
+            versions,
+            pending,
+            origin: None,
+            args,
+            clock: clock::system(),
+        }
+    }
+
+    /// Schedules playback by `clock` rather than the system's.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// How long until the next version is due, or `None` once playback is
+    /// finished.
+    fn wait(&mut self) -> Option<Duration> {
+        let pending = self.pending.as_ref()?;
+        if !self.args.realtime {
+            return Some(Duration::ZERO);
+        }
+        let now = self.clock.now();
+        let (started, first) = *self.origin.get_or_insert((now, pending.at));
+        let offset = pending.at.duration_since(first).unwrap_or_default();
+        // Very slow playback of long gaps may be due beyond what an
+        // `Instant` can hold, which is as good as never.
+        let due = Duration::try_from_secs_f64(offset.as_secs_f64() / self.args.speed)
+            .ok()
+            .and_then(|gap| started.checked_add(gap));
+        Some(due.map_or(Duration::MAX, |due| due.saturating_duration_since(now)))
+    }
+
+    /// The next version if it is due, without blocking.
+    pub fn poll(&mut self) -> Option<FileVersion> {
+        if self.wait()? > Duration::ZERO {
+            return None;
+        }
+        self.take()
+    }
+
+    /// The next version, once it is due.
+    pub async fn next(&mut self) -> Option<FileVersion> {
+        tokio::time::sleep(self.wait()?).await;
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<FileVersion> {
+        std::mem::replace(&mut self.pending, self.versions.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    /// A replay of versions captured `seconds` after the epoch, by a clock
+    /// that stands still until advanced.
+    fn replay(seconds: &[u64], realtime: bool, speed: f64) -> (Replay, MockClock) {
+        let versions = seconds
+            .iter()
+            .enumerate()
+            .map(|(number, &seconds)| FileVersion {
+                number,
+                at: UNIX_EPOCH + Duration::from_secs(seconds),
+                ..FileVersion::new_at_now(number.to_string())
+            })
+            .collect();
+        let clock = MockClock::new(UNIX_EPOCH);
+        let args = ReplayArgs { realtime, speed };
+        let replay = Replay::new(versions, args).with_clock(Arc::new(clock.clone()));
+        (replay, clock)
+    }
+
+    fn numbers(replay: &mut Replay) -> Vec<usize> {
+        std::iter::from_fn(|| replay.poll())
+            .map(|version| version.number)
+            .collect()
+    }
+
+    #[test]
+    fn plays_everything_at_once_unless_realtime() {
+        let (mut replay, _) = replay(&[0, 10, 20], false, 1.0);
+        assert_eq!(numbers(&mut replay), [0, 1, 2]);
+    }
+
+    #[test]
+    fn realtime_keeps_the_original_gaps() {
+        let (mut replay, clock) = replay(&[100, 110, 130], true, 1.0);
+        assert_eq!(numbers(&mut replay), [0]);
+        clock.advance(Duration::from_secs(9));
+        assert!(numbers(&mut replay).is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(numbers(&mut replay), [1]);
+        clock.advance(Duration::from_secs(19));
+        assert!(numbers(&mut replay).is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(numbers(&mut replay), [2]);
+        assert!(replay.poll().is_none());
+    }
+
+    #[test]
+    fn speed_scales_the_gaps() {
+        let (mut fast, clock) = replay(&[0, 10, 20], true, 2.0);
+        assert_eq!(numbers(&mut fast), [0]);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(numbers(&mut fast), [1]);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(numbers(&mut fast), [2]);
+
+        let (mut slow, clock) = replay(&[0, 10], true, 0.5);
+        assert_eq!(numbers(&mut slow), [0]);
+        clock.advance(Duration::from_secs(19));
+        assert!(numbers(&mut slow).is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(numbers(&mut slow), [1]);
+    }
+
+    #[test]
+    fn falling_behind_plays_everything_due() {
+        let (mut replay, clock) = replay(&[0, 1, 2, 60], true, 1.0);
+        assert_eq!(numbers(&mut replay), [0]);
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(numbers(&mut replay), [1, 2]);
+    }
+
+    #[test]
+    fn versions_out_of_order_are_due_at_once() {
+        let (mut replay, _) = replay(&[50, 40], true, 1.0);
+        assert_eq!(numbers(&mut replay), [0, 1]);
+    }
+
+    #[test]
+    fn speeds_must_be_above_zero() {
+        assert_eq!(parse_speed("2.5"), Ok(2.5));
+        assert_eq!(parse_speed("1e-300"), Ok(1e-300));
+        for speed in ["0", "-1", "-0", "inf", "NaN"] {
+            assert!(parse_speed(speed).is_err(), "{speed}");
+        }
+        assert!(parse_speed("fast").is_err());
+
+        let (mut crawl, clock) = replay(&[0, u32::MAX as u64], true, 1e-300);
+        assert_eq!(numbers(&mut crawl), [0]);
+        clock.advance(Duration::from_secs(u32::MAX as u64));
+        assert!(numbers(&mut crawl).is_empty());
+    }
+}
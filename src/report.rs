@@ -0,0 +1,473 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use similar::TextDiff;
+
+use crate::{clock::Clock, stats::LineStats};
+
+/// How long the periods are that changes are counted in to find the
+/// busiest.
+const PERIOD: Duration = Duration::from_secs(60);
+
+/// How many of the busiest periods are listed.
+const BUSIEST: usize = 3;
+
+/// What `--exit-report` writes, picked by the report's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Html,
+}
+
+impl ReportFormat {
+    /// HTML for `.html` and `.htm`, text for anything else.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm") => {
+                Self::Html
+            }
+            _ => Self::Text,
+        }
+    }
+}
+
+/// Collects the changes of a watch session for `--exit-report`: how much
+/// changed, when it was busiest, the biggest single change and the net
+/// change from the start to the end of the session.
+pub struct SessionReport {
+    file: PathBuf,
+    clock: Arc<dyn Clock>,
+    started: SystemTime,
+    /// The contents when the session started.
+    base: Option<Arc<str>>,
+    changes: Vec<RecordedChange>,
+}
+
+struct RecordedChange {
+    number: usize,
+    at: SystemTime,
+    stats: LineStats,
+}
+
+impl SessionReport {
+    /// A report on `file` for a session starting now by `clock`.
+    pub fn new(file: &Path, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            started: clock.system_now(),
+            clock,
+            base: None,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Records version `number`, captured `at` with `contents`, which
+    /// changed `stats` lines from `prev`. The session starts from the first
+    /// `prev`, or from the first version if there was none before it.
+    pub fn record(
+        &mut self,
+        prev: Option<&Arc<str>>,
+        number: usize,
+        at: SystemTime,
+        contents: &Arc<str>,
+        stats: LineStats,
+    ) {
+        if self.base.is_none() {
+            self.base = Some(Arc::clone(prev.unwrap_or(contents)));
+        }
+        if prev.is_some() {
+            self.changes.push(RecordedChange { number, at, stats });
+        }
+    }
+
+    /// Writes the report of the session, ending now, with the file at
+    /// `latest`.
+    pub fn write(&self, path: &Path, latest: &str) -> io::Result<()> {
+        let summary = self.summarize(latest, self.clock.system_now());
+        let report = match ReportFormat::for_path(path) {
+            ReportFormat::Text => summary.text(),
+            ReportFormat::Html => summary.html(),
+        };
+        fs::write(path, report)
+    }
+
+    fn summarize(&self, latest: &str, ended: SystemTime) -> Summary {
+        let mut total = LineStats::default();
+        let mut periods: BTreeMap<u64, Period> = BTreeMap::new();
+        for change in &self.changes {
+            total.inserted += change.stats.inserted;
+            total.deleted += change.stats.deleted;
+            let start = change
+                .at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                / PERIOD.as_secs()
+                * PERIOD.as_secs();
+            let period = periods.entry(start).or_default();
+            period.changes += 1;
+            period.stats.inserted += change.stats.inserted;
+            period.stats.deleted += change.stats.deleted;
+        }
+        let mut busiest: Vec<_> = periods
+            .into_iter()
+            .map(|(start, period)| (UNIX_EPOCH + Duration::from_secs(start), period))
+            .collect();
+        // Stable, so ties stay in time order.
+        busiest.sort_by_key(|(_, period)| std::cmp::Reverse(period.changes));
+        busiest.truncate(BUSIEST);
+
+        let biggest = self
+            .changes
+            .iter()
+            .max_by_key(|change| change.stats.inserted + change.stats.deleted)
+            .map(|change| (change.number, change.at, change.stats));
+
+        let base = self.base.as_deref().unwrap_or(latest);
+        let name = self
+            .file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let net_diff = TextDiff::from_lines(base, latest)
+            .unified_diff()
+            .header(&format!("a/{name}"), &format!("b/{name}"))
+            .to_string();
+
+        Summary {
+            file: self.file.clone(),
+            started: self.started,
+            ended,
+            changes: self.changes.len(),
+            total,
+            busiest,
+            biggest,
+            net: LineStats::between(base, latest),
+            net_diff,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Period {
+    changes: usize,
+    stats: LineStats,
+}
+
+struct Summary {
+    file: PathBuf,
+    started: SystemTime,
+    ended: SystemTime,
+    changes: usize,
+    total: LineStats,
+    /// The busiest periods by when they started, busiest first.
+    busiest: Vec<(SystemTime, Period)>,
+    biggest: Option<(usize, SystemTime, LineStats)>,
+    net: LineStats,
+    net_diff: String,
+}
+
+impl Summary {
+    fn lines(&self) -> Vec<(&'static str, String)> {
+        let duration = self.ended.duration_since(self.started).unwrap_or_default();
+        vec![
+            ("started", timestamp(self.started)),
+            (
+                "ended",
+                format!(
+                    "{} after {}",
+                    timestamp(self.ended),
+                    humantime::format_duration(Duration::from_secs(duration.as_secs()))
+                ),
+            ),
+            (
+                "changes",
+                format!("{}, {}", self.changes, lines_changed(self.total)),
+            ),
+            (
+                "biggest",
+                match self.biggest {
+                    Some((number, at, stats)) => {
+                        format!(
+                            "version {number} at {}, {}",
+                            timestamp(at),
+                            lines_changed(stats)
+                        )
+                    }
+                    None => "none".into(),
+                },
+            ),
+            ("net", lines_changed(self.net)),
+        ]
+    }
+
+    fn periods(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.busiest.iter().map(|(start, period)| {
+            (
+                timestamp(*start),
+                format!(
+                    "{} changes, {}",
+                    period.changes,
+                    lines_changed(period.stats)
+                ),
+            )
+        })
+    }
+
+    fn text(&self) -> String {
+        let mut out = format!("Watch report for {}\n\n", self.file.display());
+        for (label, value) in self.lines() {
+            let _ = writeln!(out, "{label:<8} {value}");
+        }
+        if !self.busiest.is_empty() {
+            let _ = writeln!(out, "\nbusiest minutes");
+            for (start, value) in self.periods() {
+                let _ = writeln!(out, "  {start}  {value}");
+            }
+        }
+        if !self.net_diff.is_empty() {
+            let _ = write!(out, "\nnet change\n{}", self.net_diff);
+        }
+        out
+    }
+
+    fn html(&self) -> String {
+        let title = html_escape(&format!("Watch report for {}", self.file.display()));
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>\nbody {{ font-family: sans-serif; }}\nth {{ text-align: left; padding-right: 1em; }}\n\
+             pre {{ background: #f6f8fa; padding: 0.5em; }}\n.insert {{ color: #116329; }}\n\
+             .delete {{ color: #82071e; }}\n.hunk {{ color: #0550ae; text-decoration: none; }}\n\
+             :target {{ background: #fff8c5; }}\n</style>\n</head>\n<body>\n\
+             <h1>{title}</h1>\n<table>\n"
+        );
+        for (label, value) in self.lines() {
+            let _ = writeln!(
+                out,
+                "<tr id=\"{label}\"><th>{label}</th><td>{}</td></tr>",
+                html_escape(&value)
+            );
+        }
+        out.push_str("</table>\n");
+        if !self.busiest.is_empty() {
+            out.push_str("<h2 id=\"busiest\">Busiest minutes</h2>\n<table>\n");
+            for (start, value) in self.periods() {
+                let _ = writeln!(
+                    out,
+                    "<tr><th>{start}</th><td>{}</td></tr>",
+                    html_escape(&value)
+                );
+            }
+            out.push_str("</table>\n");
+        }
+        if !self.net_diff.is_empty() {
+            out.push_str("<h2 id=\"net\">Net change</h2>\n<pre>");
+            let mut hunks = 0;
+            for line in self.net_diff.lines() {
+                let class = match line.as_bytes().first() {
+                    _ if line.starts_with("+++") || line.starts_with("---") => None,
+                    Some(b'+') => Some("insert"),
+                    Some(b'-') => Some("delete"),
+                    Some(b'@') => Some("hunk"),
+                    _ => None,
+                };
+                let line = html_escape(line);
+                match class {
+                    // Hunks are anchored so they can be linked to as
+                    // `report.html#net-h2`.
+                    Some("hunk") => {
+                        hunks += 1;
+                        let _ = writeln!(
+                            out,
+                            "<a class=\"hunk\" id=\"net-h{hunks}\" href=\"#net-h{hunks}\">{line}</a>"
+                        );
+                    }
+                    Some(class) => {
+                        let _ = writeln!(out, "<span class=\"{class}\">{line}</span>");
+                    }
+                    None => {
+                        let _ = writeln!(out, "{line}");
+                    }
+                }
+            }
+            out.push_str("</pre>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+/// Where the report on `file` goes when one `--exit-report` path is given for
+/// several files: `report.html` becomes `report-app.conf.html` for
+/// `app.conf`.
+pub fn path_for(report: &Path, file: &Path) -> PathBuf {
+    let mut name = report.file_stem().unwrap_or_default().to_os_string();
+    name.push("-");
+    name.push(file.file_name().unwrap_or_default());
+    if let Some(extension) = report.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    report.with_file_name(name)
+}
+
+fn timestamp(at: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(at).to_string()
+}
+
+fn lines_changed(stats: LineStats) -> String {
+    format!("+{} -{} lines", stats.inserted, stats.deleted)
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn stats(inserted: usize, deleted: usize) -> LineStats {
+        LineStats { inserted, deleted }
+    }
+
+    fn clock() -> Arc<dyn Clock> {
+        Arc::new(MockClock::new(at(0)))
+    }
+
+    fn report() -> SessionReport {
+        let mut report = SessionReport::new(Path::new("dir/notes.txt"), clock());
+        let base = Arc::from("a\nb\n");
+        report.record(None, 0, at(0), &base, LineStats::default());
+        for (number, seconds, changed) in [
+            (1, 10, stats(1, 0)),
+            (2, 70, stats(1, 1)),
+            (3, 80, stats(5, 2)),
+            (4, 90, stats(1, 0)),
+            (5, 200, stats(0, 1)),
+        ] {
+            report.record(Some(&base), number, at(seconds), &Arc::from(""), changed);
+        }
+        report
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(
+            ReportFormat::for_path(Path::new("r.html")),
+            ReportFormat::Html
+        );
+        assert_eq!(
+            ReportFormat::for_path(Path::new("r.HTM")),
+            ReportFormat::Html
+        );
+        assert_eq!(
+            ReportFormat::for_path(Path::new("r.txt")),
+            ReportFormat::Text
+        );
+        assert_eq!(
+            ReportFormat::for_path(Path::new("report")),
+            ReportFormat::Text
+        );
+    }
+
+    #[test]
+    fn the_first_version_is_the_baseline() {
+        let summary = report().summarize("a\nc\n", at(300));
+        assert_eq!(summary.changes, 5);
+        assert_eq!(summary.total, stats(8, 4));
+        assert_eq!(summary.net, stats(1, 1));
+        assert!(summary
+            .net_diff
+            .starts_with("--- a/notes.txt\n+++ b/notes.txt\n"));
+    }
+
+    #[test]
+    fn a_resumed_session_starts_from_the_version_before() {
+        let mut report = SessionReport::new(Path::new("notes.txt"), clock());
+        let (before, after) = (Arc::from("a\n"), Arc::from("b\n"));
+        report.record(Some(&before), 7, at(5), &after, stats(1, 1));
+        let summary = report.summarize(&after, at(10));
+        assert_eq!(summary.changes, 1);
+        assert_eq!(summary.net, stats(1, 1));
+    }
+
+    #[test]
+    fn busiest_minutes_come_first_with_ties_in_time_order() {
+        let summary = report().summarize("", at(300));
+        let busiest: Vec<_> = summary
+            .busiest
+            .iter()
+            .map(|(start, period)| (*start, period.changes))
+            .collect();
+        assert_eq!(busiest, [(at(60), 3), (at(0), 1), (at(180), 1)]);
+    }
+
+    #[test]
+    fn biggest_change_counts_both_sides() {
+        let summary = report().summarize("", at(300));
+        assert_eq!(summary.biggest, Some((3, at(80), stats(5, 2))));
+    }
+
+    #[test]
+    fn html_escapes_the_diff() {
+        let mut report = SessionReport::new(Path::new("page.html"), clock());
+        report.record(None, 0, at(0), &Arc::from("<p>\n"), LineStats::default());
+        let html = report.summarize("<b>\n", at(1)).html();
+        assert!(html.contains("<span class=\"delete\">-&lt;p&gt;</span>"));
+        assert!(html.contains("<span class=\"insert\">+&lt;b&gt;</span>"));
+    }
+
+    #[test]
+    fn html_hunks_can_be_linked_to() {
+        let mut report = SessionReport::new(Path::new("notes.txt"), clock());
+        let base = Arc::from("a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n");
+        report.record(None, 0, at(0), &base, LineStats::default());
+        let html = report
+            .summarize("A\nb\nc\nd\ne\nf\ng\nh\ni\nJ\n", at(1))
+            .html();
+        assert!(html.contains("<h2 id=\"net\">"));
+        assert!(html.contains("id=\"net-h1\" href=\"#net-h1\">@@ -1,4 +1,4 @@</a>"));
+        assert!(html.contains("id=\"net-h2\" href=\"#net-h2\">@@ -7,4 +7,4 @@</a>"));
+    }
+
+    #[test]
+    fn the_session_ends_when_the_clock_says() {
+        let clock = MockClock::new(at(0));
+        let mut report = SessionReport::new(Path::new("notes.txt"), Arc::new(clock.clone()));
+        report.record(None, 0, at(0), &Arc::from("a\n"), LineStats::default());
+        clock.advance(Duration::from_secs(90));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.txt");
+        report.write(&path, "b\n").unwrap();
+        let text = fs::read_to_string(path).unwrap();
+        assert!(text.contains("\nstarted  1970-01-01T00:00:00Z\n"));
+        assert!(text.contains("\nended    1970-01-01T00:01:30Z after 1m 30s\n"));
+    }
+
+    #[test]
+    fn reports_on_several_files_are_named_after_them() {
+        assert_eq!(
+            path_for(Path::new("out/report.html"), Path::new("/etc/app.conf")),
+            Path::new("out/report-app.conf.html")
+        );
+        assert_eq!(
+            path_for(Path::new("report"), Path::new("notes.txt")),
+            Path::new("report-notes.txt")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// How deep includes may nest before giving up, which also stops include
+/// cycles.
+const MAX_DEPTH: usize = 16;
+
+/// A config file with its includes expanded into one logical document.
+pub struct Resolved {
+    pub text: String,
+    /// Every file that contributed to `text`, the root first.
+    pub files: Vec<PathBuf>,
+}
+
+/// Reads `path`, replacing nginx `include x;` and sshd `Include x y`
+/// directives with the contents of the files they name (globs allowed,
+/// relative to the including file), and appending systemd drop-ins from a
+/// sibling `<unit>.d/*.conf` directory.
+///
+/// Each expansion is wrapped in `# >>> path` / `# <<< path` marker lines so
+/// a diff shows which fragment changed.
+pub fn resolve(path: &Path) -> io::Result<Resolved> {
+    let mut resolved = Resolved {
+        text: String::new(),
+        files: Vec::new(),
+    };
+    expand(path, 0, &mut resolved)?;
+
+    for drop_in in drop_ins(path) {
+        resolved
+            .text
+            .push_str(&format!("# >>> {}\n", drop_in.display()));
+        expand(&drop_in, 1, &mut resolved)?;
+        resolved
+            .text
+            .push_str(&format!("# <<< {}\n", drop_in.display()));
+    }
+    Ok(resolved)
+}
+
+fn expand(path: &Path, depth: usize, resolved: &mut Resolved) -> io::Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(io::Error::other(format!(
+            "includes nested too deeply at {}",
+            path.display()
+        )));
+    }
+    let text = fs::read_to_string(path)?;
+    resolved.files.push(path.to_path_buf());
+    let base = path.parent().unwrap_or(Path::new("."));
+
+    for line in text.split_inclusive('\n') {
+        let Some(patterns) = include_patterns(line) else {
+            resolved.text.push_str(line);
+            continue;
+        };
+        resolved.text.push_str(line);
+        if !line.ends_with('\n') {
+            resolved.text.push('\n');
+        }
+        for pattern in patterns {
+            for included in matches(base, &pattern) {
+                resolved
+                    .text
+                    .push_str(&format!("# >>> {}\n", included.display()));
+                expand(&included, depth + 1, resolved)?;
+                if !resolved.text.ends_with('\n') {
+                    resolved.text.push('\n');
+                }
+                resolved
+                    .text
+                    .push_str(&format!("# <<< {}\n", included.display()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The patterns named by an include directive on this line, if it is one.
+fn include_patterns(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    let (keyword, rest) = line.split_once(char::is_whitespace)?;
+    if keyword == "include" && rest.trim_end().ends_with(';') {
+        // nginx: `include conf.d/*.conf;`
+        let pattern = rest.trim_end().trim_end_matches(';').trim();
+        return Some(vec![pattern.trim_matches('"').to_string()]);
+    }
+    if keyword.eq_ignore_ascii_case("include") {
+        // sshd_config: `Include /etc/ssh/sshd_config.d/*.conf other.conf`
+        return Some(rest.split_whitespace().map(str::to_string).collect());
+    }
+    None
+}
+
+fn matches(base: &Path, pattern: &str) -> Vec<PathBuf> {
+    let pattern = base.join(pattern);
+    let Ok(paths) = glob::glob(&pattern.to_string_lossy()) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = paths
+        .filter_map(Result::ok)
+        .filter(|p| p.is_file())
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// systemd drop-ins for a unit file: `foo.service.d/*.conf` next to it.
+fn drop_ins(path: &Path) -> Vec<PathBuf> {
+    const UNIT_TYPES: &[&str] = &[
+        "service", "socket", "timer", "mount", "path", "target", "slice", "scope",
+    ];
+    let is_unit = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| UNIT_TYPES.contains(&ext));
+    if !is_unit {
+        return Vec::new();
+    }
+    let mut dir = path.as_os_str().to_owned();
+    dir.push(".d");
+    matches(Path::new(&dir), "*.conf")
+}
@@ -0,0 +1,204 @@
+use std::{
+    fmt::Write as _,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::render::Renderers;
+
+/// Longest file or archive member shown as a hex dump; larger ones are
+/// left to the usual handling.
+pub const MAX_HEX_SIZE: usize = 64 << 10;
+
+/// Bytes per line of a hex dump.
+const HEX_WIDTH: usize = 16;
+
+/// What a [`Rule`] does with the files it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Treatment {
+    /// Diff key paths, guessing JSON, TOML or YAML if the extension doesn't
+    /// say.
+    Structured,
+    /// Capture the bytes as a hex dump and diff that.
+    Hex,
+    /// Show changes with this renderer, e.g. `words` or `side-by-side`.
+    Renderer(String),
+}
+
+/// `--rule GLOB=MODE`: treat files matching a glob a certain way, e.g.
+/// `*.md=words`, `*.conf=structured` or `*.bin=hex`, matched as by
+/// [`glob_matches`].
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pattern: glob::Pattern,
+    treatment: Treatment,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, mode) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected GLOB=MODE, e.g. `*.md=words`, got `{s}`"))?;
+        let pattern =
+            glob::Pattern::new(pattern).map_err(|e| format!("`{pattern}` isn't a glob: {e}"))?;
+        let treatment = match mode {
+            "structured" => Treatment::Structured,
+            "hex" => Treatment::Hex,
+            renderer => {
+                let renderers = Renderers::default();
+                if renderers.get(renderer).is_none() {
+                    let names: Vec<&str> = renderers.names().collect();
+                    return Err(format!(
+                        "unknown mode `{renderer}`, expected structured, hex or one of {}",
+                        names.join(", ")
+                    ));
+                }
+                Treatment::Renderer(renderer.into())
+            }
+        };
+        Ok(Self { pattern, treatment })
+    }
+}
+
+/// Whether `pattern` matches `path`. A glob without a `/` is matched
+/// against the file name, any other against the end of the path, whole
+/// components at a time, so `docs/*.md` matches `./docs/guide.md` and
+/// `/home/me/project/docs/guide.md` alike.
+pub fn glob_matches(pattern: &glob::Pattern, path: &Path) -> bool {
+    if !pattern.as_str().contains('/') {
+        return path
+            .file_name()
+            .is_some_and(|name| pattern.matches(&name.to_string_lossy()));
+    }
+    let components: Vec<Component> = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+    (0..components.len()).any(|start| {
+        let suffix: PathBuf = components[start..].iter().collect();
+        pattern.matches_path(&suffix)
+    })
+}
+
+/// The rules applied unless a `--rule` says otherwise.
+const DEFAULT_RULES: &[&str] = &[
+    "*.json=structured",
+    "*.yaml=structured",
+    "*.yml=structured",
+    "*.toml=structured",
+    "*.md=words",
+    "*.markdown=words",
+    "*.bin=hex",
+];
+
+/// The `--rule`s given, the later ones winning, then the defaults.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn new(given: &[Rule]) -> Self {
+        let defaults = DEFAULT_RULES
+            .iter()
+            .map(|rule| rule.parse().expect("default rules parse"));
+        Self {
+            rules: given.iter().rev().cloned().chain(defaults).collect(),
+        }
+    }
+
+    /// How to treat the file or archive member at `path`, if a rule says.
+    pub fn treatment(&self, path: &Path) -> Option<&Treatment> {
+        self.rules
+            .iter()
+            .find(|rule| glob_matches(&rule.pattern, path))
+            .map(|rule| &rule.treatment)
+    }
+
+    pub fn is_hex(&self, path: &Path) -> bool {
+        self.treatment(path) == Some(&Treatment::Hex)
+    }
+}
+
+/// `bytes` as lines of an offset, 16 bytes in hex and those bytes as ASCII,
+/// like `xxd`.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, chunk) in bytes.chunks(HEX_WIDTH).enumerate() {
+        let _ = write!(dump, "{:08x}:", i * HEX_WIDTH);
+        for byte in chunk {
+            let _ = write!(dump, " {byte:02x}");
+        }
+        dump.push_str(&"   ".repeat(HEX_WIDTH - chunk.len()));
+        dump.push_str("  ");
+        dump.extend(chunk.iter().map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        }));
+        dump.push('\n');
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_rules_win_over_each_other_and_the_defaults() {
+        let given: Vec<Rule> = ["*.md=side-by-side", "docs/*.md=unified"]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        let rules = Rules::new(&given);
+        let treatment = |path: &str| rules.treatment(Path::new(path)).cloned();
+        assert_eq!(
+            treatment("README.md"),
+            Some(Treatment::Renderer("side-by-side".into()))
+        );
+        assert_eq!(
+            treatment("docs/guide.md"),
+            Some(Treatment::Renderer("unified".into()))
+        );
+        assert_eq!(treatment("dist/config.json"), Some(Treatment::Structured));
+        assert!(rules.is_hex(Path::new("firmware.bin")));
+        assert_eq!(treatment("notes.txt"), None);
+    }
+
+    #[test]
+    fn globs_with_a_slash_match_the_end_of_the_path() {
+        let pattern = glob::Pattern::new("docs/*.md").unwrap();
+        assert!(glob_matches(&pattern, Path::new("docs/guide.md")));
+        assert!(glob_matches(&pattern, Path::new("./docs/guide.md")));
+        assert!(glob_matches(
+            &pattern,
+            Path::new("/home/me/project/docs/guide.md")
+        ));
+        assert!(!glob_matches(
+            &pattern,
+            Path::new("/home/me/mydocs/guide.md")
+        ));
+        assert!(!glob_matches(&pattern, Path::new("docs/guide.txt")));
+
+        let absolute = glob::Pattern::new("/etc/*.conf").unwrap();
+        assert!(glob_matches(&absolute, Path::new("/etc/app.conf")));
+        assert!(!glob_matches(&absolute, Path::new("/srv/etc/app.conf")));
+    }
+
+    #[test]
+    fn rejects_unknown_modes() {
+        assert!("*.md=wrods".parse::<Rule>().is_err());
+        assert!("*.md".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn dumps_bytes_like_xxd() {
+        assert_eq!(
+            hex_dump(b"0123456789abcdefAB\x00"),
+            "00000000: 30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  0123456789abcdef\n\
+             00000010: 41 42 00                                         AB.\n"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+#[cfg(feature = "serve")]
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use std::{io, net::SocketAddr, path::Path};
+
+#[cfg(feature = "serve")]
+use axum::{
+    extract::{Path as UrlPath, Query, State},
+    http::StatusCode,
+    Json,
+};
+#[cfg(feature = "serve")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serve")]
+use similar::{DiffTag, TextDiff};
+
+use crate::history::Version;
+#[cfg(feature = "serve")]
+use crate::{report::html_escape, stats::LineStats};
+
+/// How many updates a slow browser may fall behind before it misses some.
+#[cfg(feature = "serve")]
+const UPDATE_BACKLOG: usize = 64;
+
+/// The `--serve` web UI: version tabs and a side-by-side diff of the
+/// selected one against its predecessor, kept up to date over a WebSocket as
+/// versions are published.
+///
+/// Every version's changes can be linked to as `/#v7`, and each hunk of them
+/// as `/#v7-h2`, numbered from 1 within the version.
+///
+/// The same history is available as JSON for scripts:
+///
+/// - `GET /files` lists the watched files. There is only the one, with id 0.
+/// - `GET /files/{id}/versions` lists its versions.
+/// - `GET /files/{id}/diff?from=3&to=7` diffs two of them. `to` defaults to
+///   the latest version and `from` to the one before `to`.
+#[cfg(feature = "serve")]
+pub struct Server {
+    shared: Arc<Shared>,
+}
+
+#[cfg(feature = "serve")]
+struct Shared {
+    file: PathBuf,
+    /// The versions published so far, oldest first.
+    versions: Mutex<VecDeque<Entry>>,
+    max_versions: Option<usize>,
+    updates: tokio::sync::broadcast::Sender<Arc<str>>,
+}
+
+#[cfg(feature = "serve")]
+struct Entry {
+    number: usize,
+    at: SystemTime,
+    contents: Arc<str>,
+    /// As sent to browsers.
+    message: Arc<str>,
+}
+
+/// A version as sent to the page, with the rows of its side-by-side diff
+/// against the previous one.
+#[cfg(feature = "serve")]
+#[derive(Serialize)]
+struct Published<'a> {
+    number: usize,
+    at: String,
+    rows: Vec<Row<'a>>,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Serialize)]
+struct Row<'a> {
+    tag: &'static str,
+    /// Which hunk of the version the row belongs to, from 1. None for
+    /// unchanged rows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hunk: Option<usize>,
+    old: Option<&'a str>,
+    new: Option<&'a str>,
+}
+
+#[cfg(feature = "serve")]
+impl Server {
+    /// Starts serving on `addr` for the watch of `file`, keeping up to
+    /// `max_versions` for browsers that connect later. Must be called within
+    /// a tokio runtime, which the server runs on.
+    pub fn bind(addr: SocketAddr, file: &Path, max_versions: Option<u64>) -> io::Result<Self> {
+        use axum::{routing::get, Router};
+
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let (updates, _) = tokio::sync::broadcast::channel(UPDATE_BACKLOG);
+        let shared = Arc::new(Shared {
+            file: file.to_path_buf(),
+            versions: Mutex::default(),
+            max_versions: max_versions.and_then(|max| usize::try_from(max).ok()),
+            updates,
+        });
+        let app = Router::new()
+            .route("/", get(page))
+            .route("/ws", get(socket))
+            .route("/files", get(files))
+            .route("/files/:id/versions", get(versions))
+            .route("/files/:id/diff", get(diff))
+            .with_state(Arc::clone(&shared));
+        tokio::spawn(async move {
+            // Serving only ends with the process.
+            let _ = axum::serve(listener, app).await;
+        });
+        Ok(Self { shared })
+    }
+
+    /// Shows `version`, which followed `prev`, to every browser.
+    pub fn publish(&self, prev: Option<&str>, version: &Version) {
+        let message: Arc<str> = serde_json::to_string(&Published {
+            number: version.number,
+            at: humantime::format_rfc3339_seconds(version.at).to_string(),
+            rows: rows(prev.unwrap_or(&version.contents), &version.contents),
+        })
+        .expect("versions serialize")
+        .into();
+        let mut versions = self.shared.versions.lock().unwrap();
+        versions.push_back(Entry {
+            number: version.number,
+            at: version.at,
+            contents: Arc::clone(&version.contents),
+            message: Arc::clone(&message),
+        });
+        if self
+            .shared
+            .max_versions
+            .is_some_and(|max| versions.len() > max)
+        {
+            versions.pop_front();
+        }
+        // Fails only while no browser is connected.
+        let _ = self.shared.updates.send(message);
+    }
+}
+
+#[cfg(feature = "serve")]
+async fn page(State(shared): State<Arc<Shared>>) -> axum::response::Html<String> {
+    let title = html_escape(&shared.file.display().to_string());
+    axum::response::Html(include_str!("serve.html").replace("{{title}}", &title))
+}
+
+#[cfg(feature = "serve")]
+async fn socket(
+    upgrade: axum::extract::WebSocketUpgrade,
+    State(shared): State<Arc<Shared>>,
+) -> axum::response::Response {
+    use axum::extract::ws::Message;
+
+    upgrade.on_upgrade(|mut socket| async move {
+        // Subscribed before the history is sent so that nothing published
+        // in between is missed; the page ignores versions it already has.
+        let mut updates = shared.updates.subscribe();
+        let history: Vec<_> = shared
+            .versions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| Arc::clone(&entry.message))
+            .collect();
+        for message in history {
+            if socket
+                .send(Message::Text(message.to_string()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        while let Ok(message) = updates.recv().await {
+            if socket
+                .send(Message::Text(message.to_string()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    })
+}
+
+/// The id of the one file served, in the JSON endpoints' URLs.
+#[cfg(feature = "serve")]
+const FILE_ID: usize = 0;
+
+#[cfg(feature = "serve")]
+type ApiError = (StatusCode, Json<ErrorBody>);
+
+#[cfg(feature = "serve")]
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[cfg(feature = "serve")]
+fn not_found(error: String) -> ApiError {
+    (StatusCode::NOT_FOUND, Json(ErrorBody { error }))
+}
+
+#[cfg(feature = "serve")]
+fn check_file(id: usize) -> Result<(), ApiError> {
+    match id == FILE_ID {
+        true => Ok(()),
+        false => Err(not_found(format!("no file {id}"))),
+    }
+}
+
+#[cfg(feature = "serve")]
+#[derive(Serialize)]
+struct FileInfo {
+    id: usize,
+    path: PathBuf,
+    versions: usize,
+    latest: Option<usize>,
+}
+
+#[cfg(feature = "serve")]
+async fn files(State(shared): State<Arc<Shared>>) -> Json<Vec<FileInfo>> {
+    let versions = shared.versions.lock().unwrap();
+    Json(vec![FileInfo {
+        id: FILE_ID,
+        path: shared.file.clone(),
+        versions: versions.len(),
+        latest: versions.back().map(|entry| entry.number),
+    }])
+}
+
+#[cfg(feature = "serve")]
+#[derive(Serialize)]
+struct VersionInfo {
+    number: usize,
+    at: String,
+    bytes: usize,
+    lines: usize,
+}
+
+#[cfg(feature = "serve")]
+async fn versions(
+    UrlPath(id): UrlPath<usize>,
+    State(shared): State<Arc<Shared>>,
+) -> Result<Json<Vec<VersionInfo>>, ApiError> {
+    check_file(id)?;
+    let versions = shared.versions.lock().unwrap();
+    Ok(Json(
+        versions
+            .iter()
+            .map(|entry| VersionInfo {
+                number: entry.number,
+                at: humantime::format_rfc3339_seconds(entry.at).to_string(),
+                bytes: entry.contents.len(),
+                lines: entry.contents.lines().count(),
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(feature = "serve")]
+#[derive(Deserialize)]
+struct DiffQuery {
+    from: Option<usize>,
+    to: Option<usize>,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Serialize)]
+struct DiffBody {
+    from: usize,
+    to: usize,
+    inserted: usize,
+    deleted: usize,
+    /// Unified diff.
+    diff: String,
+}
+
+#[cfg(feature = "serve")]
+async fn diff(
+    UrlPath(id): UrlPath<usize>,
+    Query(query): Query<DiffQuery>,
+    State(shared): State<Arc<Shared>>,
+) -> Result<Json<DiffBody>, ApiError> {
+    check_file(id)?;
+    let versions = shared.versions.lock().unwrap();
+    let find = |number: usize| {
+        versions
+            .iter()
+            .position(|entry| entry.number == number)
+            .ok_or_else(|| not_found(format!("no version {number}")))
+    };
+    let to = match query.to {
+        Some(number) => find(number)?,
+        None => versions
+            .len()
+            .checked_sub(1)
+            .ok_or_else(|| not_found("no versions yet".into()))?,
+    };
+    let from = match query.from {
+        Some(number) => find(number)?,
+        None => to.saturating_sub(1),
+    };
+    let (from, to) = (&versions[from], &versions[to]);
+    let name = shared
+        .file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stats = LineStats::between(&from.contents, &to.contents);
+    let diff = TextDiff::from_lines(&*from.contents, &*to.contents)
+        .unified_diff()
+        .header(&format!("a/{name}"), &format!("b/{name}"))
+        .to_string();
+    Ok(Json(DiffBody {
+        from: from.number,
+        to: to.number,
+        inserted: stats.inserted,
+        deleted: stats.deleted,
+        diff,
+    }))
+}
+
+/// Stand-in for builds without the `serve` feature, which can't serve.
+#[cfg(not(feature = "serve"))]
+pub struct Server(std::convert::Infallible);
+
+#[cfg(not(feature = "serve"))]
+impl Server {
+    pub fn bind(_addr: SocketAddr, _file: &Path, _max_versions: Option<u64>) -> io::Result<Self> {
+        Err(io::Error::other(
+            "slip-diff was built without the `serve` feature",
+        ))
+    }
+
+    pub fn publish(&self, _prev: Option<&str>, _version: &Version) {
+        match self.0 {}
+    }
+}
+
+/// The side-by-side rows of the change from `old` to `new`, replaced lines
+/// paired up next to each other. Each run of changed rows is a hunk.
+#[cfg(feature = "serve")]
+fn rows<'a>(old: &'a str, new: &'a str) -> Vec<Row<'a>> {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut rows = Vec::new();
+    let mut hunks = 0;
+    let mut in_hunk = false;
+    for op in diff.ops() {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let hunk = match tag {
+            DiffTag::Equal => None,
+            _ => {
+                if !in_hunk {
+                    hunks += 1;
+                }
+                Some(hunks)
+            }
+        };
+        in_hunk = hunk.is_some();
+        let tag = match tag {
+            DiffTag::Equal => "equal",
+            DiffTag::Delete => "delete",
+            DiffTag::Insert => "insert",
+            DiffTag::Replace => "replace",
+        };
+        for i in 0..old_range.len().max(new_range.len()) {
+            rows.push(Row {
+                tag,
+                hunk,
+                old: (i < old_range.len()).then(|| old_lines[old_range.start + i]),
+                new: (i < new_range.len()).then(|| new_lines[new_range.start + i]),
+            });
+        }
+    }
+    rows
+}
+
+#[cfg(all(test, feature = "serve"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_run_of_changed_rows_is_a_hunk() {
+        let rows = rows("a\nb\nc\nd\ne\n", "a\nB\nx\nc\nd\nE\n");
+        let hunks: Vec<_> = rows.iter().map(|row| row.hunk).collect();
+        assert_eq!(
+            hunks,
+            [None, Some(1), Some(1), None, None, Some(2)],
+            "{:?}",
+            rows.iter().map(|row| row.tag).collect::<Vec<_>>()
+        );
+    }
+}
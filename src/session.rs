@@ -0,0 +1,255 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    digest::Digest,
+    fsck::Problem,
+    history::{FileVersion, Version, VersionMeta},
+};
+
+#[derive(Debug, Default, Clone, clap::Args)]
+pub struct SessionArgs {
+    /// Journal every captured version to this file as it arrives
+    #[clap(long)]
+    pub session: Option<PathBuf>,
+
+    /// Reload the history recorded in the session file on startup
+    #[clap(long, requires = "session")]
+    pub resume: bool,
+}
+
+/// One line of a session journal, and one version of a TUI autosave.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Entry {
+    number: usize,
+    /// Milliseconds since the Unix epoch.
+    at: u64,
+    contents: String,
+    /// What `contents` hashed to when recorded, for `fsck` to verify;
+    /// missing from journals written before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, flatten)]
+    meta: VersionMeta,
+}
+
+impl Entry {
+    pub(crate) fn new(version: &Version) -> Self {
+        let at = version
+            .at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self {
+            number: version.number,
+            at,
+            contents: version.contents.to_string(),
+            hash: Some(version.hash.to_string()),
+            meta: version.meta.clone(),
+        }
+    }
+
+    /// Checks that the contents still hash to what was recorded with them.
+    fn verify(&self) -> Result<(), String> {
+        let Some(hash) = &self.hash else {
+            return Ok(());
+        };
+        let hash: Digest = hash
+            .parse()
+            .map_err(|error| format!("has an unreadable hash: {error}"))?;
+        match hash.matches(self.contents.as_bytes()) {
+            true => Ok(()),
+            false => Err(format!("doesn't match its hash {hash}")),
+        }
+    }
+
+    pub(crate) fn into_version(self) -> FileVersion {
+        FileVersion {
+            contents: self.contents.into(),
+            at: SystemTime::UNIX_EPOCH + Duration::from_millis(self.at),
+            number: self.number,
+            meta: self.meta,
+        }
+    }
+}
+
+/// Append-only journal of captured versions, one JSON object per line.
+pub struct SessionJournal {
+    file: File,
+}
+
+impl SessionJournal {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub fn append(&mut self, version: &Version) -> io::Result<()> {
+        let mut line = serde_json::to_string(&Entry::new(version))?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()
+    }
+}
+
+/// Reads back every version recorded in a session journal. A last line cut
+/// short by a crash mid-write is skipped with a warning, as `fsck --repair`
+/// would cut it off.
+pub fn load(path: &Path) -> io::Result<Vec<FileVersion>> {
+    let journal = match fs::read(path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("no session at {}", path.display()),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut versions = Vec::new();
+    for (i, line) in journal.split_inclusive(|&b| b == b'\n').enumerate() {
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        let entry = match serde_json::from_slice::<Entry>(line) {
+            Ok(entry) => entry,
+            Err(_) if !line.ends_with(b"\n") => {
+                tracing::warn!(
+                    "{}: skipping last line {}, which was cut short",
+                    path.display(),
+                    i + 1
+                );
+                break;
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: line {} isn't a valid entry: {e}",
+                        path.display(),
+                        i + 1
+                    ),
+                ))
+            }
+        };
+        versions.push(entry.into_version());
+    }
+    Ok(versions)
+}
+
+/// [`load`]s the session to `--resume`, which is empty if it hasn't been
+/// started yet.
+pub fn resume(path: &Path) -> io::Result<Vec<FileVersion>> {
+    match load(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+/// Checks that every line of a session journal is a valid entry whose
+/// contents match the hash recorded with them. With `repair`, a last line cut
+/// short by a crash mid-write is truncated away; damage anywhere else is only
+/// reported.
+pub fn check(path: &Path, repair: bool) -> io::Result<Vec<Problem>> {
+    let journal = fs::read(path)?;
+    let mut problems = Vec::new();
+    let mut offset = 0;
+    for (i, line) in journal.split_inclusive(|&b| b == b'\n').enumerate() {
+        let complete = line.ends_with(b"\n");
+        let entry = serde_json::from_slice::<Entry>(line).ok();
+        let valid = entry.is_some();
+        if !valid && !complete {
+            if repair {
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_len(offset as u64)?;
+            }
+            problems.push(Problem::new(
+                format!("{}: last line {} was cut short", path.display(), i + 1),
+                repair,
+            ));
+        } else if !valid && !line.trim_ascii().is_empty() {
+            problems.push(Problem::new(
+                format!("{}: line {} isn't a valid entry", path.display(), i + 1),
+                false,
+            ));
+        } else if let Some(Err(error)) = entry.map(|entry| entry.verify()) {
+            problems.push(Problem::new(
+                format!("{}: line {} {error}", path.display(), i + 1),
+                false,
+            ));
+        }
+        offset += line.len();
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::HashAlgorithm;
+
+    fn journal(dir: &Path, contents: &[&str]) -> PathBuf {
+        let path = dir.join("session.jsonl");
+        let mut journal = SessionJournal::open(&path).unwrap();
+        for (number, contents) in contents.iter().enumerate() {
+            let mut version = FileVersion::new_at_now(*contents);
+            version.number = number;
+            journal
+                .append(&version.as_version(HashAlgorithm::default()))
+                .unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn check_finds_entries_not_matching_their_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = journal(dir.path(), &["one\n", "two\n"]);
+        assert!(check(&path, false).unwrap().is_empty());
+
+        let tampered = fs::read_to_string(&path).unwrap().replace("two", "TWO");
+        fs::write(&path, tampered).unwrap();
+        let problems = check(&path, false).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].description.contains("line 2 doesn't match"));
+    }
+
+    #[test]
+    fn only_resuming_takes_a_missing_session_as_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.jsonl");
+        let Err(error) = load(&path) else {
+            panic!("loaded a missing session");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("no session at "));
+        assert!(resume(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn load_skips_a_last_line_cut_short() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = journal(dir.path(), &["one\n", "two\n"]);
+        let mut journal = fs::read(&path).unwrap();
+        journal.truncate(journal.len() - 10);
+        fs::write(&path, &journal).unwrap();
+        let versions = load(&path).unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(&*versions[0].contents, "one\n");
+
+        // Damage before the end is still an error.
+        let mut broken = b"{not json}\n".to_vec();
+        broken.extend(journal);
+        fs::write(&path, broken).unwrap();
+        assert!(load(&path).is_err());
+    }
+}
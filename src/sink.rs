@@ -0,0 +1,399 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use similar::TextDiff;
+
+use crate::{
+    clock::Clock,
+    history::Version,
+    mail::{Mail, MailSpec},
+    mqtt::{Mqtt, MqttSpec},
+    stats::LineStats,
+    system_log::{LogTarget, SystemLog},
+    webhook::{Webhook, WebhookSpec},
+};
+
+/// A local endpoint another process reads changes from, or a chat webhook,
+/// as given to `--sink`.
+#[derive(Debug, Clone)]
+pub enum SinkSpec {
+    /// `fifo:<path>`, a named pipe created with `mkfifo`.
+    Fifo(PathBuf),
+    /// `unix:<path>`, a listening Unix stream socket.
+    Unix(PathBuf),
+    /// `slack:<url>` or `discord:<url>`, posting at most every 10 seconds,
+    /// or e.g. `slack/5m:<url>` for every five minutes.
+    Webhook(WebhookSpec),
+    /// `smtp:smtp://<server>?to=<address>`, mailing a digest of the changes
+    /// every hour, or e.g. `smtp/1d:…` every day.
+    Mail(MailSpec),
+    /// `mqtt://[user:password@]host[:port]/topic`, publishing every change
+    /// to the topic.
+    Mqtt(MqttSpec),
+    /// Syslog or the journal, from `--log-to` rather than `--sink`.
+    SystemLog(LogTarget),
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("fifo", path)) if !path.is_empty() => Ok(Self::Fifo(path.into())),
+            Some(("unix", path)) if !path.is_empty() => Ok(Self::Unix(path.into())),
+            Some(("mqtt", rest)) => Ok(Self::Mqtt(MqttSpec::parse(rest)?)),
+            Some((kind, url)) => {
+                if let Some(spec) = WebhookSpec::parse(kind, url)? {
+                    return Ok(Self::Webhook(spec));
+                }
+                match MailSpec::parse(kind, url)? {
+                    Some(spec) => Ok(Self::Mail(spec)),
+                    None => Err(unsupported(s)),
+                }
+            }
+            None => Err(unsupported(s)),
+        }
+    }
+}
+
+fn unsupported(sink: &str) -> String {
+    format!(
+        "unsupported sink `{sink}`, expected fifo:<path>, unix:<path>, slack:<url>, discord:<url>, \
+         smtp:<url> or mqtt://<host>/<topic>"
+    )
+}
+
+/// What is written to a sink for every change; webhooks and mail always get
+/// a summary.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SinkFormat {
+    /// One JSON object per line with the version, line counts and diff.
+    #[default]
+    Ndjson,
+    /// The unified diff as is.
+    Diff,
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    path: &'a Path,
+    number: usize,
+    /// Milliseconds since the Unix epoch.
+    at: u64,
+    inserted: usize,
+    deleted: usize,
+    /// For a drift report, the version the change is from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<usize>,
+    diff: &'a str,
+}
+
+/// Writes every change to a FIFO or Unix socket or MQTT topic, or posts it
+/// to a webhook or mail digest.
+///
+/// Nobody may be listening, so the sink never blocks or fails the watch:
+/// changes are dropped while there is no reader and the connection is
+/// retried on the next one. A reader too slow to take a whole change gets
+/// the rest of it before the next, and misses changes until it catches up.
+pub struct Sink {
+    spec: SinkSpec,
+    format: SinkFormat,
+    path: PathBuf,
+    writer: Option<Box<dyn Write + Send>>,
+    /// The end of a change the reader had no room for yet.
+    unsent: Vec<u8>,
+    mqtt: Option<Mqtt>,
+    poster: Option<Poster>,
+    previous: Option<Arc<str>>,
+}
+
+impl Sink {
+    /// A sink for changes to `file`, with webhooks and mail digests timed by
+    /// `clock`.
+    pub fn new(spec: SinkSpec, format: SinkFormat, file: &Path, clock: Arc<dyn Clock>) -> Self {
+        let poster = match &spec {
+            SinkSpec::Webhook(webhook) => Some(Poster::Webhook(Webhook::start(
+                webhook.clone(),
+                file,
+                clock,
+            ))),
+            SinkSpec::Mail(mail) => Some(Poster::Mail(Mail::start(mail.clone(), file, clock))),
+            _ => None,
+        };
+        let mqtt = match &spec {
+            SinkSpec::Mqtt(mqtt) => Some(Mqtt::start(mqtt)),
+            _ => None,
+        };
+        Self {
+            spec,
+            format,
+            path: file.to_path_buf(),
+            writer: None,
+            unsent: Vec::new(),
+            mqtt,
+            poster,
+            previous: None,
+        }
+    }
+
+    /// Records every change in `log`.
+    pub fn system_log(target: LogTarget, log: SystemLog, file: &Path) -> Self {
+        let spec = SinkSpec::SystemLog(target);
+        let mut sink = Self::new(spec, SinkFormat::default(), file, crate::clock::system());
+        sink.poster = Some(Poster::SystemLog(log));
+        sink
+    }
+
+    /// Sends the change from the previously sent version to `version`. The
+    /// first version only becomes the baseline.
+    pub fn send(&mut self, version: &Version) {
+        let Some(previous) = self.previous.replace(Arc::clone(&version.contents)) else {
+            return;
+        };
+        if previous == version.contents {
+            return;
+        }
+        self.deliver(previous, version, None);
+    }
+
+    /// Sends a drift report: the change from `since` to `version`, however
+    /// many versions apart they are. Doesn't affect what the next
+    /// [`Self::send`] is diffed against.
+    pub fn report(&mut self, since: &Version, version: &Version) {
+        self.deliver(Arc::clone(&since.contents), version, Some(since.number));
+    }
+
+    fn deliver(&mut self, previous: Arc<str>, version: &Version, since: Option<usize>) {
+        if let Some(poster) = &self.poster {
+            let change = Change {
+                number: version.number,
+                at: version.at,
+                before: previous,
+                after: Arc::clone(&version.contents),
+            };
+            match poster {
+                Poster::Webhook(webhook) => webhook.post(change),
+                Poster::Mail(mail) => mail.post(change),
+                Poster::SystemLog(log) => log.record(&self.path, &change),
+            }
+            return;
+        }
+
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let diff = TextDiff::from_lines(&*previous, &*version.contents)
+            .unified_diff()
+            .header(&format!("a/{name}"), &format!("b/{name}"))
+            .to_string();
+        let message = match self.format {
+            SinkFormat::Diff => diff,
+            SinkFormat::Ndjson => {
+                let stats = LineStats::between(&previous, &version.contents);
+                let event = Event {
+                    path: &self.path,
+                    number: version.number,
+                    at: version
+                        .at
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64,
+                    inserted: stats.inserted,
+                    deleted: stats.deleted,
+                    since,
+                    diff: &diff,
+                };
+                let Ok(mut line) = serde_json::to_string(&event) else {
+                    return;
+                };
+                line.push('\n');
+                line
+            }
+        };
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(message.trim_end_matches('\n'));
+            return;
+        }
+
+        if self.writer.is_none() {
+            self.writer = self.connect().ok();
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let written = match write_some(writer, &self.unsent) {
+            // Still no room for the last change, so none for this one.
+            Ok(written) if written < self.unsent.len() => {
+                self.unsent.drain(..written);
+                return;
+            }
+            Ok(_) => write_some(writer, message.as_bytes()),
+            Err(error) => Err(error),
+        };
+        match written {
+            Ok(written) => self.unsent = message.as_bytes()[written..].to_vec(),
+            Err(_) => {
+                // The reader went away; reconnect on the next change.
+                self.writer = None;
+                self.unsent.clear();
+            }
+        }
+    }
+
+    fn connect(&self) -> io::Result<Box<dyn Write + Send>> {
+        match &self.spec {
+            SinkSpec::Fifo(path) => open_fifo(path),
+            SinkSpec::Unix(path) => connect_unix(path),
+            SinkSpec::Webhook(_)
+            | SinkSpec::Mail(_)
+            | SinkSpec::Mqtt(_)
+            | SinkSpec::SystemLog(_) => {
+                unreachable!("webhooks, mail, MQTT and logs are posted to, not written")
+            }
+        }
+    }
+}
+
+/// Writes as much of `bytes` as the reader has room for, returning how much
+/// that was.
+fn write_some(writer: &mut dyn Write, bytes: &[u8]) -> io::Result<usize> {
+    let mut written = 0;
+    while written < bytes.len() {
+        match writer.write(&bytes[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// A change handed to a sink that posts changes in the background.
+pub struct Change {
+    pub number: usize,
+    pub at: SystemTime,
+    pub before: Arc<str>,
+    pub after: Arc<str>,
+}
+
+/// What posts the changes of a sink that isn't written to.
+enum Poster {
+    Webhook(Webhook),
+    Mail(Mail),
+    SystemLog(SystemLog),
+}
+
+/// Opens a FIFO for writing without waiting for a reader, failing instead if
+/// there is none.
+#[cfg(unix)]
+fn open_fifo(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    Ok(Box::new(file))
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_nonblocking(true)?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(unix))]
+fn open_fifo(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(OpenOptions::new().write(true).open(path)?))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets are not supported on this platform",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{ffi::CString, fs::File, io::Read, os::unix::ffi::OsStrExt};
+
+    use super::*;
+    use crate::{clock, digest::HashAlgorithm, history::FileVersion};
+
+    /// Everything waiting in `fifo`, without blocking.
+    fn read_available(fifo: &mut File) -> Vec<u8> {
+        let mut read = Vec::new();
+        let mut buf = [0; 8192];
+        loop {
+            match fifo.read(&mut buf) {
+                Ok(0) => return read,
+                Ok(n) => read.extend_from_slice(&buf[..n]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return read,
+                Err(error) => panic!("reading the FIFO: {error}"),
+            }
+        }
+    }
+
+    #[test]
+    fn changes_larger_than_the_pipe_arrive_whole() {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("changes");
+        let fifo = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        let file = Path::new("app.log");
+        let mut sink = Sink::new(
+            SinkSpec::Fifo(path.clone()),
+            SinkFormat::Ndjson,
+            file,
+            clock::system(),
+        );
+
+        let large = "a line that makes the change bigger than a pipe holds\n".repeat(2000);
+        for (number, contents) in ["", large.as_str(), "small\n"].into_iter().enumerate() {
+            let mut version = FileVersion::new_at_now(contents);
+            version.number = number;
+            sink.send(&version.as_version(HashAlgorithm::default()));
+        }
+        assert!(large.len() > 64 * 1024);
+        // The pipe only took the start of the large change.
+        let mut received = read_available(&mut reader);
+        assert!(!received.contains(&b'\n'));
+
+        // The rest of it goes ahead of the next change.
+        let mut version = FileVersion::new_at_now("smaller\n");
+        version.number = 3;
+        sink.send(&version.as_version(HashAlgorithm::default()));
+        received.extend(read_available(&mut reader));
+        let numbers: Vec<u64> = received
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let event: serde_json::Value = serde_json::from_slice(line).unwrap();
+                event["number"].as_u64().unwrap()
+            })
+            .collect();
+        assert_eq!(numbers, [1, 3]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use similar::{ChangeTag, TextDiff};
+
+/// How many lines a change added and removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LineStats {
+    pub inserted: usize,
+    pub deleted: usize,
+}
+
+impl LineStats {
+    pub fn between(old: &str, new: &str) -> Self {
+        let mut stats = Self::default();
+        for change in TextDiff::from_lines(old, new).iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => stats.inserted += 1,
+                ChangeTag::Delete => stats.deleted += 1,
+                ChangeTag::Equal => {}
+            }
+        }
+        stats
+    }
+}
+
+/// A version marked known-good, which later versions are compared with as
+/// well as with their predecessor, to see how far the file drifted from it.
+#[derive(Clone)]
+pub struct KnownGood {
+    pub number: usize,
+    contents: Arc<str>,
+}
+
+impl KnownGood {
+    pub fn new(number: usize, contents: Arc<str>) -> Self {
+        Self { number, contents }
+    }
+
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// How many lines `contents` added and removed since the known-good
+    /// version.
+    pub fn drift(&self, contents: &str) -> LineStats {
+        LineStats::between(&self.contents, contents)
+    }
+}
@@ -0,0 +1,503 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+#[cfg(feature = "sqlite")]
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[cfg(feature = "sqlite")]
+use rusqlite::{params, Connection, OptionalExtension};
+
+#[cfg(feature = "sqlite")]
+use crate::digest::Digest;
+
+use crate::{
+    cache::WarmCache,
+    fsck::Problem,
+    git_export::{GitExport, ShadowRef},
+    history::{FileVersion, Version},
+    patch::PatchDir,
+    session::SessionJournal,
+    sink::Sink,
+};
+
+/// Where captured versions are persisted, as given to `--store`.
+#[derive(Debug, Clone)]
+pub enum StoreSpec {
+    /// `sqlite:<path>`
+    Sqlite(PathBuf),
+}
+
+impl FromStr for StoreSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Self::Sqlite(path.into())),
+            _ => Err(format!("unsupported store `{s}`, expected sqlite:<path>")),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+/// Versions, their hashes and timestamps in a SQLite database.
+///
+/// Each row is one captured version of one watched path, so the database can
+/// be queried later, e.g. by time range with
+/// `SELECT number, contents FROM versions WHERE at BETWEEN ? AND ?`.
+///
+/// A store can be shared between threads, e.g. by every watch in one
+/// daemon, and the same database opened by several processes at once.
+/// Writes go through one connection at a time and each is a single
+/// transaction, so a crash never leaves a version half written.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        // Wait for other processes' writes instead of failing with
+        // SQLITE_BUSY. In WAL mode readers don't block the writer and
+        // `synchronous = NORMAL` still can't corrupt the database in a crash.
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS versions (
+                id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL,
+                number INTEGER NOT NULL,
+                at INTEGER NOT NULL,
+                hash TEXT NOT NULL,
+                contents TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS versions_path_at ON versions (path, at);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// The connection, which stays usable even if a thread panicked while
+    /// holding it since every statement is its own transaction.
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn insert(&self, path: &Path, version: &Version) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO versions (path, number, at, hash, contents) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                key(path),
+                version.number as i64,
+                to_millis(version.at),
+                version.hash.to_string(),
+                version.contents.as_ref(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recently captured version of `path`. Numbers start over
+    /// with every run that doesn't resume, so this is the last one inserted
+    /// rather than the highest numbered.
+    pub fn latest(&self, path: &Path) -> rusqlite::Result<Option<FileVersion>> {
+        self.conn()
+            .query_row(
+                "SELECT number, at, contents FROM versions WHERE path = ?1
+                 ORDER BY id DESC LIMIT 1",
+                params![key(path)],
+                row_to_version,
+            )
+            .optional()
+    }
+
+    /// The version of `path` with sequence number `number`.
+    pub fn version(&self, path: &Path, number: usize) -> rusqlite::Result<Option<FileVersion>> {
+        self.conn()
+            .query_row(
+                "SELECT number, at, contents FROM versions WHERE path = ?1 AND number = ?2
+                 ORDER BY id DESC LIMIT 1",
+                params![key(path), number as i64],
+                row_to_version,
+            )
+            .optional()
+    }
+
+    /// The newest version of `path` captured at or before `at`.
+    pub fn at(&self, path: &Path, at: SystemTime) -> rusqlite::Result<Option<FileVersion>> {
+        self.conn()
+            .query_row(
+                "SELECT number, at, contents FROM versions WHERE path = ?1 AND at <= ?2
+                 ORDER BY at DESC, id DESC LIMIT 1",
+                params![key(path), to_millis(at)],
+                row_to_version,
+            )
+            .optional()
+    }
+
+    /// Every path with versions in the store.
+    pub fn paths(&self) -> rusqlite::Result<Vec<PathBuf>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare("SELECT DISTINCT path FROM versions ORDER BY path")?;
+        let paths = statement.query_map([], |row| row.get::<_, String>(0))?;
+        paths.map(|path| path.map(PathBuf::from)).collect()
+    }
+
+    /// Every version of `path` captured between `from` and `to`, inclusive,
+    /// in the order they were inserted.
+    pub fn between(
+        &self,
+        path: &Path,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> Result<Vec<FileVersion>, Box<dyn Error>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT number, at, contents FROM versions
+             WHERE path = ?1 AND at BETWEEN ?2 AND ?3 ORDER BY id",
+        )?;
+        let rows = statement.query_map(
+            params![key(path), to_millis(from), to_millis(to)],
+            row_to_version,
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Runs SQLite's own integrity check and verifies every version against
+    /// its hash. With `repair`, indexes are rebuilt; versions whose contents
+    /// don't match can only be reported.
+    pub fn check(&self, repair: bool) -> Result<Vec<Problem>, Box<dyn Error>> {
+        let mut problems = Vec::new();
+        let integrity = self.integrity()?;
+        if !integrity.is_empty() {
+            let repaired = repair && {
+                self.conn().execute_batch("REINDEX")?;
+                self.integrity()?.is_empty()
+            };
+            for message in integrity {
+                problems.push(Problem::new(message, repaired));
+            }
+        }
+
+        let conn = self.conn();
+        let mut statement =
+            conn.prepare("SELECT path, number, hash, contents FROM versions ORDER BY id")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(0)?;
+            let number: i64 = row.get(1)?;
+            let hash: String = row.get(2)?;
+            let contents: String = row.get(3)?;
+            let description = match hash.parse::<Digest>() {
+                Ok(digest) if digest.matches(contents.as_bytes()) => continue,
+                Ok(_) => format!("version {number} of {path} doesn't match its hash"),
+                Err(error) => format!("version {number} of {path}: {error}"),
+            };
+            problems.push(Problem::new(description, false));
+        }
+        Ok(problems)
+    }
+
+    /// What `PRAGMA integrity_check` found wrong, if anything.
+    fn integrity(&self) -> rusqlite::Result<Vec<String>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare("PRAGMA integrity_check")?;
+        let messages = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(messages.into_iter().filter(|m| m != "ok").collect())
+    }
+}
+
+/// What versions of `path` are stored under: its canonical path, so that
+/// e.g. `./app.conf` and `/etc/app.conf` share a history, or absolute if it
+/// no longer exists. Remote files are kept as given.
+#[cfg(feature = "sqlite")]
+fn key(path: &Path) -> String {
+    let path = match crate::remote::is_remote(path) {
+        true => path.to_path_buf(),
+        false => std::fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    };
+    path.to_string_lossy().into_owned()
+}
+
+/// How long a write waits for another connection to finish its own.
+#[cfg(feature = "sqlite")]
+pub(crate) const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[cfg(feature = "sqlite")]
+fn row_to_version(row: &rusqlite::Row) -> rusqlite::Result<FileVersion> {
+    let number: i64 = row.get(0)?;
+    let at: i64 = row.get(1)?;
+    Ok(FileVersion {
+        contents: row.get(2)?,
+        at: UNIX_EPOCH + Duration::from_millis(at as u64),
+        number: number as usize,
+        meta: Default::default(),
+    })
+}
+
+#[cfg(feature = "sqlite")]
+fn to_millis(at: SystemTime) -> i64 {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Stand-in for builds without the `sqlite` feature, which fails to open.
+#[cfg(not(feature = "sqlite"))]
+pub struct SqliteStore(std::convert::Infallible);
+
+#[cfg(not(feature = "sqlite"))]
+impl SqliteStore {
+    pub fn open(_path: &Path) -> Result<Self, Box<dyn Error>> {
+        Err("slip-diff was built without the `sqlite` feature".into())
+    }
+
+    pub fn insert(&self, _path: &Path, _version: &Version) -> Result<(), Box<dyn Error>> {
+        match self.0 {}
+    }
+
+    pub fn latest(&self, _path: &Path) -> Result<Option<FileVersion>, Box<dyn Error>> {
+        match self.0 {}
+    }
+
+    pub fn version(
+        &self,
+        _path: &Path,
+        _number: usize,
+    ) -> Result<Option<FileVersion>, Box<dyn Error>> {
+        match self.0 {}
+    }
+
+    pub fn at(&self, _path: &Path, _at: SystemTime) -> Result<Option<FileVersion>, Box<dyn Error>> {
+        match self.0 {}
+    }
+
+    pub fn paths(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        match self.0 {}
+    }
+
+    pub fn between(
+        &self,
+        _path: &Path,
+        _from: SystemTime,
+        _to: SystemTime,
+    ) -> Result<Vec<FileVersion>, Box<dyn Error>> {
+        match self.0 {}
+    }
+
+    pub fn check(&self, _repair: bool) -> Result<Vec<Problem>, Box<dyn Error>> {
+        match self.0 {}
+    }
+}
+
+/// Everywhere a captured version is persisted besides memory.
+#[derive(Default)]
+pub struct Persistence {
+    pub journal: Option<SessionJournal>,
+    pub store: Option<SqliteStore>,
+    pub git: Option<GitExport>,
+    pub patches: Option<PatchDir>,
+    pub shadow: Option<ShadowRef>,
+    pub cache: Option<WarmCache>,
+    pub sinks: Vec<Sink>,
+}
+
+impl Persistence {
+    pub fn open(
+        file: &Path,
+        session: Option<&Path>,
+        store: Option<&StoreSpec>,
+        git: Option<&Path>,
+        patches: Option<&Path>,
+        autocommit: bool,
+        cache: Option<&Path>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let journal = session.map(SessionJournal::open).transpose()?;
+        let store = match store {
+            Some(StoreSpec::Sqlite(path)) => Some(SqliteStore::open(path)?),
+            None => None,
+        };
+        let git = git.map(|dir| GitExport::open(dir, file)).transpose()?;
+        let patches = patches.map(|dir| PatchDir::open(dir, file)).transpose()?;
+        let shadow = autocommit.then(|| ShadowRef::open(file)).transpose()?;
+        let cache = cache.map(WarmCache::open).transpose()?;
+        Ok(Self {
+            journal,
+            store,
+            git,
+            patches,
+            shadow,
+            cache,
+            sinks: Vec::new(),
+        })
+    }
+
+    /// Whether every version is kept somewhere that outlives the process.
+    pub fn keeps_history(&self) -> bool {
+        self.journal.is_some()
+            || self.store.is_some()
+            || self.git.is_some()
+            || self.patches.is_some()
+            || self.shadow.is_some()
+    }
+
+    pub fn record(&mut self, path: &Path, version: &Version) -> Result<(), Box<dyn Error>> {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(version)?;
+        }
+        if let Some(store) = &self.store {
+            store.insert(path, version)?;
+        }
+        if let Some(git) = &self.git {
+            git.commit(version)?;
+        }
+        if let Some(patches) = self.patches.as_mut() {
+            patches.write(version)?;
+        }
+        if let Some(shadow) = &self.shadow {
+            shadow.commit(version)?;
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.update(path, version)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `version` to the sinks, as the change from the last version
+    /// sent. Unlike [`Self::record`], it's only called for versions that are
+    /// shown, so changes held back by `--confirm-window` go out as one.
+    pub fn send(&mut self, version: &Version) {
+        for sink in &mut self.sinks {
+            sink.send(version);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+    use crate::digest::HashAlgorithm;
+
+    const WRITERS: usize = 32;
+    const VERSIONS: usize = 40;
+
+    /// Appends a line per version to one file, like the `cat_file`
+    /// simulator, recording each version in `store`.
+    fn simulate(store: &SqliteStore, writer: usize) {
+        let path = PathBuf::from(format!("/watched/{writer}.log"));
+        let mut contents = String::new();
+        for number in 0..VERSIONS {
+            contents.push_str(&format!("line {number} from writer {writer}\n"));
+            let mut version = FileVersion::new_at_now(contents.clone());
+            version.number = number;
+            store
+                .insert(&path, &version.as_version(HashAlgorithm::Blake3))
+                .unwrap();
+        }
+    }
+
+    /// Checks that every writer's versions all made it in, in order and
+    /// intact.
+    fn assert_complete(store: &SqliteStore) {
+        assert_eq!(store.paths().unwrap().len(), WRITERS);
+        for writer in 0..WRITERS {
+            let path = PathBuf::from(format!("/watched/{writer}.log"));
+            let versions = store.between(&path, UNIX_EPOCH, SystemTime::now()).unwrap();
+            assert_eq!(versions.len(), VERSIONS);
+            for (number, version) in versions.iter().enumerate() {
+                assert_eq!(version.number, number);
+                assert_eq!(version.contents.lines().count(), number + 1);
+            }
+        }
+        assert!(store.check(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn shared_store_keeps_every_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(SqliteStore::open(&dir.path().join("versions.db")).unwrap());
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|writer| {
+                let store = Arc::clone(&store);
+                thread::spawn(move || simulate(&store, writer))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_complete(&store);
+    }
+
+    #[test]
+    fn separate_connections_keep_every_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("versions.db");
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|writer| {
+                let db = db.clone();
+                thread::spawn(move || simulate(&SqliteStore::open(&db).unwrap(), writer))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_complete(&SqliteStore::open(&db).unwrap());
+    }
+
+    #[test]
+    fn store_survives_a_panicking_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(SqliteStore::open(&dir.path().join("versions.db")).unwrap());
+        let panicking = Arc::clone(&store);
+        let result = thread::spawn(move || {
+            let _conn = panicking.conn();
+            panic!("watch crashed while writing");
+        })
+        .join();
+        assert!(result.is_err());
+
+        simulate(&store, 0);
+        let latest = store.latest(Path::new("/watched/0.log")).unwrap().unwrap();
+        assert_eq!(latest.number, VERSIONS - 1);
+    }
+
+    #[test]
+    fn later_runs_come_after_earlier_ones_whatever_their_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(&dir.path().join("versions.db")).unwrap();
+        let file = dir.path().join("app.conf");
+        std::fs::write(&file, "").unwrap();
+        // The same file, spelled another way.
+        let other = dir.path().join(".").join("app.conf");
+        for (path, number, contents) in [(&file, 0, "a"), (&file, 1, "b"), (&other, 0, "c")] {
+            let mut version = FileVersion::new_at_now(contents);
+            version.number = number;
+            store
+                .insert(path, &version.as_version(HashAlgorithm::Blake3))
+                .unwrap();
+        }
+
+        assert_eq!(store.paths().unwrap().len(), 1);
+        assert_eq!(&*store.latest(&file).unwrap().unwrap().contents, "c");
+        let versions = store
+            .between(&other, UNIX_EPOCH, SystemTime::now())
+            .unwrap();
+        let contents: Vec<&str> = versions.iter().map(|v| &*v.contents).collect();
+        assert_eq!(contents, ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,317 @@
+use std::{fmt, path::Path};
+
+#[cfg(feature = "yaml")]
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Formats whose documents can be diffed by key path rather than by line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Syntax {
+    /// The syntax `path`'s extension implies, if it is a structured format.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// The first syntax `text` parses as, for files a `--rule` says are
+    /// structured whatever their extension. YAML comes last since most text
+    /// is valid YAML.
+    pub fn guess(text: &str) -> Option<Self> {
+        [Self::Json, Self::Toml, Self::Yaml]
+            .into_iter()
+            .find(|syntax| syntax.parse(text).is_some())
+    }
+
+    /// Parses `text` into a tree, or `None` if it isn't valid, e.g. because
+    /// it was caught halfway through being written.
+    pub fn parse(&self, text: &str) -> Option<Value> {
+        match self {
+            Self::Json => serde_json::from_str(text).ok(),
+            Self::Yaml => parse_yaml(text),
+            Self::Toml => parse_toml(text),
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+/// Parses every document in a YAML stream, resolving anchors, aliases and
+/// merge keys so only the data they expand to is compared. A stream of
+/// several documents becomes an array of them.
+fn parse_yaml(text: &str) -> Option<Value> {
+    let mut documents = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(text) {
+        let mut value = serde_yaml::Value::deserialize(document).ok()?;
+        value.apply_merge().ok()?;
+        documents.push(serde_json::to_value(value).ok()?);
+    }
+    match documents.len() {
+        1 => documents.pop(),
+        _ => Some(Value::Array(documents)),
+    }
+}
+
+/// Without the `yaml` feature YAML never parses, so it is diffed by line.
+#[cfg(not(feature = "yaml"))]
+fn parse_yaml(_text: &str) -> Option<Value> {
+    None
+}
+
+/// One difference between two documents, addressed by its path such as
+/// `spec.replicas` or `items[2].name`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {path}: {value}"),
+            Change::Removed { path, value } => write!(f, "- {path}: {value}"),
+            Change::Changed { path, old, new } => write!(f, "~ {path}: {old} → {new}"),
+        }
+    }
+}
+
+/// Compares two documents key by key. Object key order never matters;
+/// arrays are compared position by position.
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(String::new(), old, new, &mut changes);
+    changes
+}
+
+fn diff_at(path: String, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let child = join_key(&path, key);
+                match new.get(key) {
+                    Some(new_value) => diff_at(child, old_value, new_value, changes),
+                    None => changes.push(Change::Removed {
+                        path: child,
+                        value: old_value.clone(),
+                    }),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    changes.push(Change::Added {
+                        path: join_key(&path, key),
+                        value: new_value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for i in 0..old.len().max(new.len()) {
+                let child = format!("{path}[{i}]");
+                match (old.get(i), new.get(i)) {
+                    (Some(old), Some(new)) => diff_at(child, old, new, changes),
+                    (Some(old), None) => changes.push(Change::Removed {
+                        path: child,
+                        value: old.clone(),
+                    }),
+                    (None, Some(new)) => changes.push(Change::Added {
+                        path: child,
+                        value: new.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        _ if old != new => changes.push(Change::Changed {
+            path: if path.is_empty() {
+                ".".to_string()
+            } else {
+                path
+            },
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Appends `key` to `path`, quoting it if it wouldn't read as a plain
+/// identifier.
+fn join_key(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    match (path.is_empty(), plain) {
+        (true, true) => key.to_string(),
+        (false, true) => format!("{path}.{key}"),
+        (_, false) => format!("{path}[{}]", Value::String(key.to_string())),
+    }
+}
+
+/// Parses a TOML document, keeping datetimes as their string form.
+#[cfg(feature = "toml")]
+fn parse_toml(text: &str) -> Option<Value> {
+    fn convert(value: toml::Value) -> Value {
+        match value {
+            toml::Value::String(s) => Value::String(s),
+            toml::Value::Integer(i) => Value::from(i),
+            toml::Value::Float(f) => Value::from(f),
+            toml::Value::Boolean(b) => Value::Bool(b),
+            toml::Value::Datetime(d) => Value::String(d.to_string()),
+            toml::Value::Array(items) => Value::Array(items.into_iter().map(convert).collect()),
+            toml::Value::Table(table) => Value::Object(
+                table
+                    .into_iter()
+                    .map(|(key, value)| (key, convert(value)))
+                    .collect(),
+            ),
+        }
+    }
+    let table: toml::Table = text.parse().ok()?;
+    Some(convert(toml::Value::Table(table)))
+}
+
+/// Without the `toml` feature TOML never parses, so it is diffed by line.
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_text: &str) -> Option<Value> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn changes(syntax: Syntax, old: &str, new: &str) -> Vec<String> {
+        let (old, new) = (syntax.parse(old).unwrap(), syntax.parse(new).unwrap());
+        diff(&old, &new).iter().map(Change::to_string).collect()
+    }
+
+    #[test]
+    fn json_changes_are_addressed_by_key_path() {
+        let old = r#"{"spec": {"replicas": 2, "ports": [80, 443]}, "name": "web", "old": true}"#;
+        let new = r#"{"name": "web", "spec": {"replicas": 3, "ports": [80, 8443, 9000]},
+                      "labels": {"tier": "front"}, "my key": null}"#;
+        assert_eq!(
+            changes(Syntax::Json, old, new),
+            [
+                "- old: true",
+                "~ spec.ports[1]: 443 → 8443",
+                "+ spec.ports[2]: 9000",
+                "~ spec.replicas: 2 → 3",
+                "+ labels: {\"tier\":\"front\"}",
+                "+ [\"my key\"]: null",
+            ]
+        );
+        assert_eq!(
+            diff(&json!([{"a": 1}]), &json!([])),
+            [Change::Removed {
+                path: "[0]".into(),
+                value: json!({"a": 1}),
+            }]
+        );
+        assert_eq!(changes(Syntax::Json, "1", "2"), ["~ .: 1 → 2"]);
+        assert!(Syntax::Json.parse(r#"{"half": "#).is_none());
+        assert_eq!(Syntax::detect(Path::new("a/b.JSON")), Some(Syntax::Json));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_changes_are_addressed_by_key_path() {
+        let old = "\
+defaults: &defaults
+  image: web:1
+  env: [A, B]
+service:
+  <<: *defaults
+  replicas: 2
+";
+        let new = "\
+defaults: &defaults
+  image: web:2
+  env: [A]
+service:
+  <<: *defaults
+  replicas: 2
+";
+        // Merged keys are compared where they end up.
+        assert_eq!(
+            changes(Syntax::Yaml, old, new),
+            [
+                "- defaults.env[1]: \"B\"",
+                "~ defaults.image: \"web:1\" → \"web:2\"",
+                "- service.env[1]: \"B\"",
+                "~ service.image: \"web:1\" → \"web:2\"",
+            ]
+        );
+        assert_eq!(
+            changes(
+                Syntax::Yaml,
+                "a: 1\n---\nb: [x]\n",
+                "a: 1\n---\nb: [x, y]\n"
+            ),
+            ["+ [1].b[1]: \"y\""]
+        );
+        assert_eq!(Syntax::detect(Path::new("ci.yml")), Some(Syntax::Yaml));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_changes_are_addressed_by_key_path() {
+        let old = "\
+[package]
+name = \"app\"
+released = 2024-05-01
+
+[[bin]]
+name = \"app\"
+";
+        let new = "\
+[package]
+name = \"app\"
+released = 2024-06-01
+edition = \"2021\"
+
+[[bin]]
+name = \"app\"
+
+[[bin]]
+name = \"tool\"
+";
+        assert_eq!(
+            changes(Syntax::Toml, old, new),
+            [
+                "+ bin[1]: {\"name\":\"tool\"}",
+                "~ package.released: \"2024-05-01\" → \"2024-06-01\"",
+                "+ package.edition: \"2021\"",
+            ]
+        );
+        assert_eq!(
+            Syntax::guess("[package]\nname = \"app\"\n"),
+            Some(Syntax::Toml)
+        );
+    }
+}
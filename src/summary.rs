@@ -0,0 +1,268 @@
+use similar::{ChangeTag, TextDiff};
+
+use crate::{structured::Change, table::TableDiff};
+
+/// How many individual changes a summary names before counting the rest.
+const MAX_ITEMS: usize = 3;
+
+/// Summarises key-path changes, e.g. "changed `timeout` from 30 to 60 in
+/// section [server]; added `retries`".
+pub fn structured(changes: &[Change]) -> String {
+    let items = changes.iter().map(|change| match change {
+        Change::Added { path, .. } => {
+            let (key, section) = split_path(path);
+            format!("added {key}{section}")
+        }
+        Change::Removed { path, .. } => {
+            let (key, section) = split_path(path);
+            format!("removed {key}{section}")
+        }
+        Change::Changed { path, old, new } => {
+            let (key, section) = split_path(path);
+            format!("changed {key} from {old} to {new}{section}")
+        }
+    });
+    join(items.collect())
+}
+
+/// Summarises a table diff, e.g. "added 2 rows; changed 3 cells in `price`".
+pub fn table(diff: &TableDiff) -> String {
+    let mut items = Vec::new();
+    for column in &diff.added_columns {
+        items.push(format!("added column `{column}`"));
+    }
+    for column in &diff.removed_columns {
+        items.push(format!("removed column `{column}`"));
+    }
+    if !diff.added_rows.is_empty() {
+        items.push(format!("added {}", plural(diff.added_rows.len(), "row")));
+    }
+    if !diff.removed_rows.is_empty() {
+        items.push(format!(
+            "removed {}",
+            plural(diff.removed_rows.len(), "row")
+        ));
+    }
+    let mut columns: Vec<(&str, usize)> = Vec::new();
+    for change in &diff.changed_cells {
+        match columns
+            .iter_mut()
+            .find(|(column, _)| *column == change.column)
+        {
+            Some((_, count)) => *count += 1,
+            None => columns.push((&change.column, 1)),
+        }
+    }
+    for (column, count) in columns {
+        items.push(format!("changed {} in `{column}`", plural(count, "cell")));
+    }
+    join(items)
+}
+
+/// Summarises a line diff of free-form text. Lines that look like
+/// `key = value` settings are reported as changed values within their
+/// `[section]`; comments and blank lines are counted apart from the rest.
+pub fn lines(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut deleted = Vec::new();
+    let mut inserted = Vec::new();
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Delete => deleted.push(change.value().trim_end()),
+            ChangeTag::Insert => {
+                inserted.push((change.new_index().unwrap(), change.value().trim_end()))
+            }
+            ChangeTag::Equal => {}
+        }
+    }
+
+    // Lines that only lost or gained trailing whitespace aren't counted.
+    deleted.retain(
+        |line| match inserted.iter().position(|(_, new)| new == line) {
+            Some(position) => {
+                inserted.remove(position);
+                false
+            }
+            None => true,
+        },
+    );
+
+    let mut items = Vec::new();
+    deleted.retain(|line| {
+        let Some((key, old_value)) = setting(line) else {
+            return true;
+        };
+        let Some(position) = inserted
+            .iter()
+            .position(|(_, line)| setting(line).is_some_and(|(k, _)| k == key))
+        else {
+            return true;
+        };
+        let (index, line) = inserted.remove(position);
+        let (_, new_value) = setting(line).unwrap();
+        let mut item = format!("changed `{key}` from {old_value} to {new_value}");
+        if let Some(section) = section_at(&new_lines, index) {
+            item.push_str(&format!(" in section {section}"));
+        }
+        items.push(item);
+        false
+    });
+
+    let inserted: Vec<&str> = inserted.into_iter().map(|(_, line)| line).collect();
+    items.extend(count_lines("added", &inserted));
+    items.extend(count_lines("removed", &deleted));
+    if items.is_empty() && old != new {
+        items.push("changed whitespace".to_string());
+    }
+    join(items)
+}
+
+/// Counts lines by kind, e.g. "added 3 lines" and "added 1 commented line".
+fn count_lines(verb: &str, lines: &[&str]) -> Vec<String> {
+    let comments = lines.iter().filter(|line| is_comment(line)).count();
+    let blank = lines.iter().filter(|line| line.trim().is_empty()).count();
+    let other = lines.len() - comments - blank;
+    let mut items = Vec::new();
+    if other > 0 {
+        items.push(format!("{verb} {}", plural(other, "line")));
+    }
+    if comments > 0 {
+        items.push(format!("{verb} {}", plural(comments, "commented line")));
+    }
+    if blank > 0 {
+        items.push(format!("{verb} {}", plural(blank, "blank line")));
+    }
+    items
+}
+
+fn is_comment(line: &str) -> bool {
+    let line = line.trim_start();
+    ["#", "//", ";", "--", "/*", "*"]
+        .iter()
+        .any(|marker| line.starts_with(marker))
+}
+
+/// Splits `key = value` or `key: value` into its parts.
+fn setting(line: &str) -> Option<(&str, &str)> {
+    if is_comment(line) {
+        return None;
+    }
+    let (key, value) = line.split_once('=').or_else(|| line.split_once(':'))?;
+    let key = key.trim();
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    valid.then(|| (key, value.trim()))
+}
+
+/// The `[section]` header closest above line `index`.
+fn section_at<'a>(lines: &[&'a str], index: usize) -> Option<&'a str> {
+    lines[..index.min(lines.len())]
+        .iter()
+        .rev()
+        .map(|line| line.trim())
+        .find(|line| line.starts_with('[') && line.ends_with(']'))
+}
+
+/// Splits a key path into its quoted last component and an
+/// ` in section [parent]` suffix, empty at the top level.
+fn split_path(path: &str) -> (String, String) {
+    match path.rsplit_once('.') {
+        Some((section, key)) if !section.is_empty() => {
+            (format!("`{key}`"), format!(" in section [{section}]"))
+        }
+        _ => (format!("`{path}`"), String::new()),
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+fn join(mut items: Vec<String>) -> String {
+    if items.is_empty() {
+        return "no changes".to_string();
+    }
+    if items.len() > MAX_ITEMS + 1 {
+        let rest = items.len() - MAX_ITEMS;
+        items.truncate(MAX_ITEMS);
+        items.push(format!("and {rest} more changes"));
+    }
+    items.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::table::CellChange;
+
+    #[test]
+    fn settings_comments_and_lines_are_counted_apart() {
+        let old = "[server]\ntimeout = 30\nname = web\n";
+        let new =
+            "[server]\n# raised for slow clients\ntimeout = 60\nname = web\n\nretries = 3\nlog\n";
+        assert_eq!(
+            lines(old, new),
+            "changed `timeout` from 30 to 60 in section [server]; added 2 lines; \
+             added 1 commented line; added 1 blank line"
+        );
+        assert_eq!(lines("a\nb\n", "a\n"), "removed 1 line");
+        assert_eq!(lines("a \n", "a\n"), "changed whitespace");
+        assert_eq!(lines("a\n", "a\n"), "no changes");
+    }
+
+    #[test]
+    fn key_path_changes_name_their_section() {
+        let changes = [
+            Change::Changed {
+                path: "server.timeout".into(),
+                old: json!(30),
+                new: json!(60),
+            },
+            Change::Added {
+                path: "retries".into(),
+                value: json!(3),
+            },
+        ];
+        assert_eq!(
+            structured(&changes),
+            "changed `timeout` from 30 to 60 in section [server]; added `retries`"
+        );
+    }
+
+    #[test]
+    fn table_changes_are_counted_per_column() {
+        let cell = |row: &str, column: &str| CellChange {
+            row: row.into(),
+            column: column.into(),
+            old: "1".into(),
+            new: "2".into(),
+        };
+        let diff = TableDiff {
+            added_rows: vec![("4".into(), Vec::new()), ("5".into(), Vec::new())],
+            removed_rows: vec![("2".into(), Vec::new())],
+            changed_cells: vec![cell("1", "price"), cell("3", "price"), cell("1", "qty")],
+            ..TableDiff::default()
+        };
+        assert_eq!(
+            table(&diff),
+            "added 2 rows; removed 1 row; changed 2 cells in `price`; changed 1 cell in `qty`"
+        );
+        let diff = TableDiff {
+            added_columns: vec!["a".into(), "b".into(), "c".into(), "d".into(), "e".into()],
+            ..TableDiff::default()
+        };
+        assert_eq!(
+            table(&diff),
+            "added column `a`; added column `b`; added column `c`; and 2 more changes"
+        );
+    }
+}
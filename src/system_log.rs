@@ -0,0 +1,188 @@
+use std::{io, path::Path};
+
+use similar::TextDiff;
+
+use crate::{sink::Change, stats::LineStats};
+
+/// The most of a diff put in one record, so it fits in a datagram.
+const DIFF_LIMIT: usize = 32 * 1024;
+
+/// The system log `--log-to` records changes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogTarget {
+    /// The local syslog daemon, through /dev/log.
+    Syslog,
+    /// The systemd journal, with the stats as their own fields.
+    Journald,
+}
+
+impl LogTarget {
+    #[cfg(unix)]
+    fn socket(self) -> &'static str {
+        match self {
+            Self::Syslog => "/dev/log",
+            Self::Journald => "/run/systemd/journal/socket",
+        }
+    }
+}
+
+/// Records every change in syslog or the journal, as `user.notice` from
+/// `slip-diff`, with the file, version and line counts as `key=value`
+/// pairs or journal fields, and the diff if asked for.
+///
+/// Like the other sinks it never fails the watch: records the log can't
+/// take are dropped.
+pub struct SystemLog {
+    target: LogTarget,
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    diff: bool,
+}
+
+impl SystemLog {
+    #[cfg(unix)]
+    pub fn connect(target: LogTarget, diff: bool) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(target.socket()).map_err(|e| {
+            io::Error::new(e.kind(), format!("connecting to {}: {e}", target.socket()))
+        })?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            target,
+            socket,
+            diff,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_target: LogTarget, _diff: bool) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--log-to is not supported on this platform",
+        ))
+    }
+
+    pub fn record(&self, file: &Path, change: &Change) {
+        let stats = LineStats::between(&change.before, &change.after);
+        let diff = self.diff.then(|| {
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut diff = TextDiff::from_lines(&*change.before, &*change.after)
+                .unified_diff()
+                .header(&format!("a/{name}"), &format!("b/{name}"))
+                .to_string();
+            if diff.len() > DIFF_LIMIT {
+                let end = (0..=DIFF_LIMIT)
+                    .rev()
+                    .find(|&i| diff.is_char_boundary(i))
+                    .unwrap_or(0);
+                diff.truncate(end);
+                diff.push_str("\n… truncated\n");
+            }
+            diff
+        });
+        let record = match self.target {
+            LogTarget::Syslog => syslog_record(file, change.number, stats, diff.as_deref()),
+            LogTarget::Journald => journal_record(file, change.number, stats, diff.as_deref()),
+        };
+        self.send(&record);
+    }
+
+    #[cfg(unix)]
+    fn send(&self, record: &[u8]) {
+        let _ = self.socket.send(record);
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _record: &[u8]) {}
+}
+
+/// `user.notice`.
+const PRIORITY: u8 = 8 + 5;
+
+/// A record as the C library's `syslog` sends it, leaving the time to the
+/// daemon.
+fn syslog_record(file: &Path, number: usize, stats: LineStats, diff: Option<&str>) -> Vec<u8> {
+    let path = file.display().to_string();
+    let mut record = format!(
+        "<{PRIORITY}>slip-diff[{}]: change path=\"{}\" version={number} inserted={} deleted={}",
+        std::process::id(),
+        path.replace('\\', "\\\\").replace('"', "\\\""),
+        stats.inserted,
+        stats.deleted,
+    );
+    if let Some(diff) = diff {
+        record.push('\n');
+        record.push_str(diff);
+    }
+    record.into_bytes()
+}
+
+/// A record in the journal's native protocol: a field per line, or a
+/// length-prefixed value for fields with several lines.
+fn journal_record(file: &Path, number: usize, stats: LineStats, diff: Option<&str>) -> Vec<u8> {
+    let path = file.display().to_string();
+    let message = format!(
+        "{path} changed to version {number}: +{} -{} lines",
+        stats.inserted, stats.deleted
+    );
+    let mut fields = vec![
+        ("MESSAGE", message),
+        ("PRIORITY", "5".to_string()),
+        ("SYSLOG_IDENTIFIER", "slip-diff".to_string()),
+        ("SLIP_DIFF_PATH", path),
+        ("SLIP_DIFF_VERSION", number.to_string()),
+        ("SLIP_DIFF_INSERTED", stats.inserted.to_string()),
+        ("SLIP_DIFF_DELETED", stats.deleted.to_string()),
+    ];
+    if let Some(diff) = diff {
+        fields.push(("SLIP_DIFF_DIFF", diff.to_string()));
+    }
+    let mut record = Vec::new();
+    for (name, value) in fields {
+        record.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            record.push(b'\n');
+            record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            record.push(b'=');
+        }
+        record.extend_from_slice(value.as_bytes());
+        record.push(b'\n');
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(inserted: usize, deleted: usize) -> LineStats {
+        LineStats { inserted, deleted }
+    }
+
+    #[test]
+    fn syslog_has_the_stats_as_pairs() {
+        let record = syslog_record(Path::new("/etc/my \"app\".conf"), 3, stats(2, 1), None);
+        let record = String::from_utf8(record).unwrap();
+        assert!(record.starts_with("<13>slip-diff["));
+        assert!(record.ends_with(
+            "]: change path=\"/etc/my \\\"app\\\".conf\" version=3 inserted=2 deleted=1"
+        ));
+    }
+
+    #[test]
+    fn journal_fields_with_lines_are_length_prefixed() {
+        let record = journal_record(Path::new("/etc/app.conf"), 3, stats(1, 0), Some("+a\n"));
+        let expected = [
+            &b"MESSAGE=/etc/app.conf changed to version 3: +1 -0 lines\n"[..],
+            b"PRIORITY=5\nSYSLOG_IDENTIFIER=slip-diff\nSLIP_DIFF_PATH=/etc/app.conf\n",
+            b"SLIP_DIFF_VERSION=3\nSLIP_DIFF_INSERTED=1\nSLIP_DIFF_DELETED=0\n",
+            b"SLIP_DIFF_DIFF\n\x03\0\0\0\0\0\0\0+a\n\n",
+        ]
+        .concat();
+        assert_eq!(record, expected);
+    }
+}
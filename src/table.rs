@@ -0,0 +1,293 @@
+use std::{collections::HashMap, path::Path};
+
+/// Delimited tabular text whose first row names the columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Csv,
+    Tsv,
+}
+
+impl Dialect {
+    /// The dialect `path`'s extension implies, if it is tabular.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Self::Csv),
+            "tsv" | "tab" => Some(Self::Tsv),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    fn delimiter(&self) -> u8 {
+        match self {
+            Self::Csv => b',',
+            Self::Tsv => b'\t',
+        }
+    }
+
+    /// Parses `text` into a table, or `None` if it has no header row or
+    /// isn't valid.
+    pub fn parse(&self, text: &str) -> Option<Table> {
+        parse(*self, text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[cfg(feature = "csv")]
+fn parse(dialect: Dialect, text: &str) -> Option<Table> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(dialect.delimiter())
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader.headers().ok()?.iter().map(str::to_string).collect();
+    let rows = reader
+        .records()
+        .map(|record| record.map(|r| r.iter().map(str::to_string).collect()))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    Some(Table { headers, rows })
+}
+
+/// Without the `csv` feature tables never parse, so they are diffed by line.
+#[cfg(not(feature = "csv"))]
+fn parse(_dialect: Dialect, _text: &str) -> Option<Table> {
+    None
+}
+
+/// What changed between two versions of a table.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TableDiff {
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    /// Rows only in the new table, labelled by key.
+    pub added_rows: Vec<(String, Vec<String>)>,
+    /// Rows only in the old table, labelled by key.
+    pub removed_rows: Vec<(String, Vec<String>)>,
+    pub changed_cells: Vec<CellChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellChange {
+    /// The row's key column value, or its 1-based position without one.
+    pub row: String,
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+            && self.added_rows.is_empty()
+            && self.removed_rows.is_empty()
+            && self.changed_cells.is_empty()
+    }
+}
+
+/// Compares two tables cell by cell over the columns they share.
+///
+/// Rows are matched by the value in `key_column` when it exists in both
+/// tables (a header name, or a 1-based column number), otherwise by
+/// position.
+pub fn diff(old: &Table, new: &Table, key_column: Option<&str>) -> TableDiff {
+    let mut result = TableDiff {
+        added_columns: new
+            .headers
+            .iter()
+            .filter(|h| !old.headers.contains(h))
+            .cloned()
+            .collect(),
+        removed_columns: old
+            .headers
+            .iter()
+            .filter(|h| !new.headers.contains(h))
+            .cloned()
+            .collect(),
+        ..TableDiff::default()
+    };
+    let shared: Vec<(&String, usize, usize)> = old
+        .headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| Some((h, i, new.headers.iter().position(|n| n == h)?)))
+        .collect();
+
+    let key = key_column.and_then(|key| Some((column(old, key)?, column(new, key)?)));
+    let old_keys = row_keys(old, key.map(|(old, _)| old));
+    let new_keys = row_keys(new, key.map(|(_, new)| new));
+    let new_rows: HashMap<&str, &Vec<String>> =
+        new_keys.iter().map(String::as_str).zip(&new.rows).collect();
+    let old_rows: HashMap<&str, &Vec<String>> =
+        old_keys.iter().map(String::as_str).zip(&old.rows).collect();
+
+    for (row_key, old_row) in old_keys.iter().zip(&old.rows) {
+        let Some(new_row) = new_rows.get(row_key.as_str()) else {
+            result.removed_rows.push((row_key.clone(), old_row.clone()));
+            continue;
+        };
+        for (header, old_index, new_index) in &shared {
+            let old_cell = cell(old_row, *old_index);
+            let new_cell = cell(new_row, *new_index);
+            if old_cell != new_cell {
+                result.changed_cells.push(CellChange {
+                    row: row_key.clone(),
+                    column: header.to_string(),
+                    old: old_cell.to_string(),
+                    new: new_cell.to_string(),
+                });
+            }
+        }
+    }
+    for (row_key, new_row) in new_keys.iter().zip(&new.rows) {
+        if !old_rows.contains_key(row_key.as_str()) {
+            result.added_rows.push((row_key.clone(), new_row.clone()));
+        }
+    }
+    result
+}
+
+/// Finds a column by header name, or by 1-based number.
+fn column(table: &Table, key: &str) -> Option<usize> {
+    table.headers.iter().position(|h| h == key).or_else(|| {
+        key.parse::<usize>()
+            .ok()
+            .filter(|n| (1..=table.headers.len()).contains(n))
+            .map(|n| n - 1)
+    })
+}
+
+fn row_keys(table: &Table, key: Option<usize>) -> Vec<String> {
+    table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| match key {
+            Some(key) => cell(row, key).to_string(),
+            None => (i + 1).to_string(),
+        })
+        .collect()
+}
+
+fn cell(row: &[String], index: usize) -> &str {
+    row.get(index).map_or("", String::as_str)
+}
+
+/// Lays out rows of cells as left-aligned columns separated by two spaces.
+pub fn align(rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| {
+            rows.iter()
+                .map(|row| row.get(c).map_or(0, |cell| cell.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (c, cell) in row.iter().enumerate() {
+                if c + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(&format!("{cell:<width$}  ", width = widths[c]));
+                }
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rows: &[&str]) -> Table {
+        let mut rows = rows
+            .iter()
+            .map(|row| row.split(',').map(str::to_string).collect());
+        Table {
+            headers: rows.next().unwrap(),
+            rows: rows.collect(),
+        }
+    }
+
+    fn strings(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn rows_are_matched_by_key_column() {
+        let old = table(&["id,name,qty", "1,apple,3", "2,pear,5", "3,plum,1"]);
+        let new = table(&[
+            "id,name,qty,price",
+            "3,plum,2,9",
+            "1,apple,3,4",
+            "4,fig,7,2",
+        ]);
+        let changes = diff(&old, &new, Some("id"));
+        assert_eq!(changes.added_columns, ["price"]);
+        assert!(changes.removed_columns.is_empty());
+        assert_eq!(
+            changes.removed_rows,
+            [("2".to_string(), strings(&["2", "pear", "5"]))]
+        );
+        assert_eq!(
+            changes.added_rows,
+            [("4".to_string(), strings(&["4", "fig", "7", "2"]))]
+        );
+        assert_eq!(
+            changes.changed_cells,
+            [CellChange {
+                row: "3".into(),
+                column: "qty".into(),
+                old: "1".into(),
+                new: "2".into(),
+            }]
+        );
+        // A column number works as a key too.
+        assert_eq!(diff(&old, &new, Some("1")), changes);
+    }
+
+    #[test]
+    fn rows_are_matched_by_position_without_a_key() {
+        let old = table(&["name,qty", "apple,3", "pear,5"]);
+        let new = table(&["name", "apple", "plum", "fig"]);
+        let changes = diff(&old, &new, Some("id"));
+        assert_eq!(changes.removed_columns, ["qty"]);
+        assert_eq!(changes.added_rows, [("3".to_string(), strings(&["fig"]))]);
+        assert_eq!(changes.changed_cells.len(), 1);
+        assert_eq!(changes.changed_cells[0].row, "2");
+        assert!(diff(&old, &old, None).is_empty());
+    }
+
+    #[test]
+    fn columns_are_aligned_to_their_widest_cell() {
+        let rows = [
+            strings(&["id", "name", "qty"]),
+            strings(&["10", "äpfel", "3"]),
+            strings(&["2", "fig", ""]),
+        ];
+        assert_eq!(align(&rows), ["id  name   qty", "10  äpfel  3", "2   fig"]);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn tables_parse_with_quoted_fields() {
+        let parsed = Dialect::Csv.parse("id,name\n1,\"Smith, J\"\n2\n").unwrap();
+        assert_eq!(parsed.headers, ["id", "name"]);
+        assert_eq!(parsed.rows, [strings(&["1", "Smith, J"]), strings(&["2"])]);
+        assert_eq!(
+            Dialect::Tsv.parse("a\tb\n1\t2\n").unwrap().rows,
+            [strings(&["1", "2"])]
+        );
+        assert_eq!(Dialect::detect(Path::new("data.TAB")), Some(Dialect::Tsv));
+    }
+}
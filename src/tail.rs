@@ -0,0 +1,309 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Reads only the bytes appended to a file since the last read.
+///
+/// Keeps the trailing partial line around so an append that completes it can
+/// be shown as a change to that line rather than a brand new one.
+#[derive(Debug, Default)]
+pub struct TailReader {
+    offset: u64,
+    partial: String,
+    /// Whether to replace invalid UTF-8 with U+FFFD rather than fail, as
+    /// `--lossy` asks.
+    lossy: bool,
+}
+
+/// The result of reading an append-only file.
+pub struct Appended {
+    /// Unterminated last line as it was before the append.
+    pub before: String,
+    /// The same line followed by everything appended since the last read.
+    pub after: String,
+    /// Whether invalid UTF-8 in what was appended was replaced with U+FFFD.
+    pub replaced_invalid: bool,
+}
+
+impl TailReader {
+    /// Starts tailing at the current end of `path`, replacing invalid UTF-8
+    /// if `lossy` is set.
+    pub fn at_end(path: &Path, lossy: bool) -> io::Result<Self> {
+        let mut reader = Self {
+            lossy,
+            ..Self::default()
+        };
+        let (contents, _) = reader.read_from_offset(path)?;
+        reader.remember(&contents);
+        Ok(reader)
+    }
+
+    /// Reads whatever was appended since the last call, or `None` if the file
+    /// did not grow by a whole character. A file that shrank is treated as truncated and re-read
+    /// from the start.
+    pub fn read_appended(&mut self, path: &Path) -> io::Result<Option<Appended>> {
+        let len = path.metadata()?.len();
+        if len < self.offset {
+            *self = Self {
+                lossy: self.lossy,
+                ..Self::default()
+            };
+        }
+        if len == self.offset {
+            return Ok(None);
+        }
+
+        let (appended, replaced_invalid) = self.read_from_offset(path)?;
+        if appended.is_empty() {
+            return Ok(None);
+        }
+        let before = std::mem::take(&mut self.partial);
+        let after = format!("{before}{appended}");
+        self.remember(&after);
+        Ok(Some(Appended {
+            before,
+            after,
+            replaced_invalid,
+        }))
+    }
+
+    /// What was appended since the last read, and whether invalid UTF-8 in
+    /// it was replaced.
+    fn read_from_offset(&mut self, path: &Path) -> io::Result<(String, bool)> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        // A character cut off by the end of the file is read once the rest
+        // of it is appended.
+        let complete = &bytes[..complete_len(&bytes)];
+        let text = match std::str::from_utf8(complete) {
+            Ok(text) => (text.to_string(), false),
+            Err(_) if self.lossy => (String::from_utf8_lossy(complete).into_owned(), true),
+            Err(error) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} had invalid UTF-8 appended: {error} (use --lossy to replace it)",
+                        path.display()
+                    ),
+                ))
+            }
+        };
+        self.offset += complete.len() as u64;
+        Ok(text)
+    }
+
+    fn remember(&mut self, text: &str) {
+        self.partial = match text.rfind('\n') {
+            Some(i) => text[i + 1..].to_string(),
+            None => text.to_string(),
+        };
+    }
+}
+
+/// How much of `bytes` is left once a character cut off at the end is,
+/// which is all of it unless the last one to four bytes start one.
+fn complete_len(bytes: &[u8]) -> usize {
+    (bytes.len().saturating_sub(3)..bytes.len())
+        .find(|&start| {
+            matches!(
+                std::str::from_utf8(&bytes[start..]),
+                Err(error) if error.valid_up_to() == 0 && error.error_len().is_none()
+            )
+        })
+        .unwrap_or(bytes.len())
+}
+
+/// How many bytes before the end of the last read are read again to check
+/// that the file was only appended to since.
+const CHECK_SIZE: usize = 4096;
+
+/// Reads a file that only ever grows by adding what was appended to it to
+/// the text read before, so each read costs the appended bytes rather than
+/// the whole file.
+///
+/// Whether the file was really only appended to is checked by hashing the
+/// bytes just before the old end again, which catches it being truncated,
+/// rotated or rewritten with anything else there.
+pub struct AppendReader {
+    contents: String,
+    /// Hash of the last [`CHECK_SIZE`] bytes of `contents`.
+    check: blake3::Hash,
+}
+
+impl AppendReader {
+    /// Continues from `contents`, read in full from the file. If they aren't
+    /// the file's bytes verbatim, e.g. because they were decoded from
+    /// another encoding, the first check fails.
+    pub fn new(contents: String) -> Self {
+        Self {
+            check: blake3::hash(check_window(&contents)),
+            contents,
+        }
+    }
+
+    /// The whole text of `path` after reading whatever was appended since the
+    /// last call, or `None` if it changed in some other way, or what was
+    /// appended isn't UTF-8, and must be read in full.
+    pub fn read_appended(&mut self, path: &Path) -> io::Result<Option<String>> {
+        let offset = self.contents.len() as u64;
+        let check_len = check_window(&self.contents).len();
+        let mut file = File::open(path)?;
+        if file.metadata()?.len() < offset {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(offset - check_len as u64))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if bytes.len() < check_len || blake3::hash(&bytes[..check_len]) != self.check {
+            return Ok(None);
+        }
+
+        let appended = match std::str::from_utf8(&bytes[check_len..]) {
+            Ok(appended) => appended,
+            // A character cut off by the end of the file is read once the
+            // rest of it is appended.
+            Err(error) if error.error_len().is_none() => {
+                std::str::from_utf8(&bytes[check_len..check_len + error.valid_up_to()]).unwrap()
+            }
+            Err(_) => return Ok(None),
+        };
+        if !appended.is_empty() {
+            self.contents.push_str(appended);
+            self.check = blake3::hash(check_window(&self.contents));
+        }
+        Ok(Some(self.contents.clone()))
+    }
+}
+
+/// The last [`CHECK_SIZE`] bytes of `text`, or all of it if shorter.
+fn check_window(text: &str) -> &[u8] {
+    let bytes = text.as_bytes();
+    &bytes[bytes.len().saturating_sub(CHECK_SIZE)..]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use super::*;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn reads_only_what_was_appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "one\ntw").unwrap();
+        let mut reader = TailReader::at_end(&path, false).unwrap();
+        assert!(reader.read_appended(&path).unwrap().is_none());
+
+        append(&path, "o\nthree\n");
+        let appended = reader.read_appended(&path).unwrap().unwrap();
+        assert_eq!(appended.before, "tw");
+        assert_eq!(appended.after, "two\nthree\n");
+
+        append(&path, "four\n");
+        let appended = reader.read_appended(&path).unwrap().unwrap();
+        assert_eq!((&*appended.before, &*appended.after), ("", "four\n"));
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_character() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "caf").unwrap();
+        let mut reader = TailReader::at_end(&path, false).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&"é".as_bytes()[..1]).unwrap();
+        assert!(reader.read_appended(&path).unwrap().is_none());
+        file.write_all(&"é".as_bytes()[1..]).unwrap();
+        file.write_all(b"\n").unwrap();
+        let appended = reader.read_appended(&path).unwrap().unwrap();
+        assert_eq!((&*appended.before, &*appended.after), ("caf", "café\n"));
+    }
+
+    #[test]
+    fn replaces_invalid_bytes_only_if_lossy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "").unwrap();
+        let mut strict = TailReader::at_end(&path, false).unwrap();
+        let mut lossy = TailReader::at_end(&path, true).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"bad \xff byte\n").unwrap();
+
+        let error = strict.read_appended(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("(use --lossy to replace it)"));
+        let appended = lossy.read_appended(&path).unwrap().unwrap();
+        assert_eq!(appended.after, "bad \u{FFFD} byte\n");
+        assert!(appended.replaced_invalid);
+
+        file.write_all(b"good\n").unwrap();
+        let appended = lossy.read_appended(&path).unwrap().unwrap();
+        assert_eq!(appended.after, "good\n");
+        assert!(!appended.replaced_invalid);
+    }
+
+    #[test]
+    fn starts_over_when_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "a long first line\n").unwrap();
+        let mut reader = TailReader::at_end(&path, false).unwrap();
+        fs::write(&path, "new\n").unwrap();
+        let appended = reader.read_appended(&path).unwrap().unwrap();
+        assert_eq!((&*appended.before, &*appended.after), ("", "new\n"));
+    }
+
+    #[test]
+    fn append_reader_adds_what_was_appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let text = "x".repeat(2 * CHECK_SIZE);
+        fs::write(&path, &text).unwrap();
+        let mut reader = AppendReader::new(text.clone());
+        assert_eq!(reader.read_appended(&path).unwrap().unwrap(), text);
+
+        append(&path, "more\n");
+        assert_eq!(
+            reader.read_appended(&path).unwrap().unwrap(),
+            format!("{text}more\n")
+        );
+    }
+
+    #[test]
+    fn append_reader_waits_for_the_rest_of_a_character() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "a").unwrap();
+        let mut reader = AppendReader::new("a".into());
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&"é".as_bytes()[..1]).unwrap();
+        assert_eq!(reader.read_appended(&path).unwrap().unwrap(), "a");
+        file.write_all(&"é".as_bytes()[1..]).unwrap();
+        assert_eq!(reader.read_appended(&path).unwrap().unwrap(), "aé");
+    }
+
+    #[test]
+    fn append_reader_gives_up_on_other_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut reader = AppendReader::new("one\ntwo\n".into());
+        fs::write(&path, "one\nTWO\nthree\n").unwrap();
+        assert!(reader.read_appended(&path).unwrap().is_none());
+        fs::write(&path, "one\n").unwrap();
+        assert!(reader.read_appended(&path).unwrap().is_none());
+        fs::write(&path, b"one\ntwo\n\xff").unwrap();
+        assert!(reader.read_appended(&path).unwrap().is_none());
+    }
+}
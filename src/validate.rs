@@ -0,0 +1,241 @@
+use std::{
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Longest validator output kept with a version, so a chatty linter doesn't
+/// bloat journals and stores.
+const MAX_OUTPUT: usize = 4096;
+
+/// What the `--validate` command made of one version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validation {
+    pub passed: bool,
+    /// Its stdout and stderr, trimmed and cut off at [`MAX_OUTPUT`] bytes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub output: String,
+}
+
+impl Validation {
+    /// The first line of output, for places with room for only one.
+    pub fn headline(&self) -> &str {
+        self.output.lines().next().unwrap_or_default()
+    }
+}
+
+/// How `--validate` commands are run, which a `[[profile]]` in the
+/// configuration file can set for each watched file.
+#[derive(Debug, Clone, clap::Args)]
+pub struct CommandArgs {
+    /// Directory to run --validate commands in [default: the watched file's]
+    #[clap(long, value_name = "DIR")]
+    pub command_dir: Option<PathBuf>,
+
+    /// Set an environment variable for --validate commands, e.g. `LANG=C`
+    #[clap(long, value_name = "NAME=VALUE", value_parser = parse_env)]
+    pub command_env: Vec<(String, String)>,
+
+    /// Don't pass slip-diff's own environment on to --validate commands, only PATH and what --command-env sets
+    #[clap(long)]
+    pub command_clear_env: bool,
+
+    /// Shell to run --validate commands with, as `<shell> -c <command>`
+    #[clap(long, value_name = "SHELL", default_value = "sh")]
+    pub command_shell: String,
+
+    /// Kill --validate commands still running after this long, e.g. `30s`, and count the version as failing
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub command_timeout: Option<Duration>,
+}
+
+impl Default for CommandArgs {
+    fn default() -> Self {
+        Self {
+            command_dir: None,
+            command_env: Vec::new(),
+            command_clear_env: false,
+            command_shell: "sh".into(),
+            command_timeout: None,
+        }
+    }
+}
+
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.into(), value.into())),
+        _ => Err(format!("expected NAME=VALUE, got `{s}`")),
+    }
+}
+
+/// A shell command checking the watched file, e.g. `nginx -t` or
+/// `yamllint {}`, where `{}` stands for the file's path. It runs in the
+/// file's directory unless told otherwise and passes if it exits
+/// successfully.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    command: String,
+    options: CommandArgs,
+}
+
+impl Validator {
+    pub fn new(command: String, options: &CommandArgs) -> Self {
+        Self {
+            command,
+            options: options.clone(),
+        }
+    }
+
+    /// Runs the command against `file` as it is on disk now.
+    pub fn run(&self, file: &Path) -> io::Result<Validation> {
+        let command = self.command.replace("{}", &shell_quote(file));
+        let dir = match &self.options.command_dir {
+            Some(dir) => dir.as_path(),
+            None => file
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+        };
+        let mut child = Command::new(&self.options.command_shell);
+        child
+            .arg("-c")
+            .arg(&command)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if self.options.command_clear_env {
+            child.env_clear();
+            if let Some(path) = std::env::var_os("PATH") {
+                child.env("PATH", path);
+            }
+        }
+        child.envs(self.options.command_env.iter().cloned());
+        // In a group of its own, so that a timeout kills what it started too.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut child, 0);
+        let (passed, mut output) = run(child.spawn()?, self.options.command_timeout)?;
+        if passed.is_none() {
+            let timeout = self.options.command_timeout.unwrap_or_default();
+            let killed = format!(
+                "killed after running for {}",
+                humantime::format_duration(timeout)
+            );
+            tracing::warn!("--validate command `{}` {killed}", self.command);
+            output = format!("{killed}\n{output}");
+        }
+        let mut output = output.trim().to_string();
+        if output.len() > MAX_OUTPUT {
+            let mut end = MAX_OUTPUT;
+            while !output.is_char_boundary(end) {
+                end -= 1;
+            }
+            output.truncate(end);
+            output.push('…');
+        }
+        Ok(Validation {
+            passed: passed.unwrap_or(false),
+            output,
+        })
+    }
+}
+
+/// Waits for `child` to exit, killing it and its process group if it takes
+/// longer than `timeout`. Returns whether it succeeded, or `None` if it was
+/// killed, and its stdout and stderr.
+fn run(mut child: Child, timeout: Option<Duration>) -> io::Result<(Option<bool>, String)> {
+    // Read on threads so a chatty command doesn't block on a full pipe.
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut bytes);
+            }
+            bytes
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let passed = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status.success());
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            kill(&mut child);
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let mut output = String::from_utf8_lossy(&stdout.join().unwrap_or_default()).into_owned();
+    output.push_str(&String::from_utf8_lossy(&stderr.join().unwrap_or_default()));
+    Ok((passed, output))
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // SAFETY: signals the process group the child leads, which it was
+    // started in.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+/// `path` made absolute, since the command runs in the file's directory,
+/// and single-quoted for `sh`.
+fn shell_quote(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_in_the_given_directory_and_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        std::fs::write(&file, "").unwrap();
+        let options = CommandArgs {
+            command_dir: Some("/".into()),
+            command_env: vec![("GREETING".into(), "hi".into())],
+            command_clear_env: true,
+            ..CommandArgs::default()
+        };
+        let validation = Validator::new("echo $PWD $GREETING $HOME".into(), &options)
+            .run(&file)
+            .unwrap();
+        assert!(validation.passed);
+        assert_eq!(validation.output, "/ hi");
+    }
+
+    #[test]
+    fn commands_running_too_long_are_killed_and_fail() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        std::fs::write(&file, "").unwrap();
+        let options = CommandArgs {
+            command_timeout: Some(Duration::from_millis(100)),
+            ..CommandArgs::default()
+        };
+        let started = Instant::now();
+        let validation = Validator::new("echo started; sleep 10 & wait".into(), &options)
+            .run(&file)
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!validation.passed);
+        assert_eq!(validation.output, "killed after running for 100ms\nstarted");
+    }
+}
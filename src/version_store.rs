@@ -0,0 +1,565 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use crate::{
+    digest::{Digest, HashAlgorithm},
+    history::{
+        EvictionPolicy, FileVersion, HistoryLimits, Version, VersionHistory, VersionInfo,
+        VersionMeta,
+    },
+};
+
+/// The versions of the watched file that are kept to diff and browse, in
+/// memory or elsewhere, as chosen with `--history`.
+pub trait VersionStore {
+    /// Appends a version and evicts older ones if a limit was exceeded.
+    ///
+    /// Returns the positions that were removed, in removal order, so callers
+    /// holding indices into the history can shift them.
+    fn push(&mut self, version: FileVersion) -> io::Result<Vec<usize>>;
+
+    /// Appends a version that was captured earlier, e.g. reloaded from a
+    /// session journal, keeping its original sequence number.
+    fn restore(&mut self, version: FileVersion) -> io::Result<Vec<usize>>;
+
+    /// The version at position `index`, or `None` past the end. Fails if
+    /// its contents can't be read back.
+    fn get(&self, index: usize) -> io::Result<Option<Version<'_>>>;
+
+    fn len(&self) -> usize;
+
+    /// Every version in order, each failing if its contents can't be read
+    /// back.
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<Version<'_>>> + '_>;
+
+    /// What is known about the version at position `index`, without
+    /// reading its contents.
+    fn info(&self, index: usize) -> Option<VersionInfo<'_>>;
+
+    /// What is known about every version in order, without reading any
+    /// contents, e.g. to list them.
+    fn infos(&self) -> Box<dyn Iterator<Item = VersionInfo<'_>> + '_>;
+
+    /// Drops the version at position `index`.
+    fn evict(&mut self, index: usize) -> io::Result<()>;
+
+    /// The sequence number the next pushed version will get.
+    fn next_number(&self) -> usize;
+
+    /// The algorithm versions are deduplicated by.
+    fn algorithm(&self) -> HashAlgorithm;
+
+    fn latest_hash(&self) -> Option<Digest>;
+
+    /// Stops or resumes spending work on keeping versions small, for stores
+    /// that do.
+    fn set_keep_whole(&mut self, _on: bool) {}
+
+    fn last(&self) -> io::Result<Option<Version<'_>>> {
+        match self.len().checked_sub(1) {
+            Some(index) => self.get(index),
+            None => Ok(None),
+        }
+    }
+
+    fn last_info(&self) -> Option<VersionInfo<'_>> {
+        self.info(self.len().checked_sub(1)?)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `contents` are what the latest version holds, by hash.
+    fn is_latest(&self, contents: &str) -> bool {
+        self.latest_hash() == Some(self.algorithm().digest(contents.as_bytes()))
+    }
+}
+
+impl VersionStore for VersionHistory {
+    fn push(&mut self, version: FileVersion) -> io::Result<Vec<usize>> {
+        Ok(VersionHistory::push(self, version))
+    }
+
+    fn restore(&mut self, version: FileVersion) -> io::Result<Vec<usize>> {
+        Ok(VersionHistory::restore(self, version))
+    }
+
+    fn get(&self, index: usize) -> io::Result<Option<Version<'_>>> {
+        Ok(VersionHistory::get(self, index))
+    }
+
+    fn len(&self) -> usize {
+        VersionHistory::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<Version<'_>>> + '_> {
+        Box::new(VersionHistory::iter(self).map(Ok))
+    }
+
+    fn info(&self, index: usize) -> Option<VersionInfo<'_>> {
+        VersionHistory::info(self, index)
+    }
+
+    fn infos(&self) -> Box<dyn Iterator<Item = VersionInfo<'_>> + '_> {
+        Box::new(VersionHistory::infos(self))
+    }
+
+    fn evict(&mut self, index: usize) -> io::Result<()> {
+        VersionHistory::evict(self, index);
+        Ok(())
+    }
+
+    fn next_number(&self) -> usize {
+        VersionHistory::next_number(self)
+    }
+
+    fn algorithm(&self) -> HashAlgorithm {
+        VersionHistory::algorithm(self)
+    }
+
+    fn latest_hash(&self) -> Option<Digest> {
+        VersionHistory::last(self).map(|version| version.hash)
+    }
+
+    fn set_keep_whole(&mut self, on: bool) {
+        VersionHistory::set_keep_whole(self, on);
+    }
+}
+
+/// Where the history keeps the contents of versions, as given to
+/// `--history`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum HistoryBackend {
+    /// `memory`: delta-compressed in memory.
+    #[default]
+    Memory,
+    /// `disk:<dir>`: one file per distinct contents in a directory.
+    Disk(PathBuf),
+    /// `sqlite:<path>`: one row per distinct contents in a SQLite database.
+    Sqlite(PathBuf),
+}
+
+impl FromStr for HistoryBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "memory" => Ok(Self::Memory),
+            Some(("disk", dir)) if !dir.is_empty() => Ok(Self::Disk(dir.into())),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Self::Sqlite(path.into())),
+            _ => Err(format!(
+                "unsupported history `{s}`, expected memory, disk:<dir> or sqlite:<path>"
+            )),
+        }
+    }
+}
+
+/// Opens the history `limits` asks for.
+#[cfg_attr(not(feature = "sqlite"), allow(unreachable_code))]
+pub fn open(limits: HistoryLimits, algorithm: HashAlgorithm) -> io::Result<Box<dyn VersionStore>> {
+    Ok(match &limits.history {
+        HistoryBackend::Memory => Box::new(VersionHistory::new(limits, algorithm)),
+        HistoryBackend::Disk(dir) => {
+            let blobs = DiskBlobs::open(dir)?;
+            Box::new(OffloadedHistory::new(blobs, limits, algorithm))
+        }
+        HistoryBackend::Sqlite(path) => {
+            let blobs = SqliteBlobs::open(path)?;
+            Box::new(OffloadedHistory::new(blobs, limits, algorithm))
+        }
+    })
+}
+
+/// Somewhere outside memory to keep contents, by hash.
+pub trait BlobStore {
+    /// Stores `contents` under `hash` unless they are there already.
+    fn put(&mut self, hash: &Digest, contents: &str) -> io::Result<()>;
+
+    fn get(&self, hash: &Digest) -> io::Result<String>;
+
+    fn remove(&mut self, hash: &Digest) -> io::Result<()>;
+}
+
+/// A version's place in the history; its contents live in the blob store.
+struct Entry {
+    hash: Digest,
+    at: SystemTime,
+    number: usize,
+    size: usize,
+    meta: VersionMeta,
+}
+
+impl Entry {
+    fn info(&self) -> VersionInfo<'_> {
+        VersionInfo {
+            hash: self.hash,
+            at: self.at,
+            number: self.number,
+            size: self.size,
+            meta: &self.meta,
+        }
+    }
+}
+
+/// A history keeping only what identifies each version in memory and their
+/// contents in a [`BlobStore`], for files too large to keep many versions of
+/// in memory. `--max-memory` doesn't apply.
+pub struct OffloadedHistory<B> {
+    blobs: B,
+    entries: Vec<Entry>,
+    /// Versions referring to each stored blob.
+    refs: HashMap<Digest, usize>,
+    limits: HistoryLimits,
+    algorithm: HashAlgorithm,
+    next_number: usize,
+}
+
+pub type DiskHistory = OffloadedHistory<DiskBlobs>;
+pub type SqliteHistory = OffloadedHistory<SqliteBlobs>;
+
+impl<B: BlobStore> OffloadedHistory<B> {
+    pub fn new(blobs: B, limits: HistoryLimits, algorithm: HashAlgorithm) -> Self {
+        Self {
+            blobs,
+            entries: Vec::new(),
+            refs: HashMap::new(),
+            limits,
+            algorithm,
+            next_number: 0,
+        }
+    }
+
+    fn version<'a>(&'a self, entry: &'a Entry) -> io::Result<Version<'a>> {
+        let contents = self.blobs.get(&entry.hash)?;
+        Ok(Version {
+            contents: Arc::from(contents),
+            hash: entry.hash,
+            at: entry.at,
+            number: entry.number,
+            meta: &entry.meta,
+        })
+    }
+
+    /// The version to drop next, never the latest one.
+    fn eviction_index(&self) -> Option<usize> {
+        let too_many = self
+            .limits
+            .max_versions
+            .is_some_and(|max| self.entries.len() as u64 > max);
+        let index = match self.limits.eviction {
+            EvictionPolicy::DropOldest => 0,
+            EvictionPolicy::KeepFirst => 1,
+        };
+        (too_many && index + 1 < self.entries.len()).then_some(index)
+    }
+}
+
+impl<B: BlobStore> VersionStore for OffloadedHistory<B> {
+    fn push(&mut self, version: FileVersion) -> io::Result<Vec<usize>> {
+        let hash = self.algorithm.digest(version.contents.as_bytes());
+        if !self.refs.contains_key(&hash) {
+            self.blobs.put(&hash, &version.contents)?;
+        }
+        *self.refs.entry(hash).or_insert(0) += 1;
+        self.entries.push(Entry {
+            hash,
+            at: version.at,
+            number: self.next_number,
+            size: version.contents.len(),
+            meta: version.meta,
+        });
+        self.next_number += 1;
+
+        let mut evicted = Vec::new();
+        while let Some(index) = self.eviction_index() {
+            self.evict(index)?;
+            evicted.push(index);
+        }
+        Ok(evicted)
+    }
+
+    fn restore(&mut self, version: FileVersion) -> io::Result<Vec<usize>> {
+        let number = version.number;
+        let evicted = self.push(version)?;
+        if let Some(last) = self.entries.last_mut() {
+            last.number = number;
+        }
+        self.next_number = number + 1;
+        Ok(evicted)
+    }
+
+    fn get(&self, index: usize) -> io::Result<Option<Version<'_>>> {
+        self.entries
+            .get(index)
+            .map(|entry| self.version(entry))
+            .transpose()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<Version<'_>>> + '_> {
+        Box::new(self.entries.iter().map(|entry| self.version(entry)))
+    }
+
+    fn info(&self, index: usize) -> Option<VersionInfo<'_>> {
+        self.entries.get(index).map(Entry::info)
+    }
+
+    fn infos(&self) -> Box<dyn Iterator<Item = VersionInfo<'_>> + '_> {
+        Box::new(self.entries.iter().map(Entry::info))
+    }
+
+    fn evict(&mut self, index: usize) -> io::Result<()> {
+        let removed = self.entries.remove(index);
+        let refs = self.refs.get_mut(&removed.hash).unwrap();
+        *refs -= 1;
+        if *refs == 0 {
+            self.refs.remove(&removed.hash);
+            self.blobs.remove(&removed.hash)?;
+        }
+        Ok(())
+    }
+
+    fn next_number(&self) -> usize {
+        self.next_number
+    }
+
+    fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    fn latest_hash(&self) -> Option<Digest> {
+        self.entries.last().map(|entry| entry.hash)
+    }
+}
+
+/// Contents kept as one file per hash in a directory.
+pub struct DiskBlobs {
+    dir: PathBuf,
+}
+
+impl DiskBlobs {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn path(&self, hash: &Digest) -> PathBuf {
+        // `sha256:<hex>` isn't a valid file name everywhere.
+        self.dir.join(hash.to_string().replace(':', "-"))
+    }
+}
+
+impl BlobStore for DiskBlobs {
+    fn put(&mut self, hash: &Digest, contents: &str) -> io::Result<()> {
+        let path = self.path(hash);
+        if path.exists() {
+            return Ok(());
+        }
+        // Written aside and renamed, so a crash never leaves a blob half
+        // written under its hash.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, contents)?;
+        fs::rename(partial, path)
+    }
+
+    fn get(&self, hash: &Digest) -> io::Result<String> {
+        fs::read_to_string(self.path(hash))
+    }
+
+    fn remove(&mut self, hash: &Digest) -> io::Result<()> {
+        match fs::remove_file(self.path(hash)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Contents kept as one row per hash in a SQLite database, which may be the
+/// one given to `--store`. Every run keeps its rows apart, so that several
+/// can share the database, and deletes them when it ends.
+#[cfg(feature = "sqlite")]
+pub struct SqliteBlobs {
+    conn: rusqlite::Connection,
+    run: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteBlobs {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let conn = rusqlite::Connection::open(path).map_err(io::Error::other)?;
+        // Other runs write to the same database, so wait for them as the
+        // `--store` does.
+        conn.busy_timeout(crate::store::BUSY_TIMEOUT)
+            .map_err(io::Error::other)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS history_blobs (
+                 run TEXT NOT NULL,
+                 hash TEXT NOT NULL,
+                 contents TEXT NOT NULL,
+                 PRIMARY KEY (run, hash)
+             );",
+        )
+        .map_err(io::Error::other)?;
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let run = format!("{}-{}", std::process::id(), started.as_nanos());
+        Ok(Self { conn, run })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Drop for SqliteBlobs {
+    fn drop(&mut self) {
+        if let Err(error) = self
+            .conn
+            .execute("DELETE FROM history_blobs WHERE run = ?1", [&self.run])
+        {
+            tracing::warn!("deleting the history's blobs: {error}");
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl BlobStore for SqliteBlobs {
+    fn put(&mut self, hash: &Digest, contents: &str) -> io::Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO history_blobs (run, hash, contents) VALUES (?1, ?2, ?3)",
+                (&self.run, hash.to_string(), contents),
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn get(&self, hash: &Digest) -> io::Result<String> {
+        self.conn
+            .query_row(
+                "SELECT contents FROM history_blobs WHERE run = ?1 AND hash = ?2",
+                [&self.run, &hash.to_string()],
+                |row| row.get(0),
+            )
+            .map_err(io::Error::other)
+    }
+
+    fn remove(&mut self, hash: &Digest) -> io::Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM history_blobs WHERE run = ?1 AND hash = ?2",
+                [&self.run, &hash.to_string()],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+/// Stand-in for builds without the `sqlite` feature, which can't open one.
+#[cfg(not(feature = "sqlite"))]
+pub struct SqliteBlobs(std::convert::Infallible);
+
+#[cfg(not(feature = "sqlite"))]
+impl SqliteBlobs {
+    pub fn open(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::other(
+            "slip-diff was built without the `sqlite` feature",
+        ))
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+impl BlobStore for SqliteBlobs {
+    fn put(&mut self, _hash: &Digest, _contents: &str) -> io::Result<()> {
+        match self.0 {}
+    }
+
+    fn get(&self, _hash: &Digest) -> io::Result<String> {
+        match self.0 {}
+    }
+
+    fn remove(&mut self, _hash: &Digest) -> io::Result<()> {
+        match self.0 {}
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    fn sqlite_history(path: &Path) -> SqliteHistory {
+        let blobs = SqliteBlobs::open(path).unwrap();
+        OffloadedHistory::new(blobs, HistoryLimits::default(), HashAlgorithm::default())
+    }
+
+    #[test]
+    fn runs_sharing_a_database_keep_their_own_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("versions.db");
+        let mut first = sqlite_history(&db);
+        first.push(FileVersion::new_at_now("first\n")).unwrap();
+        let mut second = sqlite_history(&db);
+        second.push(FileVersion::new_at_now("second\n")).unwrap();
+        drop(second);
+
+        let version = first.last().unwrap().unwrap();
+        assert_eq!(&*version.contents, "first\n");
+    }
+
+    #[test]
+    fn runs_can_write_at_the_same_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("versions.db");
+        let runs: Vec<_> = (0..8)
+            .map(|run| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    let mut history = sqlite_history(&db);
+                    for number in 0..50 {
+                        let contents = format!("run {run}, version {number}\n");
+                        history.push(FileVersion::new_at_now(contents)).unwrap();
+                    }
+                    let latest = history.last().unwrap().unwrap();
+                    assert_eq!(&*latest.contents, format!("run {run}, version 49\n"));
+                })
+            })
+            .collect();
+        for run in runs {
+            run.join().unwrap();
+        }
+        let mode: String = rusqlite::Connection::open(&db)
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+    }
+
+    #[test]
+    fn unreadable_blobs_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("versions.db");
+        let mut history = sqlite_history(&db);
+        history.push(FileVersion::new_at_now("one\n")).unwrap();
+        history.push(FileVersion::new_at_now("two\n")).unwrap();
+        rusqlite::Connection::open(&db)
+            .unwrap()
+            .execute("DELETE FROM history_blobs WHERE contents = 'one\n'", [])
+            .unwrap();
+
+        assert!(history.get(0).is_err());
+        let versions: Vec<_> = history.iter().collect();
+        assert!(versions[0].is_err());
+        assert_eq!(&*versions[1].as_ref().unwrap().contents, "two\n");
+    }
+}
@@ -0,0 +1,95 @@
+//! JavaScript bindings for a browser frontend, for the wasm32 build with the
+//! `wasm` feature. Versions are kept and rendered by the same code as in the
+//! CLI; capturing them is up to the page.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    history::{FileVersion, HistoryLimits, VersionHistory, VersionMeta},
+    render::{RenderOptions, Renderers},
+};
+
+/// The versions of one file, as pushed by the page.
+#[wasm_bindgen]
+pub struct History {
+    versions: VersionHistory,
+}
+
+#[wasm_bindgen]
+impl History {
+    /// Keeps up to `max_versions` versions, or all of them.
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_versions: Option<u32>) -> History {
+        let limits = HistoryLimits {
+            max_versions: max_versions.map(u64::from),
+            ..HistoryLimits::default()
+        };
+        History {
+            versions: VersionHistory::new(limits, Default::default()),
+        }
+    }
+
+    /// Adds `contents`, captured at `at_ms` milliseconds since the epoch
+    /// (`Date.now()`), unless they are what the latest version holds.
+    /// Returns whether a version was added.
+    pub fn push(&mut self, contents: String, at_ms: f64) -> bool {
+        if self.versions.is_latest(&contents) {
+            return false;
+        }
+        // The clock isn't available from WebAssembly, so the page passes
+        // the time in.
+        self.versions.push(FileVersion {
+            contents: contents.into(),
+            at: UNIX_EPOCH + Duration::from_millis(at_ms as u64),
+            number: 0,
+            meta: VersionMeta::default(),
+        });
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+
+    /// The contents of the version at position `index`.
+    pub fn contents(&self, index: usize) -> Option<String> {
+        Some(self.versions.get(index)?.contents.to_string())
+    }
+
+    /// The sequence number of the version at position `index`.
+    pub fn number(&self, index: usize) -> Option<usize> {
+        Some(self.versions.get(index)?.number)
+    }
+
+    /// Renders the change from the version at position `from` to the one at
+    /// `to` with the renderer `--renderer` calls `renderer`.
+    pub fn render(&self, from: usize, to: usize, renderer: &str) -> Result<String, JsError> {
+        let (Some(old), Some(new)) = (self.versions.get(from), self.versions.get(to)) else {
+            return Err(JsError::new("no version at that position"));
+        };
+        render(&old.contents, &new.contents, renderer)
+    }
+}
+
+/// Renders the change from `old` to `new` with the renderer `--renderer`
+/// calls `renderer`.
+#[wasm_bindgen]
+pub fn render(old: &str, new: &str, renderer: &str) -> Result<String, JsError> {
+    let renderer = Renderers::default()
+        .get(renderer)
+        .ok_or_else(|| JsError::new(&format!("unknown renderer `{renderer}`")))?;
+    let options = RenderOptions {
+        // There is no terminal to fill.
+        width: Some(160),
+        ..RenderOptions::default()
+    };
+    renderer
+        .render(old, new, &options)
+        .map_err(|error| JsError::new(&error.to_string()))
+}
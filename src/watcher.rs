@@ -0,0 +1,517 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{
+    event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _,
+};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time::{self, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    capture::{Capture, Snapshot},
+    digest::{Digest, HashAlgorithm},
+    events::{self, EventLog, LogKind},
+    history::FileVersion,
+    poll::PollArgs,
+    power,
+    remote::{self, RemoteFile, RemoteWatch},
+};
+
+/// How many times longer the poll interval gets with `--low-power` on
+/// battery.
+const LOW_POWER_POLL_FACTOR: u32 = 5;
+/// How long changes are gathered with `--low-power` on battery before the
+/// file is read once for all of them.
+const LOW_POWER_BATCH: Duration = Duration::from_secs(5);
+/// How often `--low-power` checks whether the machine is on battery.
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Watches one file, and the files its captures were assembled from, for
+/// changes: by events from the OS, or by polling for virtual files that
+/// never emit any. What changed is read with [`Watcher::read`].
+///
+/// ```no_run
+/// # async fn example(poll: &slip_diff::poll::PollArgs) -> Result<(), Box<dyn std::error::Error>> {
+/// use slip_diff::{capture::CaptureArgs, history::FileVersion, watcher::Change, VersionHistory, Watcher};
+///
+/// let capture = CaptureArgs::default().capture()?;
+/// let mut watcher = Watcher::new("app.conf".as_ref(), capture, poll)?;
+/// let mut versions = VersionHistory::default();
+/// versions.push(FileVersion::new_at_now(watcher.read()?.contents));
+/// loop {
+///     if watcher.next().await? == Change::Contents {
+///         let contents = watcher.read()?.contents;
+///         if !versions.is_latest(&contents) {
+///             versions.push(FileVersion::new_at_now(contents));
+///         }
+///     }
+/// }
+/// # }
+/// ```
+pub struct Watcher {
+    path: PathBuf,
+    capture: Capture,
+    watcher: RecommendedWatcher,
+    events: UnboundedReceiver<notify::Result<Event>>,
+    /// Files being watched for events; empty when polling.
+    watched: HashSet<PathBuf>,
+    /// Ticks when the file is due to be re-read, if it is polled.
+    poll: Option<Interval>,
+    /// Tells when a remote file changes, if it can.
+    remote: Option<RemoteWatch>,
+    interval: Duration,
+    low_power: bool,
+    /// Whether `--low-power` is saving work because the machine is on
+    /// battery.
+    throttled: bool,
+    power_checked: time::Instant,
+    /// Changes being gathered while throttled, and when they are due.
+    batch: Option<(time::Instant, Change)>,
+    /// What was seen since the log was last taken, if it is kept.
+    log: Option<EventLog>,
+}
+
+/// What [`Watcher::next`] noticed about the watched files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Their contents may have changed.
+    Contents,
+    /// Only their permissions, owner or other metadata changed.
+    Metadata,
+    /// The watched file was removed, renamed or created, e.g. replaced by
+    /// an editor saving it or rotated by a logger.
+    Moved,
+    /// An event about something else, only returned while the log is kept
+    /// so that it can be shown as it arrives.
+    Ignored,
+}
+
+impl Watcher {
+    /// Starts watching `path`, which is read with `capture`, polling it at
+    /// `poll`'s interval if it is a virtual file.
+    pub fn new(path: &Path, capture: Capture, poll: &PollArgs) -> notify::Result<Self> {
+        // Events name the files they are about by absolute path.
+        let path = match remote::is_remote(path) {
+            true => path.to_path_buf(),
+            false => std::path::absolute(path).map_err(notify::Error::io)?,
+        };
+        let (mut watcher, events) = events::watcher()?;
+        let mut watched = HashSet::new();
+        let low_power = poll.low_power;
+        let throttled = low_power && power::on_battery();
+        let interval = poll.interval;
+        let remote = match RemoteFile::from_path(&path)? {
+            Some(remote) => remote.watch()?,
+            None => None,
+        };
+        let poll = if remote.is_some() {
+            None
+        } else if poll.should_poll(&path) {
+            Some(poll_interval(interval, throttled))
+        } else {
+            watcher.watch(&path, RecursiveMode::NonRecursive)?;
+            watched.insert(path.clone());
+            None
+        };
+        Ok(Self {
+            path,
+            capture,
+            watcher,
+            events,
+            watched,
+            poll,
+            remote,
+            interval,
+            low_power,
+            throttled,
+            power_checked: time::Instant::now(),
+            batch: None,
+            log: None,
+        })
+    }
+
+    /// Whether `--low-power` is saving work because the machine is on
+    /// battery, which versions should be kept whole for too.
+    pub fn throttled(&self) -> bool {
+        self.throttled
+    }
+
+    /// Starts logging the raw events and polls seen and what was made of
+    /// them, for [`Watcher::take_log`].
+    pub fn keep_log(&mut self) {
+        self.log.get_or_insert_with(EventLog::default);
+    }
+
+    /// Takes what was logged since the last call, if logging.
+    pub fn take_log(&mut self) -> EventLog {
+        self.log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Traces what was seen and keeps it in the log, if either wants it.
+    fn log(&mut self, kind: LogKind, message: impl FnOnce() -> String) {
+        if self.log.is_none() && !kind.traced() {
+            return;
+        }
+        let message = message();
+        kind.trace(&message);
+        if let Some(log) = &mut self.log {
+            log.push(kind, message);
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Captures the file, and starts watching any files it was assembled
+    /// from that aren't watched yet.
+    pub fn read(&mut self) -> io::Result<Snapshot> {
+        let snapshot = self.capture.read(&self.path)?;
+        if self.poll.is_none() {
+            for include in &snapshot.includes {
+                if self.watched.insert(include.clone()) {
+                    self.watcher
+                        .watch(include, RecursiveMode::NonRecursive)
+                        .map_err(io::Error::other)?;
+                }
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Waits until the watched files change, or the next poll is due. This
+    /// is cancel-safe, so it can be awaited in a `tokio::select!`.
+    pub async fn next(&mut self) -> notify::Result<Change> {
+        self.check_power();
+        loop {
+            if let Some((due, change)) = self.batch {
+                tokio::select! {
+                    _ = time::sleep_until(due) => {
+                        self.batch = None;
+                        return Ok(change);
+                    }
+                    more = self.event() => if let Some(more) = more? {
+                        // New contents are read anyway, which shows new
+                        // metadata too.
+                        let change = match (change, more) {
+                            (Change::Moved, _) | (_, Change::Moved) => Change::Moved,
+                            (Change::Metadata, more) => more,
+                            (change, _) => change,
+                        };
+                        self.batch = Some((due, change));
+                    }
+                }
+                continue;
+            }
+            let Some(change) = self.event().await? else {
+                if self.log.is_some() {
+                    return Ok(Change::Ignored);
+                }
+                continue;
+            };
+            if !self.throttled || self.poll.is_some() {
+                return Ok(change);
+            }
+            self.log(LogKind::Debounce, || {
+                format!(
+                    "on battery, gathering changes for {}",
+                    humantime::format_duration(LOW_POWER_BATCH)
+                )
+            });
+            self.batch = Some((time::Instant::now() + LOW_POWER_BATCH, change));
+        }
+    }
+
+    /// The next change event or poll, or `None` for events about something
+    /// other than contents or metadata.
+    async fn event(&mut self) -> notify::Result<Option<Change>> {
+        if let Some(remote) = self.remote.as_mut() {
+            remote.changed().await;
+            self.log(LogKind::Event, || "remote change".to_string());
+            return Ok(Some(Change::Contents));
+        }
+        let event = match self.poll.as_mut() {
+            Some(poll) => {
+                // Events for the includes of a polled file aren't watched,
+                // so there is only the timer.
+                poll.tick().await;
+                self.log(LogKind::Event, || "poll".to_string());
+                return Ok(Some(Change::Contents));
+            }
+            None => match self.events.recv().await {
+                Some(Ok(event)) => event,
+                Some(Err(error)) => {
+                    self.log(LogKind::Error, || error.to_string());
+                    return Err(error);
+                }
+                // The sender lives as long as the watcher.
+                None => unreachable!("file watcher stopped"),
+            },
+        };
+        let change = match event.kind {
+            EventKind::Modify(ModifyKind::Metadata(_)) => Some(Change::Metadata),
+            EventKind::Modify(ModifyKind::Data(_)) => Some(Change::Contents),
+            EventKind::Modify(ModifyKind::Name(_))
+            | EventKind::Remove(_)
+            | EventKind::Create(_)
+                if event.paths.iter().any(|path| path == &self.path) =>
+            {
+                Some(Change::Moved)
+            }
+            _ => None,
+        };
+        self.log(LogKind::Event, || {
+            let paths: Vec<_> = event
+                .paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            let ignored = if change.is_none() { " (ignored)" } else { "" };
+            format!("{:?} {}{ignored}", event.kind, paths.join(", "))
+        });
+        Ok(change)
+    }
+
+    /// Watches the path afresh, after the file it named was replaced.
+    pub fn rewatch(&mut self) -> notify::Result<()> {
+        if self.poll.is_some() || self.remote.is_some() {
+            return Ok(());
+        }
+        // The old watch went with the old file, if it isn't gone already.
+        let _ = self.watcher.unwatch(&self.path);
+        self.watcher.watch(&self.path, RecursiveMode::NonRecursive)
+    }
+
+    /// Hands the watcher to a task that reads the file whenever it changes,
+    /// and returns the versions it captured and whatever else happened to
+    /// the file as [`ChangeEvent`]s, starting with the current version. The
+    /// task ends once the receiver is dropped. Must be called within a tokio
+    /// runtime.
+    pub fn subscribe(self) -> UnboundedReceiver<ChangeEvent> {
+        let (events, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(Subscription::new(self, events).run());
+        receiver
+    }
+
+    /// Throttles or stops throttling with `--low-power` as the machine goes
+    /// on or off battery.
+    fn check_power(&mut self) {
+        if !self.low_power || self.power_checked.elapsed() < POWER_CHECK_INTERVAL {
+            return;
+        }
+        self.power_checked = time::Instant::now();
+        let throttled = power::on_battery();
+        if throttled == self.throttled {
+            return;
+        }
+        self.throttled = throttled;
+        self.log(LogKind::Debounce, || match throttled {
+            true => "on battery, watching less often".to_string(),
+            false => "on mains power, watching as usual".to_string(),
+        });
+        if let Some(poll) = self.poll.as_mut() {
+            *poll = poll_interval(self.interval, throttled);
+        }
+    }
+}
+
+/// Ticks every `interval` from one `interval` from now, or less often if
+/// `throttled`.
+fn poll_interval(interval: Duration, throttled: bool) -> Interval {
+    let interval = if throttled {
+        interval * LOW_POWER_POLL_FACTOR
+    } else {
+        interval
+    };
+    let mut poll = time::interval_at(time::Instant::now() + interval, interval);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    poll
+}
+
+/// What [`Watcher::subscribe`] reports about the watched file.
+pub enum ChangeEvent {
+    /// New contents were read, numbered from 0 in the order captured.
+    VersionCaptured(FileVersion),
+    /// The file was removed or moved away. It is checked for every poll
+    /// interval until it is back.
+    WatchLost,
+    /// Another file took the place of the watched one, e.g. after it was
+    /// saved by renaming over it or rotated by a logger. Its contents follow
+    /// as a version if they differ.
+    FileRotated,
+    /// Watching or reading the file failed; watching goes on.
+    Error(io::Error),
+}
+
+/// The task behind [`Watcher::subscribe`].
+struct Subscription {
+    watcher: Watcher,
+    events: UnboundedSender<ChangeEvent>,
+    latest: Option<Digest>,
+    next_number: usize,
+    /// The file the path named when last read.
+    identity: Option<FileIdentity>,
+    lost: bool,
+}
+
+impl Subscription {
+    fn new(watcher: Watcher, events: UnboundedSender<ChangeEvent>) -> Self {
+        let identity = file_identity(&watcher.path);
+        Self {
+            watcher,
+            events,
+            latest: None,
+            next_number: 0,
+            identity,
+            lost: false,
+        }
+    }
+
+    async fn run(mut self) {
+        self.capture();
+        while !self.events.is_closed() {
+            if self.lost {
+                time::sleep(self.watcher.interval).await;
+            } else {
+                match self.watcher.next().await {
+                    Ok(Change::Contents | Change::Moved) => {}
+                    Ok(Change::Metadata | Change::Ignored) => continue,
+                    Err(error) => {
+                        self.send(ChangeEvent::Error(io::Error::other(error)));
+                        continue;
+                    }
+                }
+            }
+            if !remote::is_remote(&self.watcher.path) && !self.watcher.path.exists() {
+                if !self.lost {
+                    tracing::info!(
+                        "{} is gone, checking for it every poll interval",
+                        self.watcher.path.display()
+                    );
+                    self.lost = true;
+                    self.send(ChangeEvent::WatchLost);
+                }
+                continue;
+            }
+            let identity = file_identity(&self.watcher.path);
+            if self.lost || identity != self.identity {
+                self.lost = false;
+                self.identity = identity;
+                self.send(ChangeEvent::FileRotated);
+                tracing::info!(
+                    "{} was replaced, watching it afresh",
+                    self.watcher.path.display()
+                );
+                if let Err(error) = self.watcher.rewatch() {
+                    self.send(ChangeEvent::Error(io::Error::other(error)));
+                }
+            }
+            self.capture();
+        }
+    }
+
+    /// Reads the file and reports its contents if they are new.
+    fn capture(&mut self) {
+        let snapshot = match self.watcher.read() {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                tracing::warn!("reading {}: {error}", self.watcher.path.display());
+                return self.send(ChangeEvent::Error(error));
+            }
+        };
+        let hash = HashAlgorithm::default().digest(snapshot.contents.as_bytes());
+        if self.latest == Some(hash) {
+            return;
+        }
+        self.latest = Some(hash);
+        let mut version = FileVersion::new_at_now(snapshot.contents);
+        version.number = self.next_number;
+        version.meta.replaced_invalid = snapshot.replaced_invalid;
+        self.next_number += 1;
+        self.send(ChangeEvent::VersionCaptured(version));
+    }
+
+    fn send(&self, event: ChangeEvent) {
+        // Only fails once the receiver is gone, which ends the task.
+        let _ = self.events.send(event);
+    }
+}
+
+/// What tells one file from another at the same path.
+#[cfg(unix)]
+type FileIdentity = (u64, u64);
+#[cfg(not(unix))]
+type FileIdentity = ();
+
+/// The device and inode of the file at `path`. Elsewhere files can only be
+/// told apart by one disappearing before the other takes its place.
+#[cfg(unix)]
+fn file_identity(path: &Path) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(path: &Path) -> Option<FileIdentity> {
+    fs::metadata(path).ok().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime;
+
+    use super::*;
+    use crate::capture::CaptureArgs;
+
+    /// Waits for `wanted`, skipping whatever else the watcher notices.
+    async fn wait_for(watcher: &mut Watcher, wanted: Change) {
+        loop {
+            let change = time::timeout(Duration::from_secs(5), watcher.next())
+                .await
+                .unwrap_or_else(|_| panic!("no {wanted:?} within 5s"))
+                .unwrap();
+            if change == wanted {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn follows_a_file_saved_by_renaming_over_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+        fs::write(&path, "one\n").unwrap();
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let capture = CaptureArgs::default().capture().unwrap();
+            let mut watcher = Watcher::new(&path, capture, &PollArgs::default()).unwrap();
+
+            let saved = dir.path().join("app.conf~");
+            fs::write(&saved, "two\n").unwrap();
+            fs::rename(&saved, &path).unwrap();
+            wait_for(&mut watcher, Change::Moved).await;
+            watcher.rewatch().unwrap();
+            assert_eq!(watcher.read().unwrap().contents, "two\n");
+
+            // Only seen if the new file is watched rather than the old one.
+            fs::write(&path, "three\n").unwrap();
+            wait_for(&mut watcher, Change::Contents).await;
+            assert_eq!(watcher.read().unwrap().contents, "three\n");
+        });
+    }
+}